@@ -0,0 +1,69 @@
+package ml.dmlc.tvm.android;
+
+import android.content.Context;
+
+import java.io.ByteArrayOutputStream;
+import java.io.File;
+import java.io.FileOutputStream;
+import java.io.IOException;
+import java.io.InputStream;
+import java.io.OutputStream;
+
+/** Runs a graph compiled by TVM, whose artifacts are packaged as assets of the app. */
+public final class GraphExecutor implements AutoCloseable {
+  static {
+    System.loadLibrary("tvm_android");
+  }
+
+  private static native void init(String nativeLibraryDir);
+
+  private static native long create(String graph, String lib, byte[] params);
+
+  private static native float[] run(long handle, String name, float[] data);
+
+  private static native void free(long handle);
+
+  private long handle;
+
+  public GraphExecutor(Context context, String graph, String lib, String params)
+      throws IOException {
+    init(context.getApplicationInfo().nativeLibraryDir);
+    // the compiled library is loaded with dlopen, so it must be a file
+    File libFile = new File(context.getFilesDir(), lib);
+    try (InputStream in = context.getAssets().open(lib);
+        OutputStream out = new FileOutputStream(libFile)) {
+      copy(in, out);
+    }
+    handle = create(
+        new String(readAsset(context, graph), "UTF-8"),
+        libFile.getAbsolutePath(),
+        readAsset(context, params));
+  }
+
+  /** Sets the input {@code name}, runs the graph and returns its first output. */
+  public float[] run(String name, float[] data) {
+    return run(handle, name, data);
+  }
+
+  @Override
+  public void close() {
+    free(handle);
+    handle = 0;
+  }
+
+  private static byte[] readAsset(Context context, String name) throws IOException {
+    try (InputStream in = context.getAssets().open(name)) {
+      ByteArrayOutputStream out = new ByteArrayOutputStream();
+      copy(in, out);
+      return out.toByteArray();
+    }
+  }
+
+  private static void copy(InputStream in, OutputStream out) throws IOException {
+    byte[] buffer = new byte[8192];
+    int n;
+    while ((n = in.read(buffer)) != -1) {
+      out.write(buffer, 0, n);
+    }
+  }
+}
@@ -0,0 +1,110 @@
+//! JNI bindings of the `ml.dmlc.tvm.android.GraphExecutor` Java class, which runs a
+//! graph compiled by TVM with the graph runtime on the cpu of an Android device.
+//!
+//! The runtime library is not linked but opened from the native library directory of
+//! the app, where Android extracts the `libtvm_runtime.so` packaged in the APK.
+
+extern crate jni;
+extern crate tvm_frontend as tvm;
+
+use std::{error::Error, fmt::Display, path::PathBuf, ptr};
+
+use jni::{
+    objects::{JClass, JString},
+    sys::{jbyteArray, jfloatArray, jlong},
+    JNIEnv,
+};
+
+use tvm::{ndarray::empty, Artifacts, Config, DataType, GraphRuntime, TVMContext};
+
+type Result<T> = ::std::result::Result<T, Box<Error>>;
+
+// Throws a `java.lang.RuntimeException` with the error, returning `default` to the JVM.
+fn throw<T, E: Display>(env: &JNIEnv, err: E, default: T) -> T {
+    let _ = env.throw_new("java/lang/RuntimeException", err.to_string());
+    default
+}
+
+fn init(env: &JNIEnv, native_library_dir: JString) -> Result<()> {
+    let dir: String = env.get_string(native_library_dir)?.into();
+    Config::new().with_library_path(dir).init()?;
+    Ok(())
+}
+
+fn create(env: &JNIEnv, graph: JString, lib: JString, params: jbyteArray) -> Result<jlong> {
+    let graph: String = env.get_string(graph)?.into();
+    let lib: String = env.get_string(lib)?.into();
+    let params = env.convert_byte_array(params)?;
+    let artifacts = Artifacts::new(graph, PathBuf::from(lib), params);
+    let runtime = GraphRuntime::new(&artifacts, TVMContext::cpu(0))?;
+    Ok(Box::into_raw(Box::new(runtime)) as jlong)
+}
+
+fn run(env: &JNIEnv, handle: jlong, name: JString, data: jfloatArray) -> Result<jfloatArray> {
+    let runtime = unsafe { &mut *(handle as *mut GraphRuntime) };
+    let name: String = env.get_string(name)?.into();
+    let mut shape = match runtime.inputs().iter().find(|info| info.name == name) {
+        Some(info) => info.shape.clone(),
+        None => return Err(format!("the graph has no input `{}`", name).into()),
+    };
+    let mut values = vec![0f32; env.get_array_length(data)? as usize];
+    if values.len() != shape.iter().product::<usize>() {
+        return Err(format!("expected {:?} values for `{}`", shape, name).into());
+    }
+    env.get_float_array_region(data, 0, &mut values)?;
+    let mut input = empty(&mut shape, TVMContext::cpu(0), DataType::from("float"));
+    input.copy_from_buffer(&values)?;
+    let outputs = runtime.infer_batch(&[(&name, &input)])?;
+    let output = outputs[0].to_vec::<f32>()?;
+    let array = env.new_float_array(output.len() as i32)?;
+    env.set_float_array_region(array, 0, &output)?;
+    Ok(array)
+}
+
+/// Opens `libtvm_runtime.so` from the given directory, which is the
+/// `ApplicationInfo.nativeLibraryDir` of the app.
+#[no_mangle]
+pub extern "system" fn Java_ml_dmlc_tvm_android_GraphExecutor_init(
+    env: JNIEnv,
+    _class: JClass,
+    native_library_dir: JString,
+) {
+    init(&env, native_library_dir).unwrap_or_else(|err| throw(&env, err, ()))
+}
+
+/// Creates a graph runtime and returns its handle. The compiled library must be a file,
+/// e.g. an asset copied to the files directory of the app.
+#[no_mangle]
+pub extern "system" fn Java_ml_dmlc_tvm_android_GraphExecutor_create(
+    env: JNIEnv,
+    _class: JClass,
+    graph: JString,
+    lib: JString,
+    params: jbyteArray,
+) -> jlong {
+    create(&env, graph, lib, params).unwrap_or_else(|err| throw(&env, err, 0))
+}
+
+/// Sets the input `name`, runs the graph and returns its first output.
+#[no_mangle]
+pub extern "system" fn Java_ml_dmlc_tvm_android_GraphExecutor_run(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    name: JString,
+    data: jfloatArray,
+) -> jfloatArray {
+    run(&env, handle, name, data).unwrap_or_else(|err| throw(&env, err, ptr::null_mut()))
+}
+
+/// Frees the graph runtime of the handle.
+#[no_mangle]
+pub extern "system" fn Java_ml_dmlc_tvm_android_GraphExecutor_free(
+    _env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) {
+    if handle != 0 {
+        drop(unsafe { Box::from_raw(handle as *mut GraphRuntime) });
+    }
+}
@@ -0,0 +1,24 @@
+extern crate image;
+extern crate tvm_frontend as tvm;
+
+use std::{env, error::Error, path::Path, result::Result};
+
+use tvm::{zoo::ImageClassifier, TVMContext};
+
+// Classifies the images given as arguments, `cat.png` by default, with the artifacts
+// saved by the build script in the current directory.
+fn main() -> Result<(), Box<Error>> {
+    let classifier = ImageClassifier::from_dir(Path::new("."), TVMContext::cpu(0), 2)?;
+    let mut paths = env::args().skip(1).collect::<Vec<_>>();
+    if paths.is_empty() {
+        paths.push("cat.png".to_owned());
+    }
+    for path in paths {
+        let img = image::open(&path)?;
+        println!("{}:", path);
+        for prediction in classifier.classify(&img, 5)? {
+            println!("  {}", prediction);
+        }
+    }
+    Ok(())
+}
@@ -43,11 +43,8 @@ fn main() -> Result<(), Box<Error>> {
     // make arr shape as [1, 3, 224, 224] acceptable to resnet
     let arr = arr.insert_axis(Axis(0));
     // create input tensor from rust's ndarray
-    let input = NDArray::from_rust_ndarray(&arr, TVMContext::cpu(0), TVMType::from("float"))?;
-    println!(
-        "input size is {:?}",
-        input.shape().expect("cannot get the input shape")
-    );
+    let input = NDArray::from_rust_ndarray(&arr, TVMContext::cpu(0), DataType::from("float"))?;
+    println!("input size is {:?}", input.shape());
     let graph = fs::read_to_string("deploy_graph.json")?;
     // load the built module
     let lib = Module::load(&Path::new("deploy_lib.so"))?;
@@ -83,7 +80,7 @@ fn main() -> Result<(), Box<Error>> {
     call_packed!(run_fn,)?;
     // prepare to get the output
     let output_shape = &mut [1, 1000];
-    let output = empty(output_shape, TVMContext::cpu(0), TVMType::from("float"));
+    let output = empty(output_shape, TVMContext::cpu(0), DataType::from("float"));
     // get the `get_output` function from runtime module
     let get_output_fn = graph_runtime_module
         .get_function("get_output", false)
@@ -0,0 +1,19 @@
+extern crate tvm_frontend as tvm;
+
+use std::{error::Error, result::Result};
+
+use tvm::*;
+
+fn main() -> Result<(), Box<Error>> {
+    let runtime = tvm::init()?;
+    println!("TVM version: {}", runtime.version());
+    println!("enabled targets: {:?}", runtime::enabled_targets());
+    // the graph runtime registers itself on static initialization, so it is only
+    // available if the whole archive is linked
+    runtime.get_function("tvm.graph_runtime.create")?;
+    let mut nd = empty(&mut [3], TVMContext::cpu(0), DataType::from("float"));
+    nd.copy_from_buffer(&[1f32, 2., 3.])?;
+    assert_eq!(nd.to_vec::<f32>()?, vec![1f32, 2., 3.]);
+    println!("the statically linked runtime is usable");
+    Ok(())
+}
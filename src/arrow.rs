@@ -0,0 +1,214 @@
+//! Conversions between [Apache Arrow](https://docs.rs/arrow) tensors and arrays and TVM
+//! [`NDArray`], enabled by the `arrow` feature.
+//!
+//! Row-major Arrow tensors are shared with TVM without copying through DLPack, other
+//! layouts and fixed-size list arrays are copied into a new cpu NDArray.
+//!
+//! # Example
+//!
+//! ```
+//! let buffer = Buffer::from(&[1i32, 2, 3, 4].to_byte_slice());
+//! let tensor = Tensor::<Int32Type>::new(buffer, Some(vec![2, 2]), None, None);
+//! let nd = NDArray::try_from(&tensor).unwrap();
+//! assert_eq!(nd.shape(), &[2, 2]);
+//! let back: Tensor<Int32Type> = Tensor::try_from(&nd).unwrap();
+//! ```
+
+use std::{convert::TryFrom, mem, os::raw::c_void, ptr, slice};
+
+use rust_arrow::{
+    array::{Array, FixedSizeListArray, PrimitiveArray},
+    buffer::Buffer,
+    datatypes::{ArrowPrimitiveType, DataType as ArrowDataType, Float32Type, Int32Type, UInt32Type},
+    tensor::Tensor,
+};
+
+use ts;
+
+use ndarray::{empty, Num32};
+use DataType;
+use Error;
+use ErrorKind;
+use NDArray;
+use Result;
+use TVMContext;
+
+// Keeps the Arrow buffer alive as long as TVM holds the shared tensor.
+struct ArrowManager {
+    buffer: Buffer,
+    shape: Vec<i64>,
+}
+
+unsafe extern "C" fn arrow_deleter(managed: *mut ts::DLManagedTensor) {
+    let managed = Box::from_raw(managed);
+    mem::drop(Box::from_raw(managed.manager_ctx as *mut ArrowManager));
+}
+
+// Copies a strided tensor (strides in number of elements) into a row-major `Vec`.
+fn to_row_major<T: Copy>(data: &[T], shape: &[usize], strides: &[usize]) -> Vec<T> {
+    let size = shape.iter().product();
+    let mut out = Vec::with_capacity(size);
+    let mut index = vec![0; shape.len()];
+    for _ in 0..size {
+        let offset: usize = index.iter().zip(strides).map(|(i, s)| i * s).sum();
+        out.push(data[offset]);
+        for axis in (0..shape.len()).rev() {
+            index[axis] += 1;
+            if index[axis] < shape[axis] {
+                break;
+            }
+            index[axis] = 0;
+        }
+    }
+    out
+}
+
+macro_rules! impl_arrow_conversions {
+    ($arrow_type:ty, $type:ty, $type_name:tt) => {
+        impl<'a, 'b> TryFrom<&'b Tensor<'a, $arrow_type>> for NDArray {
+            type Error = Error;
+            fn try_from(tensor: &Tensor<'a, $arrow_type>) -> Result<NDArray> {
+                let shape = match tensor.shape() {
+                    Some(shape) => shape.clone(),
+                    None => vec![tensor.size()],
+                };
+                if tensor.is_row_major() {
+                    let mut manager = Box::new(ArrowManager {
+                        buffer: tensor.data().clone(),
+                        shape: shape.iter().map(|&d| d as i64).collect(),
+                    });
+                    let dl_tensor = ts::DLTensor {
+                        data: manager.buffer.raw_data() as *mut c_void,
+                        ctx: TVMContext::cpu(0).into(),
+                        ndim: shape.len() as i32,
+                        dtype: DataType::from($type_name).into(),
+                        shape: manager.shape.as_mut_ptr(),
+                        strides: ptr::null_mut(),
+                        byte_offset: 0,
+                    };
+                    let managed = Box::new(ts::DLManagedTensor {
+                        dl_tensor,
+                        manager_ctx: Box::into_raw(manager) as *mut c_void,
+                        deleter: Some(arrow_deleter),
+                    });
+                    return Ok(unsafe { NDArray::from_dlpack(Box::into_raw(managed)) });
+                }
+                let data = unsafe {
+                    slice::from_raw_parts(
+                        tensor.data().raw_data() as *const $type,
+                        tensor.data().len() / mem::size_of::<$type>(),
+                    )
+                };
+                // Arrow strides are in bytes.
+                let strides = match tensor.strides() {
+                    Some(strides) => strides
+                        .iter()
+                        .map(|s| s / mem::size_of::<$type>())
+                        .collect::<Vec<_>>(),
+                    None => bail!("the tensor is neither row-major nor strided"),
+                };
+                let buf = to_row_major(data, &shape, &strides);
+                let mut shape = shape;
+                let mut nd = empty(&mut shape, TVMContext::cpu(0), DataType::from($type_name));
+                nd.copy_from_buffer(&buf)?;
+                Ok(nd)
+            }
+        }
+
+        impl<'a, 'b> TryFrom<&'b NDArray> for Tensor<'a, $arrow_type> {
+            type Error = Error;
+            fn try_from(nd: &NDArray) -> Result<Tensor<'a, $arrow_type>> {
+                let dtype = DataType::from($type_name);
+                if nd.dtype() != dtype {
+                    bail!(ErrorKind::TypeMismatch(dtype.to_string(), nd.dtype().to_string()));
+                }
+                let shape = nd.dims();
+                let data = nd.to_vec::<$type>()?;
+                let bytes = unsafe {
+                    slice::from_raw_parts(
+                        data.as_ptr() as *const u8,
+                        data.len() * mem::size_of::<$type>(),
+                    )
+                };
+                Ok(Tensor::new(Buffer::from(bytes), Some(shape), None, None))
+            }
+        }
+    };
+}
+
+impl_arrow_conversions!(Int32Type, i32, "int");
+impl_arrow_conversions!(UInt32Type, u32, "uint");
+impl_arrow_conversions!(Float32Type, f32, "float");
+
+fn list_values_to_ndarray<T: ArrowPrimitiveType>(
+    array: &FixedSizeListArray,
+    dtype: DataType,
+) -> Result<NDArray>
+where
+    T::Native: Num32 + Copy,
+{
+    let values = array.values();
+    let values = values
+        .as_any()
+        .downcast_ref::<PrimitiveArray<T>>()
+        .expect("data type was checked");
+    let len = array.value_length() as usize;
+    let offset = array.value_offset(0) as usize;
+    let buf = values.value_slice(offset, array.len() * len);
+    let mut shape = vec![array.len(), len];
+    let mut nd = empty(&mut shape, TVMContext::cpu(0), dtype);
+    nd.copy_from_buffer(buf)?;
+    Ok(nd)
+}
+
+/// Copies a fixed-size list array of `n` lists of length `len` into a cpu NDArray
+/// of shape `[n, len]`.
+impl<'a> TryFrom<&'a FixedSizeListArray> for NDArray {
+    type Error = Error;
+    fn try_from(array: &FixedSizeListArray) -> Result<NDArray> {
+        if array.null_count() > 0 {
+            bail!("cannot convert a fixed-size list array with null values");
+        }
+        match array.value_type() {
+            ArrowDataType::Int32 => {
+                list_values_to_ndarray::<Int32Type>(array, DataType::from("int"))
+            }
+            ArrowDataType::UInt32 => {
+                list_values_to_ndarray::<UInt32Type>(array, DataType::from("uint"))
+            }
+            ArrowDataType::Float32 => {
+                list_values_to_ndarray::<Float32Type>(array, DataType::from("float"))
+            }
+            dtype => bail!("unsupported list value type {:?}", dtype),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn row_major() {
+        let data = [1, 2, 3, 4, 5, 6];
+        assert_eq!(to_row_major(&data, &[2, 3], &[3, 1]), data.to_vec());
+        assert_eq!(to_row_major(&data, &[3, 2], &[1, 3]), vec![1, 4, 2, 5, 3, 6]);
+    }
+
+    #[test]
+    fn tensor() {
+        let data = [1f32, 2., 3., 4.];
+        let bytes = unsafe { slice::from_raw_parts(data.as_ptr() as *const u8, 16) };
+        let tensor =
+            Tensor::<Float32Type>::new(Buffer::from(bytes), Some(vec![2, 2]), None, None);
+        let nd = NDArray::try_from(&tensor).unwrap();
+        assert_eq!(nd.shape(), &[2, 2]);
+        assert_eq!(nd.to_vec::<f32>().unwrap(), data.to_vec());
+        let back = Tensor::<Float32Type>::try_from(&nd).unwrap();
+        assert_eq!(back.shape(), Some(&vec![2, 2]));
+        match Tensor::<Int32Type>::try_from(&nd) {
+            Err(Error(ErrorKind::TypeMismatch(..), _)) => (),
+            ret => panic!("expected a type mismatch, found {:?}", ret.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,404 @@
+//! Provides [`BatchScheduler`], which coalesces the requests to a [`GraphRuntimePool`]
+//! into batches to raise the throughput, e.g. of a GPU serving single images to a model
+//! compiled for a batch of 8.
+//!
+//! A dispatcher thread collects the requests until they add up to the maximum batch
+//! size, or the oldest one waited for the maximum wait time, and hands them to a worker
+//! while it collects the next batch. The workers, as many as the pool has runtimes, run
+//! each batch like [`GraphRuntime::infer_batch`] and split the outputs back per request.
+//! Requests with other input names, dtypes or shapes than the batch being collected
+//! start the next one.
+//!
+//! # Example
+//!
+//! ```
+//! let pool = Arc::new(GraphRuntimePool::new(&artifacts, TVMContext::gpu(0), 2).unwrap());
+//! let scheduler = BatchScheduler::new(pool, 8, Duration::from_millis(5)).unwrap();
+//! // on every request thread
+//! let image = empty(&mut [1, 3, 224, 224], TVMContext::cpu(0), DataType::from("float"));
+//! let outputs = scheduler.infer(&[("data", &image)]).unwrap();
+//! assert_eq!(outputs[0].shape(), &[1, 1000]);
+//! ```
+//!
+//! [`BatchScheduler`]:struct.BatchScheduler.html
+//! [`GraphRuntimePool`]:../graph_runtime/struct.GraphRuntimePool.html
+//! [`GraphRuntime::infer_batch`]:../graph_runtime/struct.GraphRuntime.html#method.infer_batch
+
+use std::{
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use graph_runtime::{GraphRuntimePool, HostTensor};
+use Error;
+use ErrorKind;
+use NDArray;
+use Result;
+use TVMContext;
+
+// The outputs of a request, or the error of its batch.
+type Reply = Result<Vec<HostTensor>>;
+
+#[derive(Debug)]
+struct Request {
+    inputs: Vec<(String, HostTensor)>,
+    rows: usize,
+    reply: Sender<Reply>,
+}
+
+impl Request {
+    // Returns whether the requests can run in the same batch.
+    fn fits_with(&self, other: &Request) -> bool {
+        self.inputs.len() == other.inputs.len()
+            && self.inputs.iter().zip(&other.inputs).all(|(a, b)| {
+                a.0 == b.0 && a.1.dtype == b.1.dtype && a.1.shape[1..] == b.1.shape[1..]
+            })
+    }
+}
+
+/// Coalesces concurrent requests to a [`GraphRuntimePool`] into batches, see the
+/// [module-level documentation].
+///
+/// Dropping the scheduler waits for the batches being run.
+///
+/// [`GraphRuntimePool`]:../graph_runtime/struct.GraphRuntimePool.html
+/// [module-level documentation]:index.html
+#[derive(Debug)]
+pub struct BatchScheduler {
+    sender: Mutex<Option<Sender<Request>>>,
+    dispatcher: Option<JoinHandle<()>>,
+    workers: Vec<JoinHandle<()>>,
+    max_batch: usize,
+    max_wait: Duration,
+}
+
+impl BatchScheduler {
+    /// Starts the dispatcher collecting batches of up to `max_batch` rows, usually the
+    /// batch size the model is compiled for, and a worker per runtime of the pool to run
+    /// them. A batch waits at most `max_wait` for requests after its first one.
+    pub fn new(
+        pool: Arc<GraphRuntimePool>,
+        max_batch: usize,
+        max_wait: Duration,
+    ) -> Result<Self> {
+        if max_batch == 0 {
+            bail!("the maximum batch size must be positive");
+        }
+        // a rendezvous channel, so a batch is only handed over once a worker is idle and
+        // the dispatcher keeps collecting meanwhile
+        let (batches, idle) = mpsc::sync_channel(0);
+        let idle = Arc::new(Mutex::new(idle));
+        let mut workers = Vec::with_capacity(pool.size().max(1));
+        for i in 0..pool.size().max(1) {
+            let pool = pool.clone();
+            let idle = idle.clone();
+            let worker = thread::Builder::new()
+                .name(format!("tvm-batch-worker-{}", i))
+                .spawn(move || work(&pool, &idle))?;
+            workers.push(worker);
+        }
+        let (sender, receiver) = mpsc::channel();
+        let dispatcher = thread::Builder::new()
+            .name("tvm-batcher".to_owned())
+            .spawn(move || dispatch(&receiver, &batches, max_batch, max_wait))?;
+        Ok(BatchScheduler {
+            sender: Mutex::new(Some(sender)),
+            dispatcher: Some(dispatcher),
+            workers,
+            max_batch,
+            max_wait,
+        })
+    }
+
+    /// Runs the inputs as part of a batch and returns their outputs in cpu, blocking until
+    /// the batch is run.
+    ///
+    /// The inputs share their leading dimension, usually `1`, which the outputs have too.
+    pub fn infer(&self, inputs: &[(&str, &NDArray)]) -> Result<Vec<NDArray>> {
+        let inputs = inputs
+            .iter()
+            .map(|&(name, input)| Ok((name.to_owned(), HostTensor::from_ndarray(input)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let rows = match inputs.first() {
+            Some((_, input)) if !input.shape.is_empty() => input.shape[0],
+            _ => bail!("no batched inputs to infer"),
+        };
+        let mismatches = inputs
+            .iter()
+            .filter(|(_, input)| input.shape.first() != Some(&rows))
+            .map(|(name, input)| {
+                format!(
+                    "input `{}` has a batch size of {:?}, but expects {}",
+                    name,
+                    input.shape.first(),
+                    rows
+                )
+            })
+            .collect::<Vec<_>>();
+        if !mismatches.is_empty() {
+            bail!(ErrorKind::InputMismatch(mismatches));
+        }
+        let (reply, replies) = mpsc::channel();
+        let request = Request {
+            inputs,
+            rows,
+            reply,
+        };
+        let sent = match *self.sender.lock().unwrap() {
+            Some(ref sender) => sender.send(request).is_ok(),
+            None => false,
+        };
+        if !sent {
+            bail!("the batch dispatcher is stopped");
+        }
+        match replies.recv() {
+            Ok(Ok(outputs)) => outputs
+                .iter()
+                .map(|output| output.to_ndarray(TVMContext::cpu(0)))
+                .collect(),
+            Ok(Err(err)) => Err(err),
+            Err(_) => bail!("the batch dispatcher panicked"),
+        }
+    }
+
+    pub fn max_batch(&self) -> usize {
+        self.max_batch
+    }
+
+    pub fn max_wait(&self) -> Duration {
+        self.max_wait
+    }
+}
+
+impl Drop for BatchScheduler {
+    fn drop(&mut self) {
+        // disconnecting the channel stops the dispatcher, which disconnects the workers
+        self.sender.lock().unwrap().take();
+        if let Some(dispatcher) = self.dispatcher.take() {
+            let _ = dispatcher.join();
+        }
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+// Collects the batches and hands them to the workers until the scheduler is dropped.
+fn dispatch(
+    receiver: &Receiver<Request>,
+    batches: &SyncSender<Vec<Request>>,
+    max_batch: usize,
+    max_wait: Duration,
+) {
+    let mut next = None;
+    loop {
+        let first = match next.take() {
+            Some(first) => first,
+            None => match receiver.recv() {
+                Ok(first) => first,
+                Err(_) => return,
+            },
+        };
+        let deadline = Instant::now() + max_wait;
+        let mut rows = first.rows;
+        let mut batch = vec![first];
+        while rows < max_batch {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            match receiver.recv_timeout(deadline - now) {
+                Ok(request) => {
+                    if !batch[0].fits_with(&request) || rows + request.rows > max_batch {
+                        next = Some(request);
+                        break;
+                    }
+                    rows += request.rows;
+                    batch.push(request);
+                }
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        if let Err(mpsc::SendError(batch)) = batches.send(batch) {
+            for request in batch {
+                let _ = request.reply.send(Err("the batch workers panicked".into()));
+            }
+            return;
+        }
+    }
+}
+
+// Runs the batches handed over by the dispatcher until it stops.
+fn work(pool: &GraphRuntimePool, idle: &Mutex<Receiver<Vec<Request>>>) {
+    loop {
+        // the lock is only held while waiting, so the other workers keep running batches
+        let batch = match idle.lock().unwrap().recv() {
+            Ok(batch) => batch,
+            Err(_) => return,
+        };
+        run_batch(pool, batch);
+    }
+}
+
+fn run_batch(pool: &GraphRuntimePool, batch: Vec<Request>) {
+    let outputs = concat_inputs(&batch).and_then(|host_inputs| {
+        let inputs = host_inputs
+            .iter()
+            .map(|(name, input)| (name.as_str(), input))
+            .collect::<Vec<_>>();
+        pool.run(|runtime| runtime.infer_host_batch(&inputs))
+    });
+    let rows = batch.iter().map(|request| request.rows).collect::<Vec<_>>();
+    match outputs.and_then(|outputs| split_outputs(&outputs, &rows)) {
+        Ok(outputs) => {
+            for (request, outputs) in batch.into_iter().zip(outputs) {
+                let _ = request.reply.send(Ok(outputs));
+            }
+        }
+        Err(err) => {
+            // the last request gets the error itself, the others a copy of its kind
+            let mut requests = batch.into_iter();
+            let last = requests.next_back();
+            for request in requests {
+                let _ = request.reply.send(Err(copy_error(&err)));
+            }
+            if let Some(request) = last {
+                let _ = request.reply.send(Err(err));
+            }
+        }
+    }
+}
+
+// Copies the kind of an error of a batch, which is not `Clone`, keeping the kinds of the
+// pool and of the inputs that callers match on and the message of the others.
+fn copy_error(err: &Error) -> Error {
+    match *err.kind() {
+        ErrorKind::QueueFull(capacity) => ErrorKind::QueueFull(capacity).into(),
+        ErrorKind::PoolShutDown => ErrorKind::PoolShutDown.into(),
+        ErrorKind::DeadlineExceeded => ErrorKind::DeadlineExceeded.into(),
+        ErrorKind::Timeout(timeout) => ErrorKind::Timeout(timeout).into(),
+        ErrorKind::Cancelled => ErrorKind::Cancelled.into(),
+        ErrorKind::InputMismatch(ref mismatches) => {
+            ErrorKind::InputMismatch(mismatches.clone()).into()
+        }
+        ErrorKind::TypeMismatch(ref expected, ref found) => {
+            ErrorKind::TypeMismatch(expected.clone(), found.clone()).into()
+        }
+        _ => ErrorKind::Msg(err.to_string()).into(),
+    }
+}
+
+// Concatenates the inputs of the requests along their leading dimension.
+fn concat_inputs(batch: &[Request]) -> Result<Vec<(String, HostTensor)>> {
+    let rows = batch.iter().map(|request| request.rows).sum();
+    Ok(batch[0]
+        .inputs
+        .iter()
+        .enumerate()
+        .map(|(i, (name, input))| {
+            let mut shape = input.shape.clone();
+            shape[0] = rows;
+            let bytes = batch
+                .iter()
+                .flat_map(|request| request.inputs[i].1.bytes.iter().cloned())
+                .collect();
+            let tensor = HostTensor {
+                shape,
+                dtype: input.dtype,
+                bytes,
+            };
+            (name.clone(), tensor)
+        })
+        .collect())
+}
+
+// Splits the outputs of a batch into the outputs of each request, by number of rows.
+fn split_outputs(outputs: &[HostTensor], rows: &[usize]) -> Result<Vec<Vec<HostTensor>>> {
+    let total = rows.iter().sum::<usize>();
+    if let Some(output) = outputs.iter().find(|output| output.shape.first() != Some(&total)) {
+        bail!(
+            "expected outputs with a batch size of {}, found {:?}",
+            total,
+            output.shape
+        );
+    }
+    let mut split = vec![Vec::with_capacity(outputs.len()); rows.len()];
+    for output in outputs {
+        let row_bytes = output.row_bytes();
+        let mut start = 0;
+        for (request_outputs, &rows) in split.iter_mut().zip(rows) {
+            let mut shape = output.shape.clone();
+            shape[0] = rows;
+            request_outputs.push(HostTensor {
+                shape,
+                dtype: output.dtype,
+                bytes: output.bytes[start * row_bytes..(start + rows) * row_bytes].to_vec(),
+            });
+            start += rows;
+        }
+    }
+    Ok(split)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DataType;
+
+    fn tensor(shape: Vec<usize>, bytes: Vec<u8>) -> HostTensor {
+        HostTensor {
+            shape,
+            dtype: DataType::new(1, 8, 1),
+            bytes,
+        }
+    }
+
+    fn request(rows: usize, bytes: Vec<u8>) -> Request {
+        Request {
+            inputs: vec![("data".to_owned(), tensor(vec![rows, 2], bytes))],
+            rows,
+            reply: mpsc::channel().0,
+        }
+    }
+
+    #[test]
+    fn concat_and_split() {
+        let batch = vec![request(1, vec![1, 2]), request(2, vec![3, 4, 5, 6])];
+        assert!(batch[0].fits_with(&batch[1]));
+        let inputs = concat_inputs(&batch).unwrap();
+        assert_eq!(inputs[0].1, tensor(vec![3, 2], vec![1, 2, 3, 4, 5, 6]));
+        let split = split_outputs(&[inputs[0].1.clone()], &[1, 2]).unwrap();
+        assert_eq!(split[0], vec![tensor(vec![1, 2], vec![1, 2])]);
+        assert_eq!(split[1], vec![tensor(vec![2, 2], vec![3, 4, 5, 6])]);
+        assert!(split_outputs(&[tensor(vec![2, 2], vec![0; 4])], &[1, 2]).is_err());
+    }
+
+    #[test]
+    fn copied_errors() {
+        let err = Error::from(ErrorKind::InputMismatch(vec!["unknown input `x`".to_owned()]));
+        match copy_error(&err) {
+            Error(ErrorKind::InputMismatch(ref mismatches), _) => {
+                assert_eq!(mismatches, &["unknown input `x`"])
+            }
+            other => panic!("expected an input mismatch, but found {:?}", other),
+        }
+        match copy_error(&ErrorKind::QueueFull(4).into()) {
+            Error(ErrorKind::QueueFull(4), _) => (),
+            other => panic!("expected a full queue, but found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fits_with() {
+        let mut other = request(1, vec![1, 2, 3]);
+        other.inputs[0].1.shape = vec![1, 3];
+        assert!(!request(1, vec![1, 2]).fits_with(&other));
+        other.inputs[0].0 = "label".to_owned();
+        other.inputs[0].1.shape = vec![1, 2];
+        assert!(!request(1, vec![1, 2]).fits_with(&other));
+    }
+}
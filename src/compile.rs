@@ -0,0 +1,165 @@
+//! Compiles models into graph runtime [`Artifacts`] through the global functions of the
+//! TVM compiler, so a Relay program becomes an executor without Python.
+//!
+//! This module requires the `compiler` feature and a full `libtvm` loaded instead of
+//! `libtvm_runtime`, see the [`relay`] module.
+//!
+//! TVM implements the ONNX frontend in Python, so [`from_onnx`] requires the
+//! `python-frontend` feature and an application embedding Python, which registers the
+//! global function [`ONNX_IMPORTER`] wrapping `relay.frontend.from_onnx`. It takes the
+//! serialized model as bytes and returns the Relay function with the weights bound as
+//! constants. Without Python, models are compiled from Relay with [`from_relay`].
+//!
+//! The best records of a tuning log set with [`CompileOptions::tuning_log`] are applied
+//! through the global function [`APPLY_HISTORY_BEST`], which AutoTVM implements in Python
+//! too, so it also requires the `python-frontend` feature and is registered by the
+//! application wrapping `autotvm.apply_history_best`.
+//!
+//! # Example
+//!
+//! ```
+//! // with the `python-frontend` feature
+//! let mut opts = CompileOptions::new("build");
+//! opts.toolchain(Toolchain::from("clang++"))
+//!     .tuning_log("resnet18.log");
+//! let artifacts = compile::from_onnx(Path::new("resnet18.onnx"), "llvm", &opts).unwrap();
+//! let mut runtime = GraphRuntime::new(&artifacts, TVMContext::cpu(0)).unwrap();
+//! ```
+//!
+//! [`Artifacts`]:../graph_runtime/struct.Artifacts.html
+//! [`relay`]:../relay/index.html
+//! [`from_onnx`]:fn.from_onnx.html
+//! [`from_relay`]:fn.from_relay.html
+//! [`ONNX_IMPORTER`]:constant.ONNX_IMPORTER.html
+//! [`CompileOptions::tuning_log`]:struct.CompileOptions.html#method.tuning_log
+//! [`APPLY_HISTORY_BEST`]:constant.APPLY_HISTORY_BEST.html
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use contrib::Toolchain;
+#[cfg(feature = "python-frontend")]
+use function;
+use relay::{self, Node};
+#[cfg(feature = "python-frontend")]
+use tuning::TuningLog;
+use Artifacts;
+#[cfg(feature = "python-frontend")]
+use ErrorKind;
+use Result;
+#[cfg(feature = "python-frontend")]
+use TVMByteArray;
+
+/// The global function importing an ONNX model, registered by the application from Python.
+#[cfg(feature = "python-frontend")]
+pub const ONNX_IMPORTER: &'static str = "relay.frontend.from_onnx";
+
+/// The global function registered by the application from Python that applies the
+/// records of the tuning log at the given path to the following builds on the thread.
+#[cfg(feature = "python-frontend")]
+pub const APPLY_HISTORY_BEST: &'static str = "autotvm.apply_history_best";
+
+/// Where and how the compiled library is written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompileOptions {
+    output_dir: PathBuf,
+    toolchain: Toolchain,
+    #[cfg(feature = "python-frontend")]
+    tuning_log: Option<PathBuf>,
+}
+
+impl CompileOptions {
+    /// Writes the artifacts into `output_dir`, which is created if needed.
+    pub fn new<P: AsRef<Path>>(output_dir: P) -> Self {
+        CompileOptions {
+            output_dir: output_dir.as_ref().to_owned(),
+            toolchain: Toolchain::default(),
+            #[cfg(feature = "python-frontend")]
+            tuning_log: None,
+        }
+    }
+
+    /// Sets the toolchain linking the library, e.g. a cross-compiler for an ARM board.
+    pub fn toolchain(&mut self, toolchain: Toolchain) -> &mut Self {
+        self.toolchain = toolchain;
+        self
+    }
+
+    /// Sets an AutoTVM or auto-scheduler log whose best records are applied when building.
+    #[cfg(feature = "python-frontend")]
+    pub fn tuning_log<P: AsRef<Path>>(&mut self, tuning_log: P) -> &mut Self {
+        self.tuning_log = Some(tuning_log.as_ref().to_owned());
+        self
+    }
+
+    pub fn output_dir(&self) -> &Path {
+        &self.output_dir
+    }
+}
+
+/// Imports the ONNX model with the [`ONNX_IMPORTER`] and compiles it like [`from_relay`].
+///
+/// [`ONNX_IMPORTER`]:constant.ONNX_IMPORTER.html
+/// [`from_relay`]:fn.from_relay.html
+#[cfg(feature = "python-frontend")]
+pub fn from_onnx(path: &Path, target: &str, opts: &CompileOptions) -> Result<Artifacts> {
+    let model = fs::read(path)?;
+    let importer = function::get_global_func(ONNX_IMPORTER, true)
+        .ok_or_else(|| ErrorKind::NullHandle(ONNX_IMPORTER.to_owned()))?;
+    let barr = TVMByteArray::from(&model);
+    let func = Node::from_ret(call_packed!(importer, &barr)?, ONNX_IMPORTER)?;
+    from_relay(&func, target, opts)
+}
+
+/// Compiles the Relay function for the `target` and writes `deploy_graph.json` and
+/// `deploy_lib.so` into the output directory.
+///
+/// The weights are constants of the function, so the artifacts have no parameters.
+/// With a tuning log and the `python-frontend` feature, its best records are written into
+/// `tuning_best.log` and applied with the [`APPLY_HISTORY_BEST`] before building.
+///
+/// [`APPLY_HISTORY_BEST`]:constant.APPLY_HISTORY_BEST.html
+pub fn from_relay(func: &Node, target: &str, opts: &CompileOptions) -> Result<Artifacts> {
+    fs::create_dir_all(&opts.output_dir)?;
+    #[cfg(feature = "python-frontend")]
+    {
+        if let Some(ref tuning_log) = opts.tuning_log {
+            let best_log = opts.output_dir.join("tuning_best.log");
+            TuningLog::load(tuning_log)?.write_best(&best_log)?;
+            let apply_fn = function::get_global_func(APPLY_HISTORY_BEST, true)
+                .ok_or_else(|| ErrorKind::NullHandle(APPLY_HISTORY_BEST.to_owned()))?;
+            call_packed!(apply_fn, &best_log.to_string_lossy().into_owned())?;
+        }
+    }
+    let built = relay::build(func, target)?;
+    let lib = opts.output_dir.join("deploy_lib.so");
+    built.lib.export_library_with(&lib, &opts.toolchain)?;
+    fs::write(opts.output_dir.join("deploy_graph.json"), &built.graph)?;
+    Ok(Artifacts::new(built.graph, lib, Vec::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_dir() {
+        let opts = CompileOptions::new("build");
+        assert_eq!(opts.output_dir(), Path::new("build"));
+    }
+
+    #[test]
+    #[cfg(feature = "python-frontend")]
+    fn missing_importer() {
+        let opts = CompileOptions::new("build");
+        if function::get_global_func(ONNX_IMPORTER, true).is_none() {
+            let path = Path::new(file!());
+            match from_onnx(path, "llvm", &opts) {
+                Err(err) => assert!(err.to_string().contains(ONNX_IMPORTER)),
+                Ok(_) => panic!("expected a missing importer"),
+            }
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! Provides [`RunConfig`] describing a deployment in a json or TOML file: the device, the
+//! number of threads, the artifacts and the input shapes, from which the graph runtime is
+//! created.
+//!
+//! # Example
+//!
+//! ```toml
+//! device = "cuda:0"
+//! num_threads = 4
+//!
+//! [artifacts]
+//! graph = "deploy_graph.json"
+//! lib = "deploy_lib.so"
+//! params = "deploy_param.params"
+//!
+//! [[inputs]]
+//! name = "data"
+//! shape = [8, 3, 224, 224]
+//! dtype = "float32"
+//! ```
+//!
+//! ```
+//! let config = RunConfig::load(Path::new("resnet18.toml")).unwrap();
+//! let mut runtime = config.build().unwrap();
+//! runtime.run().unwrap();
+//! ```
+//!
+//! Reading TOML requires the `toml` feature, json is always supported. The paths of a
+//! loaded file are relative to its directory.
+//!
+//! [`RunConfig`]:struct.RunConfig.html
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{de, Deserialize, Deserializer};
+use serde_json;
+
+use graph::{dtype_name, GraphJson};
+use internal_api;
+use metadata::Metadata;
+use ndarray::empty;
+use Artifacts;
+use DataType;
+use ErrorKind;
+use GraphRuntime;
+use Result;
+use TVMContext;
+
+/// The shape and dtype an input is allocated with.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct InputConfig {
+    pub name: String,
+    pub shape: Vec<usize>,
+    #[serde(default = "default_dtype", deserialize_with = "deserialize_dtype")]
+    pub dtype: DataType,
+}
+
+/// A deployment read from a config file, see the [module-level documentation].
+///
+/// It can also be deserialized as part of another config, with the paths relative to the
+/// working directory.
+///
+/// [module-level documentation]:index.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunConfig {
+    pub ctx: TVMContext,
+    pub num_threads: Option<usize>,
+    pub graph: PathBuf,
+    pub lib: PathBuf,
+    pub params: Option<PathBuf>,
+    pub inputs: Vec<InputConfig>,
+}
+
+// The layout of a config file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default = "default_ctx", deserialize_with = "deserialize_ctx")]
+    device: TVMContext,
+    num_threads: Option<usize>,
+    artifacts: ArtifactFiles,
+    #[serde(default)]
+    inputs: Vec<InputConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ArtifactFiles {
+    graph: PathBuf,
+    lib: PathBuf,
+    params: Option<PathBuf>,
+}
+
+fn default_ctx() -> TVMContext {
+    TVMContext::cpu(0)
+}
+
+fn default_dtype() -> DataType {
+    DataType::from("float")
+}
+
+fn deserialize_ctx<'de, D>(deserializer: D) -> ::std::result::Result<TVMContext, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(de::Error::custom)
+}
+
+fn deserialize_dtype<'de, D>(deserializer: D) -> ::std::result::Result<DataType, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(de::Error::custom)
+}
+
+fn invalid_config(msg: String) -> ErrorKind {
+    ErrorKind::InvalidConfig(msg)
+}
+
+impl ConfigFile {
+    // Resolves the paths of the artifacts relative to `base`.
+    fn into_config(self, base: &Path) -> RunConfig {
+        RunConfig {
+            ctx: self.device,
+            num_threads: self.num_threads,
+            graph: base.join(self.artifacts.graph),
+            lib: base.join(self.artifacts.lib),
+            params: self.artifacts.params.map(|params| base.join(params)),
+            inputs: self.inputs,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for RunConfig {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(ConfigFile::deserialize(deserializer)?.into_config(Path::new("")))
+    }
+}
+
+impl RunConfig {
+    /// Parses a json config, with the paths relative to the working directory.
+    pub fn from_json(json: &str) -> Result<RunConfig> {
+        Ok(parse_json(json)?.into_config(Path::new("")))
+    }
+
+    /// Parses a TOML config, with the paths relative to the working directory.
+    #[cfg(feature = "toml")]
+    pub fn from_toml(toml: &str) -> Result<RunConfig> {
+        Ok(parse_toml(toml)?.into_config(Path::new("")))
+    }
+
+    /// Reads a config file, as TOML if its extension is `toml` and as json otherwise.
+    pub fn load(path: &Path) -> Result<RunConfig> {
+        let content = fs::read_to_string(path)?;
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        let file = if path.extension().map_or(false, |ext| ext == "toml") {
+            parse_toml(&content)?
+        } else {
+            parse_json(&content)?
+        };
+        Ok(file.into_config(base))
+    }
+
+    /// Reads the graph json, and the parameters and the metadata sidecar if any, failing
+    /// if the sidecar does not describe the graph.
+    pub fn artifacts(&self) -> Result<Artifacts> {
+        let params = match self.params {
+            Some(ref params) => fs::read(params)?,
+            None => Vec::new(),
+        };
+        let mut artifacts = Artifacts::new(
+            fs::read_to_string(&self.graph)?,
+            self.lib.clone(),
+            params,
+        );
+        if let Some(metadata) = Metadata::load_sidecar(&self.graph)? {
+            metadata.check(&GraphJson::parse(&artifacts.graph)?)?;
+            artifacts.set_metadata(Some(metadata));
+        }
+        Ok(artifacts)
+    }
+
+    /// Creates the graph runtime on the configured device, with its inputs allocated with
+    /// the configured shapes, e.g. to adapt the batch size.
+    ///
+    /// The number of threads reconfigures the thread pool of the runtime, which is shared
+    /// by all the runtimes of the process.
+    pub fn build(&self) -> Result<GraphRuntime> {
+        if let Some(num_threads) = self.num_threads {
+            internal_api::config_threadpool(1, num_threads)?;
+        }
+        let mut runtime = GraphRuntime::new(&self.artifacts()?, self.ctx.clone())?;
+        for input in &self.inputs {
+            let declared = runtime.inputs().iter().find(|info| info.name == input.name);
+            if let Some(info) = declared {
+                if info.dtype != dtype_name(&input.dtype) {
+                    bail!(ErrorKind::InputMismatch(vec![format!(
+                        "input `{}` is {} in the graph but {} in the config",
+                        input.name,
+                        info.dtype,
+                        dtype_name(&input.dtype)
+                    )]));
+                }
+            }
+            let mut shape = input.shape.clone();
+            let array = empty(&mut shape, self.ctx.clone(), input.dtype);
+            runtime.set_input_with_shape(&input.name, &array)?;
+        }
+        Ok(runtime)
+    }
+}
+
+fn parse_json(json: &str) -> Result<ConfigFile> {
+    serde_json::from_str(json).map_err(|err| invalid_config(err.to_string()).into())
+}
+
+#[cfg(feature = "toml")]
+fn parse_toml(toml: &str) -> Result<ConfigFile> {
+    ::toml::from_str(toml).map_err(|err| invalid_config(err.to_string()).into())
+}
+
+#[cfg(not(feature = "toml"))]
+fn parse_toml(_toml: &str) -> Result<ConfigFile> {
+    bail!(invalid_config("reading TOML requires the `toml` feature".to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &'static str = r#"{
+        "device": "cuda:1",
+        "num_threads": 4,
+        "artifacts": {"graph": "graph.json", "lib": "lib.so"},
+        "inputs": [{"name": "data", "shape": [8, 3, 224, 224], "dtype": "float16"}]
+    }"#;
+
+    #[test]
+    fn json() {
+        let config = RunConfig::from_json(CONFIG).unwrap();
+        assert_eq!(config.ctx, TVMContext::gpu(1));
+        assert_eq!(config.num_threads, Some(4));
+        assert_eq!(config.graph, PathBuf::from("graph.json"));
+        assert_eq!(config.params, None);
+        assert_eq!(config.inputs[0].shape, vec![8, 3, 224, 224]);
+        assert_eq!(dtype_name(&config.inputs[0].dtype), "float16");
+        let deserialized: RunConfig = serde_json::from_str(CONFIG).unwrap();
+        assert_eq!(deserialized, config);
+    }
+
+    #[test]
+    fn invalid() {
+        let invalid = CONFIG.replace("cuda:1", "tpu");
+        match RunConfig::from_json(&invalid) {
+            Err(err) => assert!(err.to_string().contains("tpu")),
+            Ok(_) => panic!("expected an invalid device"),
+        }
+        assert!(RunConfig::from_json(r#"{"artifacts": {"graph": "graph.json"}}"#).is_err());
+        let unknown = CONFIG.replace("num_threads", "threads");
+        assert!(RunConfig::from_json(&unknown).is_err());
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn toml() {
+        let config = RunConfig::from_toml(
+            "device = \"cpu\"\n[artifacts]\ngraph = \"graph.json\"\nlib = \"lib.so\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.ctx, TVMContext::cpu(0));
+        assert!(config.inputs.is_empty());
+    }
+}
@@ -10,23 +10,51 @@
 //! assert_eq!(ctx, cpu0);
 //! ```
 //!
-//! Or from a supported device name.
+//! Or by parsing a supported device name with an optional device id, which fails with the
+//! supported names for unsupported devices, e.g. of a user config.
 //!
 //! ```
-//! let cpu0 = TVMContext::from("cpu");
-//! println!("{}", cpu0);
+//! let gpu1: TVMContext = "cuda:1".parse().unwrap();
+//! println!("{}", gpu1);
 //! ```
+//!
+//! Custom accelerators integrated through the device API of the runtime are extension
+//! devices, whose names can be registered with [`register_device_type`].
+//!
+//! ```
+//! context::register_device_type("npu", TVMDeviceType::ext(32).unwrap()).unwrap();
+//! let npu0: TVMContext = "npu:0".parse().unwrap();
+//! ```
+//!
+//! [`register_device_type`]:fn.register_device_type.html
 
+#[cfg(feature = "cuda")]
+use std::{collections::HashSet, sync::Mutex};
 use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
     fmt::{self, Display, Formatter},
-    os::raw::c_void,
+    marker::PhantomData,
+    os::raw::{c_int, c_void},
+    panic::{self, AssertUnwindSafe},
     ptr,
+    str::FromStr,
+    sync::RwLock,
+    thread,
 };
 
+use serde_json;
+
+use ffi;
 use function;
 use internal_api;
 use ts;
+use value;
+use ArgValue;
+use Error;
+use ErrorKind;
 use Result;
+use TVMRetValue;
 
 /// Device type can be from a supported device name. See the supported devices
 /// in [TVM](https://github.com/dmlc/tvm).
@@ -34,13 +62,116 @@ use Result;
 /// ## Example
 ///
 /// ```
-/// let cpu = TVMDeviceType::from("cpu");
+/// let cpu: TVMDeviceType = "cpu".parse().unwrap();
 /// println!("device is: {}", cpu);
 ///```
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct TVMDeviceType(pub usize);
 
+/// The device type of CUDA gpus, `kDLGPU`.
+pub const GPU: TVMDeviceType = TVMDeviceType(2);
+
+/// The device type of TVM's extension devices, `kDLExtDev`.
+pub const EXT_DEV: TVMDeviceType = TVMDeviceType(12);
+
+// Device types from this mask on are of remote devices, see `RPC_SESS_MASK` in TVM.
+pub(crate) const RPC_SESS_MASK: usize = 128;
+
+// The device types of the DLPack `DLDeviceType` enum, by code. The first name is printed.
+const DEVICE_TYPE_NAMES: [(usize, &'static [&'static str]); 16] = [
+    (1, &["cpu", "llvm", "stackvm"]),
+    (2, &["cuda", "gpu", "nvptx"]),
+    (3, &["cuda_host", "cpu_pinned"]),
+    (4, &["opencl", "cl"]),
+    (7, &["vulkan"]),
+    (8, &["metal"]),
+    (9, &["vpi"]),
+    (10, &["rocm"]),
+    (11, &["rocm_host"]),
+    (12, &["ext_dev"]),
+    (13, &["cuda_managed"]),
+    (14, &["oneapi"]),
+    (15, &["webgpu"]),
+    (16, &["hexagon"]),
+    (17, &["maia"]),
+    (RPC_SESS_MASK, &["rpc"]),
+];
+
+lazy_static! {
+    // Device types registered with `register_device_type`, by name.
+    static ref DEVICE_TYPES: RwLock<HashMap<String, usize>> = RwLock::new(HashMap::new());
+}
+
+#[cfg(feature = "cuda")]
+lazy_static! {
+    // Pairs of gpus whose peer access is enabled, as `(device, peer)` ids.
+    static ref PEER_ACCESS: Mutex<HashSet<(usize, usize)>> = Mutex::new(HashSet::new());
+}
+
+// Returns the names of a DLPack device type.
+fn builtin_names(device_type: usize) -> Option<&'static [&'static str]> {
+    DEVICE_TYPE_NAMES
+        .iter()
+        .find(|&&(code, _)| code == device_type)
+        .map(|&(_, names)| names)
+}
+
+impl TVMDeviceType {
+    /// Creates the device type of an extension device from its code, e.g. of a custom
+    /// accelerator whose device API is registered in the runtime. Extension devices are
+    /// `kDLExtDev` and the codes not used by DLPack below the remote device types.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let npu = TVMDeviceType::ext(32).unwrap();
+    /// let input = empty(&mut [1, 3, 224, 224], TVMContext::new(npu, 0), dtype);
+    /// ```
+    pub fn ext(code: usize) -> Result<Self> {
+        let device_type = TVMDeviceType(code);
+        if !device_type.is_ext() {
+            bail!("device type {} is not an extension device type", code);
+        }
+        Ok(device_type)
+    }
+
+    /// Returns whether the device type is an extension device type.
+    pub fn is_ext(&self) -> bool {
+        *self == EXT_DEV
+            || (self.0 > EXT_DEV.0 && self.0 < RPC_SESS_MASK && builtin_names(self.0).is_none())
+    }
+}
+
+/// Registers the name of an extension device type, so that it can be parsed and printed
+/// like the builtin device types, e.g. in `"npu:0"`.
+///
+/// Fails if the name is already used by another device type.
+pub fn register_device_type(name: &str, device_type: TVMDeviceType) -> Result<()> {
+    if !device_type.is_ext() {
+        bail!("only extension device types can be registered, found {}", device_type.0);
+    }
+    let mut device_types = DEVICE_TYPES.write().unwrap();
+    match device_type_of_builtin(name).or_else(|| device_types.get(name).cloned()) {
+        Some(code) if code != device_type.0 => {
+            bail!("device name `{}` is already used by device type {}", name, code)
+        }
+        _ => (),
+    }
+    device_types.insert(name.to_owned(), device_type.0);
+    Ok(())
+}
+
+// Returns the registered name of an extension device type.
+fn registered_name(device_type: usize) -> Option<String> {
+    DEVICE_TYPES
+        .read()
+        .unwrap()
+        .iter()
+        .find(|&(_, &code)| code == device_type)
+        .map(|(name, _)| name.clone())
+}
+
 impl Default for TVMDeviceType {
     /// default device is cpu.
     fn default() -> Self {
@@ -48,74 +179,101 @@ impl Default for TVMDeviceType {
     }
 }
 
+// The codes of `TVMDeviceType` are the ones of DLPack, including the codes of newer DLPack
+// versions than the bindings and of extension devices.
 impl From<TVMDeviceType> for ts::DLDeviceType {
     fn from(device_type: TVMDeviceType) -> Self {
-        match device_type.0 {
-            1 => ts::DLDeviceType_kDLCPU,
-            2 => ts::DLDeviceType_kDLGPU,
-            3 => ts::DLDeviceType_kDLCPUPinned,
-            4 => ts::DLDeviceType_kDLOpenCL,
-            7 => ts::DLDeviceType_kDLVulkan,
-            8 => ts::DLDeviceType_kDLMetal,
-            9 => ts::DLDeviceType_kDLVPI,
-            10 => ts::DLDeviceType_kDLROCM,
-            12 => ts::DLDeviceType_kDLExtDev,
-            _ => panic!("device type not found!"),
-        }
+        device_type.0 as ts::DLDeviceType
     }
 }
 
 impl From<ts::DLDeviceType> for TVMDeviceType {
     fn from(device_type: ts::DLDeviceType) -> Self {
-        match device_type {
-            ts::DLDeviceType_kDLCPU => TVMDeviceType(1),
-            ts::DLDeviceType_kDLGPU => TVMDeviceType(2),
-            ts::DLDeviceType_kDLCPUPinned => TVMDeviceType(3),
-            ts::DLDeviceType_kDLOpenCL => TVMDeviceType(4),
-            ts::DLDeviceType_kDLVulkan => TVMDeviceType(7),
-            ts::DLDeviceType_kDLMetal => TVMDeviceType(8),
-            ts::DLDeviceType_kDLVPI => TVMDeviceType(9),
-            ts::DLDeviceType_kDLROCM => TVMDeviceType(10),
-            ts::DLDeviceType_kDLExtDev => TVMDeviceType(12),
-            _ => panic!("device type not found!"),
-        }
+        TVMDeviceType(device_type as usize)
     }
 }
 
+/// Prints the canonical name of the device type, e.g. `cuda`, or `unknown(21)` for
+/// extension device types without a registered name, which are parsed back.
 impl Display for TVMDeviceType {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                TVMDeviceType(1) => "cpu",
-                TVMDeviceType(2) => "gpu",
-                TVMDeviceType(3) => "cpu_pinned",
-                TVMDeviceType(4) => "opencl",
-                TVMDeviceType(8) => "meta",
-                TVMDeviceType(9) => "vpi",
-                TVMDeviceType(10) => "rocm",
-                TVMDeviceType(_) => "rpc",
-            }
-        )
+        if self.0 >= RPC_SESS_MASK {
+            return write!(f, "rpc");
+        }
+        if let Some(names) = builtin_names(self.0) {
+            return write!(f, "{}", names[0]);
+        }
+        match registered_name(self.0) {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "unknown({})", self.0),
+        }
+    }
+}
+
+// Returns the device type of a supported or registered device name, or of the printed
+// name of an unregistered extension device type.
+fn device_type_of(name: &str) -> Option<usize> {
+    device_type_of_builtin(name)
+        .or_else(|| DEVICE_TYPES.read().unwrap().get(name).cloned())
+        .or_else(|| match split_parenthesized(name)? {
+            ("unknown", code) => Some(TVMDeviceType::ext(code.parse().ok()?).ok()?.0),
+            _ => None,
+        })
+}
+
+// Splits `cuda(1)` into `cuda` and `1` at the last parenthesis.
+fn split_parenthesized(spec: &str) -> Option<(&str, &str)> {
+    if !spec.ends_with(')') {
+        return None;
     }
+    let open = spec.rfind('(')?;
+    Some((&spec[..open], &spec[open + 1..spec.len() - 1]))
 }
 
+fn device_type_of_builtin(name: &str) -> Option<usize> {
+    DEVICE_TYPE_NAMES
+        .iter()
+        .filter(|&&(code, _)| code != RPC_SESS_MASK)
+        .find(|&&(_, names)| names.contains(&name))
+        .map(|&(code, _)| code)
+}
+
+// Returns the supported and registered device names, for error messages.
+fn device_names() -> Vec<String> {
+    let mut names = DEVICE_TYPE_NAMES
+        .iter()
+        .filter(|&&(code, _)| code != RPC_SESS_MASK)
+        .flat_map(|&(_, names)| names.iter().map(|name| name.to_string()))
+        .collect::<Vec<_>>();
+    let mut registered = DEVICE_TYPES.read().unwrap().keys().cloned().collect::<Vec<_>>();
+    registered.sort();
+    names.extend(registered);
+    names
+}
+
+/// Deprecated, panics on unsupported device names. Use `str::parse`, which fails instead.
 impl<'a> From<&'a str> for TVMDeviceType {
     fn from(type_str: &'a str) -> Self {
-        match type_str {
-            "cpu" => TVMDeviceType(1),
-            "llvm" => TVMDeviceType(1),
-            "stackvm" => TVMDeviceType(1),
-            "gpu" => TVMDeviceType(2),
-            "cuda" => TVMDeviceType(2),
-            "nvptx" => TVMDeviceType(2),
-            "cl" => TVMDeviceType(4),
-            "opencl" => TVMDeviceType(4),
-            "metal" => TVMDeviceType(8),
-            "vpi" => TVMDeviceType(9),
-            "rocm" => TVMDeviceType(10),
-            _ => panic!("{:?} not supported!", type_str),
+        match type_str.parse() {
+            Ok(device_type) => device_type,
+            Err(err) => panic!("{}", err),
+        }
+    }
+}
+
+/// Parses a supported or registered device name, failing with the supported names
+/// otherwise.
+impl FromStr for TVMDeviceType {
+    type Err = Error;
+
+    fn from_str(type_str: &str) -> Result<Self> {
+        match device_type_of(type_str) {
+            Some(device_type) => Ok(TVMDeviceType(device_type)),
+            None => bail!(
+                "device {:?} not supported, expected one of {}",
+                type_str,
+                device_names().join(", ")
+            ),
         }
     }
 }
@@ -125,16 +283,17 @@ impl<'a> From<&'a str> for TVMDeviceType {
 /// ## Examples
 ///
 /// ```
-/// let ctx = TVMContext::from("gpu");
-/// assert!(ctx.exist());
+/// let ctx: TVMContext = "gpu".parse().unwrap();
+/// assert!(ctx.exist().unwrap());
 ///
 /// ```
 ///
 /// It is possible to query the underlying context as follows
 ///
 /// ```
-/// println!("maximun threads per block: {}", ctx.max_threads_per_block());
-/// println!("compute version: {}", ctx.compute_version());
+/// println!("maximun threads per block: {}", ctx.max_threads_per_block().unwrap());
+/// println!("compute version: {}", ctx.compute_version().unwrap());
+/// println!("thread dimensions: {:?}", ctx.max_thread_dimensions().unwrap());
 /// ```
 
 #[derive(Debug, Default, Clone, Hash, PartialEq, Eq)]
@@ -153,6 +312,27 @@ impl TVMContext {
             device_id: device_id,
         }
     }
+
+    /// Creates a context like [`new`], failing if the device does not exist, e.g. for an
+    /// id beyond the number of GPUs, instead of at the first use of the context.
+    ///
+    /// Fails with `ErrorKind::Unsupported` if the runtime is built without the backend of
+    /// the device.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let ctx = TVMContext::checked(TVMDeviceType(2), gpu_id)?;
+    /// ```
+    ///
+    /// [`new`]:struct.TVMContext.html#method.new
+    pub fn checked(device_type: TVMDeviceType, device_id: usize) -> Result<Self> {
+        let ctx = TVMContext::new(device_type, device_id);
+        if !ctx.exist()? {
+            bail!("device {} does not exist", ctx);
+        }
+        Ok(ctx)
+    }
 }
 
 macro_rules! impl_ctxs {
@@ -172,32 +352,185 @@ impl_ctxs!((cpu, 1);
             (nvptx, 2);
             (cuda, 2);
             (cpu_pinned, 3);
+            (cuda_host, 3);
             (cl, 4);
             (opencl, 4);
+            (vulkan, 7);
             (metal, 8);
             (vpi, 9);
-            (rocm, 10));
+            (rocm, 10);
+            (rocm_host, 11);
+            (ext_dev, 12);
+            (cuda_managed, 13);
+            (oneapi, 14);
+            (webgpu, 15);
+            (hexagon, 16);
+            (maia, 17));
 
+/// Deprecated, panics on unsupported device names. Use `str::parse`, which fails instead
+/// and accepts a device id.
 impl<'a> From<&'a str> for TVMContext {
     fn from(target: &str) -> Self {
         TVMContext::new(TVMDeviceType::from(target), 0)
     }
 }
 
+/// Parses a device name with an optional device id, e.g. `cpu` or `cuda:1`, or a context
+/// in the printed form, e.g. `cuda(1)`.
+///
+/// ## Example
+///
+/// ```
+/// let ctx: TVMContext = "cuda:1".parse().unwrap();
+/// assert_eq!(ctx, TVMContext::gpu(1));
+/// assert_eq!(ctx.to_string().parse::<TVMContext>().unwrap(), ctx);
+/// ```
+impl FromStr for TVMContext {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        let (device_type, device_id) = match split_parenthesized(spec) {
+            // not the printed form if the parenthesis belong to the device type, e.g. in
+            // `unknown(21)`
+            Some((name, id)) if device_type_of(name).is_some() => (name, Some(id)),
+            _ => {
+                let mut parts = spec.splitn(2, ':');
+                (parts.next().unwrap_or(""), parts.next())
+            }
+        };
+        let device_id = match device_id {
+            Some(id) => id
+                .trim()
+                .parse()
+                .map_err(|_| Error::from(format!("invalid device id in {:?}", spec)))?,
+            None => 0,
+        };
+        Ok(TVMContext::new(device_type.trim().parse()?, device_id))
+    }
+}
+
 impl TVMContext {
-    /// Checks whether the context exists or not.
-    pub fn exist(&self) -> bool {
-        let func = internal_api::get_api("_GetDeviceAttr".to_owned());
+    /// Checks whether the context exists or not, failing with `ErrorKind::Unsupported` if
+    /// the runtime is built without the backend of the device, e.g. to fall back to
+    /// another device.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let ctx = match TVMContext::gpu(0).exist() {
+    ///     Ok(true) => TVMContext::gpu(0),
+    ///     _ => TVMContext::cpu(0),
+    /// };
+    /// ```
+    pub fn exist(&self) -> Result<bool> {
+        Ok(self.attr(0, "exist")?.to_bool())
+    }
+
+    // Queries the attribute `kind` of the device, failing with `ErrorKind::Unsupported` if
+    // the backend of the device is missing or does not report the attribute.
+    fn attr(&self, kind: usize, name: &str) -> Result<TVMRetValue> {
+        let dt = self.device_type.0 as usize;
+        let ret = self.call_device_api(|| internal_api::get_device_attr(dt, self.device_id, kind))?;
+        if let ArgValue::Null = ret.as_value() {
+            bail!(ErrorKind::Unsupported(format!("attribute `{}` of {}", name, self)));
+        }
+        Ok(ret)
+    }
+
+    // Calls into the device API of the runtime, failing with `ErrorKind::Unsupported` if
+    // the backend of the device is missing.
+    fn call_device_api<T, F: FnOnce() -> Result<T>>(&self, f: F) -> Result<T> {
+        match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(ret) => ret,
+            Err(cause) => bail!(ErrorKind::Unsupported(format!(
+                "device {} ({})",
+                self,
+                function::panic_message(&*cause)
+            ))),
+        }
+    }
+
+    /// Makes the context the current device of the calling thread, on which the runtime
+    /// allocates and launches kernels without an explicit context, e.g. in packed
+    /// functions written against the CUDA API. The current device is thread-local.
+    ///
+    /// The returned guard restores the previous current device of its device type when
+    /// dropped, which is the one of an enclosing guard or, for the outermost guard, the
+    /// one current before it: queried from the CUDA API with the `cuda` feature and the
+    /// device 0, the default of the runtime, otherwise. Guards must be dropped in the
+    /// reverse order of their creation, which scopes ensure. Dropping one out of order
+    /// panics unless the thread is panicking already, and keeps the device of the innermost
+    /// live guard current. They cannot be sent to other threads.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// {
+    ///     let _current = TVMContext::gpu(1).make_current().unwrap();
+    ///     assert_eq!(TVMContext::current(), Some(TVMContext::gpu(1)));
+    ///     call_packed!(custom_kernel, &input).unwrap();
+    /// }
+    /// assert_eq!(TVMContext::current(), None);
+    /// ```
+    pub fn make_current(&self) -> Result<CurrentContext> {
+        let enclosing = CURRENT.with(|current| {
+            current
+                .borrow()
+                .iter()
+                .rev()
+                .find(|&&(_, ref ctx)| ctx.device_type == self.device_type)
+                .map(|&(_, ref ctx)| ctx.clone())
+        });
+        let previous = enclosing.unwrap_or_else(|| self.device_before_guards());
+        self.set_device()?;
+        let id = NEXT_GUARD.with(|next| {
+            let id = next.get();
+            next.set(id + 1);
+            id
+        });
+        CURRENT.with(|current| current.borrow_mut().push((id, self.clone())));
+        Ok(CurrentContext {
+            ctx: self.clone(),
+            previous,
+            id,
+            _not_send: PhantomData,
+        })
+    }
+
+    // Returns the current device of the device type of the context on the calling thread,
+    // outside of any guard.
+    #[cfg(feature = "cuda")]
+    fn device_before_guards(&self) -> TVMContext {
+        let mut device = 0;
+        if self.device_type == TVMDeviceType(2) && unsafe { cuda::cudaGetDevice(&mut device) } == 0
+        {
+            return TVMContext::gpu(device as usize);
+        }
+        TVMContext::new(self.device_type, 0)
+    }
+
+    #[cfg(not(feature = "cuda"))]
+    fn device_before_guards(&self) -> TVMContext {
+        TVMContext::new(self.device_type, 0)
+    }
+
+    /// Returns the context made current on the calling thread by the innermost live guard
+    /// of [`make_current`], if any.
+    ///
+    /// [`make_current`]:struct.TVMContext.html#method.make_current
+    pub fn current() -> Option<TVMContext> {
+        CURRENT.with(|current| current.borrow().last().map(|&(_, ref ctx)| ctx.clone()))
+    }
+
+    fn set_device(&self) -> Result<()> {
         let dt = self.device_type.0 as usize;
-        // `unwrap` is ok here because if there is any error,
-        // if would occure inside `call_packed!`
-        let ret = call_packed!(func, &dt, &self.device_id, &0).unwrap();
-        ret.to_int() != 0
+        self.call_device_api(|| internal_api::set_device(dt, self.device_id))
     }
 
     /// Synchronize the context stream.
     pub fn sync(&self) -> Result<()> {
-        check_call!(ts::TVMSynchronize(
+        check_call!(ffi::api().synchronize(
             self.device_type.0 as i32,
             self.device_id as i32,
             ptr::null_mut() as *mut c_void
@@ -206,19 +539,127 @@ impl TVMContext {
     }
 }
 
+#[cfg(feature = "cuda")]
+pub(crate) mod cuda {
+    use std::os::raw::{c_int, c_uint, c_void};
+
+    // `cudaErrorPeerAccessAlreadyEnabled`
+    pub const PEER_ACCESS_ALREADY_ENABLED: c_int = 704;
+    // `cudaMemAttachGlobal`
+    pub const MEM_ATTACH_GLOBAL: c_uint = 1;
+    // `cudaCpuDeviceId`
+    pub const CPU_DEVICE_ID: c_int = -1;
+    // `cudaMemoryTypeManaged`
+    pub const MEMORY_TYPE_MANAGED: c_int = 3;
+
+    // `cudaPointerAttributes` of CUDA 10 and later.
+    #[repr(C)]
+    pub struct PointerAttributes {
+        pub memory_type: c_int,
+        pub device: c_int,
+        pub device_pointer: *mut c_void,
+        pub host_pointer: *mut c_void,
+    }
+
+    #[link(name = "cudart")]
+    extern "C" {
+        pub fn cudaGetDevice(device: *mut c_int) -> c_int;
+        pub fn cudaSetDevice(device: c_int) -> c_int;
+        pub fn cudaDeviceCanAccessPeer(can_access: *mut c_int, device: c_int, peer: c_int) -> c_int;
+        pub fn cudaDeviceEnablePeerAccess(peer: c_int, flags: c_uint) -> c_int;
+        pub fn cudaMallocManaged(ptr: *mut *mut c_void, size: usize, flags: c_uint) -> c_int;
+        pub fn cudaFree(ptr: *mut c_void) -> c_int;
+        pub fn cudaMemPrefetchAsync(
+            ptr: *const c_void,
+            count: usize,
+            device: c_int,
+            stream: *mut c_void,
+        ) -> c_int;
+        pub fn cudaPointerGetAttributes(attrs: *mut PointerAttributes, ptr: *const c_void)
+            -> c_int;
+        pub fn cudaMemcpyPeer(
+            dst: *mut c_void,
+            dst_device: c_int,
+            src: *const c_void,
+            src_device: c_int,
+            count: usize,
+        ) -> c_int;
+    }
+}
+
+/// Enables the direct access between the memories of two gpu contexts in both directions,
+/// so that [`NDArray`] copies between them do not bounce through the host memory.
+///
+/// Requires the `cuda` feature, which links `libcudart`. Enabling an already enabled
+/// access is not an error.
+///
+/// ## Example
+///
+/// ```
+/// context::enable_peer_access(&TVMContext::gpu(0), &TVMContext::gpu(1)).unwrap();
+/// let on_gpu1 = nd_on_gpu0.copy_to_ctx(&TVMContext::gpu(1)).unwrap();
+/// ```
+///
+/// [`NDArray`]:../ndarray/struct.NDArray.html
+pub fn enable_peer_access(a: &TVMContext, b: &TVMContext) -> Result<()> {
+    if a.device_type != TVMDeviceType(2) || b.device_type != TVMDeviceType(2) {
+        bail!(
+            "peer access is only supported between gpu contexts, found {} and {}",
+            a,
+            b
+        );
+    }
+    if a.device_id == b.device_id {
+        return Ok(());
+    }
+    enable_cuda_peer_access(a.device_id as c_int, b.device_id as c_int)?;
+    enable_cuda_peer_access(b.device_id as c_int, a.device_id as c_int)
+}
+
+// Returns whether `enable_peer_access` succeeded for the two gpu contexts.
+#[cfg(feature = "cuda")]
+pub(crate) fn peer_access_enabled(a: &TVMContext, b: &TVMContext) -> bool {
+    PEER_ACCESS.lock().unwrap().contains(&(a.device_id, b.device_id))
+}
+
+#[cfg(feature = "cuda")]
+fn enable_cuda_peer_access(device: c_int, peer: c_int) -> Result<()> {
+    let mut can_access = 0 as c_int;
+    if unsafe { cuda::cudaDeviceCanAccessPeer(&mut can_access, device, peer) } != 0
+        || can_access == 0
+    {
+        bail!("gpu({}) cannot access the memory of gpu({})", device, peer);
+    }
+    let ret = unsafe {
+        let mut current = 0 as c_int;
+        cuda::cudaGetDevice(&mut current);
+        cuda::cudaSetDevice(device);
+        let ret = cuda::cudaDeviceEnablePeerAccess(peer, 0);
+        cuda::cudaSetDevice(current);
+        ret
+    };
+    if ret != 0 && ret != cuda::PEER_ACCESS_ALREADY_ENABLED {
+        bail!(
+            "failed to enable the access of gpu({}) to gpu({}), cuda error {}",
+            device,
+            peer,
+            ret
+        );
+    }
+    PEER_ACCESS.lock().unwrap().insert((device as usize, peer as usize));
+    Ok(())
+}
+
+#[cfg(not(feature = "cuda"))]
+fn enable_cuda_peer_access(_device: c_int, _peer: c_int) -> Result<()> {
+    bail!("enabling peer access requires the `cuda` feature")
+}
+
 macro_rules! impl_dev_attrs {
     ($attr_name:ident, $attr_kind:expr) => {
         impl TVMContext {
-            pub fn $attr_name(&self) -> usize {
-                let func = ::internal_api::get_api("_GetDeviceAttr".to_owned());
-                let dt = self.device_type.0 as usize;
-                // `unwrap` is ok here because if there is any error,
-                // if would occur in function call.
-                let ret = function::Builder::from(func)
-                    .args(&[dt, self.device_id, $attr_kind])
-                    .invoke()
-                    .unwrap();
-                ret.to_int() as usize
+            pub fn $attr_name(&self) -> Result<usize> {
+                self.attr($attr_kind, stringify!($attr_name))?.to_usize()
             }
         }
     };
@@ -227,11 +668,86 @@ macro_rules! impl_dev_attrs {
 impl_dev_attrs!(max_threads_per_block, 1);
 impl_dev_attrs!(warp_size, 2);
 impl_dev_attrs!(max_shared_memory_per_block, 3);
-impl_dev_attrs!(compute_version, 4);
-impl_dev_attrs!(device_name, 5);
 impl_dev_attrs!(max_clock_rate, 6);
 impl_dev_attrs!(multi_processor_count, 7);
-impl_dev_attrs!(max_thread_dimensions, 8);
+
+impl TVMContext {
+    // Queries an attribute encoded as a string.
+    fn str_attr(&self, kind: usize, name: &str) -> Result<String> {
+        value::into_string(self.attr(kind, name)?)
+    }
+
+    /// Returns the compute version of the device, e.g. `"7.5"` for CUDA devices.
+    pub fn compute_version(&self) -> Result<String> {
+        self.str_attr(4, "compute_version")
+    }
+
+    /// Returns the name of the device, e.g. `"GeForce RTX 2080"`.
+    pub fn device_name(&self) -> Result<String> {
+        self.str_attr(5, "device_name")
+    }
+
+    /// Returns the maximum number of threads along each dimension of a block.
+    pub fn max_thread_dimensions(&self) -> Result<Vec<usize>> {
+        parse_thread_dimensions(&self.str_attr(8, "max_thread_dimensions")?)
+    }
+}
+
+// Parses the dimensions the runtime encodes as a json list, e.g. `[1024, 1024, 64]`.
+fn parse_thread_dimensions(dims: &str) -> Result<Vec<usize>> {
+    serde_json::from_str(dims)
+        .map_err(|_| format!("invalid thread dimensions `{}`", dims).into())
+}
+
+thread_local! {
+    // The contexts made current on this thread by the live `CurrentContext` guards, with
+    // the ids of the guards.
+    static CURRENT: RefCell<Vec<(u64, TVMContext)>> = RefCell::new(Vec::new());
+    static NEXT_GUARD: Cell<u64> = Cell::new(0);
+}
+
+/// A guard of the current device of a thread, see [`TVMContext::make_current`].
+///
+/// [`TVMContext::make_current`]:struct.TVMContext.html#method.make_current
+#[derive(Debug)]
+pub struct CurrentContext {
+    ctx: TVMContext,
+    // the device of the same type current before the guard, restored on drop.
+    previous: TVMContext,
+    // identifies the entry of the guard in `CURRENT`.
+    id: u64,
+    // the current device is thread-local
+    _not_send: PhantomData<*const ()>,
+}
+
+impl CurrentContext {
+    /// Returns the context made current.
+    pub fn ctx(&self) -> &TVMContext {
+        &self.ctx
+    }
+}
+
+impl Drop for CurrentContext {
+    fn drop(&mut self) {
+        // the entry is removed first, so that the current context stays right after misuse
+        let innermost = CURRENT.with(|current| {
+            let mut current = current.borrow_mut();
+            match current.iter().position(|&(id, _)| id == self.id) {
+                Some(index) => {
+                    current.remove(index);
+                    index == current.len()
+                }
+                None => false,
+            }
+        });
+        if innermost {
+            // the previous device was set successfully, and errors cannot be reported in drop
+            let _ = self.previous.set_device();
+        } else if !thread::panicking() {
+            panic!("the guard of {} is dropped before the guards created after it", self.ctx);
+        }
+    }
+}
 
 impl From<ts::DLContext> for TVMContext {
     fn from(ctx: ts::DLContext) -> Self {
@@ -251,6 +767,7 @@ impl From<TVMContext> for ts::DLContext {
     }
 }
 
+/// Prints the context as `cuda(1)`, which is parsed back except for remote contexts.
 impl Display for TVMContext {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "{}({})", self.device_type, self.device_id)
@@ -269,9 +786,118 @@ mod tests {
         assert_eq!(ctx.clone(), default_ctx);
         assert_ne!(ctx, TVMContext::gpu(0));
 
-        let str_ctx = TVMContext::new(TVMDeviceType::from("gpu"), 0);
+        let str_ctx: TVMContext = "gpu".parse().unwrap();
         assert_eq!(str_ctx.clone(), str_ctx);
-        assert_ne!(str_ctx, TVMContext::new(TVMDeviceType::from("cpu"), 0));
+        assert_ne!(str_ctx, "cpu".parse().unwrap());
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("cpu".parse::<TVMContext>().unwrap(), TVMContext::cpu(0));
+        assert_eq!("cuda:1".parse::<TVMContext>().unwrap(), TVMContext::gpu(1));
+        let err = "tpu".parse::<TVMContext>().unwrap_err().to_string();
+        assert!(err.contains("tpu") && err.contains("cuda"));
+        assert!("cuda:x".parse::<TVMContext>().is_err());
+        assert!("cuda(x)".parse::<TVMContext>().is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let ctxs = [
+            TVMContext::cpu(0),
+            TVMContext::gpu(1),
+            TVMContext::new(TVMDeviceType(100), 2),
+        ];
+        for ctx in ctxs.iter() {
+            assert_eq!(&ctx.to_string().parse::<TVMContext>().unwrap(), ctx);
+        }
+        assert_eq!(TVMContext::gpu(1).to_string(), "cuda(1)");
+        assert_eq!(
+            "unknown(100)".parse::<TVMContext>().unwrap(),
+            TVMContext::new(TVMDeviceType(100), 0)
+        );
+    }
+
+    #[test]
+    fn device_types() {
+        for &(code, names) in DEVICE_TYPE_NAMES.iter() {
+            let device_type = TVMDeviceType(code);
+            assert_eq!(device_type.to_string(), names[0]);
+            let raw = ts::DLDeviceType::from(device_type);
+            assert_eq!(TVMDeviceType::from(raw), device_type);
+        }
+        assert_eq!(TVMContext::hexagon(0).device_type, "hexagon".parse().unwrap());
+        assert_eq!("webgpu".parse::<TVMDeviceType>().unwrap(), TVMDeviceType(15));
+        assert_eq!(TVMDeviceType(100).to_string(), "unknown(100)");
+        assert!(!TVMDeviceType(16).is_ext());
+    }
+
+    #[test]
+    fn ext_device_types() {
+        assert!(TVMDeviceType::ext(2).is_err());
+        let npu = TVMDeviceType::ext(24).unwrap();
+        register_device_type("test_npu", npu).unwrap();
+        assert!(register_device_type("test_npu", TVMDeviceType(25)).is_err());
+        assert!(register_device_type("cuda", TVMDeviceType(26)).is_err());
+        assert_eq!(npu.to_string(), "test_npu");
+        assert_eq!(
+            "test_npu:1".parse::<TVMContext>().unwrap(),
+            TVMContext::new(npu, 1)
+        );
+        assert_eq!(ts::DLDeviceType::from(npu) as usize, 24);
+        assert_eq!("ext_dev".parse::<TVMDeviceType>().unwrap(), EXT_DEV);
+    }
+
+    #[test]
+    fn peer_access() {
+        let gpu = TVMContext::gpu(0);
+        assert!(enable_peer_access(&gpu, &gpu).is_ok());
+        assert!(enable_peer_access(&TVMContext::cpu(0), &gpu).is_err());
+    }
+
+    #[test]
+    fn thread_dimensions() {
+        assert_eq!(
+            parse_thread_dimensions("[1024, 1024, 64]").unwrap(),
+            vec![1024, 1024, 64]
+        );
+        assert!(parse_thread_dimensions("1024").is_err());
+        match TVMContext::cpu(0).device_name() {
+            Err(Error(ErrorKind::Unsupported(_), _)) => (),
+            other => panic!("expected an unsupported attribute, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn exist() {
+        assert!(TVMContext::cpu(0).exist().unwrap());
+        assert!(TVMContext::checked(TVMDeviceType(1), 0).is_ok());
+    }
+
+    #[test]
+    fn current() {
+        assert_eq!(TVMContext::current(), None);
+        {
+            let outer = TVMContext::cpu(0).make_current().unwrap();
+            assert_eq!(outer.ctx(), &TVMContext::cpu(0));
+            {
+                let _inner = TVMContext::cpu(1).make_current().unwrap();
+                assert_eq!(TVMContext::current(), Some(TVMContext::cpu(1)));
+            }
+            assert_eq!(TVMContext::current(), Some(TVMContext::cpu(0)));
+        }
+        assert_eq!(TVMContext::current(), None);
+    }
+
+    #[test]
+    fn current_out_of_order() {
+        let outer = TVMContext::cpu(0).make_current().unwrap();
+        let inner = TVMContext::cpu(1).make_current().unwrap();
+        assert_eq!(inner.previous, TVMContext::cpu(0));
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| drop(outer))).is_err());
+        assert_eq!(TVMContext::current(), Some(TVMContext::cpu(1)));
+        drop(inner);
+        assert_eq!(TVMContext::current(), None);
     }
 
     #[test]
@@ -0,0 +1,184 @@
+//! Provides helpers for working with the model library packages produced by TVM such as
+//! unpacking `.tar` archives and linking object files into a shared library.
+//!
+//! Like the `tvm.contrib` Python package, the helpers invoke the system `tar` and the C++
+//! compiler, which is `g++` by default or the value of the `CXX` environment variable.
+//! A cross-compiler and a sysroot can be configured with [`Toolchain`] for producing
+//! libraries for other platforms, e.g. ARM boards deployed to through RPC.
+//!
+//! # Example
+//!
+//! ```
+//! let dir = contrib::TempDir::new().unwrap();
+//! contrib::untar(Path::new("deploy.tar"), dir.path()).unwrap();
+//! let objects = [dir.path().join("lib.o")];
+//! contrib::create_shared(&dir.path().join("deploy.so"), &objects, &Toolchain::new()).unwrap();
+//! ```
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT},
+};
+
+use Result;
+
+static TEMP_DIR_COUNT: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// A uniquely named temporary directory, removed with its content on drop.
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates a new directory in the temporary directory of the system.
+    pub fn new() -> Result<Self> {
+        let path = env::temp_dir().join(format!(
+            "tvm-frontend-{}-{}",
+            ::std::process::id(),
+            TEMP_DIR_COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&path)?;
+        Ok(TempDir { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+// Runs the command and fails with its output if it does not succeed.
+fn run(command: &mut Command) -> Result<()> {
+    let output = command.output()?;
+    if !output.status.success() {
+        bail!(
+            "command {:?} failed: {}",
+            command,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(())
+}
+
+/// Unpacks a `.tar` archive into the directory.
+pub fn untar(archive: &Path, dir: &Path) -> Result<()> {
+    run(Command::new("tar").arg("-xf").arg(archive).arg("-C").arg(dir))
+}
+
+/// The compiler used for linking shared libraries, possibly cross-compiling for
+/// another platform. Default is the host compiler without extra options.
+///
+/// ## Example
+///
+/// ```
+/// let mut toolchain = Toolchain::new();
+/// toolchain
+///     .compiler("aarch64-linux-gnu-g++")
+///     .sysroot("/opt/sysroots/aarch64")
+///     .option("-O3");
+/// module.export_library_with(Path::new("deploy_arm.so"), &toolchain).unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Toolchain {
+    compiler: Option<String>,
+    sysroot: Option<PathBuf>,
+    options: Vec<String>,
+}
+
+impl Toolchain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the compiler command. Default is the `CXX` environment variable or `g++`.
+    pub fn compiler(&mut self, compiler: &str) -> &mut Self {
+        self.compiler = Some(compiler.to_owned());
+        self
+    }
+
+    /// Sets the root directory of the headers and libraries of the target platform.
+    pub fn sysroot<P: AsRef<Path>>(&mut self, sysroot: P) -> &mut Self {
+        self.sysroot = Some(sysroot.as_ref().to_owned());
+        self
+    }
+
+    /// Appends an option passed to the compiler.
+    pub fn option(&mut self, option: &str) -> &mut Self {
+        self.options.push(option.to_owned());
+        self
+    }
+
+    // Returns the compiler command linking `objects` into the shared library `output`.
+    fn command<P: AsRef<Path>>(&self, output: &Path, objects: &[P]) -> Command {
+        let compiler = match self.compiler {
+            Some(ref compiler) => compiler.clone(),
+            None => env::var("CXX").unwrap_or_else(|_| "g++".to_owned()),
+        };
+        let mut command = Command::new(compiler);
+        command.arg("-shared").arg("-fPIC");
+        if let Some(ref sysroot) = self.sysroot {
+            command.arg(format!("--sysroot={}", sysroot.display()));
+        }
+        command.args(&self.options).arg("-o").arg(output);
+        for object in objects {
+            command.arg(object.as_ref());
+        }
+        command
+    }
+}
+
+impl<'a> From<&'a str> for Toolchain {
+    fn from(compiler: &'a str) -> Self {
+        let mut toolchain = Toolchain::new();
+        toolchain.compiler(compiler);
+        toolchain
+    }
+}
+
+/// Links object files into a shared library with the given toolchain.
+pub fn create_shared<P: AsRef<Path>>(
+    output: &Path,
+    objects: &[P],
+    toolchain: &Toolchain,
+) -> Result<()> {
+    run(&mut toolchain.command(output, objects))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temp_dir() {
+        let path = {
+            let dir = TempDir::new().unwrap();
+            fs::write(dir.path().join("file"), b"content").unwrap();
+            assert_ne!(dir.path(), TempDir::new().unwrap().path());
+            dir.path().to_owned()
+        };
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn toolchain_command() {
+        let mut toolchain = Toolchain::from("aarch64-linux-gnu-g++");
+        toolchain.sysroot("/sysroot").option("-O3");
+        let command = format!("{:?}", toolchain.command(Path::new("lib.so"), &["lib.o"]));
+        assert!(command.starts_with(r#""aarch64-linux-gnu-g++" "-shared" "-fPIC""#));
+        assert!(command.ends_with(r#""--sysroot=/sysroot" "-O3" "-o" "lib.so" "lib.o""#));
+    }
+
+    #[test]
+    fn untar_missing_archive() {
+        let dir = TempDir::new().unwrap();
+        assert!(untar(&dir.path().join("missing.tar"), dir.path()).is_err());
+    }
+}
@@ -1,8 +1,9 @@
 //! This module implements TVM custom [`Error`], [`ErrorKind`] and [`Result`] types.
 
-use std::{ffi, option};
+use std::{ffi, io, option, time::Duration};
 
 use rust_ndarray;
+use serde_json;
 
 error_chain!{
     errors {
@@ -15,9 +16,24 @@ error_chain!{
             display("requested `{}` handle is null", name)
         }
 
-        FunctionNotFound {
+        FunctionNotFound(name: String, module_type: String) {
             description("function not found")
-            display("function was not set in `function::Builder`")
+            display("function `{}` not found in the `{}` module", name, module_type)
+        }
+
+        InvalidFunctionName(name: String, reason: String) {
+            description("invalid function name")
+            display("invalid function name `{}`: {}", name.escape_default(), reason)
+        }
+
+        FunctionAlreadyRegistered(name: String) {
+            description("function already registered")
+            display("function `{}` is already registered", name)
+        }
+
+        FunctionsNotFound(names: Vec<String>, module_type: String) {
+            description("functions not found")
+            display("functions `{}` not found in the `{}` module", names.join("`, `"), module_type)
         }
 
         TypeMismatch(expected: String, found: String) {
@@ -30,12 +46,110 @@ error_chain!{
             display("called `Option::unwrap()` on a `None` value")
         }
 
+        ModelNotFound(name: String, version: u32) {
+            description("model not found")
+            display("model `{}` with version {} is not registered", name, version)
+        }
+
+        RuntimeUnavailable(msg: String) {
+            description("TVM runtime is not usable")
+            display("TVM runtime is not usable: {}", msg)
+        }
+
+        ArgCountMismatch(expected: usize, found: usize) {
+            description("argument count mismatch")
+            display("expected {} arguments, but found {}", expected, found)
+        }
+
+        InvalidGraph(field: String) {
+            description("invalid graph json")
+            display("invalid or missing `{}` in the graph json", field)
+        }
+
+        InvalidParams(msg: String) {
+            description("invalid params")
+            display("invalid serialized params: {}", msg)
+        }
+
+        InvalidMetadata(msg: String) {
+            description("invalid metadata")
+            display("invalid artifacts metadata: {}", msg)
+        }
+
+        InvalidNpy(msg: String) {
+            description("invalid npy file")
+            display("invalid npy file: {}", msg)
+        }
+
+        InvalidTuningLog(line: usize, msg: String) {
+            description("invalid tuning log")
+            display("invalid tuning log at line {}: {}", line, msg)
+        }
+
+        InvalidConfig(msg: String) {
+            description("invalid run config")
+            display("invalid run config: {}", msg)
+        }
+
+        InputMismatch(mismatches: Vec<String>) {
+            description("input mismatch")
+            display("input mismatch: {}", mismatches.join("; "))
+        }
+
+        OutOfRange(value: String, ty: String) {
+            description("value out of range")
+            display("value {} does not fit in `{}`", value, ty)
+        }
+
+        Timeout(timeout: Duration) {
+            description("call timed out")
+            display("the call did not return within {:?}", timeout)
+        }
+
+        Cancelled {
+            description("call cancelled")
+            display("the call was cancelled")
+        }
+
+        QueueFull(capacity: usize) {
+            description("queue full")
+            display("the queue of {} calls waiting for an executor is full", capacity)
+        }
+
+        DeadlineExceeded {
+            description("deadline exceeded")
+            display("the call could not start before its deadline")
+        }
+
+        PoolShutDown {
+            description("pool shut down")
+            display("the executor pool is shut down")
+        }
+
+        Unsupported(what: String) {
+            description("unsupported by the runtime")
+            display("{} is not supported by the runtime", what)
+        }
+
+        UnknownVersion(functions: String) {
+            description("unknown runtime version")
+            display("the runtime reports its version through none of {}", functions)
+        }
+
+        VersionMismatch(bindings: String, runtime: String) {
+            description("runtime version mismatch")
+            display("the bindings are generated for TVM {} but the runtime is TVM {}",
+                    bindings, runtime)
+        }
+
     }
 
     foreign_links {
         ShapeError(rust_ndarray::ShapeError);
         NulError(ffi::NulError);
         IntoStringError(ffi::IntoStringError);
+        Io(io::Error);
+        Json(serde_json::Error);
     }
 }
 
@@ -0,0 +1,765 @@
+//! Provides the [`RuntimeApi`] trait through which all the calls to the TVM C runtime API
+//! are made, so that the runtime can be replaced with a test double.
+//!
+//! By default every thread uses [`Ffi`], which calls into the linked `libtvm_runtime`.
+//! [`MockApi`] is an in-memory implementation supporting cpu NDArrays and the error
+//! facility, enough for unit testing code that does not need compiled kernels.
+//!
+//! With the `dynamic` feature, `libtvm_runtime` is not linked but opened at run time as a
+//! [`Dylib`], e.g. from the native libraries packaged in an Android APK. How the library
+//! is found is configured with [`set_library_loader`]. The library is only opened on the
+//! first call through the default API, so the tests using [`MockApi`] run without it, e.g.
+//! with `cargo test --features dynamic mock`.
+//!
+//! The API of a thread can only be replaced while no resource obtained from the current
+//! one on that thread, e.g. an NDArray, is alive, since resources are freed through the
+//! API of the thread dropping them.
+//!
+//! # Example
+//!
+//! ```
+//! let _guard = ffi::set_api(Rc::new(MockApi::default())).unwrap();
+//! let mut nd = empty(&mut [2], TVMContext::cpu(0), DataType::from("float"));
+//! nd.copy_from_buffer(&[1f32, 2.]).unwrap();
+//! assert_eq!(nd.to_vec::<f32>().unwrap(), vec![1f32, 2.]);
+//! ```
+
+#[cfg(feature = "dynamic")]
+use std::{
+    ffi::OsStr,
+    fmt::{self, Debug, Formatter},
+    path::PathBuf,
+    sync::Mutex,
+};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    ffi::CString,
+    os::raw::{c_char, c_int, c_void},
+    ptr::{self, NonNull},
+    rc::Rc,
+    slice,
+    sync::{
+        atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT},
+        Arc,
+    },
+    thread,
+};
+
+#[cfg(feature = "dynamic")]
+use libloading::Library;
+use ts;
+
+#[cfg(feature = "dynamic")]
+use runtime;
+#[cfg(feature = "dynamic")]
+use Error;
+#[cfg(feature = "dynamic")]
+use ErrorKind;
+use Result;
+
+thread_local! {
+    // The api set with `set_api`, if any.
+    static API: RefCell<Option<Rc<RuntimeApi>>> = RefCell::new(None);
+    // The default api with the generation it was created in. It is created on first use,
+    // so that replacing it never loads the runtime.
+    static DEFAULT_API: RefCell<Option<(Rc<RuntimeApi>, usize)>> = RefCell::new(None);
+    // The number of live resources obtained from the current api of the thread.
+    static HANDLES: RefCell<Arc<AtomicUsize>> = RefCell::new(Arc::new(AtomicUsize::new(0)));
+}
+
+#[cfg(not(feature = "dynamic"))]
+fn default_api() -> Result<Rc<RuntimeApi>> {
+    Ok(Rc::new(Ffi))
+}
+
+#[cfg(feature = "dynamic")]
+fn default_api() -> Result<Rc<RuntimeApi>> {
+    Ok(Rc::new(Dylib::shared()?))
+}
+
+// Incremented by `release_api`, so that every thread drops its default api on next use.
+static API_GENERATION: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Returns the runtime API used by the current thread.
+///
+/// With the `dynamic` feature, if the runtime library cannot be opened, the returned API
+/// fails every call with the reason, which is then reported by `get_last_error`. The
+/// library is opened again on the next call.
+pub fn api() -> Rc<RuntimeApi> {
+    match try_api() {
+        Ok(api) => api,
+        #[cfg(feature = "dynamic")]
+        Err(err) => Rc::new(Unavailable::new(&err)),
+        #[cfg(not(feature = "dynamic"))]
+        Err(err) => unreachable!("the linked runtime is always available: {}", err),
+    }
+}
+
+// Returns the runtime API used by the current thread, failing with `RuntimeUnavailable`
+// if the runtime library cannot be opened. The failure is not cached.
+pub(crate) fn try_api() -> Result<Rc<RuntimeApi>> {
+    if let Some(api) = API.with(|api| api.borrow().clone()) {
+        return Ok(api);
+    }
+    let generation = API_GENERATION.load(Ordering::SeqCst);
+    DEFAULT_API.with(|default| {
+        let mut default = default.borrow_mut();
+        match *default {
+            Some((ref api, created)) if created == generation => return Ok(api.clone()),
+            _ => (),
+        }
+        // the stale api is dropped first, so that the library can be reopened
+        default.take();
+        let api = default_api()?;
+        *default = Some((api.clone(), generation));
+        Ok(api)
+    })
+}
+
+/// Replaces the runtime API used by the current thread until the returned guard is dropped.
+///
+/// Fails if resources obtained from the current API on this thread are still alive. The
+/// guard panics on drop if resources obtained from `api` are.
+pub fn set_api(api: Rc<RuntimeApi>) -> Result<ApiGuard> {
+    let live = HANDLES.with(|handles| handles.borrow().load(Ordering::SeqCst));
+    if live > 0 {
+        bail!(
+            "cannot replace the runtime api while {} resources obtained from it are alive",
+            live
+        );
+    }
+    let handles = Arc::new(AtomicUsize::new(0));
+    let previous_handles = HANDLES.with(|current| current.replace(handles.clone()));
+    let previous = API.with(|current| current.replace(Some(api)));
+    Ok(ApiGuard {
+        previous,
+        previous_handles,
+        handles,
+    })
+}
+
+// Drops the default runtime API of every thread, the one of the current thread now and
+// the ones of the other threads on their next use or when they exit, and with the `dynamic`
+// feature the shared runtime library, which is unloaded once no thread uses it anymore.
+// The next use creates the default API again. The APIs set with `set_api` are kept.
+pub(crate) fn release_api() {
+    API_GENERATION.fetch_add(1, Ordering::SeqCst);
+    DEFAULT_API.with(|api| api.borrow_mut().take());
+    #[cfg(feature = "dynamic")]
+    SHARED.lock().unwrap().take();
+}
+
+// The number of live `RuntimeRef`s.
+static LIVE_RESOURCES: AtomicUsize = ATOMIC_USIZE_INIT;
+
+// A reference to the runtime held by the resources obtained from it, e.g. NDArrays,
+// modules and functions, which are freed through the runtime on drop. `runtime::shutdown`
+// fails while any is alive, and with the `dynamic` feature the runtime library stays
+// loaded until the last one is dropped, after the resource has been freed.
+pub(crate) struct RuntimeRef {
+    // the live resources of the api the resource was obtained from.
+    handles: Arc<AtomicUsize>,
+    #[cfg(feature = "dynamic")]
+    _library: Option<Arc<Library>>,
+}
+
+impl RuntimeRef {
+    pub(crate) fn acquire() -> Self {
+        LIVE_RESOURCES.fetch_add(1, Ordering::SeqCst);
+        let handles = HANDLES.with(|handles| handles.borrow().clone());
+        handles.fetch_add(1, Ordering::SeqCst);
+        RuntimeRef {
+            handles,
+            #[cfg(feature = "dynamic")]
+            _library: SHARED
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|dylib| dylib._library.clone()),
+        }
+    }
+}
+
+impl Clone for RuntimeRef {
+    fn clone(&self) -> Self {
+        LIVE_RESOURCES.fetch_add(1, Ordering::SeqCst);
+        self.handles.fetch_add(1, Ordering::SeqCst);
+        RuntimeRef {
+            handles: self.handles.clone(),
+            #[cfg(feature = "dynamic")]
+            _library: self._library.clone(),
+        }
+    }
+}
+
+impl Drop for RuntimeRef {
+    fn drop(&mut self) {
+        LIVE_RESOURCES.fetch_sub(1, Ordering::SeqCst);
+        self.handles.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl ::std::fmt::Debug for RuntimeRef {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "RuntimeRef")
+    }
+}
+
+// Returns the number of resources obtained from the runtime which are still alive.
+pub(crate) fn live_resources() -> usize {
+    LIVE_RESOURCES.load(Ordering::SeqCst)
+}
+
+/// Restores the previous runtime API of the current thread when dropped.
+pub struct ApiGuard {
+    previous: Option<Rc<RuntimeApi>>,
+    previous_handles: Arc<AtomicUsize>,
+    // the live resources obtained from the replacing api.
+    handles: Arc<AtomicUsize>,
+}
+
+impl Drop for ApiGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        API.with(|current| current.replace(previous));
+        HANDLES.with(|current| current.replace(self.previous_handles.clone()));
+        let live = self.handles.load(Ordering::SeqCst);
+        if live > 0 && !thread::panicking() {
+            panic!("{} resources obtained from the replaced runtime api outlive it", live);
+        }
+    }
+}
+
+// Sets the last error of `api` for an unsupported call and returns the failure code.
+fn unsupported<A: RuntimeApi + ?Sized>(api: &A, name: &str) -> c_int {
+    let msg = CString::new(format!("`{}` is not supported by this runtime api", name))
+        .expect("function names have no NUL bytes");
+    unsafe { api.set_last_error(msg.as_ptr()) };
+    -1
+}
+
+macro_rules! runtime_api {
+    ($(fn $name:ident($($arg:ident: $ty:ty),*) = $ffi:ident;)+) => {
+        /// Calls of the TVM C runtime API. Each method has the same contract as the
+        /// C function of the same name and returns `0` on success.
+        ///
+        /// All the calls except the error facility fail by default, so a test double
+        /// only needs to implement what it supports.
+        pub trait RuntimeApi {
+            unsafe fn get_last_error(&self) -> *const c_char;
+            unsafe fn set_last_error(&self, msg: *const c_char);
+            $(
+                #[allow(unused_variables)]
+                unsafe fn $name(&self, $($arg: $ty),*) -> c_int {
+                    unsupported(self, stringify!($ffi))
+                }
+            )+
+        }
+
+        /// The runtime API backed by the linked `libtvm_runtime`.
+        #[cfg(not(feature = "dynamic"))]
+        #[derive(Debug, Clone, Copy, Default)]
+        pub struct Ffi;
+
+        #[cfg(not(feature = "dynamic"))]
+        impl RuntimeApi for Ffi {
+            unsafe fn get_last_error(&self) -> *const c_char {
+                ts::TVMGetLastError()
+            }
+
+            unsafe fn set_last_error(&self, msg: *const c_char) {
+                ts::TVMAPISetLastError(msg)
+            }
+
+            $(
+                unsafe fn $name(&self, $($arg: $ty),*) -> c_int {
+                    ts::$ffi($($arg),*)
+                }
+            )+
+        }
+
+        /// The runtime API backed by a `libtvm_runtime` opened at run time.
+        ///
+        /// Cloning is cheap and the library stays open as long as a clone is alive.
+        #[cfg(feature = "dynamic")]
+        #[derive(Clone)]
+        pub struct Dylib {
+            _library: Arc<Library>,
+            get_last_error: unsafe extern "C" fn() -> *const c_char,
+            set_last_error: unsafe extern "C" fn(*const c_char),
+            $($name: unsafe extern "C" fn($($ty),*) -> c_int,)+
+        }
+
+        #[cfg(feature = "dynamic")]
+        impl Dylib {
+            /// Opens the runtime library at `path` and resolves the functions of the
+            /// runtime API. A bare file name is searched like `dlopen` does.
+            pub fn open<P: AsRef<OsStr>>(path: P) -> Result<Self> {
+                let library = Library::new(path.as_ref()).map_err(|err| {
+                    ErrorKind::RuntimeUnavailable(format!(
+                        "cannot open {:?}: {}",
+                        path.as_ref(),
+                        err
+                    ))
+                })?;
+                unsafe {
+                    Ok(Dylib {
+                        get_last_error: symbol(&library, "TVMGetLastError")?,
+                        set_last_error: symbol(&library, "TVMAPISetLastError")?,
+                        $($name: symbol(&library, stringify!($ffi))?,)+
+                        _library: Arc::new(library),
+                    })
+                }
+            }
+        }
+
+        #[cfg(feature = "dynamic")]
+        impl RuntimeApi for Dylib {
+            unsafe fn get_last_error(&self) -> *const c_char {
+                (self.get_last_error)()
+            }
+
+            unsafe fn set_last_error(&self, msg: *const c_char) {
+                (self.set_last_error)(msg)
+            }
+
+            $(
+                unsafe fn $name(&self, $($arg: $ty),*) -> c_int {
+                    (self.$name)($($arg),*)
+                }
+            )+
+        }
+    };
+}
+
+runtime_api! {
+    fn func_list_global_names(
+        out_size: *mut c_int,
+        out_array: *mut *mut *const c_char
+    ) = TVMFuncListGlobalNames;
+    fn func_get_global(name: *const c_char, out: *mut ts::TVMFunctionHandle) = TVMFuncGetGlobal;
+    fn func_free(func: ts::TVMFunctionHandle) = TVMFuncFree;
+    fn func_call(
+        func: ts::TVMFunctionHandle,
+        arg_values: *mut ts::TVMValue,
+        type_codes: *mut c_int,
+        num_args: c_int,
+        ret_val: *mut ts::TVMValue,
+        ret_type_code: *mut c_int
+    ) = TVMFuncCall;
+    fn cb_arg_to_return(value: *mut ts::TVMValue, code: c_int) = TVMCbArgToReturn;
+    fn cfunc_set_return(
+        ret: ts::TVMRetValueHandle,
+        value: *mut ts::TVMValue,
+        type_code: *mut c_int,
+        num_ret: c_int
+    ) = TVMCFuncSetReturn;
+    fn func_create_from_cfunc(
+        func: ts::TVMPackedCFunc,
+        resource_handle: *mut c_void,
+        fin: ts::TVMPackedCFuncFinalizer,
+        out: *mut ts::TVMFunctionHandle
+    ) = TVMFuncCreateFromCFunc;
+    fn func_register_global(
+        name: *const c_char,
+        func: ts::TVMFunctionHandle,
+        override_: c_int
+    ) = TVMFuncRegisterGlobal;
+    fn mod_get_function(
+        module: ts::TVMModuleHandle,
+        func_name: *const c_char,
+        query_imports: c_int,
+        out: *mut ts::TVMFunctionHandle
+    ) = TVMModGetFunction;
+    fn mod_import(module: ts::TVMModuleHandle, dep: ts::TVMModuleHandle) = TVMModImport;
+    fn mod_free(module: ts::TVMModuleHandle) = TVMModFree;
+    fn object_free(obj: ts::TVMObjectHandle) = TVMObjectFree;
+    fn synchronize(
+        device_type: c_int,
+        device_id: c_int,
+        stream: ts::TVMStreamHandle
+    ) = TVMSynchronize;
+    fn device_alloc_data_space(
+        ctx: ts::DLContext,
+        nbytes: usize,
+        alignment: usize,
+        type_hint: ts::DLDataType,
+        out_data: *mut *mut c_void
+    ) = TVMDeviceAllocDataSpace;
+    fn device_free_data_space(ctx: ts::DLContext, ptr: *mut c_void) = TVMDeviceFreeDataSpace;
+    fn array_alloc(
+        shape: *const i64,
+        ndim: c_int,
+        dtype_code: c_int,
+        dtype_bits: c_int,
+        dtype_lanes: c_int,
+        device_type: c_int,
+        device_id: c_int,
+        out: *mut ts::TVMArrayHandle
+    ) = TVMArrayAlloc;
+    fn array_free(handle: ts::TVMArrayHandle) = TVMArrayFree;
+    fn array_copy_from_bytes(
+        handle: ts::TVMArrayHandle,
+        data: *mut c_void,
+        nbytes: usize
+    ) = TVMArrayCopyFromBytes;
+    fn array_copy_to_bytes(
+        handle: ts::TVMArrayHandle,
+        data: *mut c_void,
+        nbytes: usize
+    ) = TVMArrayCopyToBytes;
+    fn array_copy_from_to(
+        from: ts::TVMArrayHandle,
+        to: ts::TVMArrayHandle,
+        stream: ts::TVMStreamHandle
+    ) = TVMArrayCopyFromTo;
+    fn array_from_dlpack(
+        from: *mut ts::DLManagedTensor,
+        out: *mut ts::TVMArrayHandle
+    ) = TVMArrayFromDLPack;
+    fn array_to_dlpack(
+        from: ts::TVMArrayHandle,
+        out: *mut *mut ts::DLManagedTensor
+    ) = TVMArrayToDLPack;
+}
+
+// Resolves the function `name` of the library.
+#[cfg(feature = "dynamic")]
+unsafe fn symbol<T: Copy>(library: &Library, name: &str) -> Result<T> {
+    match library.get::<T>(format!("{}\0", name).as_bytes()) {
+        Ok(symbol) => Ok(*symbol),
+        Err(err) => bail!(ErrorKind::RuntimeUnavailable(format!(
+            "missing `{}`: {}",
+            name, err
+        ))),
+    }
+}
+
+/// The file name of the runtime library on the target platform.
+#[cfg(target_os = "macos")]
+pub const RUNTIME_LIBRARY: &'static str = "libtvm_runtime.dylib";
+#[cfg(windows)]
+pub const RUNTIME_LIBRARY: &'static str = "tvm_runtime.dll";
+#[cfg(not(any(target_os = "macos", windows)))]
+pub const RUNTIME_LIBRARY: &'static str = "libtvm_runtime.so";
+
+#[cfg(feature = "dynamic")]
+lazy_static! {
+    static ref LOADER: Mutex<Box<Fn() -> Result<Dylib> + Send>> =
+        Mutex::new(Box::new(default_loader));
+    static ref SHARED: Mutex<Option<Dylib>> = Mutex::new(None);
+}
+
+// Searches the locations given by the environment, see `runtime::library_candidates`.
+// The last one is `RUNTIME_LIBRARY`, which on Android finds the library packaged in the APK.
+#[cfg(feature = "dynamic")]
+fn default_loader() -> Result<Dylib> {
+    open_first(&runtime::library_candidates(&[]))
+}
+
+/// Opens the first of the candidate paths that can be opened, or fails listing the
+/// errors of all the searched paths.
+#[cfg(feature = "dynamic")]
+pub(crate) fn open_first(candidates: &[PathBuf]) -> Result<Dylib> {
+    let mut errors = Vec::new();
+    for path in candidates {
+        match Dylib::open(path) {
+            Ok(dylib) => return Ok(dylib),
+            Err(Error(ErrorKind::RuntimeUnavailable(msg), _)) => errors.push(msg),
+            Err(err) => errors.push(err.to_string()),
+        }
+    }
+    bail!(ErrorKind::RuntimeUnavailable(format!(
+        "cannot open the runtime library, searched:\n  {}",
+        errors.join("\n  ")
+    )))
+}
+
+/// Sets how the runtime library used by default is opened, e.g. from the native library
+/// directory of an Android app. It has no effect once the library has been opened.
+///
+/// ## Example
+///
+/// ```
+/// ffi::set_library_loader(move || Dylib::open(native_lib_dir.join(ffi::RUNTIME_LIBRARY)));
+/// ```
+#[cfg(feature = "dynamic")]
+pub fn set_library_loader<F>(loader: F)
+where
+    F: Fn() -> Result<Dylib> + Send + 'static,
+{
+    *LOADER.lock().unwrap() = Box::new(loader);
+}
+
+#[cfg(feature = "dynamic")]
+impl Dylib {
+    /// Returns the runtime library used by default, which is opened with the loader
+    /// on the first call and shared afterwards.
+    pub fn shared() -> Result<Dylib> {
+        let mut shared = SHARED.lock().unwrap();
+        if shared.is_none() {
+            let loader = LOADER.lock().unwrap();
+            *shared = Some((*loader)()?);
+        }
+        Ok(shared.clone().expect("the library is opened above"))
+    }
+}
+
+// The runtime API used while the runtime library cannot be opened. All the calls fail and
+// the last error is always the reason the library is unavailable.
+#[cfg(feature = "dynamic")]
+struct Unavailable {
+    reason: CString,
+}
+
+#[cfg(feature = "dynamic")]
+impl Unavailable {
+    fn new(err: &Error) -> Self {
+        let reason = err.to_string().replace('\0', "");
+        Unavailable {
+            reason: CString::new(reason).expect("NUL bytes are removed above"),
+        }
+    }
+}
+
+#[cfg(feature = "dynamic")]
+impl RuntimeApi for Unavailable {
+    unsafe fn get_last_error(&self) -> *const c_char {
+        self.reason.as_ptr()
+    }
+
+    unsafe fn set_last_error(&self, _msg: *const c_char) {}
+}
+
+#[cfg(feature = "dynamic")]
+impl Debug for Dylib {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Dylib").finish()
+    }
+}
+
+// An array allocated by `MockApi`. The tensor is the first field so that
+// the array handle can be cast back to the allocation.
+#[repr(C)]
+struct MockArray {
+    tensor: ts::DLTensor,
+    shape: Vec<i64>,
+    // u64 words keep the data aligned for every supported dtype.
+    data: Vec<u64>,
+}
+
+unsafe fn nbytes(tensor: &ts::DLTensor) -> usize {
+    let shape = slice::from_raw_parts(tensor.shape, tensor.ndim as usize);
+    let size = shape.iter().product::<i64>() as usize;
+    (size * tensor.dtype.bits as usize * tensor.dtype.lanes as usize + 7) / 8
+}
+
+/// In-memory test double of the runtime supporting cpu arrays and data spaces,
+/// synchronization, an empty global function registry and the error facility.
+#[derive(Debug, Default)]
+pub struct MockApi {
+    last_error: RefCell<CString>,
+    // Allocated data spaces by address. Only an alignment up to 8 bytes is honored.
+    data_spaces: RefCell<HashMap<usize, Vec<u64>>>,
+}
+
+impl MockApi {
+    fn fail(&self, msg: &str) -> c_int {
+        *self.last_error.borrow_mut() = CString::new(msg).expect("messages have no NUL bytes");
+        -1
+    }
+}
+
+impl RuntimeApi for MockApi {
+    unsafe fn get_last_error(&self) -> *const c_char {
+        self.last_error.borrow().as_ptr()
+    }
+
+    unsafe fn set_last_error(&self, msg: *const c_char) {
+        *self.last_error.borrow_mut() = ::std::ffi::CStr::from_ptr(msg).to_owned();
+    }
+
+    unsafe fn func_list_global_names(
+        &self,
+        out_size: *mut c_int,
+        out_array: *mut *mut *const c_char,
+    ) -> c_int {
+        // the names are read as a slice, which must not start at a null pointer.
+        *out_size = 0;
+        *out_array = NonNull::dangling().as_ptr();
+        0
+    }
+
+    unsafe fn func_get_global(
+        &self,
+        _name: *const c_char,
+        out: *mut ts::TVMFunctionHandle,
+    ) -> c_int {
+        *out = ptr::null_mut();
+        0
+    }
+
+    unsafe fn synchronize(
+        &self,
+        _device_type: c_int,
+        _device_id: c_int,
+        _stream: ts::TVMStreamHandle,
+    ) -> c_int {
+        0
+    }
+
+    unsafe fn device_alloc_data_space(
+        &self,
+        ctx: ts::DLContext,
+        nbytes: usize,
+        _alignment: usize,
+        _type_hint: ts::DLDataType,
+        out_data: *mut *mut c_void,
+    ) -> c_int {
+        if ctx.device_type != ts::DLDeviceType_kDLCPU {
+            return self.fail("MockApi only supports cpu data spaces");
+        }
+        let mut data = vec![0u64; (nbytes + 7) / 8];
+        *out_data = data.as_mut_ptr() as *mut c_void;
+        self.data_spaces.borrow_mut().insert(*out_data as usize, data);
+        0
+    }
+
+    unsafe fn device_free_data_space(&self, _ctx: ts::DLContext, ptr: *mut c_void) -> c_int {
+        match self.data_spaces.borrow_mut().remove(&(ptr as usize)) {
+            Some(_) => 0,
+            None => self.fail("unknown data space"),
+        }
+    }
+
+    unsafe fn array_alloc(
+        &self,
+        shape: *const i64,
+        ndim: c_int,
+        dtype_code: c_int,
+        dtype_bits: c_int,
+        dtype_lanes: c_int,
+        device_type: c_int,
+        device_id: c_int,
+        out: *mut ts::TVMArrayHandle,
+    ) -> c_int {
+        if device_type != 1 {
+            return self.fail("MockApi only supports cpu arrays");
+        }
+        let shape = slice::from_raw_parts(shape, ndim as usize).to_vec();
+        let size = shape.iter().product::<i64>() as usize;
+        let nbytes = (size * dtype_bits as usize * dtype_lanes as usize + 7) / 8;
+        let mut array = Box::new(MockArray {
+            tensor: ts::DLTensor {
+                data: ptr::null_mut(),
+                ctx: ts::DLContext {
+                    device_type: ts::DLDeviceType_kDLCPU,
+                    device_id,
+                },
+                ndim,
+                dtype: ts::DLDataType {
+                    code: dtype_code as u8,
+                    bits: dtype_bits as u8,
+                    lanes: dtype_lanes as u16,
+                },
+                shape: ptr::null_mut(),
+                strides: ptr::null_mut(),
+                byte_offset: 0,
+            },
+            shape,
+            data: vec![0; (nbytes + 7) / 8],
+        });
+        array.tensor.data = array.data.as_mut_ptr() as *mut c_void;
+        array.tensor.shape = array.shape.as_mut_ptr();
+        *out = Box::into_raw(array) as ts::TVMArrayHandle;
+        0
+    }
+
+    unsafe fn array_free(&self, handle: ts::TVMArrayHandle) -> c_int {
+        drop(Box::from_raw(handle as *mut MockArray));
+        0
+    }
+
+    unsafe fn array_copy_from_bytes(
+        &self,
+        handle: ts::TVMArrayHandle,
+        data: *mut c_void,
+        nbytes: usize,
+    ) -> c_int {
+        if nbytes != self::nbytes(&*handle) {
+            return self.fail("data size and array size mismatch");
+        }
+        ptr::copy_nonoverlapping(data as *const u8, (*handle).data as *mut u8, nbytes);
+        0
+    }
+
+    unsafe fn array_copy_to_bytes(
+        &self,
+        handle: ts::TVMArrayHandle,
+        data: *mut c_void,
+        nbytes: usize,
+    ) -> c_int {
+        if nbytes != self::nbytes(&*handle) {
+            return self.fail("data size and array size mismatch");
+        }
+        ptr::copy_nonoverlapping((*handle).data as *const u8, data as *mut u8, nbytes);
+        0
+    }
+
+    unsafe fn array_copy_from_to(
+        &self,
+        from: ts::TVMArrayHandle,
+        to: ts::TVMArrayHandle,
+        _stream: ts::TVMStreamHandle,
+    ) -> c_int {
+        let nbytes = nbytes(&*from);
+        if nbytes != self::nbytes(&*to) {
+            return self.fail("array sizes mismatch");
+        }
+        ptr::copy_nonoverlapping((*from).data as *const u8, (*to).data as *mut u8, nbytes);
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use function;
+    use ndarray::empty;
+    use DataType;
+    use TVMContext;
+
+    #[test]
+    fn mock_ndarray() {
+        let _guard = set_api(Rc::new(MockApi::default())).unwrap();
+        let shape = &mut [2, 2];
+        let data = vec![1f32, 2., 3., 4.];
+        let mut nd = empty(shape, TVMContext::cpu(0), DataType::from("float"));
+        nd.copy_from_buffer(&data).unwrap();
+        assert_eq!(nd.shape(), &[2, 2]);
+        assert_eq!(nd.to_vec::<f32>().unwrap(), data);
+        assert!(TVMContext::cpu(0).sync().is_ok());
+    }
+
+    #[test]
+    fn mock_errors() {
+        let _guard = set_api(Rc::new(MockApi::default())).unwrap();
+        ::set_last_error("mock error");
+        assert_eq!(::get_last_error(), "mock error");
+        assert_eq!(unsafe { api().mod_free(ptr::null_mut()) }, -1);
+        assert!(::get_last_error().contains("TVMModFree"));
+        assert!(function::list_global_names().unwrap().is_empty());
+    }
+
+    #[test]
+    fn mock_swap() {
+        let guard = set_api(Rc::new(MockApi::default())).unwrap();
+        let nd = empty(&mut [2], TVMContext::cpu(0), DataType::from("float"));
+        assert!(set_api(Rc::new(MockApi::default())).is_err());
+        drop(nd);
+        drop(set_api(Rc::new(MockApi::default())).unwrap());
+        drop(guard);
+    }
+}
@@ -2,60 +2,291 @@
 //!
 //! For calling an already registered TVM function use [`function::Builder`]
 //! To register a TVM packed function from Rust side either
-//! use [`function::register`] or the macro [`register_global_func`].
+//! use [`function::register`], [`function::register_typed`] for functions and closures
+//! with typed arguments or the macro [`register_global_func`].
+//! To discover the global functions provided by the runtime use [`function::registry`].
 //!
 //! See the tests and examples repository for more examples.
 
 use std::{
+    any::Any,
+    collections::{btree_map, BTreeMap, HashMap},
     ffi::{CStr, CString},
+    fmt::{self, Display, Formatter},
+    hash::{Hash, Hasher},
+    marker::PhantomData,
     mem,
     os::raw::{c_char, c_int, c_void},
+    panic::{self, AssertUnwindSafe},
     ptr, slice, str,
-    sync::Mutex,
+    sync::{
+        atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT},
+        mpsc::{self, RecvTimeoutError},
+        Arc, RwLock,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
+use serde_json::Value;
+use smallvec::SmallVec;
 use ts;
 
+use ffi;
+use module::ModuleHandle;
+use replay;
+use shape::Shape;
 use ty::TypeCode;
-use value::{TVMValue, ValueKind};
+use value::{FromArgValue, IntoRetValue, TVMValue, ValueKind};
 use ErrorKind;
 use Module;
+use NDArray;
 use Result;
 use TVMArgValue;
 use TVMRetValue;
 
+// Lists the names of the global functions, failing instead of panicking when the
+// runtime library is not usable. The names are copied, as the runtime reuses its buffer.
+pub(crate) fn list_global_names() -> Result<Vec<String>> {
+    let mut out_size = 0 as c_int;
+    let name = ptr::null_mut() as *mut c_char;
+    let mut out_array = name as *mut _;
+    let api = ffi::try_api()?;
+    let ret = unsafe { api.func_list_global_names(&mut out_size as *mut _, &mut out_array) };
+    if ret != 0 {
+        bail!(ErrorKind::RuntimeUnavailable(::get_last_error()));
+    }
+    let names_list = unsafe { slice::from_raw_parts(out_array, out_size as usize) };
+    Ok(names_list
+        .into_iter()
+        .map(|&p| unsafe { CStr::from_ptr(p).to_string_lossy().into_owned() })
+        .collect())
+}
+
 lazy_static! {
-    static ref GLOBAL_FUNCTION_NAMES: Mutex<Vec<&'static str>> = {
-        let mut out_size = 0 as c_int;
-        let name = ptr::null_mut() as *mut c_char;
-        let mut out_array = name as *mut _;
-        check_call!(ts::TVMFuncListGlobalNames(
-            &mut out_size as *mut _,
-            &mut out_array
-        ));
-        let names_list = unsafe { slice::from_raw_parts(out_array, out_size as usize) };
-        Mutex::new(
-            names_list
-                .into_iter()
-                .map(|&p| unsafe { CStr::from_ptr(p).to_str().unwrap() })
-                .collect(),
-        )
-    };
+    // Handles of the global functions looked up with `is_global`, by name. The runtime
+    // returns a new handle on every lookup, so they are kept instead of leaked per lookup.
+    // Lookups of cached functions only take the read lock, so they do not contend.
+    static ref GLOBAL_HANDLES: RwLock<HashMap<String, Arc<GlobalHandle>>> =
+        RwLock::new(HashMap::new());
+}
+
+// A handle of a global function shared by the cache and the functions obtained with it,
+// freed once none of them holds it anymore.
+#[derive(Debug)]
+pub(crate) struct GlobalHandle(ts::TVMFunctionHandle);
+
+// Function handles are reference counted atomically by the runtime, so the last reference
+// can be released from any thread.
+unsafe impl Send for GlobalHandle {}
+unsafe impl Sync for GlobalHandle {}
+
+impl Drop for GlobalHandle {
+    fn drop(&mut self) {
+        check_call!(ffi::api().func_free(self.0));
+    }
+}
+
+// Drops the cached handles of the global functions, which are freed once the functions
+// obtained with them are dropped too.
+pub(crate) fn clear_global_handles() {
+    let handles = mem::replace(&mut *GLOBAL_HANDLES.write().unwrap(), HashMap::new());
+    drop(handles);
+}
+
+// Drops the cached handle of a global function replaced in the registry, so that the next
+// lookup returns the new function.
+fn evict_global_handle(name: &str) {
+    let handle = GLOBAL_HANDLES.write().unwrap().remove(name);
+    drop(handle);
 }
 
 /// Returns a registered TVM function by name.
+///
+/// With `is_global`, the handle is looked up once per name and shared by the returned
+/// functions, which keep it alive. Otherwise the function owns a new handle, freed on drop.
 pub fn get_global_func(name: &str, is_global: bool) -> Option<Function> {
-    let name = CString::new(name).expect("function name should not contain any `0` byte");
+    let fn_name = Arc::from(name);
+    if !is_global {
+        let handle = lookup_global(name)?;
+        return Some(Function::new(handle, false, false).with_name(Some(fn_name)));
+    }
+    let cached = GLOBAL_HANDLES.read().unwrap().get(name).cloned();
+    let handle = match cached {
+        Some(handle) => handle,
+        None => {
+            let handle = Arc::new(GlobalHandle(lookup_global(name)?));
+            // another thread may have cached the function in the meantime, in which case
+            // the new handle is freed on return
+            let mut handles = GLOBAL_HANDLES.write().unwrap();
+            handles.entry(name.to_owned()).or_insert(handle).clone()
+        }
+    };
+    Some(
+        Function::new(handle.0, true, false)
+            .with_global(Some(handle))
+            .with_name(Some(fn_name)),
+    )
+}
+
+// Returns the first registered of the global functions, e.g. the names of a function in
+// the TVM versions the crate supports, failing with the first name otherwise.
+pub(crate) fn get_first_global_func(names: &[&'static str]) -> Result<Function> {
+    ffi::try_api()?;
+    names
+        .iter()
+        .filter_map(|name| get_global_func(name, true))
+        .next()
+        .ok_or_else(|| ErrorKind::NullHandle(names[0].to_owned()).into())
+}
+
+// Returns a new handle of the global function, or `None` if it is not registered, the
+// name contains a NUL byte or the runtime library cannot be opened.
+fn lookup_global(name: &str) -> Option<ts::TVMFunctionHandle> {
+    let name = CString::new(name).ok()?;
+    let api = ffi::try_api().ok()?;
     let mut handle = ptr::null_mut() as ts::TVMFunctionHandle;
-    check_call!(ts::TVMFuncGetGlobal(
+    check_call!(api.func_get_global(
         name.as_ptr() as *const c_char,
         &mut handle as *mut _
     ));
-    if !(handle.is_null()) {
-        mem::forget(name);
-        return Some(Function::new(handle, is_global, false));
-    } else {
+    if handle.is_null() {
         None
+    } else {
+        Some(handle)
+    }
+}
+
+/// Returns the global functions provided by the runtime, including the ones registered
+/// from Rust, as a tree grouped by their dotted prefixes.
+///
+/// ## Example
+///
+/// ```
+/// let registry = function::registry().unwrap();
+/// println!("{}", registry.get("tvm.graph_runtime").unwrap());
+/// assert!(registry.search("graph_runtime").contains(&"tvm.graph_runtime.create"));
+/// ```
+pub fn registry() -> Result<Namespace> {
+    Ok(Namespace::from_names(list_global_names()?))
+}
+
+/// A namespace of global functions, e.g. `tvm.graph_runtime` which contains the function
+/// `tvm.graph_runtime.create`. The root namespace has the empty path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Namespace {
+    path: String,
+    // full names of the functions directly in the namespace, sorted.
+    functions: Vec<String>,
+    // nested namespaces by their last path segment.
+    namespaces: BTreeMap<String, Namespace>,
+}
+
+impl Namespace {
+    /// Builds the tree of the given function names.
+    pub fn from_names<I: IntoIterator<Item = S>, S: AsRef<str>>(names: I) -> Self {
+        let mut root = Namespace::default();
+        for name in names {
+            let name = name.as_ref();
+            let mut namespace = &mut root;
+            let mut segments = name.split('.').collect::<Vec<_>>();
+            segments.pop();
+            for segment in segments {
+                let path = if namespace.path.is_empty() {
+                    segment.to_owned()
+                } else {
+                    format!("{}.{}", namespace.path, segment)
+                };
+                namespace = namespace
+                    .namespaces
+                    .entry(segment.to_owned())
+                    .or_insert_with(|| Namespace {
+                        path,
+                        ..Namespace::default()
+                    });
+            }
+            namespace.functions.push(name.to_owned());
+        }
+        root.sort();
+        root
+    }
+
+    fn sort(&mut self) {
+        self.functions.sort();
+        self.functions.dedup();
+        for namespace in self.namespaces.values_mut() {
+            namespace.sort();
+        }
+    }
+
+    /// Returns the dotted path of the namespace.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the full names of the functions directly in the namespace.
+    pub fn functions(&self) -> &[String] {
+        &self.functions
+    }
+
+    /// Returns the nested namespaces sorted by name.
+    pub fn namespaces(&self) -> btree_map::Values<String, Namespace> {
+        self.namespaces.values()
+    }
+
+    /// Returns the nested namespace with the dotted path relative to this one.
+    pub fn get(&self, path: &str) -> Option<&Namespace> {
+        path.split('.')
+            .try_fold(self, |namespace, segment| namespace.namespaces.get(segment))
+    }
+
+    /// Returns the number of functions in the namespace, including the nested ones.
+    pub fn len(&self) -> usize {
+        self.functions.len() + self.namespaces().map(Namespace::len).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the full names of the functions in the namespace, including the nested
+    /// ones, which contain `pattern` ignoring the case.
+    pub fn search(&self, pattern: &str) -> Vec<&str> {
+        let pattern = pattern.to_lowercase();
+        let mut found = Vec::new();
+        self.search_into(&pattern, &mut found);
+        found.sort();
+        found
+    }
+
+    fn search_into<'a>(&'a self, pattern: &str, found: &mut Vec<&'a str>) {
+        for name in &self.functions {
+            if name.to_lowercase().contains(pattern) {
+                found.push(name);
+            }
+        }
+        for namespace in self.namespaces() {
+            namespace.search_into(pattern, found);
+        }
+    }
+
+    fn fmt_indented(&self, f: &mut Formatter, depth: usize) -> fmt::Result {
+        for name in &self.functions {
+            let short_name = name.rsplit('.').next().unwrap_or(name);
+            writeln!(f, "{:2$}{}", "", short_name, depth * 2)?;
+        }
+        for (segment, namespace) in &self.namespaces {
+            writeln!(f, "{:2$}{}.", "", segment, depth * 2)?;
+            namespace.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints the tree of the namespace, nesting by two spaces.
+impl Display for Namespace {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.fmt_indented(f, 0)
     }
 }
 
@@ -64,7 +295,7 @@ pub fn get_global_func(name: &str, is_global: bool) -> Option<Function> {
 /// to hint dropping the function handle and `is_cloned` showing
 /// not to drop a cloned function from Rust side.
 /// The value of these fields can be accessed through their respective methods.
-#[derive(Debug, Hash)]
+#[derive(Debug)]
 pub struct Function {
     pub(crate) handle: ts::TVMFunctionHandle,
     // whether the registered function is global or not.
@@ -73,6 +304,22 @@ pub struct Function {
     is_released: bool,
     // whether the function has been cloned from frontend or not.
     is_cloned: bool,
+    // keeps the module which the function was obtained from alive.
+    module: Option<Arc<ModuleHandle>>,
+    // keeps the cached handle of a global function alive.
+    global: Option<Arc<GlobalHandle>>,
+    // the name the function was looked up with, e.g. reported to the call observer.
+    name: Option<Arc<str>>,
+    _runtime: ffi::RuntimeRef,
+}
+
+impl Hash for Function {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.handle.hash(state);
+        self.is_global.hash(state);
+        self.is_released.hash(state);
+        self.is_cloned.hash(state);
+    }
 }
 
 impl Function {
@@ -82,21 +329,82 @@ impl Function {
             is_global: is_global,
             is_released: is_released,
             is_cloned: false,
+            module: None,
+            global: None,
+            name: None,
+            _runtime: ffi::RuntimeRef::acquire(),
         }
     }
 
+    // Ties the function to the module it was obtained from.
+    pub(crate) fn with_module(mut self, module: Option<Arc<ModuleHandle>>) -> Self {
+        self.module = module;
+        self
+    }
+
+    // Ties the function to the cached handle of the global function it was obtained with.
+    pub(crate) fn with_global(mut self, global: Option<Arc<GlobalHandle>>) -> Self {
+        self.global = global;
+        self
+    }
+
+    // Records the name the function was looked up with.
+    pub(crate) fn with_name(mut self, name: Option<Arc<str>>) -> Self {
+        self.name = name;
+        self
+    }
+
     /// For a given function, it returns a function by name.
     pub fn get_function(name: &str, is_global: bool) -> Option<Function> {
-        let gnames = GLOBAL_FUNCTION_NAMES.lock().unwrap();
-        let fn_name = gnames.iter().find(|&&s| s == name)?;
-        get_global_func(fn_name, is_global)
+        get_global_func(name, is_global)
+    }
+
+    /// Returns the name the function was looked up with in the registry or in a module,
+    /// or `None` for other functions, e.g. a converted Rust closure.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(|name| &**name)
     }
 
     /// Returns the underlying TVM function handle.
-    pub fn handle(&self) -> ts::TVMFunctionHandle {
+    ///
+    /// # Safety
+    ///
+    /// The handle is owned by the function, or by the runtime for global functions. It
+    /// must not be freed and must not be used after the function is dropped.
+    pub unsafe fn handle(&self) -> ts::TVMFunctionHandle {
         self.handle
     }
 
+    /// Creates a function taking the ownership of a function handle, e.g. one created
+    /// with `TVMFuncCreateFromCFunc` by other code using the TVM C API or returned by
+    /// [`into_raw`].
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be a valid function handle which is not freed by anyone else, since
+    /// the function frees it with `TVMFuncFree` on drop.
+    ///
+    /// [`into_raw`]:struct.Function.html#method.into_raw
+    pub unsafe fn from_raw(handle: ts::TVMFunctionHandle) -> Function {
+        Function::new(handle, false, false)
+    }
+
+    /// Returns the function handle, transferring its ownership to the caller, who must
+    /// free it with `TVMFuncFree` or pass it back to [`from_raw`].
+    ///
+    /// Fails for functions which do not own their handle: global functions looked up
+    /// with `is_global`, whose handles are cached by the crate, and clones.
+    ///
+    /// [`from_raw`]:struct.Function.html#method.from_raw
+    pub fn into_raw(mut self) -> Result<ts::TVMFunctionHandle> {
+        if self.is_global || self.is_cloned || self.is_released {
+            bail!("the function does not own its handle");
+        }
+        // not freed on drop anymore
+        self.is_released = true;
+        Ok(self.handle)
+    }
+
     /// Returns `true` if the underlying TVM function is global and `false` otherwise.
     pub fn is_global(&self) -> bool {
         self.is_global
@@ -123,9 +431,16 @@ impl Clone for Function {
                 is_global: self.is_global,
                 is_released: self.is_released,
                 is_cloned: true,
+                module: self.module.clone(),
+                global: self.global.clone(),
+                name: self.name.clone(),
+                _runtime: self._runtime.clone(),
             }
         } else {
             Function::new(self.handle, self.is_global, self.is_released)
+                .with_module(self.module.clone())
+                .with_global(self.global.clone())
+                .with_name(self.name.clone())
         }
     }
 }
@@ -133,20 +448,30 @@ impl Clone for Function {
 impl Drop for Function {
     fn drop(&mut self) {
         if !self.is_released && !self.is_global && !self.is_cloned {
-            check_call!(ts::TVMFuncFree(self.handle));
+            check_call!(ffi::api().func_free(self.handle));
             self.is_released = true;
         }
     }
 }
 
+// The number of arguments a packed call buffers without allocating.
+const INLINE_ARGS: usize = 8;
+
+type ArgBuf<'a> = SmallVec<[TVMArgValue<'a>; INLINE_ARGS]>;
+
 /// Function builder in order to create and call functions.
 ///
+/// The arguments of calls with up to 8 arguments are buffered and checked against their
+/// [`Signature`] inline, so such calls only allocate to copy string arguments.
+///
 /// *Note:* Currently TVM functions accept *at most* one return value.
+///
+/// [`Signature`]:struct.Signature.html
 #[derive(Debug, Clone, Default)]
 pub struct Builder<'a> {
-    pub func: Option<Function>,
-    pub arg_buf: Option<Box<[TVMArgValue<'a>]>>,
-    pub ret_buf: Option<Box<[TVMRetValue]>>,
+    func: Option<Function>,
+    arg_buf: ArgBuf<'a>,
+    ret_buf: Option<TVMRetValue>,
 }
 
 impl<'a> Builder<'a> {
@@ -157,8 +482,8 @@ impl<'a> Builder<'a> {
     ) -> Self {
         Self {
             func,
-            arg_buf,
-            ret_buf,
+            arg_buf: arg_buf.map_or_else(SmallVec::new, |args| args.into_vec().into()),
+            ret_buf: ret_buf.and_then(|rets| rets.into_vec().into_iter().next()),
         }
     }
 
@@ -167,25 +492,35 @@ impl<'a> Builder<'a> {
         self
     }
 
+    /// Returns the function to call, if any.
+    pub fn function(&self) -> Option<&Function> {
+        self.func.as_ref()
+    }
+
+    /// Takes the function out of the builder, e.g. to call it with other arguments.
+    pub fn take_function(&mut self) -> Option<Function> {
+        self.func.take()
+    }
+
+    /// Returns the arguments pushed so far.
+    pub fn arg_values(&self) -> &[TVMArgValue<'a>] {
+        &self.arg_buf
+    }
+
+    /// Returns the output set with [`set_output`], if any.
+    ///
+    /// [`set_output`]:struct.Builder.html#method.set_output
+    pub fn output(&self) -> Option<&TVMRetValue> {
+        self.ret_buf.as_ref()
+    }
+
     /// Pushes a [`TVMArgValue`] into the function argument buffer.
     pub fn arg<'b, T: ?Sized>(&mut self, arg: &'b T) -> &mut Self
     where
         TVMValue: From<&'b T>,
         TypeCode: From<&'b T>,
     {
-        let tvm_arg = TVMArgValue::from(arg);
-        if self.arg_buf.is_none() {
-            self.arg_buf = Some(Box::new([tvm_arg]));
-        } else {
-            let new_arg_buf = self.arg_buf.take().map(|bbuf| {
-                let mut new_arg_buf = Vec::from(bbuf);
-                new_arg_buf.push(tvm_arg);
-                let new_len = new_arg_buf.len();
-                new_arg_buf.truncate(new_len);
-                new_arg_buf.into_boxed_slice()
-            });
-            self.arg_buf = new_arg_buf;
-        }
+        self.arg_buf.push(TVMArgValue::from(arg));
         self
     }
 
@@ -202,6 +537,16 @@ impl<'a> Builder<'a> {
         self
     }
 
+    /// Pushes the dims of a [`Shape`] as repeated `int64` arguments.
+    ///
+    /// [`Shape`]:../shape/struct.Shape.html
+    pub fn shape_args(&mut self, shape: &Shape) -> &mut Self {
+        for dim in shape.dims() {
+            self.arg(dim);
+        }
+        self
+    }
+
     /// Sets an output for a function that requirs a mutable output to be provided.
     /// See the `basics` in tests for an example.
     pub fn set_output<'b, T: 'b + ?Sized>(&mut self, arg: &'b mut T) -> &mut Self
@@ -209,77 +554,596 @@ impl<'a> Builder<'a> {
         TVMValue: From<&'b T>,
         TypeCode: From<&'b T>,
     {
-        let tvm_ret = TVMRetValue::new(TVMValue::from(arg), TypeCode::from(arg));
-        if self.ret_buf.is_none() {
-            self.ret_buf = Some(Box::new([tvm_ret]));
-        } else {
-            let new_ret_buf = self.ret_buf.take().map(|_| {
-                let mut new_buf = Vec::with_capacity(1);
-                new_buf.push(tvm_ret);
-                new_buf.into_boxed_slice()
-            });
-            self.ret_buf = new_ret_buf;
-        }
+        self.ret_buf = Some(TVMRetValue::new(TVMValue::from(arg), TypeCode::from(arg)));
         self
     }
 
-    /// Calls the function that created from `Builder`.
+    /// Calls the function that created from `Builder`, failing without calling it if the
+    /// arguments do not match its registered [`Signature`].
+    ///
+    /// [`Signature`]:struct.Signature.html
     pub fn invoke(&mut self) -> Result<TVMRetValue> {
-        self.clone()(())
+        self.call()
+    }
+
+    /// Calls the function like [`invoke`] on a watchdog thread, failing with
+    /// `ErrorKind::Timeout` if it does not return within `timeout`, e.g. because of a hung
+    /// kernel or a deadlocked remote call.
+    ///
+    /// A call cannot be interrupted, so on timeout it is abandoned and keeps running in
+    /// the background. The call uses the default runtime API of the watchdog thread,
+    /// not one set with `ffi::set_api` on the calling thread.
+    ///
+    /// # Safety
+    ///
+    /// The arguments are used on the watchdog thread until the call returns, which may be
+    /// after this returns on timeout. The caller must keep the values the arguments borrow,
+    /// such as NDArrays, alive and must not use them from another thread until then, e.g.
+    /// by moving them into an `Arc` held by a thread waiting for the call.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut remote = function::Builder::from(remote_fn);
+    /// remote.arg(&input);
+    /// // `input` lives until the end of the process
+    /// match unsafe { remote.invoke_with_timeout(Duration::from_secs(5)) } {
+    ///     Err(Error(ErrorKind::Timeout(_), _)) => eprintln!("the remote call is stuck"),
+    ///     ret => println!("{:?}", ret),
+    /// }
+    /// ```
+    ///
+    /// [`invoke`]:struct.Builder.html#method.invoke
+    pub unsafe fn invoke_with_timeout(&mut self, timeout: Duration) -> Result<TVMRetValue> {
+        self.invoke_keeping(timeout, ())
+    }
+
+    // Calls the function like `invoke_with_timeout`, dropping `keep` on the watchdog thread
+    // once the call returns, e.g. an `Arc` of the values the arguments borrow so that they
+    // outlive an abandoned call. `keep` must not be used by other threads meanwhile.
+    pub(crate) unsafe fn invoke_keeping<K>(
+        &mut self,
+        timeout: Duration,
+        keep: K,
+    ) -> Result<TVMRetValue> {
+        // the lifetime of the arguments is not tracked past the call, see the safety section
+        let builder = unsafe { mem::transmute::<Builder<'a>, Builder<'static>>(self.clone()) };
+        let detached = Detached((builder, keep));
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("tvm-watchdog".to_owned())
+            .spawn(move || {
+                let Detached((mut builder, keep)) = detached;
+                let ret = match panic::catch_unwind(AssertUnwindSafe(|| builder.invoke())) {
+                    Ok(ret) => ret.map(Detached),
+                    Err(cause) => Err(panic_message(&*cause).into()),
+                };
+                drop(builder);
+                drop(keep);
+                let _ = sender.send(ret);
+            })?;
+        match receiver.recv_timeout(timeout) {
+            Ok(ret) => ret.map(|Detached(ret)| ret),
+            Err(RecvTimeoutError::Timeout) => bail!(ErrorKind::Timeout(timeout)),
+            Err(RecvTimeoutError::Disconnected) => bail!("the watchdog thread stopped"),
+        }
+    }
+
+    // Calls the function like `invoke`, returning the error of a failing call instead of
+    // panicking, e.g. to probe whether an object is of the type the function expects.
+    pub(crate) fn invoke_catching(&mut self) -> Result<TVMRetValue> {
+        match panic::catch_unwind(AssertUnwindSafe(|| self.invoke())) {
+            Ok(ret) => ret,
+            Err(cause) => Err(panic_message(&*cause).into()),
+        }
+    }
+}
+
+/// A packed call reported to the observer set with [`set_call_observer`].
+///
+/// [`set_call_observer`]:fn.set_call_observer.html
+#[derive(Debug, Clone, Copy)]
+pub struct CallRecord<'a> {
+    /// Name of the function, or `None` for functions not looked up by name,
+    /// e.g. the entry function of a module or a converted Rust closure.
+    pub name: Option<&'a str>,
+    /// Number of the arguments, including the return buffer if any.
+    pub num_args: usize,
+    /// Wall time of the call into the runtime.
+    pub duration: Duration,
+    /// Error message of the runtime if the call failed.
+    pub error: Option<&'a str>,
+}
+
+impl<'a> CallRecord<'a> {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+type CallObserver = Arc<Fn(&CallRecord) + Send + Sync>;
+
+// Whether an observer is set, so that unobserved calls are not timed.
+static OBSERVING: AtomicBool = ATOMIC_BOOL_INIT;
+
+lazy_static! {
+    static ref CALL_OBSERVER: RwLock<Option<CallObserver>> = RwLock::new(None);
+}
+
+/// Sets the observer invoked after every packed call made through a [`Builder`] on any
+/// thread, replacing the previous one, e.g. to export metrics of the TVM activity.
+///
+/// The observer runs on the calling thread, so it should be quick. It also sees the
+/// calls made internally by the crate, and a failed call is reported before the
+/// caller panics.
+///
+/// ## Example
+///
+/// ```
+/// function::set_call_observer(|record| {
+///     let name = record.name.unwrap_or("<anonymous>");
+///     histogram.with_label_values(&[name]).observe(record.duration.as_secs() as f64);
+/// });
+/// ```
+pub fn set_call_observer<F: Fn(&CallRecord) + Send + Sync + 'static>(observer: F) {
+    *CALL_OBSERVER.write().unwrap() = Some(Arc::new(observer));
+    OBSERVING.store(true, Ordering::SeqCst);
+}
+
+/// Removes the call observer.
+pub fn clear_call_observer() {
+    OBSERVING.store(false, Ordering::SeqCst);
+    *CALL_OBSERVER.write().unwrap() = None;
+}
+
+// Makes the FFI call of `func`, reporting it to the observer if any, and panics like
+// `check_call!` if it fails.
+fn observe_call<F: FnOnce() -> c_int>(func: &Function, num_args: usize, call: F) {
+    let observer = if OBSERVING.load(Ordering::SeqCst) {
+        CALL_OBSERVER.read().unwrap().clone()
+    } else {
+        None
+    };
+    let observer = match observer {
+        Some(observer) => observer,
+        None => {
+            if call() != 0 {
+                panic!("{}", ::get_last_error());
+            }
+            return;
+        }
+    };
+    let start = Instant::now();
+    let code = call();
+    let duration = start.elapsed();
+    let error = if code != 0 {
+        Some(::get_last_error())
+    } else {
+        None
+    };
+    observer(&CallRecord {
+        name: func.name(),
+        num_args,
+        duration,
+        error: error.as_ref().map(String::as_str),
+    });
+    if let Some(error) = error {
+        panic!("{}", error);
+    }
+}
+
+/// The expected arguments of a packed function, checked by [`Builder::invoke`] before
+/// calling it, see [`register_signature`].
+///
+/// Every argument accepts one of its type codes. The arguments may end with a group
+/// repeated any number of times, e.g. the device pairs of `tvm.graph_runtime.create`.
+///
+/// [`Builder::invoke`]:struct.Builder.html#method.invoke
+/// [`register_signature`]:fn.register_signature.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    args: Vec<Vec<TypeCode>>,
+    repeated: Vec<Vec<TypeCode>>,
+}
+
+impl Signature {
+    pub fn new(args: &[&[TypeCode]]) -> Self {
+        Signature {
+            args: args.iter().map(|codes| codes.to_vec()).collect(),
+            repeated: Vec::new(),
+        }
+    }
+
+    /// Lets the arguments end with any number of groups of the `repeated` arguments.
+    pub fn repeated(mut self, repeated: &[&[TypeCode]]) -> Self {
+        self.repeated = repeated.iter().map(|codes| codes.to_vec()).collect();
+        self
+    }
+
+    /// Checks the type codes of the arguments of a call to the function `name`.
+    pub fn check(&self, name: &str, type_codes: &[TypeCode]) -> Result<()> {
+        let (fixed, group) = (self.args.len(), self.repeated.len());
+        let found = type_codes.len();
+        if found < fixed || (group == 0 && found > fixed) {
+            bail!(ErrorKind::ArgCountMismatch(fixed, found));
+        }
+        if group > 0 && (found - fixed) % group != 0 {
+            let expected = fixed + ((found - fixed) / group + 1) * group;
+            bail!(ErrorKind::ArgCountMismatch(expected, found));
+        }
+        let expected = self.args.iter().chain(self.repeated.iter().cycle());
+        for (i, (type_code, codes)) in type_codes.iter().zip(expected).enumerate() {
+            if !codes.contains(type_code) {
+                bail!(ErrorKind::TypeMismatch(
+                    format!("{} as argument {} of `{}`", codes[0], i, name),
+                    type_code.to_string()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Returns the signatures of the runtime functions the crate calls.
+fn known_signatures() -> HashMap<String, Signature> {
+    let int: &[TypeCode] = &[TypeCode::kDLInt];
+    let string: &[TypeCode] = &[TypeCode::kStr];
+    let module: &[TypeCode] = &[TypeCode::kModuleHandle];
+    let signatures = vec![
+        (
+            "tvm.graph_runtime.create",
+            Signature::new(&[string, module, int, int]).repeated(&[int, int]),
+        ),
+        ("module._LoadFromFile", Signature::new(&[string, string])),
+        ("module._Enabled", Signature::new(&[string])),
+        ("module._GetTypeKey", Signature::new(&[module])),
+        ("module._SaveToFile", Signature::new(&[module, string, string])),
+        ("module._ImportsSize", Signature::new(&[module])),
+        ("codegen._PackImportsToC", Signature::new(&[module, int])),
+        ("_GetDeviceAttr", Signature::new(&[int, int, int])),
+    ];
+    signatures
+        .into_iter()
+        .map(|(name, signature)| (name.to_owned(), signature))
+        .collect()
+}
+
+// Whether the signature checks are turned off with `set_signature_checks`.
+static SKIP_SIGNATURES: AtomicBool = ATOMIC_BOOL_INIT;
+
+lazy_static! {
+    static ref SIGNATURES: RwLock<HashMap<String, Signature>> = RwLock::new(known_signatures());
+}
+
+/// Registers the signature of the function looked up by `name`, replacing the previous
+/// one, so that calls with other arguments fail before crossing the FFI.
+///
+/// The signatures of the runtime functions the crate calls, such as
+/// `tvm.graph_runtime.create` and `module._LoadFromFile`, are registered by default.
+/// Functions of modules are matched by the name they are looked up with too.
+///
+/// ## Example
+///
+/// ```
+/// let int: &[TypeCode] = &[TypeCode::kDLInt];
+/// function::register_signature("runtime.config_threadpool", Signature::new(&[int, int]));
+/// ```
+pub fn register_signature(name: &str, signature: Signature) {
+    SIGNATURES.write().unwrap().insert(name.to_owned(), signature);
+}
+
+/// Returns the registered signature of the function looked up by `name`.
+pub fn signature(name: &str) -> Option<Signature> {
+    SIGNATURES.read().unwrap().get(name).cloned()
+}
+
+/// Turns the signature checks of all calls on or off. They are on by default.
+pub fn set_signature_checks(enabled: bool) {
+    SKIP_SIGNATURES.store(!enabled, Ordering::SeqCst);
+}
+
+// Checks the arguments of a call against the signature of the function, if registered.
+fn check_signature(func: &Function, args: &[TVMArgValue]) -> Result<()> {
+    with_signature(func, |name, signature| {
+        let type_codes = args
+            .iter()
+            .map(|arg| arg.type_code)
+            .collect::<SmallVec<[TypeCode; INLINE_ARGS]>>();
+        signature.check(name, &type_codes)
+    })
+}
+
+// Fails for the unsigned arguments above `i64::MAX`, the only ones passed with the `uint`
+// type code, since the runtime reads integer arguments as `int64_t`.
+fn check_int_args(args: &[TVMArgValue]) -> Result<()> {
+    match args.iter().find(|arg| arg.type_code == TypeCode::kDLUInt) {
+        Some(arg) => bail!(ErrorKind::OutOfRange(
+            (arg.value.to_int() as u64).to_string(),
+            "i64".to_owned()
+        )),
+        None => Ok(()),
+    }
+}
+
+fn check_type_codes(func: &Function, type_codes: &[TypeCode]) -> Result<()> {
+    with_signature(func, |name, signature| signature.check(name, type_codes))
+}
+
+// Runs `check` with the name and the signature of the function if checks are on and a
+// signature is registered, so that unchecked calls do not convert their arguments.
+fn with_signature<F>(func: &Function, check: F) -> Result<()>
+where
+    F: FnOnce(&str, &Signature) -> Result<()>,
+{
+    if SKIP_SIGNATURES.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    let name = match func.name() {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+    match SIGNATURES.read().unwrap().get(name) {
+        Some(signature) => check(name, signature),
+        None => Ok(()),
+    }
+}
+
+// Moves the builder and its return value between the caller and the watchdog thread,
+// which only one of them uses at a time. Only `invoke_keeping` creates it, whose callers
+// guarantee that the borrowed arguments and the kept values are not used concurrently.
+struct Detached<T>(T);
+
+unsafe impl<T> Send for Detached<T> {}
+
+// Returns the message of a panic payload.
+pub(crate) fn panic_message(cause: &(Any + Send)) -> String {
+    match cause.downcast_ref::<&str>() {
+        Some(s) => s.to_string(),
+        None => match cause.downcast_ref::<String>() {
+            Some(s) => s.clone(),
+            None => "Rust callback panicked".to_owned(),
+        },
+    }
+}
+
+impl<'a> Builder<'a> {
+    // Calls the function with the argument and the output buffers borrowed in place.
+    fn call(&self) -> Result<TVMRetValue> {
+        let func = match self.func {
+            Some(ref func) => func,
+            None => bail!("function was not set in `function::Builder`"),
+        };
+        let args = self.arg_values();
+        check_signature(func, args)?;
+        check_int_args(args)?;
+        let call_id = replay::record(func, args);
+        let num_args = args.len() + self.output().map_or(0, |_| 1);
+        let mut values = SmallVec::<[ts::TVMValue; INLINE_ARGS + 1]>::with_capacity(num_args);
+        let mut tcodes = SmallVec::<[c_int; INLINE_ARGS + 1]>::with_capacity(num_args);
+        for arg in args {
+            values.push(arg.value.inner);
+            tcodes.push(arg.type_code as c_int);
+        }
+        if let Some(output) = self.output() {
+            values.push(output.value.inner);
+            tcodes.push(output.type_code as c_int);
+        }
+        let mut ret_val = ts::TVMValue { v_int64: 0 };
+        let mut ret_type_code = 0 as c_int;
+        observe_call(func, num_args, || unsafe {
+            ffi::api().func_call(
+                func.handle,
+                values.as_mut_ptr(),
+                tcodes.as_mut_ptr(),
+                num_args as c_int,
+                &mut ret_val as *mut _,
+                &mut ret_type_code as *mut _,
+            )
+        });
+        let ret = TVMRetValue::from_return(ret_val, ret_type_code.into());
+        replay::record_return(call_id, &ret);
+        Ok(ret)
     }
 }
 
 impl<'a> FnOnce<((),)> for Builder<'a> {
     type Output = Result<TVMRetValue>;
     extern "rust-call" fn call_once(self, _: ((),)) -> Self::Output {
-        if self.func.is_none() {
-            bail!("{}", ErrorKind::FunctionNotFound);
+        self.call()
+    }
+}
+
+/// The maximum number of arguments of a [`PreparedCall`].
+///
+/// [`PreparedCall`]:struct.PreparedCall.html
+pub const MAX_PREPARED_ARGS: usize = 8;
+
+/// An argument of a [`PreparedCall`], converted to a packed value without allocating.
+///
+/// [`PreparedCall`]:struct.PreparedCall.html
+pub trait PreparedArg {
+    fn type_code() -> TypeCode;
+
+    fn raw_value(&self) -> ts::TVMValue;
+}
+
+macro_rules! impl_prepared_int {
+    ($($ty:ty),+) => {
+        $(
+            impl PreparedArg for $ty {
+                fn type_code() -> TypeCode {
+                    TypeCode::kDLInt
+                }
+
+                fn raw_value(&self) -> ts::TVMValue {
+                    ts::TVMValue { v_int64: *self as i64 }
+                }
+            }
+        )+
+    };
+}
+
+impl_prepared_int!(i8, i16, i32, i64, u8, u16, u32, usize, bool);
+
+macro_rules! impl_prepared_float {
+    ($($ty:ty),+) => {
+        $(
+            impl PreparedArg for $ty {
+                fn type_code() -> TypeCode {
+                    TypeCode::kDLFloat
+                }
+
+                fn raw_value(&self) -> ts::TVMValue {
+                    ts::TVMValue { v_float64: *self as f64 }
+                }
+            }
+        )+
+    };
+}
+
+impl_prepared_float!(f32, f64);
+
+/// Arrays are passed as `DLTensor*`, the type code generated kernels expect.
+impl<'a> PreparedArg for &'a NDArray {
+    fn type_code() -> TypeCode {
+        TypeCode::kArrayHandle
+    }
+
+    fn raw_value(&self) -> ts::TVMValue {
+        ts::TVMValue {
+            v_handle: self.handle() as *mut c_void,
         }
-        let mut ret_val = unsafe { mem::uninitialized::<ts::TVMValue>() };
-        let mut ret_type_code = 0 as c_int;
-        if self.arg_buf.is_some() {
-            let arg_buf = self.arg_buf?;
-            let mut num_args = arg_buf.len();
-            let mut values = arg_buf
-                .iter()
-                .map(|tav| tav.value.inner)
-                .collect::<Vec<ts::TVMValue>>();
-            let mut tcodes = arg_buf
-                .iter()
-                .map(|tav| tav.type_code as c_int)
-                .collect::<Vec<_>>();
-            if self.ret_buf.is_some() {
-                num_args = num_args + 1;
-                ret_val = *self.ret_buf.clone()?[0].value;
-                ret_type_code = self.ret_buf.clone()?[0].type_code as c_int;
-                values.append(&mut vec![ret_val]);
-                tcodes.append(&mut vec![ret_type_code]);
+    }
+}
+
+/// The arguments of a [`PreparedCall`], implemented for tuples of up to
+/// [`MAX_PREPARED_ARGS`] [`PreparedArg`]s.
+///
+/// [`PreparedCall`]:struct.PreparedCall.html
+/// [`MAX_PREPARED_ARGS`]:constant.MAX_PREPARED_ARGS.html
+/// [`PreparedArg`]:trait.PreparedArg.html
+pub trait PreparedArgs {
+    /// Writes the type codes of the arguments and returns their number.
+    fn type_codes(codes: &mut [TypeCode; MAX_PREPARED_ARGS]) -> usize;
+
+    fn write_values(&self, values: &mut [ts::TVMValue; MAX_PREPARED_ARGS]);
+}
+
+macro_rules! impl_prepared_args {
+    ($($ty:ident : $index:tt),*) => {
+        impl<$($ty: PreparedArg),*> PreparedArgs for ($($ty,)*) {
+            #[allow(unused_variables)]
+            fn type_codes(codes: &mut [TypeCode; MAX_PREPARED_ARGS]) -> usize {
+                $(
+                    codes[$index] = $ty::type_code();
+                )*
+                <[&str]>::len(&[$(stringify!($ty)),*])
             }
-            values.truncate(num_args);
-            tcodes.truncate(num_args);
-            check_call!(ts::TVMFuncCall(
-                self.func?.handle,
+
+            #[allow(unused_variables)]
+            fn write_values(&self, values: &mut [ts::TVMValue; MAX_PREPARED_ARGS]) {
+                $(
+                    values[$index] = self.$index.raw_value();
+                )*
+            }
+        }
+    };
+}
+
+impl_prepared_args!();
+impl_prepared_args!(A: 0);
+impl_prepared_args!(A: 0, B: 1);
+impl_prepared_args!(A: 0, B: 1, C: 2);
+impl_prepared_args!(A: 0, B: 1, C: 2, D: 3);
+impl_prepared_args!(A: 0, B: 1, C: 2, D: 3, E: 4);
+impl_prepared_args!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+impl_prepared_args!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+impl_prepared_args!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7);
+
+/// A call of a function with a fixed signature, see [`Function::prepare`].
+///
+/// The type codes are computed once and the values are written into buffers owned by
+/// the call, so calling it does not allocate. Unlike [`Builder::invoke`], prepared calls
+/// are not recorded by [`replay`], and their signature is checked once when prepared.
+///
+/// [`Function::prepare`]:struct.Function.html#method.prepare
+/// [`Builder::invoke`]:struct.Builder.html#method.invoke
+/// [`replay`]:../replay/index.html
+pub struct PreparedCall<Args> {
+    func: Function,
+    num_args: usize,
+    values: [ts::TVMValue; MAX_PREPARED_ARGS],
+    type_codes: [c_int; MAX_PREPARED_ARGS],
+    _args: PhantomData<fn(Args)>,
+}
+
+impl<Args> fmt::Debug for PreparedCall<Args> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("PreparedCall")
+            .field("func", &self.func)
+            .field("type_codes", &&self.type_codes[..self.num_args])
+            .finish()
+    }
+}
+
+impl Function {
+    /// Prepares repeated calls of the function with the arguments `Args`, a tuple of
+    /// scalars and arrays, e.g. to call a small kernel in a loop without allocating.
+    ///
+    /// Fails if the arguments do not match the registered [`Signature`] of the function.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut add = fadd.prepare::<(&NDArray, &NDArray, &NDArray)>().unwrap();
+    /// for (a, b, c) in batches {
+    ///     add.call((&a, &b, &c)).unwrap();
+    /// }
+    /// ```
+    ///
+    /// [`Signature`]:struct.Signature.html
+    pub fn prepare<Args: PreparedArgs>(&self) -> Result<PreparedCall<Args>> {
+        let mut codes = [TypeCode::kNull; MAX_PREPARED_ARGS];
+        let num_args = Args::type_codes(&mut codes);
+        check_type_codes(self, &codes[..num_args])?;
+        let mut type_codes = [0 as c_int; MAX_PREPARED_ARGS];
+        for (type_code, &code) in type_codes.iter_mut().zip(&codes[..num_args]) {
+            *type_code = code as c_int;
+        }
+        Ok(PreparedCall {
+            func: self.clone(),
+            num_args,
+            values: [ts::TVMValue { v_int64: 0 }; MAX_PREPARED_ARGS],
+            type_codes,
+            _args: PhantomData,
+        })
+    }
+}
+
+impl<Args: PreparedArgs> PreparedCall<Args> {
+    /// Calls the function with the arguments.
+    pub fn call(&mut self, args: Args) -> Result<TVMRetValue> {
+        args.write_values(&mut self.values);
+        let mut ret_val = ts::TVMValue { v_int64: 0 };
+        let mut ret_type_code = 0 as c_int;
+        let (func, num_args) = (&self.func, self.num_args);
+        let (values, type_codes) = (&mut self.values, &mut self.type_codes);
+        observe_call(func, num_args, || unsafe {
+            ffi::api().func_call(
+                func.handle,
                 values.as_mut_ptr(),
-                tcodes.as_mut_ptr(),
+                type_codes.as_mut_ptr(),
                 num_args as c_int,
                 &mut ret_val as *mut _,
-                &mut ret_type_code as *mut _
-            ));
-        } else {
-            check_call!(ts::TVMFuncCall(
-                self.func?.handle,
-                ptr::null_mut(),
-                ptr::null_mut(),
-                0 as c_int,
-                &mut ret_val as *mut _,
-                &mut ret_type_code as *mut _
-            ));
-        }
-        let ret = TVMRetValue::new(
-            TVMValue::new(ValueKind::Return, ret_val),
-            ret_type_code.into(),
-        );
-        Ok(ret)
+                &mut ret_type_code as *mut _,
+            )
+        });
+        Ok(TVMRetValue::from_return(ret_val, ret_type_code.into()))
+    }
+
+    /// Returns the prepared function.
+    pub fn function(&self) -> &Function {
+        &self.func
     }
 }
 
@@ -291,13 +1155,23 @@ impl<'a> From<Function> for Builder<'a> {
     }
 }
 
-/// Converts a mutable reference of a [`Module`] to [`Builder`].
+/// Converts a reference of a [`Module`] to [`Builder`] calling its entry function.
+impl<'a: 'b, 'b> From<&'b Module> for Builder<'a> {
+    fn from(module: &Module) -> Self {
+        Builder::new(module.entry().ok(), None, None)
+    }
+}
+
+/// Converts a mutable reference of a [`Module`] to [`Builder`] calling its entry function.
 impl<'a: 'b, 'b> From<&'b mut Module> for Builder<'a> {
     fn from(module: &mut Module) -> Self {
-        Builder::new(module.entry.take(), None, None)
+        Builder::new(module.entry().ok(), None, None)
     }
 }
 
+// Type erased Rust function registered as a TVM packed function.
+type BoxedFn = Box<Fn(&[TVMArgValue]) -> Result<TVMRetValue> + Send + Sync>;
+
 unsafe extern "C" fn tvm_callback(
     args: *mut ts::TVMValue,
     type_codes: *mut c_int,
@@ -306,55 +1180,56 @@ unsafe extern "C" fn tvm_callback(
     fhandle: *mut c_void,
 ) -> c_int {
     let len = num_args as usize;
-    let args_list = slice::from_raw_parts_mut(args, len);
-    let type_codes_list = slice::from_raw_parts_mut(type_codes, len);
-    let mut local_args: Vec<TVMArgValue> = Vec::new();
-    // due to unsafe mem::uninitialized rustc warning about unused `value` and `tcode`.
-    let mut _value = mem::uninitialized::<ts::TVMValue>();
-    let mut _tcode = mem::uninitialized::<c_int>();
-    let rust_fn = mem::transmute::<*mut c_void, fn(&[TVMArgValue]) -> Result<TVMRetValue>>(fhandle);
-    for i in 0..len {
-        _value = args_list[i];
-        _tcode = type_codes_list[i];
-        if _tcode == TypeCode::kNodeHandle as c_int
-            || _tcode == TypeCode::kFuncHandle as c_int
-            || _tcode == TypeCode::kModuleHandle as c_int
+    let args_list = slice::from_raw_parts(args, len);
+    let type_codes_list = slice::from_raw_parts(type_codes, len);
+    let rust_fn = &*(fhandle as *mut BoxedFn);
+    // `TVMArgValue` does not share the layout of `ts::TVMValue`, so the arguments are
+    // converted into an inline buffer, which only allocates for more than 8 arguments.
+    let mut local_args = ArgBuf::with_capacity(len);
+    for (&value, &tcode) in args_list.iter().zip(type_codes_list) {
+        let mut value = value;
+        if tcode == TypeCode::kNodeHandle as c_int
+            || tcode == TypeCode::kFuncHandle as c_int
+            || tcode == TypeCode::kModuleHandle as c_int
         {
-            check_call!(ts::TVMCbArgToReturn(&mut _value as *mut _, _tcode));
+            // a failing call already set the last error, which is reported to the caller.
+            if ffi::api().cb_arg_to_return(&mut value as *mut _, tcode) != 0 {
+                return -1;
+            }
         }
-        local_args.push(TVMArgValue::new(
-            TVMValue::new(ValueKind::Handle, _value),
-            _tcode.into(),
-        ));
+        local_args.push(TVMArgValue::new(TVMValue::new(ValueKind::Handle, value), tcode.into()));
     }
 
-    let rv = match rust_fn(local_args.as_slice()) {
-        Ok(v) => v,
-        Err(msg) => {
+    // panics must not unwind into the runtime, so they are reported as errors.
+    let rv = match panic::catch_unwind(AssertUnwindSafe(|| rust_fn(&local_args))) {
+        Ok(Ok(v)) => v,
+        Ok(Err(msg)) => {
             ::set_last_error(&msg);
             return -1;
         }
+        Err(cause) => {
+            ::set_last_error(&panic_message(&*cause));
+            return -1;
+        }
     };
     let mut ret_val = *rv.value;
     let mut ret_type_code = rv.type_code as c_int;
-    check_call!(ts::TVMCFuncSetReturn(
+    ffi::api().cfunc_set_return(
         ret,
         &mut ret_val as *mut _,
         &mut ret_type_code as *mut _,
-        1 as c_int
-    ));
-    0
+        1 as c_int,
+    )
 }
 
 unsafe extern "C" fn tvm_callback_finalizer(fhandle: *mut c_void) {
-    let rust_fn = mem::transmute::<*mut c_void, fn(&[TVMArgValue]) -> Result<TVMRetValue>>(fhandle);
-    mem::drop(rust_fn);
+    mem::drop(Box::from_raw(fhandle as *mut BoxedFn));
 }
 
-fn convert_to_tvm_func(f: fn(&[TVMArgValue]) -> Result<TVMRetValue>) -> Function {
+fn convert_to_tvm_func(f: BoxedFn) -> Function {
     let mut fhandle = ptr::null_mut() as ts::TVMFunctionHandle;
-    let resource_handle = f as *mut fn(&[TVMArgValue]) -> Result<TVMRetValue>;
-    check_call!(ts::TVMFuncCreateFromCFunc(
+    let resource_handle = Box::into_raw(Box::new(f));
+    check_call!(ffi::api().func_create_from_cfunc(
         Some(tvm_callback),
         resource_handle as *mut c_void,
         Some(tvm_callback_finalizer),
@@ -363,6 +1238,58 @@ fn convert_to_tvm_func(f: fn(&[TVMArgValue]) -> Result<TVMRetValue>) -> Function
     Function::new(fhandle, false, false)
 }
 
+// Checks that a function can be registered under the name before creating it.
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        bail!(ErrorKind::InvalidFunctionName(name.to_owned(), "the name is empty".to_owned()));
+    }
+    if name.contains('\0') {
+        bail!(ErrorKind::InvalidFunctionName(
+            name.to_owned(),
+            "the name contains a NUL byte".to_owned()
+        ));
+    }
+    Ok(())
+}
+
+// Returns whether a global function is registered under the name.
+fn is_registered(name: &str) -> bool {
+    match lookup_global(name) {
+        Some(handle) => {
+            check_call!(ffi::api().func_free(handle));
+            true
+        }
+        None => false,
+    }
+}
+
+fn register_boxed(f: BoxedFn, name: String, override_: bool) -> Result<()> {
+    validate_name(&name)?;
+    ffi::try_api()?;
+    // without override, the registration itself fails if the name is taken
+    let replaced = override_ && is_registered(&name);
+    let c_name = CString::new(name.as_str())?;
+    let func = convert_to_tvm_func(f);
+    let ret = unsafe {
+        ffi::api().func_register_global(
+            c_name.as_ptr() as *const c_char,
+            func.handle,
+            override_ as c_int,
+        )
+    };
+    if ret != 0 {
+        if override_ {
+            bail!(::get_last_error());
+        }
+        bail!(ErrorKind::FunctionAlreadyRegistered(name));
+    }
+    // the runtime copies the function, so `func` is freed here
+    if replaced {
+        evict_global_handle(&name);
+    }
+    Ok(())
+}
+
 /// Registers a Rust function with signature
 /// `fn(&[TVMArgValue]) -> Result<TVMRetValue>`
 /// as a **global TVM packed function** from frontend to TVM backend.
@@ -370,6 +1297,10 @@ fn convert_to_tvm_func(f: fn(&[TVMArgValue]) -> Result<TVMRetValue>) -> Function
 /// Use [`register_global_func`] if overriding an existing global TVM function
 /// is not required.
 ///
+/// Fails with `ErrorKind::InvalidFunctionName` for an empty name or one containing a
+/// NUL byte, and with `ErrorKind::FunctionAlreadyRegistered` if a function is already
+/// registered under the name and `override_` is not set.
+///
 /// ## Example
 ///
 /// ```
@@ -385,7 +1316,7 @@ fn convert_to_tvm_func(f: fn(&[TVMArgValue]) -> Result<TVMRetValue>) -> Function
 /// tvm::function::register(sum, "mysum".to_owned(), false).unwrap();
 /// let mut registered = function::Builder::default();
 /// registered.get_function("mysum", true);
-/// assert!(registered.func.is_some());
+/// assert!(registered.function().is_some());
 /// registered.args(&[10, 20, 30]);
 /// assert_eq!(registered.invoke().unwrap().to_int(), 60);
 /// ```
@@ -394,20 +1325,279 @@ pub fn register(
     name: String,
     override_: bool,
 ) -> Result<()> {
-    let func = convert_to_tvm_func(f);
-    let name = CString::new(name)?;
-    check_call!(ts::TVMFuncRegisterGlobal(
-        name.as_ptr() as *const c_char,
-        func.handle(),
-        override_ as c_int
-    ));
-    mem::forget(name);
+    register_boxed(Box::new(f), name, override_)
+}
+
+/// Registers a Rust function like [`register`], replacing the global function
+/// registered under the name if any.
+///
+/// [`register`]:fn.register.html
+pub fn register_override(
+    f: fn(&[TVMArgValue]) -> Result<TVMRetValue>,
+    name: String,
+) -> Result<()> {
+    register_boxed(Box::new(f), name, true)
+}
+
+/// Conversion of a Rust function or closure with typed arguments into a
+/// type erased packed function. Implemented for functions of up to eight
+/// arguments implementing [`FromArgValue`], or references to them such as
+/// `&NDArray`, and returning `Result<R>` where `R` implements [`IntoRetValue`].
+///
+/// [`FromArgValue`]:../value/trait.FromArgValue.html
+/// [`IntoRetValue`]:../value/trait.IntoRetValue.html
+pub trait ToBoxedFn<Args> {
+    fn to_boxed_fn(self) -> Box<Fn(&[TVMArgValue]) -> Result<TVMRetValue> + Send + Sync>;
+}
+
+/// Marks an argument of a [`ToBoxedFn`] taken by reference, e.g. `Ref<NDArray>` for a
+/// closure taking a `&NDArray`. The argument is extracted for the duration of the call.
+///
+/// [`ToBoxedFn`]:trait.ToBoxedFn.html
+pub struct Ref<T>(PhantomData<T>);
+
+// Implements `ToBoxedFn` for every combination of arguments taken by value or by
+// reference, accumulating the type parameters, the `Args` markers, the parameters of the
+// closure and the expressions passing the extracted arguments.
+macro_rules! impl_to_boxed_fn {
+    ([$($ty:ident)*] [$($marker:ty,)*] [$($param:ty,)*] [$($arg:ident => $pass:expr,)*]) => {
+        impl<F, R, $($ty),*> ToBoxedFn<($($marker,)*)> for F
+        where
+            F: Fn($($param),*) -> Result<R> + Send + Sync + 'static,
+            R: IntoRetValue,
+            $($ty: FromArgValue,)*
+        {
+            #[allow(unused_mut, unused_variables)]
+            fn to_boxed_fn(self) -> BoxedFn {
+                Box::new(move |args: &[TVMArgValue]| {
+                    let expected = <[&str]>::len(&[$(stringify!($ty)),*]);
+                    if args.len() != expected {
+                        bail!(ErrorKind::ArgCountMismatch(expected, args.len()));
+                    }
+                    let mut args = args.iter();
+                    $(
+                        let $arg = $ty::from_arg_value(args.next().expect("length is checked"))?;
+                    )*
+                    (self)($($pass),*).map(IntoRetValue::into_ret_value)
+                })
+            }
+        }
+    };
+    (
+        [$($ty:ident)*] [$($marker:ty,)*] [$($param:ty,)*] [$($arg:ident => $pass:expr,)*]
+        $next:ident : $next_ty:ident $(, $rest:ident : $rest_ty:ident)*
+    ) => {
+        impl_to_boxed_fn!(
+            [$($ty)* $next_ty] [$($marker,)* $next_ty,] [$($param,)* $next_ty,]
+            [$($arg => $pass,)* $next => $next,] $($rest : $rest_ty),*
+        );
+        impl_to_boxed_fn!(
+            [$($ty)* $next_ty] [$($marker,)* Ref<$next_ty>,] [$($param,)* &$next_ty,]
+            [$($arg => $pass,)* $next => &$next,] $($rest : $rest_ty),*
+        );
+    };
+    ($($arg:ident : $ty:ident),*) => {
+        impl_to_boxed_fn!([] [] [] [] $($arg : $ty),*);
+    };
+}
+
+impl_to_boxed_fn!();
+impl_to_boxed_fn!(a: A);
+impl_to_boxed_fn!(a: A, b: B);
+impl_to_boxed_fn!(a: A, b: B, c: C);
+impl_to_boxed_fn!(a: A, b: B, c: C, d: D);
+impl_to_boxed_fn!(a: A, b: B, c: C, d: D, e: E);
+impl_to_boxed_fn!(a: A, b: B, c: C, d: D, e: E, f: G);
+impl_to_boxed_fn!(a: A, b: B, c: C, d: D, e: E, f: G, g: H);
+impl_to_boxed_fn!(a: A, b: B, c: C, d: D, e: E, f: G, g: H, h: I);
+
+/// Registers a Rust function or closure with typed arguments as a **global TVM packed
+/// function** without overriding. The arguments are extracted and the return value is
+/// converted automatically, and an error is returned to the caller on argument
+/// count or type mismatch.
+///
+/// ## Example
+///
+/// ```
+/// function::register_typed("scale", |x: f64, factor: i64| Ok(x * factor as f64)).unwrap();
+/// let mut registered = function::Builder::default();
+/// registered.get_function("scale", true).arg(&2.5f64).arg(&4);
+/// assert_eq!(registered.invoke().unwrap().to_float(), 10f64);
+/// ```
+pub fn register_typed<F, Args>(name: &str, f: F) -> Result<()>
+where
+    F: ToBoxedFn<Args>,
+{
+    register_boxed(f.to_boxed_fn(), name.to_owned(), false)
+}
+
+/// A named and typed argument of a [`FunctionDesc`].
+///
+/// [`FunctionDesc`]:struct.FunctionDesc.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArgDesc {
+    pub name: String,
+    pub type_code: TypeCode,
+}
+
+/// The documentation of a registered Rust function: its arguments, its return type and
+/// a doc string, queried with [`describe`].
+///
+/// ## Example
+///
+/// ```
+/// let desc = FunctionDesc::new("Adds two integers.")
+///     .arg("a", TypeCode::kDLInt)
+///     .arg("b", TypeCode::kDLInt)
+///     .returns(TypeCode::kDLInt);
+/// function::register_described("mysum", desc, |a: i64, b: i64| Ok(a + b)).unwrap();
+/// println!("{}", function::describe("mysum").unwrap());
+/// ```
+///
+/// [`describe`]:fn.describe.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionDesc {
+    pub name: String,
+    pub doc: String,
+    pub args: Vec<ArgDesc>,
+    pub ret: Option<TypeCode>,
+}
+
+impl FunctionDesc {
+    /// Describes a function without arguments nor return value, named when registered.
+    pub fn new(doc: &str) -> Self {
+        FunctionDesc {
+            name: String::new(),
+            doc: doc.to_owned(),
+            args: Vec::new(),
+            ret: None,
+        }
+    }
+
+    pub fn arg(mut self, name: &str, type_code: TypeCode) -> Self {
+        self.args.push(ArgDesc {
+            name: name.to_owned(),
+            type_code,
+        });
+        self
+    }
+
+    pub fn returns(mut self, type_code: TypeCode) -> Self {
+        self.ret = Some(type_code);
+        self
+    }
+
+    /// Returns the description as json, with the type names of the type codes, as
+    /// returned to remote callers by the [`DESCRIBE_FUNCTION`].
+    ///
+    /// [`DESCRIBE_FUNCTION`]:constant.DESCRIBE_FUNCTION.html
+    pub fn to_json(&self) -> Value {
+        let args = self
+            .args
+            .iter()
+            .map(|arg| json!({"name": arg.name, "type": arg.type_code.to_string()}))
+            .collect::<Vec<_>>();
+        json!({
+            "name": self.name,
+            "doc": self.doc,
+            "args": args,
+            "ret": self.ret.map(|ret| ret.to_string()),
+        })
+    }
+}
+
+/// Prints the description like a Rust signature followed by the doc string, e.g.
+/// `mysum(a: int, b: int) -> int`.
+impl Display for FunctionDesc {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}(", self.name)?;
+        for (i, arg) in self.args.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {}", arg.name, arg.type_code)?;
+        }
+        write!(f, ")")?;
+        if let Some(ret) = self.ret {
+            write!(f, " -> {}", ret)?;
+        }
+        if !self.doc.is_empty() {
+            write!(f, "\n{}", self.doc)?;
+        }
+        Ok(())
+    }
+}
+
+lazy_static! {
+    static ref DESCRIPTIONS: RwLock<BTreeMap<String, FunctionDesc>> =
+        RwLock::new(BTreeMap::new());
+}
+
+/// The global function registered by [`expose_descriptions`] which returns the
+/// description of a function as a json string, or an empty string if not described.
+///
+/// [`expose_descriptions`]:fn.expose_descriptions.html
+pub const DESCRIBE_FUNCTION: &'static str = "rust.describe";
+
+/// The global function registered by [`expose_descriptions`] which returns the names of
+/// the described functions as a json array.
+///
+/// [`expose_descriptions`]:fn.expose_descriptions.html
+pub const LIST_DESCRIBED_FUNCTION: &'static str = "rust.list_described";
+
+/// Registers a Rust function like [`register_typed`] and stores its description,
+/// queried with [`describe`].
+///
+/// [`register_typed`]:fn.register_typed.html
+/// [`describe`]:fn.describe.html
+pub fn register_described<F, Args>(name: &str, desc: FunctionDesc, f: F) -> Result<()>
+where
+    F: ToBoxedFn<Args>,
+{
+    register_typed(name, f)?;
+    set_description(name, desc);
     Ok(())
 }
 
+/// Stores the description of the function `name`, e.g. of one registered with
+/// [`register`], replacing the previous one.
+///
+/// [`register`]:fn.register.html
+pub fn set_description(name: &str, mut desc: FunctionDesc) {
+    desc.name = name.to_owned();
+    DESCRIPTIONS.write().unwrap().insert(name.to_owned(), desc);
+}
+
+/// Returns the description of a registered Rust function.
+pub fn describe(name: &str) -> Option<FunctionDesc> {
+    DESCRIPTIONS.read().unwrap().get(name).cloned()
+}
+
+/// Returns the names of the described functions in alphabetical order.
+pub fn described_functions() -> Vec<String> {
+    DESCRIPTIONS.read().unwrap().keys().cloned().collect()
+}
+
+/// Registers the [`DESCRIBE_FUNCTION`] and the [`LIST_DESCRIBED_FUNCTION`], e.g. so that
+/// the clients of an RPC server running in this process can discover the functions it
+/// provides.
+///
+/// [`DESCRIBE_FUNCTION`]:constant.DESCRIBE_FUNCTION.html
+/// [`LIST_DESCRIBED_FUNCTION`]:constant.LIST_DESCRIBED_FUNCTION.html
+pub fn expose_descriptions() -> Result<()> {
+    fn describe_json(name: String) -> Result<String> {
+        Ok(describe(&name).map_or_else(String::new, |desc| desc.to_json().to_string()))
+    }
+    fn list_described_json() -> Result<String> {
+        Ok(json!(described_functions()).to_string())
+    }
+    register_boxed(describe_json.to_boxed_fn(), DESCRIBE_FUNCTION.to_owned(), true)?;
+    register_boxed(list_described_json.to_boxed_fn(), LIST_DESCRIBED_FUNCTION.to_owned(), true)
+}
+
 /// Convenient macro for registering functions from frontend to backend as global
 /// TVM packed functions without overriding. If overriding an existing function is needed
-/// use the [`function::register`] function instead.
+/// use the [`function::register_override`] function instead.
 ///
 /// ## Example
 ///
@@ -425,7 +1615,7 @@ pub fn register(
 ///
 /// let mut registered = function::Builder::default();
 /// registered.get_function("sum", true);
-/// assert!(registered.func.is_some());
+/// assert!(registered.function().is_some());
 /// registered.args(&[10f64, 20f64, 30f64]);
 /// assert_eq!(registered.invoke().unwrap().to_float(), 60f64);
 /// ```
@@ -483,16 +1673,189 @@ macro_rules! call_packed {
 mod tests {
     use super::*;
 
+    use empty;
+    use serde_json;
+    use std::sync::Mutex;
+    use DataType;
+    use Error;
+    use TVMContext;
+
+    #[test]
+    fn unsigned_args() {
+        let fits = [TVMArgValue::from(&3usize), TVMArgValue::from(&(1u64 << 40))];
+        assert!(check_int_args(&fits).is_ok());
+        match check_int_args(&[TVMArgValue::from(&u64::max_value())]) {
+            Err(Error(ErrorKind::OutOfRange(value, _), _)) => {
+                assert_eq!(value, u64::max_value().to_string())
+            }
+            other => panic!("expected an out of range error, but found {:?}", other),
+        }
+    }
+
     #[test]
     fn list_global_func() {
-        assert!(
-            GLOBAL_FUNCTION_NAMES
-                .lock()
-                .unwrap()
-                .iter()
-                .find(|ref s| ***s == "tvm.graph_runtime.create")
-                .is_some()
+        let names = list_global_names().unwrap();
+        assert!(names.iter().any(|name| name == "tvm.graph_runtime.create"));
+    }
+
+    #[test]
+    fn invoke_with_timeout() {
+        register_typed("test_sleep", |ms: i64| {
+            thread::sleep(Duration::from_millis(ms as u64));
+            Ok(ms)
+        }).unwrap();
+        let func = get_global_func("test_sleep", true).unwrap();
+        let mut quick = Builder::from(func.clone());
+        quick.arg(&1i64);
+        let ret = unsafe { quick.invoke_with_timeout(Duration::from_secs(5)) }.unwrap();
+        assert_eq!(ret.to_int(), 1);
+        let mut slow = Builder::from(func);
+        slow.arg(&500i64);
+        // the arguments are integers, which the builder holds by value
+        match unsafe { slow.invoke_with_timeout(Duration::from_millis(10)) } {
+            Err(Error(ErrorKind::Timeout(timeout), _)) => {
+                assert_eq!(timeout, Duration::from_millis(10))
+            }
+            _ => panic!("expected a timeout"),
+        }
+    }
+
+    #[test]
+    fn call_observer() {
+        lazy_static! {
+            static ref RECORDS: Mutex<Vec<(String, usize, bool)>> = Mutex::new(Vec::new());
+        }
+        register_typed("test_observed", |x: i64| Ok(x + 1)).unwrap();
+        set_call_observer(|record| {
+            if record.name == Some("test_observed") {
+                let record = (record.name.unwrap().to_owned(), record.num_args, record.is_ok());
+                RECORDS.lock().unwrap().push(record);
+            }
+        });
+        let func = get_global_func("test_observed", true).unwrap();
+        let ret = call_packed!(func, &41i64).unwrap();
+        clear_call_observer();
+        assert_eq!(ret.to_int(), 42);
+        let func = get_global_func("test_observed", true).unwrap();
+        call_packed!(func, &41i64).unwrap();
+        let records = RECORDS.lock().unwrap();
+        assert_eq!(*records, vec![("test_observed".to_owned(), 1, true)]);
+    }
+
+    #[test]
+    fn global_handles() {
+        let name = "tvm.graph_runtime.create";
+        let first = get_global_func(name, true).unwrap();
+        let second = get_global_func(name, true).unwrap();
+        assert_eq!(first.handle, second.handle);
+        assert_eq!(first.name(), Some(name));
+        let owned = get_global_func(name, false).unwrap();
+        assert_ne!(owned.handle, first.handle);
+        register_typed("test_replaced", |x: i64| Ok(x)).unwrap();
+        assert!(Function::get_function("test_replaced", true).is_some());
+        let replaced = get_global_func("test_replaced", true).unwrap().handle;
+        fn zero(_args: &[TVMArgValue]) -> Result<TVMRetValue> {
+            Ok(TVMRetValue::from(&0i64))
+        }
+        match register(zero, "test_replaced".to_owned(), false) {
+            Err(Error(ErrorKind::FunctionAlreadyRegistered(_), _)) => (),
+            ret => panic!("expected the name to be taken, found {:?}", ret),
+        }
+        register_override(zero, "test_replaced".to_owned()).unwrap();
+        let func = get_global_func("test_replaced", true).unwrap();
+        assert_ne!(func.handle, replaced);
+        assert_eq!(call_packed!(func, &1i64).unwrap().to_int(), 0);
+    }
+
+    #[test]
+    fn raw_parts() {
+        let name = "tvm.graph_runtime.create";
+        assert!(get_global_func(name, true).unwrap().into_raw().is_err());
+        let handle = get_global_func(name, false).unwrap().into_raw().unwrap();
+        let func = unsafe { Function::from_raw(handle) };
+        assert_eq!(unsafe { func.handle() }, handle);
+    }
+
+    #[test]
+    fn invalid_names() {
+        let f = |x: i64| Ok(x);
+        for name in &["", "test\0invalid"] {
+            match register_typed(name, f) {
+                Err(Error(ErrorKind::InvalidFunctionName(..), _)) => (),
+                ret => panic!("expected an invalid name, found {:?}", ret),
+            }
+        }
+    }
+
+    #[test]
+    fn namespaces() {
+        let names = ["tvm.graph_runtime.create", "tvm.contrib.sort", "tvm.graph", "_Version"];
+        let root = Namespace::from_names(names.iter());
+        assert_eq!(root.len(), 4);
+        assert_eq!(root.functions(), &["_Version".to_owned()]);
+        let tvm = root.get("tvm").unwrap();
+        assert_eq!(tvm.functions(), &["tvm.graph".to_owned()]);
+        assert_eq!(
+            tvm.namespaces().map(Namespace::path).collect::<Vec<_>>(),
+            vec!["tvm.contrib", "tvm.graph_runtime"]
+        );
+        assert_eq!(root.get("tvm.graph_runtime").unwrap().len(), 1);
+        assert!(root.get("tvm.missing").is_none());
+        assert_eq!(root.search("GRAPH"), vec!["tvm.graph", "tvm.graph_runtime.create"]);
+        assert_eq!(
+            root.to_string(),
+            "_Version\ntvm.\n  graph\n  contrib.\n    sort\n  graph_runtime.\n    create\n"
         );
+        assert!(!registry().unwrap().search("graph_runtime.create").is_empty());
+    }
+
+    #[test]
+    fn signatures() {
+        let int: &[TypeCode] = &[TypeCode::kDLInt];
+        let create = signature("tvm.graph_runtime.create").unwrap();
+        let codes = [TypeCode::kStr, TypeCode::kModuleHandle, int[0], int[0]];
+        assert!(create.check("create", &codes).is_ok());
+        assert!(create.check("create", &[&codes[..], &[int[0], int[0]]].concat()).is_ok());
+        match create.check("create", &[&codes[..], int].concat()) {
+            Err(Error(ErrorKind::ArgCountMismatch(expected, found), _)) => {
+                assert_eq!((expected, found), (6, 5))
+            }
+            ret => panic!("expected an argument count mismatch, found {:?}", ret),
+        }
+        assert!(create.check("create", &[TypeCode::kStr; 4]).is_err());
+
+        let load = get_global_func("module._LoadFromFile", true).unwrap();
+        match call_packed!(load, "missing.so") {
+            Err(Error(ErrorKind::ArgCountMismatch(2, 1), _)) => (),
+            ret => panic!("expected an argument count mismatch, found {:?}", ret),
+        }
+        register_typed("test_signature", |x: i64| Ok(x)).unwrap();
+        register_signature("test_signature", Signature::new(&[int]));
+        let func = get_global_func("test_signature", true).unwrap();
+        assert!(call_packed!(func, "one").is_err());
+        let func = get_global_func("test_signature", true).unwrap();
+        assert_eq!(call_packed!(func, &1i64).unwrap().to_int(), 1);
+    }
+
+    #[test]
+    fn descriptions() {
+        let desc = FunctionDesc::new("Adds two integers.")
+            .arg("a", TypeCode::kDLInt)
+            .arg("b", TypeCode::kDLInt)
+            .returns(TypeCode::kDLInt);
+        register_described("test_described", desc, |a: i64, b: i64| Ok(a + b)).unwrap();
+        let desc = describe("test_described").unwrap();
+        assert_eq!(desc.name, "test_described");
+        assert_eq!(desc.args[1].name, "b");
+        assert!(described_functions().contains(&"test_described".to_owned()));
+        assert!(describe("test_undescribed").is_none());
+
+        expose_descriptions().unwrap();
+        let describe_fn = get_global_func(DESCRIBE_FUNCTION, true).unwrap();
+        let json = call_packed!(describe_fn, "test_described").unwrap().to_string();
+        let value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value, desc.to_json());
+        assert_eq!(value["args"][0]["name"], "a");
     }
 
     #[test]
@@ -507,7 +1870,45 @@ mod tests {
         func.get_function("tvm.graph_runtime.remote_create", true)
             .args(&[10, 20])
             .arg(&"test".to_owned());
-        assert!(func.arg_buf.is_some());
-        assert_eq!(func.arg_buf.take().map(|bv| Vec::from(bv).len()), Some(3));
+        assert_eq!(func.arg_values().len(), 3);
+        assert!(func.output().is_none());
+        assert!(func.take_function().is_some());
+        assert!(func.function().is_none());
+    }
+
+    #[test]
+    fn spilled_args() {
+        let dims: Vec<i64> = (0..INLINE_ARGS as i64 + 2).collect();
+        let mut func = Builder::default();
+        func.args(&dims);
+        assert!(func.arg_buf.spilled());
+        assert_eq!(func.arg_values().len(), dims.len());
+        assert_eq!(func.arg_values()[INLINE_ARGS + 1].to_int(), INLINE_ARGS as i64 + 1);
+    }
+
+    #[test]
+    fn typed_fn() {
+        let f = (|a: i64, b: f64| Ok(a as f64 + b)).to_boxed_fn();
+        let args = [TVMArgValue::from(&1), TVMArgValue::from(&2.5f64)];
+        assert_eq!(f(&args[..]).unwrap().to_float(), 3.5);
+        assert!(f(&args[..1]).is_err());
+        assert!(f(&[TVMArgValue::from(&1), TVMArgValue::from(&2)][..]).is_err());
+
+        let f = (|n: i64, arr: &NDArray| Ok(n * arr.shape()[0])).to_boxed_fn();
+        let arr = empty(&mut [3], TVMContext::cpu(0), DataType::from("float"));
+        let args = [TVMArgValue::from(&2), TVMArgValue::from(&arr)];
+        assert_eq!(f(&args[..]).unwrap().to_int(), 6);
+    }
+
+    #[test]
+    fn prepared_call() {
+        register_typed("test_prepared", |a: i64, b: f64| Ok(a as f64 * b)).unwrap();
+        let func = Function::get_function("test_prepared", true).unwrap();
+        let mut call = func.prepare::<(i64, f64)>().unwrap();
+        for i in 0..3 {
+            assert_eq!(call.call((i, 0.5)).unwrap().to_float(), i as f64 * 0.5);
+        }
+        register_signature("test_prepared", Signature::new(&[&[TypeCode::kDLInt]]));
+        assert!(func.prepare::<(f64,)>().is_err());
     }
 }
@@ -0,0 +1,635 @@
+//! Provides [`GraphJson`], the typed graph json of a model compiled with TVM.
+//!
+//! Parsing the graph does not need the runtime, so the inputs can be checked, the graph
+//! drawn or its memory footprint estimated before creating a [`GraphRuntime`].
+//!
+//! # Example
+//!
+//! ```
+//! let graph = GraphJson::parse(&artifacts.graph).unwrap();
+//! for input in graph.inputs() {
+//!     println!("{}: {:?} {}", input.name, input.shape, input.dtype);
+//! }
+//! println!("{} bytes of storage", graph.storage_bytes().unwrap());
+//! fs::write("graph.dot", graph.to_dot()).unwrap();
+//! ```
+//!
+//! [`GraphRuntime`]:../graph_runtime/struct.GraphRuntime.html
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde_json::{self, Value};
+
+use graph_runtime::InputInfo;
+use DataType;
+use Error;
+use ErrorKind;
+use Result;
+
+pub(crate) fn invalid_graph(field: &str) -> Error {
+    ErrorKind::InvalidGraph(field.to_owned()).into()
+}
+
+// Parses the name of a dtype as written in the graph json such as `float32`.
+pub(crate) fn parse_dtype(name: &str) -> Result<DataType> {
+    let (name, lanes) = match name.find('x') {
+        Some(x) => {
+            let lanes = name[x + 1..].parse::<u16>().map_err(|_| invalid_graph("dltype"))?;
+            (&name[..x], lanes)
+        }
+        None => (name, 1),
+    };
+    if name == "bool" {
+        return Ok(DataType::new(1, 1, lanes));
+    }
+    let (code, bits) = if name.starts_with("uint") {
+        (1, &name[4..])
+    } else if name.starts_with("int") {
+        (0, &name[3..])
+    } else if name.starts_with("float") {
+        (2, &name[5..])
+    } else {
+        bail!(invalid_graph("dltype"));
+    };
+    let bits = bits.parse::<u8>().map_err(|_| invalid_graph("dltype"))?;
+    Ok(DataType::new(code, bits, lanes))
+}
+
+// Name of the dtype as written in the graph json such as `float32`.
+pub(crate) fn dtype_name(dtype: &DataType) -> String {
+    let name = match (dtype.code, dtype.bits) {
+        (1, 1) => "bool".to_owned(),
+        (0, bits) => format!("int{}", bits),
+        (1, bits) => format!("uint{}", bits),
+        (2, bits) => format!("float{}", bits),
+        (_, bits) => format!("handle{}", bits),
+    };
+    if dtype.lanes > 1 {
+        format!("{}x{}", name, dtype.lanes)
+    } else {
+        name
+    }
+}
+
+// Returns the bytes of an array of the shape and dtype.
+fn nbytes(shape: &[usize], dtype: &DataType) -> usize {
+    shape.iter().product::<usize>() * (dtype.bits as usize * dtype.lanes as usize + 7) / 8
+}
+
+/// An output of a node, referenced by the inputs of other nodes and the graph heads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Entry {
+    pub node: usize,
+    pub index: usize,
+    pub version: usize,
+}
+
+/// A node of the graph, either an input with the `null` op or a compiled operator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub op: String,
+    pub name: String,
+    pub inputs: Vec<Entry>,
+    /// Attributes such as `func_name` and `num_inputs`, as written in the graph json.
+    pub attrs: BTreeMap<String, String>,
+}
+
+/// The graph json parsed and checked for consistency.
+///
+/// The outputs of the nodes are numbered by entry id, the output `index` of the node
+/// `nid` having the id `node_row_ptr[nid] + index`. The storage ids, shapes and dtypes
+/// are indexed by entry id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphJson {
+    pub nodes: Vec<Node>,
+    pub arg_nodes: Vec<usize>,
+    pub heads: Vec<Entry>,
+    pub node_row_ptr: Vec<usize>,
+    pub storage_ids: Vec<usize>,
+    pub shapes: Vec<Vec<usize>>,
+    pub dtypes: Vec<String>,
+    /// The device type of every entry in heterogeneous graphs, empty otherwise.
+    pub device_types: Vec<usize>,
+}
+
+fn parse_usize(value: &Value, field: &str) -> Result<usize> {
+    value
+        .as_u64()
+        .map(|v| v as usize)
+        .ok_or_else(|| invalid_graph(field))
+}
+
+fn parse_list<'a, T, F>(value: &'a Value, field: &str, parse: F) -> Result<Vec<T>>
+where
+    F: Fn(&'a Value) -> Result<T>,
+{
+    value
+        .as_array()
+        .ok_or_else(|| invalid_graph(field))?
+        .iter()
+        .map(parse)
+        .collect()
+}
+
+fn parse_entry(value: &Value, field: &str) -> Result<Entry> {
+    let entry = parse_list(value, field, |v| parse_usize(v, field))?;
+    if entry.len() < 2 || entry.len() > 3 {
+        bail!(invalid_graph(field));
+    }
+    Ok(Entry {
+        node: entry[0],
+        index: entry[1],
+        version: entry.get(2).cloned().unwrap_or(0),
+    })
+}
+
+fn parse_node(value: &Value) -> Result<Node> {
+    let op = value["op"].as_str().ok_or_else(|| invalid_graph("nodes"))?;
+    let name = value["name"].as_str().ok_or_else(|| invalid_graph("nodes"))?;
+    let inputs = parse_list(&value["inputs"], "nodes", |v| parse_entry(v, "nodes"))?;
+    let attrs = match value["attrs"] {
+        Value::Null => BTreeMap::new(),
+        Value::Object(ref attrs) => attrs
+            .iter()
+            .map(|(key, v)| {
+                let v = v.as_str().map(str::to_owned).unwrap_or_else(|| v.to_string());
+                (key.clone(), v)
+            })
+            .collect(),
+        _ => bail!(invalid_graph("nodes")),
+    };
+    Ok(Node {
+        op: op.to_owned(),
+        name: name.to_owned(),
+        inputs,
+        attrs,
+    })
+}
+
+impl GraphJson {
+    /// Parses the graph json, failing with `ErrorKind::InvalidGraph` if a field is
+    /// missing or refers to a node or an entry that does not exist.
+    pub fn parse(json: &str) -> Result<GraphJson> {
+        let graph: Value = serde_json::from_str(json)?;
+        let nodes = parse_list(&graph["nodes"], "nodes", parse_node)?;
+        let arg_nodes = parse_list(&graph["arg_nodes"], "arg_nodes", |v| {
+            parse_usize(v, "arg_nodes")
+        })?;
+        let heads = parse_list(&graph["heads"], "heads", |v| parse_entry(v, "heads"))?;
+        let node_row_ptr = parse_list(&graph["node_row_ptr"], "node_row_ptr", |v| {
+            parse_usize(v, "node_row_ptr")
+        })?;
+        let attrs = &graph["attrs"];
+        let storage_ids = parse_list(&attrs["storage_id"][1], "storage_id", |v| {
+            parse_usize(v, "storage_id")
+        })?;
+        let shapes = parse_list(&attrs["shape"][1], "shape", |v| {
+            parse_list(v, "shape", |d| parse_usize(d, "shape"))
+        })?;
+        let dtypes = parse_list(&attrs["dltype"][1], "dltype", |v| {
+            v.as_str()
+                .map(str::to_owned)
+                .ok_or_else(|| invalid_graph("dltype"))
+        })?;
+        let device_types = match attrs["device_index"] {
+            Value::Null => Vec::new(),
+            ref device_index => parse_list(&device_index[1], "device_index", |v| {
+                parse_usize(v, "device_index")
+            })?,
+        };
+        let graph = GraphJson {
+            nodes,
+            arg_nodes,
+            heads,
+            node_row_ptr,
+            storage_ids,
+            shapes,
+            dtypes,
+            device_types,
+        };
+        graph.check()?;
+        Ok(graph)
+    }
+
+    // Checks that the references between the fields are in range.
+    fn check(&self) -> Result<()> {
+        if self.node_row_ptr.len() != self.nodes.len() + 1
+            || self.node_row_ptr.windows(2).any(|w| w[0] > w[1])
+        {
+            bail!(invalid_graph("node_row_ptr"));
+        }
+        let num_entries = self.num_entries();
+        if self.storage_ids.len() != num_entries {
+            bail!(invalid_graph("storage_id"));
+        }
+        if self.shapes.len() != num_entries {
+            bail!(invalid_graph("shape"));
+        }
+        if self.dtypes.len() != num_entries {
+            bail!(invalid_graph("dltype"));
+        }
+        if !self.device_types.is_empty() && self.device_types.len() != num_entries {
+            bail!(invalid_graph("device_index"));
+        }
+        if self.arg_nodes.iter().any(|&nid| nid >= self.nodes.len()) {
+            bail!(invalid_graph("arg_nodes"));
+        }
+        let is_valid = |entry: &Entry| {
+            entry.node < self.nodes.len()
+                && self.node_row_ptr[entry.node] + entry.index < self.node_row_ptr[entry.node + 1]
+        };
+        if !self.heads.iter().all(&is_valid) {
+            bail!(invalid_graph("heads"));
+        }
+        if !self.nodes.iter().all(|node| node.inputs.iter().all(&is_valid)) {
+            bail!(invalid_graph("nodes"));
+        }
+        Ok(())
+    }
+
+    /// Returns the number of node outputs in the graph.
+    pub fn num_entries(&self) -> usize {
+        self.node_row_ptr.last().cloned().unwrap_or(0)
+    }
+
+    /// Returns the id of the entry.
+    pub fn entry_id(&self, entry: &Entry) -> usize {
+        self.node_row_ptr[entry.node] + entry.index
+    }
+
+    /// Returns the inputs, including the parameters, in the order of `arg_nodes`.
+    pub fn inputs(&self) -> Vec<InputInfo> {
+        self.arg_nodes
+            .iter()
+            .map(|&nid| {
+                let eid = self.node_row_ptr[nid];
+                InputInfo {
+                    name: self.nodes[nid].name.clone(),
+                    shape: self.shapes[eid].clone(),
+                    dtype: self.dtypes[eid].clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the input by name.
+    pub fn input(&self, name: &str) -> Option<InputInfo> {
+        self.inputs().into_iter().find(|input| input.name == name)
+    }
+
+    /// Returns the shapes and dtypes of the outputs, in the order of `heads`.
+    pub fn outputs(&self) -> Vec<(Vec<usize>, String)> {
+        self.heads
+            .iter()
+            .map(|head| {
+                let eid = self.entry_id(head);
+                (self.shapes[eid].clone(), self.dtypes[eid].clone())
+            })
+            .collect()
+    }
+
+    /// Estimates the bytes of memory the graph runtime allocates for the entries.
+    ///
+    /// The entries sharing a storage id share a buffer as large as the largest of them.
+    /// The parameters are included, the workspaces of the operators are not.
+    pub fn storage_bytes(&self) -> Result<usize> {
+        Ok(self.storage()?.values().map(|&(bytes, _)| bytes).sum())
+    }
+
+    // Returns the bytes and the device type, if any, of the buffers by storage id.
+    fn storage(&self) -> Result<HashMap<usize, (usize, Option<usize>)>> {
+        let mut storage = HashMap::new();
+        for eid in 0..self.num_entries() {
+            let entry_bytes = nbytes(&self.shapes[eid], &parse_dtype(&self.dtypes[eid])?);
+            let device_type = self.device_types.get(eid).cloned();
+            let buffer = storage
+                .entry(self.storage_ids[eid])
+                .or_insert((0, device_type));
+            if entry_bytes > buffer.0 {
+                buffer.0 = entry_bytes;
+            }
+        }
+        Ok(storage)
+    }
+
+    // Describes the entry as its shape and dtype, e.g. `[1, 4] float32`.
+    fn describe_entry(&self, eid: usize) -> String {
+        format!("{:?} {}", self.shapes[eid], self.dtypes[eid])
+    }
+
+    /// Renders the graph in the dot language of Graphviz, e.g. to visualize a graph json
+    /// with `dot -Tsvg`.
+    ///
+    /// The inputs are ellipses labeled with their shapes and dtypes, the operators are
+    /// boxes labeled with their compiled functions, and the edges are labeled with the
+    /// shapes and dtypes of the entries. The outputs of the graph have double borders.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph {\n");
+        for (nid, node) in self.nodes.iter().enumerate() {
+            let (shape, label) = match node.attrs.get("func_name") {
+                Some(func_name) if node.op != "null" => {
+                    ("box", format!("{}\n{}", node.name, func_name))
+                }
+                _ => {
+                    let eid = self.node_row_ptr[nid];
+                    ("ellipse", format!("{}\n{}", node.name, self.describe_entry(eid)))
+                }
+            };
+            let peripheries = if self.heads.iter().any(|head| head.node == nid) {
+                2
+            } else {
+                1
+            };
+            dot += &format!(
+                "  n{} [shape={}, peripheries={}, label={:?}];\n",
+                nid, shape, peripheries, label
+            );
+        }
+        for (nid, node) in self.nodes.iter().enumerate() {
+            for input in &node.inputs {
+                let label = self.describe_entry(self.entry_id(input));
+                dot += &format!("  n{} -> n{} [label={:?}];\n", input.node, nid, label);
+            }
+        }
+        dot += "}\n";
+        dot
+    }
+}
+
+const PARAMS_MAGIC: u64 = 0xF7E5_8D4F_0504_9CB7;
+const ARRAY_MAGIC: u64 = 0xDD5E_40F0_96B4_A13F;
+
+/// A parameter saved in `param.params`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param {
+    pub name: String,
+    pub shape: Vec<usize>,
+    pub dtype: DataType,
+    /// Bytes of the data of the parameter.
+    pub nbytes: usize,
+}
+
+/// The parameters of a model as serialized by `relay.save_param_dict` or
+/// `nnvm.compiler.save_param_dict`, without their data.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Params {
+    pub params: Vec<Param>,
+}
+
+// Reads the little-endian fields of the serialized parameters.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            bail!(ErrorKind::InvalidParams("unexpected end of data".to_owned()));
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn read_uint(&mut self, len: usize) -> Result<u64> {
+        Ok(self
+            .read(len)?
+            .iter()
+            .rev()
+            .fold(0, |v, &byte| v << 8 | byte as u64))
+    }
+
+    fn read_len(&mut self) -> Result<usize> {
+        Ok(self.read_uint(8)? as usize)
+    }
+}
+
+impl Params {
+    /// Parses the names, shapes and dtypes of the serialized parameters.
+    pub fn parse(bytes: &[u8]) -> Result<Params> {
+        let mut reader = Reader { bytes };
+        if reader.read_uint(8)? != PARAMS_MAGIC {
+            bail!(ErrorKind::InvalidParams("not a parameter file".to_owned()));
+        }
+        reader.read_uint(8)?; // reserved
+        let names = (0..reader.read_len()?)
+            .map(|_| {
+                let len = reader.read_len()?;
+                Ok(String::from_utf8_lossy(reader.read(len)?).into_owned())
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if reader.read_len()? != names.len() {
+            bail!(ErrorKind::InvalidParams("name and array counts differ".to_owned()));
+        }
+        let params = names
+            .into_iter()
+            .map(|name| {
+                if reader.read_uint(8)? != ARRAY_MAGIC {
+                    bail!(ErrorKind::InvalidParams(format!("invalid array `{}`", name)));
+                }
+                reader.read_uint(8)?; // reserved
+                reader.read(8)?; // context
+                let ndim = reader.read_uint(4)? as usize;
+                let code = reader.read_uint(1)? as u8;
+                let bits = reader.read_uint(1)? as u8;
+                let lanes = reader.read_uint(2)? as u16;
+                let shape = (0..ndim)
+                    .map(|_| reader.read_len())
+                    .collect::<Result<Vec<_>>>()?;
+                let nbytes = reader.read_len()?;
+                reader.read(nbytes)?;
+                Ok(Param {
+                    name,
+                    shape,
+                    dtype: DataType::new(code, bits, lanes),
+                    nbytes,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Params { params })
+    }
+
+    /// Returns the parameter by name.
+    pub fn get(&self, name: &str) -> Option<&Param> {
+        self.params.iter().find(|param| param.name == name)
+    }
+
+    /// Returns the bytes of the data of all the parameters.
+    pub fn nbytes(&self) -> usize {
+        self.params.iter().map(|param| param.nbytes).sum()
+    }
+}
+
+/// Estimated memory usage of a graph runtime, see [`estimate_memory`].
+///
+/// [`estimate_memory`]:fn.estimate_memory.html
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryEstimate {
+    /// Bytes of the parameters as loaded from `param.params`.
+    pub weight_bytes: usize,
+    /// Bytes of the buffers of the inputs and the intermediate results, i.e. the storage
+    /// not holding a parameter.
+    pub activation_bytes: usize,
+    /// Bytes of all the buffers by device type, `None` for graphs that are not
+    /// heterogeneous, which run on the context the runtime is created with.
+    pub per_device: BTreeMap<Option<usize>, usize>,
+}
+
+impl MemoryEstimate {
+    /// Returns the bytes of all the buffers.
+    pub fn total_bytes(&self) -> usize {
+        self.per_device.values().sum()
+    }
+}
+
+/// Estimates the memory a graph runtime of the graph allocates, e.g. to check that a
+/// model fits on a small device before loading it.
+///
+/// The storage sharing of the graph is accounted for, the workspaces of the operators
+/// and the code of the library are not.
+///
+/// ## Example
+///
+/// ```
+/// let graph = GraphJson::parse(&artifacts.graph).unwrap();
+/// let params = Params::parse(&artifacts.params).unwrap();
+/// let estimate = graph::estimate_memory(&graph, &params).unwrap();
+/// println!("{} bytes of weights", estimate.weight_bytes);
+/// ```
+pub fn estimate_memory(graph: &GraphJson, params: &Params) -> Result<MemoryEstimate> {
+    let param_storage = graph
+        .arg_nodes
+        .iter()
+        .filter(|&&nid| params.get(&graph.nodes[nid].name).is_some())
+        .map(|&nid| graph.storage_ids[graph.node_row_ptr[nid]])
+        .collect::<Vec<_>>();
+    let mut estimate = MemoryEstimate {
+        weight_bytes: params.nbytes(),
+        ..MemoryEstimate::default()
+    };
+    for (sid, (bytes, device_type)) in graph.storage()? {
+        if !param_storage.contains(&sid) {
+            estimate.activation_bytes += bytes;
+        }
+        *estimate.per_device.entry(device_type).or_insert(0) += bytes;
+    }
+    Ok(estimate)
+}
+
+// A graph computing `dense` from the `data` input and the `weight` parameter, shared by
+// the tests of the modules reading graphs.
+#[cfg(test)]
+pub(crate) const TEST_GRAPH: &'static str = r#"{
+    "nodes": [
+        {"op": "null", "name": "data", "inputs": []},
+        {"op": "null", "name": "weight", "inputs": []},
+        {"op": "tvm_op", "name": "dense", "inputs": [[0, 0, 0], [1, 0, 0]],
+         "attrs": {"func_name": "fused_dense", "num_inputs": "2"}}
+    ],
+    "arg_nodes": [0, 1],
+    "node_row_ptr": [0, 1, 2, 3],
+    "heads": [[2, 0, 0]],
+    "attrs": {
+        "dltype": ["list_str", ["float32", "float32", "float32"]],
+        "storage_id": ["list_int", [0, 1, 0]],
+        "shape": ["list_shape", [[1, 4], [2, 4], [1, 2]]]
+    }
+}"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let graph = GraphJson::parse(TEST_GRAPH).unwrap();
+        assert_eq!(graph.nodes.len(), 3);
+        assert_eq!(graph.nodes[2].attrs["func_name"], "fused_dense");
+        assert_eq!(graph.nodes[2].inputs[1], Entry { node: 1, index: 0, version: 0 });
+        assert_eq!(graph.num_entries(), 3);
+        assert_eq!(graph.input("weight").unwrap().shape, vec![2, 4]);
+        assert!(graph.input("dense").is_none());
+        assert_eq!(graph.outputs(), vec![(vec![1, 2], "float32".to_owned())]);
+        assert!(GraphJson::parse("{}").is_err());
+        let dangling = TEST_GRAPH.replace("[[2, 0, 0]]", "[[2, 1, 0]]");
+        assert!(GraphJson::parse(&dangling).is_err());
+    }
+
+    #[test]
+    fn dot() {
+        let dot = GraphJson::parse(TEST_GRAPH).unwrap().to_dot();
+        assert!(dot.starts_with("digraph {\n"));
+        let data = r#"n0 [shape=ellipse, peripheries=1, label="data\n[1, 4] float32"];"#;
+        assert!(dot.contains(data));
+        assert!(dot.contains(r#"n2 [shape=box, peripheries=2, label="dense\nfused_dense"];"#));
+        assert!(dot.contains(r#"n1 -> n2 [label="[2, 4] float32"];"#));
+    }
+
+    #[test]
+    fn storage_bytes() {
+        let graph = GraphJson::parse(TEST_GRAPH).unwrap();
+        // storage 0 holds `data` and `dense`, storage 1 holds `weight`.
+        assert_eq!(graph.storage_bytes().unwrap(), 4 * 4 + 8 * 4);
+    }
+
+    fn push(bytes: &mut Vec<u8>, v: u64, len: usize) {
+        bytes.extend((0..len).map(|i| (v >> (8 * i)) as u8));
+    }
+
+    // Serializes one `float32` parameter named `weight` of the shape `[2, 4]`.
+    fn weight_params() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for &(v, len) in &[(PARAMS_MAGIC, 8), (0, 8), (1, 8), (6, 8)] {
+            push(&mut bytes, v, len);
+        }
+        bytes.extend_from_slice(b"weight");
+        for &(v, len) in &[(1, 8), (ARRAY_MAGIC, 8), (0, 8), (1, 4), (0, 4), (2, 4)] {
+            push(&mut bytes, v, len);
+        }
+        for &(v, len) in &[(2, 1), (32, 1), (1, 2), (2, 8), (4, 8), (32, 8)] {
+            push(&mut bytes, v, len);
+        }
+        bytes.extend_from_slice(&[0; 32]);
+        bytes
+    }
+
+    #[test]
+    fn params() {
+        let params = Params::parse(&weight_params()).unwrap();
+        assert_eq!(
+            params.params,
+            vec![Param {
+                name: "weight".to_owned(),
+                shape: vec![2, 4],
+                dtype: DataType::from("float"),
+                nbytes: 32,
+            }]
+        );
+        let bytes = weight_params();
+        assert!(Params::parse(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Params::parse(&[0; 16]).is_err());
+    }
+
+    #[test]
+    fn memory_estimate() {
+        let graph = GraphJson::parse(TEST_GRAPH).unwrap();
+        let params = Params::parse(&weight_params()).unwrap();
+        let estimate = estimate_memory(&graph, &params).unwrap();
+        assert_eq!((estimate.weight_bytes, estimate.activation_bytes), (32, 16));
+        assert_eq!(estimate.per_device[&None], 48);
+        assert_eq!(estimate.total_bytes(), 48);
+    }
+
+    #[test]
+    fn dtype() {
+        assert_eq!(dtype_name(&DataType::from("float")), "float32");
+        assert_eq!(dtype_name(&DataType::from("uint")), "uint32");
+        assert_eq!(parse_dtype("float32").unwrap(), DataType::from("float"));
+        assert_eq!(parse_dtype("uint8").unwrap(), DataType::new(1, 8, 1));
+        assert_eq!(parse_dtype("bool").unwrap(), DataType::new(1, 1, 1));
+        assert_eq!(parse_dtype("float32x4").unwrap(), DataType::new(2, 32, 4));
+        assert_eq!(dtype_name(&parse_dtype("bool").unwrap()), "bool");
+        assert_eq!(dtype_name(&parse_dtype("int8x16").unwrap()), "int8x16");
+        assert!(parse_dtype("float32x").is_err());
+    }
+}
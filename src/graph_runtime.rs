@@ -0,0 +1,1641 @@
+//! Provides [`GraphRuntime`], a wrapper around the TVM graph runtime module, and
+//! [`GraphRuntimePool`] for serving a model from several executors at once.
+//!
+//! The model artifacts compiled with TVM, i.e. `graph.json`, `lib.so` and `param.params`,
+//! are grouped together as [`Artifacts`].
+//!
+//! # Example
+//!
+//! ```
+//! let artifacts = Artifacts::from_files(
+//!     "deploy_graph.json",
+//!     "deploy_lib.so",
+//!     "deploy_param.params",
+//! ).unwrap();
+//! let mut runtime = GraphRuntime::new(&artifacts, TVMContext::cpu(0)).unwrap();
+//! runtime.set_input("data", &input).unwrap();
+//! runtime.run().unwrap();
+//! let output = empty(&mut [1, 1000], TVMContext::cpu(0), DataType::from("float"));
+//! runtime.get_output(0, &output).unwrap();
+//! ```
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, HashMap},
+    fs,
+    marker::PhantomData,
+    mem,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc, Condvar, Mutex, MutexGuard, RwLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde_json::{self, Value};
+
+use context;
+use function::{self, Builder};
+use graph::{dtype_name, invalid_graph, parse_dtype, GraphJson};
+use metadata::Metadata;
+use ndarray::empty;
+use DataType;
+use ErrorKind;
+use Function;
+use Module;
+use NDArray;
+use Result;
+use TVMByteArray;
+use TVMContext;
+
+// The graph runtime is named graph executor from TVM 0.8 on, which alone supports capturing
+// the kernel launches into CUDA graphs.
+const GRAPH_RUNTIME_CREATE: [&'static str; 2] =
+    ["tvm.graph_runtime.create", "tvm.graph_executor.create"];
+const CUDA_GRAPH_RUNTIME_CREATE: &'static str = "tvm.graph_executor_cuda_graph.create";
+
+/// Model artifacts produced by TVM: the graph json, the path to the compiled
+/// shared library and the serialized parameters, with the [`Metadata`] describing the
+/// model if any.
+///
+/// [`Metadata`]:../metadata/struct.Metadata.html
+#[derive(Debug, Clone)]
+pub struct Artifacts {
+    pub graph: String,
+    pub lib: PathBuf,
+    pub params: Vec<u8>,
+    metadata: Option<Metadata>,
+}
+
+impl Artifacts {
+    pub fn new(graph: String, lib: PathBuf, params: Vec<u8>) -> Self {
+        Artifacts {
+            graph,
+            lib,
+            params,
+            metadata: None,
+        }
+    }
+
+    /// Reads the graph json and the parameters from files, and the metadata from the
+    /// sidecar of the graph json if it exists, failing if it does not match the graph.
+    pub fn from_files<P: AsRef<Path>>(graph: P, lib: P, params: P) -> Result<Self> {
+        let mut artifacts = Artifacts::new(
+            fs::read_to_string(&graph)?,
+            lib.as_ref().to_owned(),
+            fs::read(params)?,
+        );
+        if let Some(metadata) = Metadata::load_sidecar(graph.as_ref())? {
+            metadata.check(&GraphJson::parse(&artifacts.graph)?)?;
+            artifacts.set_metadata(Some(metadata));
+        }
+        Ok(artifacts)
+    }
+
+    /// Returns the metadata describing the inputs and outputs of the model, if any.
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    pub fn set_metadata(&mut self, metadata: Option<Metadata>) -> &mut Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Writes the metadata to the sidecar of the graph json at `graph`, removing a stale
+    /// sidecar if the artifacts have none.
+    pub fn save_metadata(&self, graph: &Path) -> Result<()> {
+        let path = Metadata::sidecar_path(graph);
+        match self.metadata {
+            Some(ref metadata) => metadata.save(&path),
+            None if path.is_file() => Ok(fs::remove_file(path)?),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Name, shape and dtype of a graph input as declared in the graph json.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputInfo {
+    pub name: String,
+    pub shape: Vec<usize>,
+    pub dtype: String,
+}
+
+// Collects the inputs (including the parameters) of the graph json.
+fn parse_inputs(graph: &str) -> Result<Vec<InputInfo>> {
+    Ok(GraphJson::parse(graph)?.inputs())
+}
+
+// Collects the shapes and dtypes of the outputs of the graph json.
+fn parse_outputs(graph: &str) -> Result<Vec<(Vec<usize>, String)>> {
+    Ok(GraphJson::parse(graph)?.outputs())
+}
+
+// An input or an output copied to the host, e.g. to move it between threads.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HostTensor {
+    pub(crate) shape: Vec<usize>,
+    pub(crate) dtype: DataType,
+    pub(crate) bytes: Vec<u8>,
+}
+
+impl HostTensor {
+    pub(crate) fn from_ndarray(nd: &NDArray) -> Result<Self> {
+        let mut bytes = vec![0u8; nd.nbytes()?];
+        nd.copy_to_bytes(&mut bytes)?;
+        Ok(HostTensor {
+            shape: nd.dims(),
+            dtype: nd.dtype(),
+            bytes,
+        })
+    }
+
+    pub(crate) fn to_ndarray(&self, ctx: TVMContext) -> Result<NDArray> {
+        let mut shape = self.shape.clone();
+        let mut nd = empty(&mut shape, ctx, self.dtype);
+        nd.copy_from_bytes(&self.bytes)?;
+        Ok(nd)
+    }
+
+    // Returns the number of bytes per entry of the leading dimension.
+    pub(crate) fn row_bytes(&self) -> usize {
+        match self.shape.first() {
+            Some(&rows) if rows > 0 => self.bytes.len() / rows,
+            _ => 0,
+        }
+    }
+}
+
+// Rewrites the shapes of the graph json for a new batch size of the input at `index`.
+//
+// The input and the entries computed from it whose leading dimension is the old batch
+// size are rescaled. The other entries, e.g. the parameters and the ones computed from
+// the parameters only, are kept.
+fn rebatch_graph(graph: &str, index: usize, batch: usize) -> Result<String> {
+    let mut graph: Value = serde_json::from_str(graph)?;
+    let input_nid = graph["arg_nodes"]
+        .get(index)
+        .and_then(Value::as_u64)
+        .ok_or_else(|| invalid_graph("arg_nodes"))? as usize;
+    let rescaled = computed_entries(&graph, input_nid)?;
+    let shapes = graph
+        .get_mut("attrs")
+        .and_then(|attrs| attrs.get_mut("shape"))
+        .and_then(|shape| shape.get_mut(1))
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| invalid_graph("shape"))?;
+    let input_eid = *rescaled.first().expect("the input entry is computed from itself");
+    let old_batch = shapes
+        .get(input_eid)
+        .and_then(|shape| shape[0].as_u64())
+        .ok_or_else(|| invalid_graph("shape"))?;
+    for eid in rescaled {
+        let shape = shapes.get_mut(eid).ok_or_else(|| invalid_graph("shape"))?;
+        if shape[0].as_u64() == Some(old_batch) {
+            shape[0] = Value::from(batch as u64);
+        }
+    }
+    Ok(graph.to_string())
+}
+
+// Returns the entries of the node `nid` and of the nodes computed from it, starting with
+// the first entry of `nid`. The nodes of a graph json are sorted topologically.
+fn computed_entries(graph: &Value, nid: usize) -> Result<Vec<usize>> {
+    let nodes = graph["nodes"].as_array().ok_or_else(|| invalid_graph("nodes"))?;
+    let row_ptr = |nid: usize| {
+        graph["node_row_ptr"][nid]
+            .as_u64()
+            .map(|eid| eid as usize)
+            .ok_or_else(|| invalid_graph("node_row_ptr"))
+    };
+    let mut computed = vec![false; nodes.len()];
+    *computed.get_mut(nid).ok_or_else(|| invalid_graph("arg_nodes"))? = true;
+    let mut entries = Vec::new();
+    for (node_id, node) in nodes.iter().enumerate().skip(nid) {
+        let inputs = node["inputs"].as_array().ok_or_else(|| invalid_graph("nodes"))?;
+        let from_input = inputs.iter().any(|input| {
+            input[0]
+                .as_u64()
+                .and_then(|src| computed.get(src as usize).cloned())
+                .unwrap_or(false)
+        });
+        if node_id == nid || from_input {
+            computed[node_id] = true;
+            entries.extend(row_ptr(node_id)?..row_ptr(node_id + 1)?);
+        }
+    }
+    Ok(entries)
+}
+
+// Creates a graph runtime module of `lib` on the given context.
+fn create_module(graph: &str, lib: &Module, ctx: &TVMContext) -> Result<Module> {
+    let create_fn = function::get_first_global_func(&GRAPH_RUNTIME_CREATE)?;
+    let ret = call_packed!(create_fn, graph, lib, &ctx.device_type, &ctx.device_id)?;
+    ret.try_into_module()
+}
+
+// Creates a graph runtime module capturing its kernel launches into a CUDA graph, failing
+// with `ErrorKind::Unsupported` if the runtime is older than TVM 0.8 or built without CUDA
+// graph support.
+fn create_capture_module(graph: &str, lib: &Module, ctx: &TVMContext) -> Result<Module> {
+    let create_fn = match Function::get_function(CUDA_GRAPH_RUNTIME_CREATE, true) {
+        Some(create_fn) => create_fn,
+        None => bail!(ErrorKind::Unsupported("capturing CUDA graphs".to_owned())),
+    };
+    let mut create = Builder::from(create_fn);
+    create.arg(graph).arg(lib).arg(&ctx.device_type).arg(&ctx.device_id);
+    create.invoke_catching()?.try_into_module()
+}
+
+/// Wrapper around a TVM graph runtime module created from [`Artifacts`].
+#[derive(Debug)]
+pub struct GraphRuntime {
+    module: Module,
+    ctx: TVMContext,
+    inputs: Vec<InputInfo>,
+    graph: String,
+    lib: Module,
+    captured: bool,
+}
+
+// The graph runtime module can be moved across threads as long as it is
+// used by one thread at a time, which `&mut self` methods guarantee.
+unsafe impl Send for GraphRuntime {}
+
+impl GraphRuntime {
+    /// Creates a graph runtime on the given context and loads the parameters.
+    pub fn new(artifacts: &Artifacts, ctx: TVMContext) -> Result<Self> {
+        let inputs = parse_inputs(&artifacts.graph)?;
+        let lib = Module::load(&artifacts.lib)?;
+        let module = create_module(&artifacts.graph, &lib, &ctx)?;
+        let mut runtime = GraphRuntime {
+            module,
+            ctx,
+            inputs,
+            graph: artifacts.graph.clone(),
+            lib,
+            captured: false,
+        };
+        runtime.load_params(&artifacts.params)?;
+        Ok(runtime)
+    }
+
+    /// Loads the serialized parameters into the runtime. Empty parameters, e.g. of graphs
+    /// with the weights bound as constants, are skipped.
+    pub fn load_params(&mut self, params: &[u8]) -> Result<()> {
+        if params.is_empty() {
+            return Ok(());
+        }
+        let params = params.to_vec();
+        let barr = TVMByteArray::from(&params);
+        let load_params_fn = self.module.get_function("load_params", false)?;
+        call_packed!(load_params_fn, &barr)?;
+        Ok(())
+    }
+
+    /// Sets the input by name.
+    pub fn set_input(&mut self, name: &str, input: &NDArray) -> Result<()> {
+        let set_input_fn = self.module.get_function("set_input", false)?;
+        call_packed!(set_input_fn, name, input)?;
+        Ok(())
+    }
+
+    /// Sets the input by name, adapting the runtime to a new batch size if the leading
+    /// dimension of `input` differs from the one of the graph.
+    ///
+    /// This is only supported by libraries compiled with a symbolic batch dimension, in
+    /// which case the runtime is recreated with the rescaled shapes of the graph json and
+    /// the other inputs, including the parameters, are copied over. The entries whose
+    /// leading dimension is the compiled batch size are assumed to be batched. Other
+    /// dimensions must match the graph.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let batch = empty(&mut [8, 3, 224, 224], TVMContext::cpu(0), DataType::from("float"));
+    /// runtime.set_input_with_shape("data", &batch).unwrap();
+    /// assert_eq!(runtime.inputs()[0].shape, vec![8, 3, 224, 224]);
+    /// ```
+    pub fn set_input_with_shape(&mut self, name: &str, input: &NDArray) -> Result<()> {
+        let index = self
+            .inputs
+            .iter()
+            .position(|info| info.name == name)
+            .ok_or_else(|| ErrorKind::InputMismatch(vec![format!("unknown input `{}`", name)]))?;
+        let shape = input.dims();
+        let expected = &self.inputs[index].shape;
+        if shape != *expected {
+            if shape.is_empty() || shape.len() != expected.len() || shape[1..] != expected[1..] {
+                bail!(ErrorKind::InputMismatch(vec![format!(
+                    "input `{}` expects shape {:?} up to the batch size, but found {:?}",
+                    name, expected, shape
+                )]));
+            }
+            self.rebatch(index, shape[0])?;
+        }
+        self.set_input(name, input)
+    }
+
+    // Recreates the runtime module for a new batch size of the input at `index`.
+    fn rebatch(&mut self, index: usize, batch: usize) -> Result<()> {
+        let graph = rebatch_graph(&self.graph, index, batch)?;
+        let inputs = parse_inputs(&graph)?;
+        let module = create_module(&graph, &self.lib, &self.ctx)?;
+        self.copy_inputs(&module, Some(index))?;
+        self.module = module;
+        self.inputs = inputs;
+        self.graph = graph;
+        self.captured = false;
+        Ok(())
+    }
+
+    // Copies the inputs, except the one at `skip`, into another module of the graph.
+    fn copy_inputs(&self, module: &Module, skip: Option<usize>) -> Result<()> {
+        let get_input_fn = self.module.get_function("get_input", false)?;
+        let set_input_fn = module.get_function("set_input", false)?;
+        for (i, info) in self.inputs.iter().enumerate() {
+            if Some(i) != skip {
+                let get_input_fn = get_input_fn.clone();
+                let set_input_fn = set_input_fn.clone();
+                let value = call_packed!(get_input_fn, &info.name)?.try_into_ndarray()?;
+                call_packed!(set_input_fn, &info.name, &value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the graph on inputs of any batch size and returns the outputs of the same
+    /// batch size on the runtime's context.
+    ///
+    /// The inputs share their leading dimension, the batch, and the other dimensions
+    /// must match the graph. They are split into chunks of the compiled batch size, the
+    /// last chunk is padded with zeros, and the outputs of the chunks are stitched back
+    /// together without the padding.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// // the graph is compiled for a batch of 4 images
+    /// let images = empty(&mut [10, 3, 224, 224], TVMContext::cpu(0), DataType::from("float"));
+    /// let outputs = runtime.infer_batch(&[("data", &images)]).unwrap();
+    /// assert_eq!(outputs[0].shape(), &[10, 1000]);
+    /// ```
+    pub fn infer_batch(&mut self, inputs: &[(&str, &NDArray)]) -> Result<Vec<NDArray>> {
+        let host_inputs = inputs
+            .iter()
+            .map(|&(name, input)| Ok((name, HostTensor::from_ndarray(input)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let host_inputs = host_inputs
+            .iter()
+            .map(|&(name, ref input)| (name, input))
+            .collect::<Vec<_>>();
+        self.infer_host_batch(&host_inputs)?
+            .iter()
+            .map(|output| output.to_ndarray(self.ctx.clone()))
+            .collect()
+    }
+
+    // Runs `infer_batch` on inputs in host memory and returns the outputs in host memory,
+    // e.g. for the batch scheduler, which moves them between threads.
+    pub(crate) fn infer_host_batch(
+        &mut self,
+        inputs: &[(&str, &HostTensor)],
+    ) -> Result<Vec<HostTensor>> {
+        let mut batch_size = None;
+        let mut host_inputs = Vec::with_capacity(inputs.len());
+        let mut mismatches = Vec::new();
+        for &(name, input) in inputs {
+            let info = match self.inputs.iter().find(|info| info.name == name) {
+                Some(info) => info.clone(),
+                None => {
+                    mismatches.push(format!("unknown input `{}`", name));
+                    continue;
+                }
+            };
+            let shape = &input.shape;
+            if shape.is_empty() || shape.len() != info.shape.len() || shape[1..] != info.shape[1..]
+            {
+                mismatches.push(format!(
+                    "input `{}` expects shape {:?} up to the batch size, but found {:?}",
+                    name, info.shape, shape
+                ));
+                continue;
+            }
+            if dtype_name(&input.dtype) != info.dtype {
+                mismatches.push(format!(
+                    "input `{}` expects dtype `{}`, but found `{}`",
+                    name,
+                    info.dtype,
+                    dtype_name(&input.dtype)
+                ));
+                continue;
+            }
+            if *batch_size.get_or_insert(shape[0]) != shape[0] {
+                mismatches.push(format!(
+                    "input `{}` has a batch size of {}, but expects {}",
+                    name,
+                    shape[0],
+                    batch_size.unwrap_or_default()
+                ));
+                continue;
+            }
+            host_inputs.push((info, input));
+        }
+        if !mismatches.is_empty() {
+            bail!(ErrorKind::InputMismatch(mismatches));
+        }
+        let batch_size = match batch_size {
+            Some(batch_size) => batch_size,
+            None => bail!("no inputs to infer"),
+        };
+        let outputs = parse_outputs(&self.graph)?
+            .into_iter()
+            .map(|(shape, dtype)| Ok((shape, parse_dtype(&dtype)?)))
+            .collect::<Result<Vec<_>>>()?;
+        if outputs.iter().any(|(shape, _)| shape.is_empty()) {
+            bail!("cannot batch the scalar outputs of the graph");
+        }
+        let mut output_bytes = vec![Vec::new(); outputs.len()];
+        let mut start = 0;
+        while start < batch_size {
+            let mut rows = batch_size - start;
+            for &(ref info, input) in &host_inputs {
+                let compiled = info.shape[0];
+                rows = rows.min(compiled);
+                let row_bytes = input.bytes.len() / batch_size;
+                let end = (start + compiled).min(batch_size);
+                let rows_bytes = &input.bytes[start * row_bytes..end * row_bytes];
+                let mut shape = info.shape.clone();
+                let mut nd = empty(&mut shape, self.ctx.clone(), input.dtype);
+                if end - start == compiled {
+                    nd.copy_from_bytes(rows_bytes)?;
+                } else {
+                    // the last chunk is padded with zeros
+                    let mut chunk = vec![0u8; compiled * row_bytes];
+                    chunk[..rows_bytes.len()].copy_from_slice(rows_bytes);
+                    nd.copy_from_bytes(&chunk)?;
+                }
+                self.set_input(&info.name, &nd)?;
+            }
+            self.run()?;
+            for (index, (shape, dtype)) in outputs.iter().enumerate() {
+                let mut shape = shape.clone();
+                let nd = empty(&mut shape, self.ctx.clone(), *dtype);
+                self.get_output(index, &nd)?;
+                let mut bytes = vec![0u8; nd.nbytes()?];
+                nd.copy_to_bytes(&mut bytes)?;
+                let row_bytes = bytes.len() / shape[0];
+                bytes.truncate(rows * row_bytes);
+                if output_bytes[index].is_empty() {
+                    output_bytes[index] = bytes;
+                } else {
+                    output_bytes[index].extend_from_slice(&bytes);
+                }
+            }
+            start += rows;
+        }
+        Ok(outputs
+            .into_iter()
+            .zip(output_bytes)
+            .map(|((mut shape, dtype), bytes)| {
+                shape[0] = batch_size;
+                HostTensor {
+                    shape,
+                    dtype,
+                    bytes,
+                }
+            })
+            .collect())
+    }
+
+    /// Validates and sets several inputs at once.
+    ///
+    /// The names, dtypes and shapes are checked against the graph json and all the
+    /// mismatches are reported together, in which case no input is set.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut inputs = HashMap::new();
+    /// inputs.insert("data", &input);
+    /// runtime.set_inputs(&inputs).unwrap();
+    /// ```
+    pub fn set_inputs(&mut self, inputs: &HashMap<&str, &NDArray>) -> Result<()> {
+        let mut mismatches = Vec::new();
+        for (&name, input) in inputs {
+            self.check_input(name, input, &mut mismatches);
+        }
+        if !mismatches.is_empty() {
+            bail!(ErrorKind::InputMismatch(mismatches));
+        }
+        for (&name, input) in inputs {
+            self.set_input(name, input)?;
+        }
+        Ok(())
+    }
+
+    // Checks `input` against the graph input `name` and returns its index, recording
+    // the mismatches.
+    fn check_input(&self, name: &str, input: &NDArray, mismatches: &mut Vec<String>) -> usize {
+        match self.inputs.iter().position(|info| info.name == name) {
+            None => {
+                mismatches.push(format!("unknown input `{}`", name));
+                0
+            }
+            Some(index) => {
+                let info = &self.inputs[index];
+                let dtype = dtype_name(&input.dtype());
+                if dtype != info.dtype {
+                    mismatches.push(format!(
+                        "input `{}` expects dtype `{}`, but found `{}`",
+                        name, info.dtype, dtype
+                    ));
+                }
+                let shape = input.dims();
+                if shape != info.shape {
+                    mismatches.push(format!(
+                        "input `{}` expects shape {:?}, but found {:?}",
+                        name, info.shape, shape
+                    ));
+                }
+                index
+            }
+        }
+    }
+
+    /// Starts an [`InferenceSession`] binding the given inputs by name and the outputs
+    /// by index. The inputs are validated like in [`set_inputs`].
+    ///
+    /// [`set_inputs`]:struct.GraphRuntime.html#method.set_inputs
+    pub fn session(
+        &mut self,
+        inputs: HashMap<&str, NDArray>,
+        outputs: Vec<NDArray>,
+    ) -> Result<InferenceSession> {
+        let mut mismatches = Vec::new();
+        let mut bound = Vec::with_capacity(inputs.len());
+        for (name, input) in inputs {
+            let index = self.check_input(name, &input, &mut mismatches);
+            bound.push((index, name.to_owned(), input));
+        }
+        let num_outputs = self.num_outputs()?;
+        if outputs.len() > num_outputs {
+            mismatches.push(format!(
+                "the graph has {} outputs, but found {}",
+                num_outputs,
+                outputs.len()
+            ));
+        }
+        if !mismatches.is_empty() {
+            bail!(ErrorKind::InputMismatch(mismatches));
+        }
+        let set_input_fn = self.module.get_function("set_input", false)?;
+        let run_fn = self.module.get_function("run", false)?;
+        let get_output_fn = self.module.get_function("get_output", false)?;
+        Ok(InferenceSession {
+            runtime: self,
+            set_input_fn,
+            run_fn,
+            get_output_fn,
+            inputs: bound,
+            outputs,
+        })
+    }
+
+    /// Allocates a [`DoubleBuffer`] for the input on the runtime's context, with the shape
+    /// and dtype declared in the graph.
+    ///
+    /// [`DoubleBuffer`]:struct.DoubleBuffer.html
+    pub fn double_buffer(&self, name: &str) -> Result<DoubleBuffer> {
+        let info = self
+            .inputs
+            .iter()
+            .find(|info| info.name == name)
+            .ok_or_else(|| ErrorKind::InputMismatch(vec![format!("unknown input `{}`", name)]))?;
+        let dtype = parse_dtype(&info.dtype)?;
+        let alloc = || Mutex::new(empty(&mut info.shape.clone(), self.ctx.clone(), dtype));
+        Ok(DoubleBuffer {
+            name: name.to_owned(),
+            buffers: [alloc(), alloc()],
+            front: AtomicUsize::new(0),
+        })
+    }
+
+    /// Sets the front buffers of the staged inputs and executes the graph, holding the
+    /// front buffers until it returns so that they cannot be swapped in the meantime.
+    pub fn run_staged(&mut self, staged: &[&DoubleBuffer]) -> Result<()> {
+        let mut fronts = Vec::with_capacity(staged.len());
+        for input in staged {
+            let front = input.lock(true);
+            self.set_input(&input.name, &front)?;
+            fronts.push(front);
+        }
+        self.run()
+    }
+
+    /// Returns the inputs, including the parameters, declared in the graph json.
+    pub fn inputs(&self) -> &[InputInfo] {
+        &self.inputs
+    }
+
+    /// Returns a view of the runtime's input buffer by name.
+    pub fn get_input(&self, name: &str) -> Result<NDArray> {
+        let get_input_fn = self.module.get_function("get_input", false)?;
+        call_packed!(get_input_fn, name)?.try_into_ndarray()
+    }
+
+    /// Executes the graph, replaying the captured launches after a successful capture in
+    /// [`warmup`].
+    ///
+    /// [`warmup`]:struct.GraphRuntime.html#method.warmup
+    pub fn run(&mut self) -> Result<()> {
+        let run_fn = if self.captured {
+            self.module.get_function("run_cuda_graph", false)?
+        } else {
+            self.module.get_function("run", false)?
+        };
+        call_packed!(run_fn,)?;
+        Ok(())
+    }
+
+    /// Executes the graph `n` times, e.g. to load the kernels and fill the allocator
+    /// pools before measuring latency, then captures the kernel launches of the graph on
+    /// CUDA devices.
+    ///
+    /// Capturing requires a TVM 0.8 or later runtime built with CUDA graph support, and
+    /// only succeeds for graphs without host synchronization. After a successful capture,
+    /// see [`is_captured`], [`run`] replays the launches in a single call, with the inputs
+    /// set in the same buffers. Setting an input of another batch size recreates the
+    /// runtime without the capture.
+    ///
+    /// Fails with the error of the capture, e.g. `ErrorKind::Unsupported` for a runtime
+    /// without CUDA graph support, after which `run` keeps launching the kernels one by one.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut runtime = GraphRuntime::new(&artifacts, TVMContext::gpu(0)).unwrap();
+    /// runtime.set_input("data", &input).unwrap();
+    /// if let Err(err) = runtime.warmup(10) {
+    ///     println!("running without capture: {}", err);
+    /// }
+    /// ```
+    ///
+    /// [`is_captured`]:struct.GraphRuntime.html#method.is_captured
+    /// [`run`]:struct.GraphRuntime.html#method.run
+    pub fn warmup(&mut self, n: usize) -> Result<()> {
+        for _ in 0..n {
+            self.run()?;
+        }
+        if !self.captured && self.ctx.device_type == context::GPU {
+            self.capture()?;
+            self.captured = true;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the kernel launches were captured by [`warmup`].
+    ///
+    /// [`warmup`]:struct.GraphRuntime.html#method.warmup
+    pub fn is_captured(&self) -> bool {
+        self.captured
+    }
+
+    // Recreates the module with CUDA graph support and captures one run of the graph. The
+    // runtime keeps the module without the capture on failure.
+    fn capture(&mut self) -> Result<()> {
+        let module = create_capture_module(&self.graph, &self.lib, &self.ctx)?;
+        self.copy_inputs(&module, None)?;
+        let start_fn = module.get_function("start_capture", false)?;
+        let end_fn = module.get_function("end_capture", false)?;
+        Builder::from(start_fn).invoke_catching()?;
+        let run = Builder::from(module.get_function("run", false)?).invoke_catching();
+        Builder::from(end_fn).invoke_catching()?;
+        run?;
+        self.module = module;
+        Ok(())
+    }
+
+    /// Copies the output at `index` into `output`.
+    pub fn get_output(&self, index: usize, output: &NDArray) -> Result<()> {
+        let get_output_fn = self.module.get_function("get_output", false)?;
+        call_packed!(get_output_fn, &index, output)?;
+        Ok(())
+    }
+
+    /// Returns a view of the runtime's output buffer at `index`, without copying it, e.g.
+    /// to read a few values of a large output.
+    ///
+    /// The buffer is overwritten by the next [`run`], which the borrow of the view
+    /// prevents until it is dropped.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// runtime.run().unwrap();
+    /// let scores = runtime.get_output_view(0).unwrap();
+    /// let top = scores.to_vec::<f32>().unwrap()[0];
+    /// ```
+    ///
+    /// [`run`]:struct.GraphRuntime.html#method.run
+    pub fn get_output_view(&self, index: usize) -> Result<NDArrayView> {
+        let num_outputs = self.num_outputs()?;
+        if index >= num_outputs {
+            bail!("output {} is out of the {} outputs", index, num_outputs);
+        }
+        let get_output_fn = self.module.get_function("get_output", false)?;
+        let array = call_packed!(get_output_fn, &index)?.try_into_ndarray()?;
+        Ok(NDArrayView {
+            array,
+            _runtime: PhantomData,
+        })
+    }
+
+    /// Returns the number of outputs of the graph.
+    pub fn num_outputs(&self) -> Result<usize> {
+        let num_outputs_fn = self.module.get_function("get_num_outputs", false)?;
+        let ret = call_packed!(num_outputs_fn,)?;
+        Ok(ret.to_int() as usize)
+    }
+
+    /// Returns the context which the runtime was created on.
+    pub fn ctx(&self) -> &TVMContext {
+        &self.ctx
+    }
+
+    /// Returns the underlying graph runtime module.
+    pub fn module(&self) -> &Module {
+        &self.module
+    }
+}
+
+/// A view of an output buffer of a [`GraphRuntime`], obtained with
+/// [`GraphRuntime::get_output_view`]. It derefs to the `NDArray` sharing the buffer.
+///
+/// [`GraphRuntime`]:struct.GraphRuntime.html
+/// [`GraphRuntime::get_output_view`]:struct.GraphRuntime.html#method.get_output_view
+#[derive(Debug)]
+pub struct NDArrayView<'a> {
+    array: NDArray,
+    _runtime: PhantomData<&'a GraphRuntime>,
+}
+
+impl<'a> Deref for NDArrayView<'a> {
+    type Target = NDArray;
+
+    fn deref(&self) -> &NDArray {
+        &self.array
+    }
+}
+
+/// Two buffers of an input, obtained with [`GraphRuntime::double_buffer`], to prepare the
+/// next input while the graph runs on the current one.
+///
+/// [`GraphRuntime::run_staged`] reads the front buffer, while [`fill`] writes the back
+/// buffer, e.g. from a preprocessing thread. [`swap`] then exchanges them atomically,
+/// waiting for a running graph or a fill to finish.
+///
+/// ## Example
+///
+/// ```
+/// let staged = Arc::new(runtime.double_buffer("data").unwrap());
+/// let loader = Arc::clone(&staged);
+/// thread::spawn(move || {
+///     for image in images {
+///         loader.fill(|buffer| buffer.copy_from_buffer(&image)).unwrap();
+///         frames.recv().unwrap();
+///         loader.swap();
+///     }
+/// });
+/// loop {
+///     runtime.run_staged(&[&staged]).unwrap();
+///     done.send(()).unwrap();
+/// }
+/// ```
+///
+/// [`GraphRuntime::double_buffer`]:struct.GraphRuntime.html#method.double_buffer
+/// [`GraphRuntime::run_staged`]:struct.GraphRuntime.html#method.run_staged
+/// [`fill`]:struct.DoubleBuffer.html#method.fill
+/// [`swap`]:struct.DoubleBuffer.html#method.swap
+#[derive(Debug)]
+pub struct DoubleBuffer {
+    name: String,
+    buffers: [Mutex<NDArray>; 2],
+    // index of the front buffer, only changed with both buffers locked
+    front: AtomicUsize,
+}
+
+// The buffers are only accessed through their mutexes.
+unsafe impl Send for DoubleBuffer {}
+unsafe impl Sync for DoubleBuffer {}
+
+impl DoubleBuffer {
+    /// Returns the name of the input.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Calls `f` with the back buffer to write the next input into it.
+    pub fn fill<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut NDArray) -> R,
+    {
+        f(&mut self.lock(false))
+    }
+
+    /// Exchanges the front and the back buffers.
+    pub fn swap(&self) {
+        let _first = self.buffers[0].lock().unwrap();
+        let _second = self.buffers[1].lock().unwrap();
+        self.front.fetch_xor(1, Ordering::SeqCst);
+    }
+
+    // Locks the front or the back buffer, retrying if they were swapped before the lock
+    // was acquired.
+    fn lock(&self, front: bool) -> MutexGuard<NDArray> {
+        loop {
+            let index = self.index(front);
+            let guard = self.buffers[index].lock().unwrap();
+            if self.index(front) == index {
+                return guard;
+            }
+        }
+    }
+
+    fn index(&self, front: bool) -> usize {
+        let index = self.front.load(Ordering::SeqCst);
+        if front {
+            index
+        } else {
+            index ^ 1
+        }
+    }
+}
+
+/// A [`GraphRuntime`] with inputs and outputs bound once, obtained with
+/// [`GraphRuntime::session`].
+///
+/// The session owns the bound NDArrays, so their shapes cannot change without starting a
+/// new, validated session. Each [`run`] copies the inputs into the runtime by index,
+/// executes the graph and copies the outputs back, without looking up names.
+///
+/// ## Example
+///
+/// ```
+/// let mut inputs = HashMap::new();
+/// inputs.insert("data", empty(&mut [1, 3, 224, 224], ctx.clone(), DataType::from("float")));
+/// let outputs = vec![empty(&mut [1, 1000], ctx, DataType::from("float"))];
+/// let mut session = runtime.session(inputs, outputs).unwrap();
+/// for image in images {
+///     session.input_mut("data").unwrap().copy_from_buffer(&image).unwrap();
+///     session.run().unwrap();
+///     let probs = session.output(0).unwrap().to_vec::<f32>().unwrap();
+/// }
+/// ```
+///
+/// [`GraphRuntime::session`]:struct.GraphRuntime.html#method.session
+/// [`run`]:struct.InferenceSession.html#method.run
+#[derive(Debug)]
+pub struct InferenceSession<'a> {
+    runtime: &'a mut GraphRuntime,
+    set_input_fn: Function,
+    run_fn: Function,
+    get_output_fn: Function,
+    inputs: Vec<(usize, String, NDArray)>,
+    outputs: Vec<NDArray>,
+}
+
+impl<'a> InferenceSession<'a> {
+    /// Returns the bound input by name for writing the next data into it.
+    pub fn input_mut(&mut self, name: &str) -> Result<&mut NDArray> {
+        self.inputs
+            .iter_mut()
+            .find(|(_, input_name, _)| *input_name == name)
+            .map(|(_, _, input)| input)
+            .ok_or_else(|| format!("input `{}` is not bound to the session", name).into())
+    }
+
+    /// Sets the bound inputs, executes the graph and copies the bound outputs.
+    pub fn run(&mut self) -> Result<()> {
+        for (index, _, input) in &self.inputs {
+            let set_input_fn = self.set_input_fn.clone();
+            call_packed!(set_input_fn, index, input)?;
+        }
+        let run_fn = self.run_fn.clone();
+        call_packed!(run_fn,)?;
+        for (index, output) in self.outputs.iter().enumerate() {
+            let get_output_fn = self.get_output_fn.clone();
+            call_packed!(get_output_fn, &index, output)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the bound output at `index` as of the last [`run`].
+    ///
+    /// [`run`]:struct.InferenceSession.html#method.run
+    pub fn output(&self, index: usize) -> Result<&NDArray> {
+        self.outputs.get(index).ok_or_else(|| {
+            format!(
+                "output {} is not bound to the session of {} outputs",
+                index,
+                self.outputs.len()
+            ).into()
+        })
+    }
+
+    /// Returns all the bound outputs.
+    pub fn outputs(&self) -> &[NDArray] {
+        &self.outputs
+    }
+
+    /// Returns the underlying graph runtime.
+    pub fn runtime(&self) -> &GraphRuntime {
+        self.runtime
+    }
+
+    /// Ends the session and returns the bound outputs.
+    pub fn into_outputs(self) -> Vec<NDArray> {
+        self.outputs
+    }
+}
+
+/// The scheduling of a call of a [`GraphRuntimePool`] while it waits for an executor.
+///
+/// [`GraphRuntimePool`]:struct.GraphRuntimePool.html
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RunOptions {
+    /// The calls with a higher priority are served first, then in arrival order.
+    pub priority: i32,
+    /// The call fails with `ErrorKind::DeadlineExceeded` instead of starting after it.
+    pub deadline: Option<Instant>,
+}
+
+impl RunOptions {
+    /// The options of a call with the priority.
+    pub fn priority(priority: i32) -> Self {
+        RunOptions {
+            priority,
+            ..Default::default()
+        }
+    }
+
+    /// The options of a call which must start within `timeout`.
+    pub fn deadline_in(timeout: Duration) -> Self {
+        RunOptions {
+            deadline: Some(Instant::now() + timeout),
+            ..Default::default()
+        }
+    }
+}
+
+// The idle executors of a generation and the tickets of the calls waiting for one, in
+// the order they are served.
+#[derive(Debug)]
+struct Slots<T> {
+    idle: Vec<T>,
+    waiting: BTreeSet<(Reverse<i32>, u64)>,
+    next_ticket: u64,
+}
+
+// A set of executors built from the same artifacts.
+#[derive(Debug)]
+struct Generation<T = GraphRuntime> {
+    slots: Mutex<Slots<T>>,
+    available: Condvar,
+}
+
+impl<T> Default for Generation<T> {
+    fn default() -> Self {
+        Generation::new(Vec::new())
+    }
+}
+
+impl Generation {
+    fn build(artifacts: &Artifacts, ctx: &TVMContext, size: usize) -> Result<Self> {
+        let mut executors = Vec::with_capacity(size);
+        for _ in 0..size {
+            executors.push(GraphRuntime::new(artifacts, ctx.clone())?);
+        }
+        Ok(Generation::new(executors))
+    }
+}
+
+impl<T> Generation<T> {
+    fn new(executors: Vec<T>) -> Self {
+        Generation {
+            slots: Mutex::new(Slots {
+                idle: executors,
+                waiting: BTreeSet::new(),
+                next_ticket: 0,
+            }),
+            available: Condvar::new(),
+        }
+    }
+
+    // Waits for an idle executor, failing once the pool is shut down, if `max_queued`
+    // calls are waiting already, or if the deadline passes first.
+    fn acquire(
+        &self,
+        closed: &AtomicBool,
+        opts: &RunOptions,
+        max_queued: Option<usize>,
+    ) -> Result<T> {
+        let mut slots = self.slots.lock().unwrap();
+        if opts.deadline.map_or(false, |deadline| Instant::now() > deadline) {
+            bail!(ErrorKind::DeadlineExceeded);
+        }
+        if slots.waiting.is_empty() {
+            if let Some(executor) = slots.idle.pop() {
+                return Ok(executor);
+            }
+        }
+        if let Some(max_queued) = max_queued {
+            if slots.waiting.len() >= max_queued {
+                bail!(ErrorKind::QueueFull(max_queued));
+            }
+        }
+        let ticket = (Reverse(opts.priority), slots.next_ticket);
+        slots.next_ticket += 1;
+        slots.waiting.insert(ticket);
+        let ret = loop {
+            if closed.load(Ordering::SeqCst) {
+                break Err(ErrorKind::PoolShutDown.into());
+            }
+            if !slots.idle.is_empty() && slots.waiting.iter().next() == Some(&ticket) {
+                break Ok(slots.idle.pop().expect("idle executors is not empty"));
+            }
+            slots = match opts.deadline {
+                None => self.available.wait(slots).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break Err(ErrorKind::DeadlineExceeded.into());
+                    }
+                    self.available.wait_timeout(slots, deadline - now).unwrap().0
+                }
+            };
+        };
+        slots.waiting.remove(&ticket);
+        // the next call in line may take an idle executor now
+        self.available.notify_all();
+        ret
+    }
+
+    fn release(&self, executor: T) {
+        self.slots.lock().unwrap().idle.push(executor);
+        self.available.notify_all();
+    }
+
+    fn num_waiting(&self) -> usize {
+        self.slots.lock().unwrap().waiting.len()
+    }
+}
+
+// An executor taken from a generation, which returns to it when the lease is dropped,
+// also if the call using the executor panics.
+#[derive(Debug)]
+struct Lease<T = GraphRuntime> {
+    generation: Arc<Generation<T>>,
+    executor: Option<T>,
+}
+
+impl<T> Lease<T> {
+    fn acquire(
+        generation: Arc<Generation<T>>,
+        closed: &AtomicBool,
+        opts: &RunOptions,
+        max_queued: Option<usize>,
+    ) -> Result<Self> {
+        let executor = generation.acquire(closed, opts, max_queued)?;
+        Ok(Lease {
+            generation,
+            executor: Some(executor),
+        })
+    }
+
+    fn executor(&mut self) -> &mut T {
+        self.executor.as_mut().expect("the executor is only taken on drop")
+    }
+}
+
+impl<T> Drop for Lease<T> {
+    fn drop(&mut self) {
+        if let Some(executor) = self.executor.take() {
+            self.generation.release(executor);
+        }
+    }
+}
+
+// The number of calls accepted by a pool and not returned yet.
+#[derive(Debug, Default)]
+struct InFlight {
+    count: Mutex<usize>,
+    drained: Condvar,
+}
+
+// Marks a call as returned when dropped, even if it panicked.
+#[derive(Debug)]
+struct InFlightGuard(Arc<InFlight>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let mut count = self.0.count.lock().unwrap();
+        *count -= 1;
+        if *count == 0 {
+            self.0.drained.notify_all();
+        }
+    }
+}
+
+// How often a cancellable call checks its token.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A token to cancel the calls waiting on it with
+/// [`GraphRuntimePool::run_cancellable`]. Clones share the same state.
+///
+/// [`GraphRuntimePool::run_cancellable`]:struct.GraphRuntimePool.html#method.run_cancellable
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the calls waiting on the token, now and in the future.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// A fixed-size pool of [`GraphRuntime`]s serving the same model on one context.
+///
+/// The calls waiting for an executor are queued by priority with [`run_with`], the queue
+/// can be bounded with [`queue_capacity`] to shed load instead of piling it up, the model
+/// can be replaced while the pool is in use with [`swap_model`], and the pool drained
+/// before a restart with [`shutdown`]. Concurrent requests can be coalesced into batches
+/// with a [`BatchScheduler`].
+///
+/// ## Example
+///
+/// ```
+/// let pool = GraphRuntimePool::new(&artifacts, TVMContext::cpu(0), 4).unwrap();
+/// pool.run(|runtime| {
+///     runtime.set_input("data", &input)?;
+///     runtime.run()?;
+///     runtime.get_output(0, &output)
+/// }).unwrap();
+/// pool.swap_model(&new_artifacts).unwrap();
+/// pool.shutdown(Duration::from_secs(30)).unwrap();
+/// ```
+///
+/// [`run_with`]:struct.GraphRuntimePool.html#method.run_with
+/// [`queue_capacity`]:struct.GraphRuntimePool.html#method.queue_capacity
+/// [`swap_model`]:struct.GraphRuntimePool.html#method.swap_model
+/// [`shutdown`]:struct.GraphRuntimePool.html#method.shutdown
+/// [`BatchScheduler`]:../batching/struct.BatchScheduler.html
+#[derive(Debug)]
+pub struct GraphRuntimePool {
+    ctx: TVMContext,
+    size: usize,
+    max_queued: Option<usize>,
+    current: RwLock<Arc<Generation>>,
+    closed: AtomicBool,
+    in_flight: Arc<InFlight>,
+}
+
+impl GraphRuntimePool {
+    /// Creates `size` executors of the model on the given context.
+    pub fn new(artifacts: &Artifacts, ctx: TVMContext, size: usize) -> Result<Self> {
+        let generation = Generation::build(artifacts, &ctx, size)?;
+        Ok(GraphRuntimePool {
+            ctx,
+            size,
+            max_queued: None,
+            current: RwLock::new(Arc::new(generation)),
+            closed: AtomicBool::new(false),
+            in_flight: Arc::new(InFlight::default()),
+        })
+    }
+
+    /// Bounds the number of calls waiting for an executor, failing the next ones with
+    /// `ErrorKind::QueueFull` instead of queuing them. The queue is unbounded by default.
+    pub fn queue_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.max_queued = Some(capacity);
+        self
+    }
+
+    // Accepts a call unless the pool is shut down.
+    fn enter(&self) -> Result<InFlightGuard> {
+        *self.in_flight.count.lock().unwrap() += 1;
+        let guard = InFlightGuard(self.in_flight.clone());
+        if self.closed.load(Ordering::SeqCst) {
+            bail!(ErrorKind::PoolShutDown);
+        }
+        Ok(guard)
+    }
+
+    /// Runs `f` on an idle executor, blocking until one becomes available.
+    ///
+    /// Fails with `ErrorKind::PoolShutDown` once the pool is [`shutdown`], including the
+    /// calls still waiting for an executor.
+    ///
+    /// [`shutdown`]:struct.GraphRuntimePool.html#method.shutdown
+    pub fn run<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut GraphRuntime) -> Result<R>,
+    {
+        self.run_with(&RunOptions::default(), f)
+    }
+
+    /// Runs `f` like [`run`], waiting for an executor with the priority and the deadline
+    /// of the options.
+    ///
+    /// Fails with `ErrorKind::QueueFull` if the queue is at its [`queue_capacity`], and
+    /// with `ErrorKind::DeadlineExceeded` if no executor is available by the deadline.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let opts = RunOptions {
+    ///     priority: 10,
+    ///     deadline: Some(Instant::now() + Duration::from_millis(50)),
+    /// };
+    /// match pool.run_with(&opts, |runtime| runtime.run()) {
+    ///     Err(Error(ErrorKind::QueueFull(_), _)) => respond_busy(),
+    ///     ret => ret.unwrap(),
+    /// }
+    /// ```
+    ///
+    /// [`run`]:struct.GraphRuntimePool.html#method.run
+    /// [`queue_capacity`]:struct.GraphRuntimePool.html#method.queue_capacity
+    pub fn run_with<F, R>(&self, opts: &RunOptions, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut GraphRuntime) -> Result<R>,
+    {
+        let _guard = self.enter()?;
+        let generation = self.current.read().unwrap().clone();
+        let mut lease = Lease::acquire(generation, &self.closed, opts, self.max_queued)?;
+        f(lease.executor())
+    }
+
+    /// Runs `f` on an idle executor like [`run`], failing with `ErrorKind::Timeout` if it
+    /// does not return within `timeout`, so that a hung kernel cannot block the caller.
+    ///
+    /// `f` runs on a watchdog thread owning the executor. On timeout it keeps running in
+    /// the background and the executor only returns to the pool once `f` returns, so a
+    /// hung executor reduces the capacity of the pool instead of blocking the service.
+    ///
+    /// [`run`]:struct.GraphRuntimePool.html#method.run
+    pub fn run_with_timeout<F, R>(&self, timeout: Duration, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut GraphRuntime) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        self.run_detached(f, Some(timeout), None)
+    }
+
+    /// Runs `f` like [`run_with_timeout`] without a timeout, failing with
+    /// `ErrorKind::Cancelled` as soon as the token is cancelled from another thread.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let token = CancelToken::new();
+    /// let canceller = token.clone();
+    /// thread::spawn(move || {
+    ///     thread::sleep(Duration::from_secs(1));
+    ///     canceller.cancel();
+    /// });
+    /// let ret = pool.run_cancellable(&token, move |runtime| runtime.run());
+    /// ```
+    ///
+    /// [`run_with_timeout`]:struct.GraphRuntimePool.html#method.run_with_timeout
+    pub fn run_cancellable<F, R>(&self, token: &CancelToken, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut GraphRuntime) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        self.run_detached(f, None, Some(token))
+    }
+
+    fn run_detached<F, R>(
+        &self,
+        f: F,
+        timeout: Option<Duration>,
+        token: Option<&CancelToken>,
+    ) -> Result<R>
+    where
+        F: FnOnce(&mut GraphRuntime) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        if token.map_or(false, CancelToken::is_cancelled) {
+            bail!(ErrorKind::Cancelled);
+        }
+        let guard = self.enter()?;
+        let generation = self.current.read().unwrap().clone();
+        let mut lease = Lease::acquire(
+            generation,
+            &self.closed,
+            &RunOptions::default(),
+            self.max_queued,
+        )?;
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("tvm-watchdog".to_owned())
+            .spawn(move || {
+                // the call stays in flight until the executor is back in the pool, which
+                // the lease returns it to even if `f` panics
+                let _guard = guard;
+                let ret = f(lease.executor());
+                drop(lease);
+                let _ = sender.send(ret);
+            })?;
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let now = Instant::now();
+            let mut wait = CANCEL_POLL_INTERVAL;
+            if let Some(deadline) = deadline {
+                if now >= deadline {
+                    bail!(ErrorKind::Timeout(timeout.expect("the deadline has a timeout")));
+                }
+                wait = wait.min(deadline - now);
+            }
+            match receiver.recv_timeout(wait) {
+                Ok(ret) => return ret,
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => bail!("the watchdog thread panicked"),
+            }
+            if token.map_or(false, CancelToken::is_cancelled) {
+                bail!(ErrorKind::Cancelled);
+            }
+        }
+    }
+
+    /// Replaces the served model with zero downtime.
+    ///
+    /// The new executors are built while the pool keeps serving requests on the
+    /// current ones. Then incoming requests are atomically switched over and the
+    /// old executors are destroyed as soon as their in-flight requests finish.
+    /// On error, the current model is kept.
+    pub fn swap_model(&self, artifacts: &Artifacts) -> Result<()> {
+        if self.is_shut_down() {
+            bail!(ErrorKind::PoolShutDown);
+        }
+        let generation = Generation::build(artifacts, &self.ctx, self.size)?;
+        let mut current = self.current.write().unwrap();
+        // the pool may have been shut down while building
+        if self.is_shut_down() {
+            bail!(ErrorKind::PoolShutDown);
+        }
+        *current = Arc::new(generation);
+        Ok(())
+    }
+
+    /// Shuts the pool down for a clean restart, giving the in-flight calls up to `grace`
+    /// to return, then destroys the executors, releasing their device memory.
+    ///
+    /// New calls fail with `ErrorKind::PoolShutDown` from then on, like the calls still
+    /// waiting for an executor. If calls are still running after `grace`, fails with
+    /// `ErrorKind::Timeout` after destroying the idle executors, and the busy ones are
+    /// destroyed as soon as their call returns. Shutting down again waits for them again.
+    pub fn shutdown(&self, grace: Duration) -> Result<()> {
+        let deadline = Instant::now() + grace;
+        self.closed.store(true, Ordering::SeqCst);
+        let generation = mem::replace(
+            &mut *self.current.write().unwrap(),
+            Arc::new(Generation::default()),
+        );
+        // wakes up the calls waiting for an executor, which fail
+        {
+            let _slots = generation.slots.lock().unwrap();
+            generation.available.notify_all();
+        }
+        let mut count = self.in_flight.count.lock().unwrap();
+        while *count > 0 {
+            let now = Instant::now();
+            if now >= deadline {
+                drop(count);
+                generation.slots.lock().unwrap().idle.clear();
+                bail!(ErrorKind::Timeout(grace));
+            }
+            count = self
+                .in_flight
+                .drained
+                .wait_timeout(count, deadline - now)
+                .unwrap()
+                .0;
+        }
+        drop(count);
+        generation.slots.lock().unwrap().idle.clear();
+        Ok(())
+    }
+
+    /// Returns whether [`shutdown`] was called.
+    ///
+    /// [`shutdown`]:struct.GraphRuntimePool.html#method.shutdown
+    pub fn is_shut_down(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of calls waiting for an executor.
+    pub fn num_queued(&self) -> usize {
+        self.current.read().unwrap().num_waiting()
+    }
+
+    /// Returns the number of executors in the pool.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the context which the executors were created on.
+    pub fn ctx(&self) -> &TVMContext {
+        &self.ctx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::TEST_GRAPH;
+    use std::panic::{self, AssertUnwindSafe};
+    use Error;
+
+    #[test]
+    fn inputs() {
+        let inputs = parse_inputs(TEST_GRAPH).unwrap();
+        assert_eq!(inputs.len(), 2);
+        assert_eq!(
+            inputs[0],
+            InputInfo {
+                name: "data".to_owned(),
+                shape: vec![1, 4],
+                dtype: "float32".to_owned(),
+            }
+        );
+        assert_eq!(inputs[1].name, "weight");
+        assert!(parse_inputs("{}").is_err());
+    }
+
+    #[test]
+    fn rebatch() {
+        let graph = rebatch_graph(TEST_GRAPH, 0, 8).unwrap();
+        let inputs = parse_inputs(&graph).unwrap();
+        assert_eq!(inputs[0].shape, vec![8, 4]);
+        assert_eq!(inputs[1].shape, vec![2, 4]);
+        let graph: Value = serde_json::from_str(&graph).unwrap();
+        assert_eq!(graph["attrs"]["shape"][1][2], Value::from(vec![8, 2]));
+        assert!(rebatch_graph(TEST_GRAPH, 2, 8).is_err());
+
+        // the output of a node computed from the weight only keeps its shape
+        let mut graph: Value = serde_json::from_str(TEST_GRAPH).unwrap();
+        graph["nodes"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({"op": "tvm_op", "name": "norm", "inputs": [[1, 0, 0]]}));
+        graph["node_row_ptr"] = json!([0, 1, 2, 3, 4]);
+        graph["attrs"]["shape"][1] = json!([[1, 4], [2, 4], [1, 2], [1, 4]]);
+        let graph = rebatch_graph(&graph.to_string(), 0, 8).unwrap();
+        let graph: Value = serde_json::from_str(&graph).unwrap();
+        assert_eq!(graph["attrs"]["shape"][1][2], Value::from(vec![8, 2]));
+        assert_eq!(graph["attrs"]["shape"][1][3], Value::from(vec![1, 4]));
+    }
+
+    #[test]
+    fn outputs() {
+        let outputs = parse_outputs(TEST_GRAPH).unwrap();
+        assert_eq!(outputs, vec![(vec![1, 2], "float32".to_owned())]);
+    }
+
+    #[test]
+    fn double_buffer() {
+        let alloc = |v: i64| {
+            let mut array = empty(&mut [1], TVMContext::cpu(0), "int64".parse().unwrap());
+            array.copy_from_buffer(&[v]).unwrap();
+            Mutex::new(array)
+        };
+        let staged = DoubleBuffer {
+            name: "data".to_owned(),
+            buffers: [alloc(1), alloc(2)],
+            front: AtomicUsize::new(0),
+        };
+        let read = |front| staged.lock(front).to_vec::<i64>().unwrap();
+        assert_eq!((read(true), read(false)), (vec![1], vec![2]));
+        staged.swap();
+        assert_eq!(read(true), vec![2]);
+        staged.fill(|back| back.copy_from_buffer(&[3i64])).unwrap();
+        assert_eq!(read(true), vec![2]);
+        staged.swap();
+        assert_eq!(read(true), vec![3]);
+    }
+
+    #[test]
+    fn missing_artifacts() {
+        assert!(Artifacts::from_files("graph.json", "lib.so", "param.params").is_err());
+    }
+
+    #[test]
+    fn shutdown() {
+        let pool = Arc::new(GraphRuntimePool {
+            ctx: TVMContext::cpu(0),
+            size: 0,
+            max_queued: None,
+            current: RwLock::new(Arc::new(Generation::default())),
+            closed: AtomicBool::new(false),
+            in_flight: Arc::new(InFlight::default()),
+        });
+        // a call waiting for an executor fails on shutdown
+        let waiting = {
+            let pool = pool.clone();
+            thread::spawn(move || pool.run(|_| Ok(())))
+        };
+        let in_flight = pool.enter().unwrap();
+        match pool.shutdown(Duration::from_millis(10)) {
+            Err(Error(ErrorKind::Timeout(_), _)) => (),
+            ret => panic!("expected a timeout, found {:?}", ret),
+        }
+        match waiting.join().unwrap() {
+            Err(Error(ErrorKind::PoolShutDown, _)) => (),
+            ret => panic!("expected a shutdown error, found {:?}", ret),
+        }
+        assert!(pool.is_shut_down());
+        assert!(pool.enter().is_err());
+        drop(in_flight);
+        pool.shutdown(Duration::from_millis(10)).unwrap();
+    }
+
+    #[test]
+    fn priorities() {
+        let generation = Arc::new(Generation::<u32>::default());
+        let closed = Arc::new(AtomicBool::new(false));
+        let waiter = |priority| {
+            let (generation, closed) = (generation.clone(), closed.clone());
+            thread::spawn(move || {
+                let opts = RunOptions::priority(priority);
+                generation.acquire(&closed, &opts, Some(2)).unwrap()
+            })
+        };
+        let low = waiter(0);
+        while generation.num_waiting() < 1 {
+            thread::yield_now();
+        }
+        let high = waiter(10);
+        while generation.num_waiting() < 2 {
+            thread::yield_now();
+        }
+        match generation.acquire(&closed, &RunOptions::default(), Some(2)) {
+            Err(Error(ErrorKind::QueueFull(2), _)) => (),
+            ret => panic!("expected a full queue, found {:?}", ret),
+        }
+        generation.release(1);
+        assert_eq!(high.join().unwrap(), 1);
+        generation.release(2);
+        assert_eq!(low.join().unwrap(), 2);
+    }
+
+    #[test]
+    fn deadlines() {
+        let generation = Generation::<u32>::default();
+        let closed = AtomicBool::new(false);
+        let opts = RunOptions::deadline_in(Duration::from_millis(10));
+        match generation.acquire(&closed, &opts, None) {
+            Err(Error(ErrorKind::DeadlineExceeded, _)) => (),
+            ret => panic!("expected a missed deadline, found {:?}", ret),
+        }
+        assert_eq!(generation.num_waiting(), 0);
+        generation.release(1);
+        assert_eq!(generation.acquire(&closed, &RunOptions::default(), None).unwrap(), 1);
+        generation.release(1);
+        let late = RunOptions {
+            deadline: Some(Instant::now() - Duration::from_millis(1)),
+            ..Default::default()
+        };
+        assert!(generation.acquire(&closed, &late, None).is_err());
+    }
+
+    #[test]
+    fn lease() {
+        let generation = Arc::new(Generation::new(vec![1u32]));
+        let closed = AtomicBool::new(false);
+        let opts = RunOptions::default();
+        let ret = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut lease = Lease::acquire(generation.clone(), &closed, &opts, None).unwrap();
+            *lease.executor() += 1;
+            panic!("the call failed");
+        }));
+        assert!(ret.is_err());
+        // the executor went back to the pool
+        assert_eq!(generation.acquire(&closed, &opts, None).unwrap(), 2);
+    }
+}
@@ -1,30 +1,162 @@
-use std::{cell::RefCell, collections::HashMap};
+//! Typed wrappers of the internal global functions of the runtime the crate relies on.
+//!
+//! Each wrapper documents the argument schema of the packed function in its signature,
+//! checks the type of the return value and fails instead of panicking if the function
+//! is not registered. The functions are looked up once per process, see
+//! [`function::get_global_func`].
+//!
+//! [`function::get_global_func`]:../function/fn.get_global_func.html
 
+use function;
+use ty::TypeCode;
+use value;
+use ErrorKind;
 use Function;
+use Module;
+use Result;
+use RetValue;
+use TVMRetValue;
 
-// access TVM internal API
-thread_local! {
-    pub(crate) static API: RefCell<HashMap<String, Function>> = RefCell::new(HashMap::new());
+// Returns the global function, failing if it is not registered.
+fn get_api(name: &'static str) -> Result<Function> {
+    match function::get_global_func(name, true) {
+        Some(func) => Ok(func),
+        None => bail!(ErrorKind::NullHandle(name.to_owned())),
+    }
+}
+
+
+// Passes scalars by reference to `call_packed!`, like the other arguments.
+trait AsArg {
+    type Target: ?Sized;
+
+    fn as_arg(&self) -> &Self::Target;
+}
+
+impl AsArg for usize {
+    type Target = usize;
+
+    fn as_arg(&self) -> &usize {
+        self
+    }
 }
 
-pub(crate) fn get(name: String) -> Option<Function> {
-    API.with(|hm| hm.borrow().get(&name).map(|f| f.clone()))
+impl AsArg for i32 {
+    type Target = i32;
+
+    fn as_arg(&self) -> &i32 {
+        self
+    }
 }
 
-pub(crate) fn set(name: String, func: Function) {
-    API.with(|hm| {
-        (*hm.borrow_mut()).insert(name, func);
-    })
+impl AsArg for bool {
+    type Target = bool;
+
+    fn as_arg(&self) -> &bool {
+        self
+    }
 }
 
-pub(crate) fn get_api(name: String) -> Function {
-    let mut func = get(name.clone());
-    if func.is_none() {
-        func = Function::get_function(&name, true);
-        set(
-            name,
-            func.clone().expect("access to `internal_api` never panics"),
-        );
+impl<'a, T: ?Sized> AsArg for &'a T {
+    type Target = T;
+
+    fn as_arg(&self) -> &T {
+        *self
+    }
+}
+
+macro_rules! internal_api {
+    ($(
+        $(#[$attr:meta])*
+        fn $fn_name:ident($($arg:ident: $ty:ty),*) -> $ret_ty:ty = $name:expr,
+            |$ret:ident| $convert:expr;
+    )+) => {
+        $(
+            $(#[$attr])*
+            pub(crate) fn $fn_name($($arg: $ty),*) -> Result<$ret_ty> {
+                let func = get_api($name)?;
+                let $ret = call_packed!(func, $($arg.as_arg()),*)?;
+                $convert
+            }
+        )+
+    };
+}
+
+internal_api! {
+    /// Loads a module file, where `format` is e.g. `so` or `ptx`.
+    fn load_from_file(path: &str, format: &str) -> Module = "module._LoadFromFile", |ret| {
+        ret.try_into_module()
+    };
+
+    /// Checks whether the runtime supports the `target`, e.g. `llvm` or `cuda`.
+    fn enabled(target: &str) -> bool = "module._Enabled", |ret| {
+        match ret.try_into_value()? {
+            RetValue::Int(v) => Ok(v != 0),
+            other => Err(value::type_mismatch(TypeCode::kDLInt, &other)),
+        }
+    };
+
+    /// Queries the attribute `kind` of a device, see `DeviceAttrKind` in the runtime.
+    /// The value is an integer or a string depending on the attribute.
+    fn get_device_attr(
+        device_type: usize,
+        device_id: usize,
+        kind: usize
+    ) -> TVMRetValue = "_GetDeviceAttr", |ret| Ok(ret);
+
+    /// Restarts the thread pool of the runtime with `num_threads` workers, or as many as
+    /// the cores if `0`. The `mode` binds the workers to the big cores if `1`, the
+    /// little ones if `-1`.
+    fn config_threadpool(
+        mode: i32,
+        num_threads: usize
+    ) -> () = "runtime.config_threadpool", |_ret| Ok(());
+
+    /// Sets the current device of the calling thread.
+    fn set_device(
+        device_type: usize,
+        device_id: usize
+    ) -> () = "__tvm_set_device", |_ret| Ok(());
+
+    /// Returns the type key of a module, e.g. `llvm`.
+    fn get_type_key(module: &Module) -> String = "module._GetTypeKey", |ret| {
+        value::into_string(ret)
+    };
+
+    /// Saves a module into the file `path` with the `format`, e.g. `o`.
+    fn save_to_file(
+        module: &Module,
+        path: &str,
+        format: &str
+    ) -> () = "module._SaveToFile", |_ret| Ok(());
+
+    /// Returns the number of modules imported by a module.
+    fn imports_size(module: &Module) -> usize = "module._ImportsSize", |ret| ret.to_usize();
+
+    /// Returns the module imported by a module at `index`.
+    fn get_import(module: &Module, index: usize) -> Module = "module._GetImport", |ret| {
+        ret.try_into_module()
+    };
+
+    /// Packs the imported device modules of a module into a C source, which registers
+    /// them in the system library if `system_lib` is set.
+    fn pack_imports_to_c(
+        module: &Module,
+        system_lib: bool
+    ) -> String = "codegen._PackImportsToC", |ret| value::into_string(ret);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typed_calls() {
+        assert!(enabled("llvm").unwrap());
+        assert!(get_device_attr(1, 0, 0).unwrap().to_bool());
+        match get_api("does not exist") {
+            Err(err) => assert!(err.to_string().contains("does not exist")),
+            Ok(_) => panic!("expected a missing function"),
+        }
     }
-    func.expect("access to `internal_api` never panics")
 }
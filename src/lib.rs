@@ -17,14 +17,34 @@
 
 #[macro_use]
 extern crate error_chain;
+#[macro_use]
 extern crate tvm_sys as ts;
 #[macro_use]
 extern crate lazy_static;
 extern crate ndarray as rust_ndarray;
 extern crate num_traits;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+extern crate smallvec;
+#[cfg(feature = "arrow")]
+extern crate arrow as rust_arrow;
+#[cfg(feature = "pyo3")]
+extern crate pyo3;
+#[cfg(feature = "tch")]
+extern crate tch as rust_tch;
+#[cfg(feature = "image")]
+extern crate image;
+#[cfg(feature = "dynamic")]
+extern crate libloading;
+#[cfg(feature = "toml")]
+extern crate toml;
 
 use std::{
     ffi::{CStr, CString},
+    fmt::Display,
     str,
 };
 
@@ -37,42 +57,158 @@ macro_rules! check_call {
     }};
 }
 
-/// Gets the last error message
-pub fn get_last_error() -> &'static str {
+/// Gets the last error message of the TVM runtime on the current thread.
+///
+/// The message is copied out of the runtime-owned buffer, which is overwritten
+/// by the next failing call on the same thread.
+pub fn get_last_error() -> String {
     unsafe {
-        match CStr::from_ptr(ts::TVMGetLastError()).to_str() {
-            Ok(s) => s,
-            Err(_) => "Invalid UTF-8 message",
-        }
+        CStr::from_ptr(ffi::api().get_last_error())
+            .to_string_lossy()
+            .into_owned()
     }
 }
 
-pub(crate) fn set_last_error(err: &Error) {
-    let c_string = CString::new(err.to_string()).unwrap();
+/// Sets the last error message of the TVM runtime on the current thread.
+///
+/// Like the runtime, the last error is thread-local, so an error set inside a callback
+/// is observed by the caller on the same thread only. Interior NUL bytes are removed.
+pub fn set_last_error<E: Display + ?Sized>(err: &E) {
+    let msg = err.to_string().replace('\0', "");
+    let c_string = CString::new(msg).expect("NUL bytes are removed");
     unsafe {
-        ts::TVMAPISetLastError(c_string.as_ptr());
+        ffi::api().set_last_error(c_string.as_ptr());
     }
 }
 
 #[macro_use]
 pub mod function;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod batching;
 pub mod bytearray;
+#[cfg(feature = "compiler")]
+pub mod compile;
+pub mod config;
 pub mod context;
+pub mod contrib;
 pub mod errors;
+pub mod ffi;
+pub mod graph;
+pub mod graph_runtime;
 mod internal_api;
+pub mod measure;
+pub mod memory;
+pub mod metadata;
 pub mod module;
 pub mod ndarray;
+pub mod ops;
+pub mod plugin;
+#[cfg(feature = "image")]
+pub mod preprocess;
+#[cfg(feature = "pyo3")]
+pub mod python;
+pub mod registry;
+#[cfg(feature = "compiler")]
+pub mod relay;
+pub mod replay;
+pub mod runtime;
+pub mod shape;
+#[cfg(feature = "tch")]
+pub mod tch;
+pub mod testing;
+pub mod tuning;
 pub mod ty;
 pub mod value;
+pub mod vm;
+#[cfg(feature = "image")]
+pub mod zoo;
 
 pub use bytearray::TVMByteArray;
 pub use context::{TVMContext, TVMDeviceType};
 pub use errors::*;
 pub use function::Function;
+pub use graph_runtime::{Artifacts, GraphRuntime};
 pub use module::Module;
 pub use ndarray::{empty, NDArray};
+pub use runtime::{init, shutdown, Config, Runtime, Target};
+pub use shape::{Shape, ShapeTuple};
+pub use ty::{DataType, TVMDataType};
+#[allow(deprecated)]
 pub use ty::TVMType;
-pub use value::{TVMArgValue, TVMRetValue};
+pub use value::{ArgValue, RetValue, TVMArgValue, TVMRetValue};
+
+// The types formerly re-exported at the root, which only keeps the common types of the
+// prelude and the runtime entry points.
+
+/// Moved to [`batching::BatchScheduler`](batching/struct.BatchScheduler.html).
+#[deprecated(since = "0.2.0", note = "use `batching::BatchScheduler`")]
+pub type BatchScheduler = batching::BatchScheduler;
+/// Moved to [`graph::GraphJson`](graph/struct.GraphJson.html).
+#[deprecated(since = "0.2.0", note = "use `graph::GraphJson`")]
+pub type Graph = graph::GraphJson;
+/// Moved to [`graph::Params`](graph/struct.Params.html).
+#[deprecated(since = "0.2.0", note = "use `graph::Params`")]
+pub type Params = graph::Params;
+/// Moved to [`graph_runtime::CancelToken`](graph_runtime/struct.CancelToken.html).
+#[deprecated(since = "0.2.0", note = "use `graph_runtime::CancelToken`")]
+pub type CancelToken = graph_runtime::CancelToken;
+/// Moved to [`graph_runtime::DoubleBuffer`](graph_runtime/struct.DoubleBuffer.html).
+#[deprecated(since = "0.2.0", note = "use `graph_runtime::DoubleBuffer`")]
+pub type DoubleBuffer = graph_runtime::DoubleBuffer;
+/// Moved to [`graph_runtime::GraphRuntimePool`](graph_runtime/struct.GraphRuntimePool.html).
+#[deprecated(since = "0.2.0", note = "use `graph_runtime::GraphRuntimePool`")]
+pub type GraphRuntimePool = graph_runtime::GraphRuntimePool;
+/// Moved to [`graph_runtime::InferenceSession`](graph_runtime/struct.InferenceSession.html).
+#[deprecated(since = "0.2.0", note = "use `graph_runtime::InferenceSession`")]
+pub type InferenceSession<'a> = graph_runtime::InferenceSession<'a>;
+/// Moved to [`graph_runtime::InputInfo`](graph_runtime/struct.InputInfo.html).
+#[deprecated(since = "0.2.0", note = "use `graph_runtime::InputInfo`")]
+pub type InputInfo = graph_runtime::InputInfo;
+/// Moved to [`graph_runtime::NDArrayView`](graph_runtime/struct.NDArrayView.html).
+#[deprecated(since = "0.2.0", note = "use `graph_runtime::NDArrayView`")]
+pub type NDArrayView<'a> = graph_runtime::NDArrayView<'a>;
+/// Moved to [`graph_runtime::RunOptions`](graph_runtime/struct.RunOptions.html).
+#[deprecated(since = "0.2.0", note = "use `graph_runtime::RunOptions`")]
+pub type RunOptions = graph_runtime::RunOptions;
+/// Moved to [`module::FunctionOptions`](module/struct.FunctionOptions.html).
+#[deprecated(since = "0.2.0", note = "use `module::FunctionOptions`")]
+pub type FunctionOptions = module::FunctionOptions;
+/// Moved to [`module::LazyModule`](module/struct.LazyModule.html).
+#[deprecated(since = "0.2.0", note = "use `module::LazyModule`")]
+pub type LazyModule = module::LazyModule;
+/// Moved to [`metadata::Metadata`](metadata/struct.Metadata.html).
+#[deprecated(since = "0.2.0", note = "use `metadata::Metadata`")]
+pub type Metadata = metadata::Metadata;
+/// Moved to [`registry::ModelRegistry`](registry/struct.ModelRegistry.html).
+#[deprecated(since = "0.2.0", note = "use `registry::ModelRegistry`")]
+pub type ModelRegistry = registry::ModelRegistry;
+
+/// The commonly used types and macros, so that user code needs a single import.
+///
+/// ```
+/// use tvm::prelude::*;
+///
+/// let mut nd = empty(&mut [2], TVMContext::cpu(0), DataType::from("float"));
+/// nd.copy_from_buffer(&[1f32, 2.]).unwrap();
+/// ```
+pub mod prelude {
+    pub use call_packed;
+    pub use register_global_func;
+
+    pub use errors::{Error, ErrorKind, Result, ResultExt};
+    pub use function::{self, Function};
+    pub use graph_runtime::{Artifacts, GraphRuntime};
+    pub use module::Module;
+    pub use ndarray::{empty, NDArray};
+    pub use runtime::init;
+    pub use shape::{Shape, ShapeTuple};
+    pub use ty::{DataType, TVMDataType};
+    pub use value::{ArgValue, RetValue, TVMArgValue, TVMRetValue};
+    pub use TVMByteArray;
+    pub use TVMContext;
+    pub use TVMDeviceType;
+}
 
 /// Outputs the current TVM version
 pub fn version() -> &'static str {
@@ -86,6 +222,8 @@ pub fn version() -> &'static str {
 mod tests {
     use super::*;
 
+    use std::thread;
+
     #[test]
     fn print_version() {
         println!("TVM version: {}", version());
@@ -93,8 +231,21 @@ mod tests {
 
     #[test]
     fn set_error() {
-        let err = ErrorKind::EmptyArray;
-        set_last_error(&err.into());
+        let err: Error = ErrorKind::EmptyArray.into();
+        set_last_error(&err);
         assert_eq!(get_last_error().trim(), ErrorKind::EmptyArray.to_string());
+        set_last_error("with\0nul");
+        assert_eq!(get_last_error().trim(), "withnul");
+    }
+
+    #[test]
+    fn error_is_thread_local() {
+        set_last_error("main thread");
+        let handle = thread::spawn(|| {
+            set_last_error("other thread");
+            assert_eq!(get_last_error().trim(), "other thread");
+        });
+        handle.join().unwrap();
+        assert_eq!(get_last_error().trim(), "main thread");
     }
 }
@@ -0,0 +1,349 @@
+//! Measures candidate kernels of a tuning campaign on a device, locally or through an RPC
+//! server, so a Rust host can act as the runner of AutoTVM and the auto-scheduler.
+//!
+//! A [`Runner`] loads the built library of every [`MeasureInput`], times its function
+//! with the time evaluator of the runtime on empty arguments allocated on the device, and
+//! reports a [`MeasureResult`] in the layout of the `result` field of tuning logs.
+//!
+//! Remote measurement needs a runtime built with RPC support. The global functions are
+//! looked up by their names in the TVM versions the crate supports, otherwise the
+//! measurements fail with `ErrorKind::NullHandle`.
+//!
+//! # Example
+//!
+//! ```
+//! let mut runner = Runner::connect("127.0.0.1", 9090, "", TVMContext::gpu(0)).unwrap();
+//! runner.repeat(3).timeout(Duration::from_secs(10));
+//! let input = MeasureInput::new("dense_1.so", "default_function")
+//!     .arg(vec![1, 512], DataType::from("float"))
+//!     .arg(vec![512, 512], DataType::from("float"))
+//!     .arg(vec![1, 512], DataType::from("float"));
+//! for result in runner.run(&[input]) {
+//!     println!("{}", result.to_json());
+//! }
+//! ```
+//!
+//! [`Runner`]:struct.Runner.html
+//! [`MeasureInput`]:struct.MeasureInput.html
+//! [`MeasureResult`]:struct.MeasureResult.html
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{self, Value};
+
+use context::RPC_SESS_MASK;
+use function::{self, Builder};
+use ndarray;
+use ty::TypeCode;
+use value;
+use DataType;
+use Error;
+use ErrorKind;
+use Module;
+use Result;
+use RetValue;
+use TVMByteArray;
+use TVMContext;
+use TVMDeviceType;
+
+/// The global functions creating a time evaluator, by TVM version.
+pub const TIME_EVALUATOR_FUNCTIONS: [&'static str; 2] =
+    ["runtime.RPCTimeEvaluator", "module._RPCTimeEvaluator"];
+
+const CONNECT_FUNCTIONS: [&'static str; 2] = ["rpc.Connect", "rpc._Connect"];
+const LOAD_REMOTE_MODULE_FUNCTIONS: [&'static str; 2] =
+    ["rpc.LoadRemoteModule", "rpc._LoadRemoteModule"];
+const SESS_TABLE_INDEX_FUNCTIONS: [&'static str; 2] =
+    ["rpc.SessTableIndex", "rpc._SessTableIndex"];
+const UPLOAD: &'static str = "tvm.rpc.server.upload";
+
+/// The kind of a failed measurement, numbered as in AutoTVM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MeasureErrorNo {
+    NoError = 0,
+    InstantiationError = 1,
+    CompileHostError = 2,
+    CompileDeviceError = 3,
+    RuntimeDeviceError = 4,
+    WrongAnswerError = 5,
+    BuildTimeoutError = 6,
+    RunTimeoutError = 7,
+    UnknownError = 8,
+}
+
+/// A candidate kernel to measure: a built library and the shapes and dtypes of the
+/// arguments of its function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeasureInput {
+    pub lib: PathBuf,
+    pub func_name: String,
+    pub args: Vec<(Vec<usize>, DataType)>,
+}
+
+impl MeasureInput {
+    pub fn new<P: AsRef<Path>>(lib: P, func_name: &str) -> Self {
+        MeasureInput {
+            lib: lib.as_ref().to_owned(),
+            func_name: func_name.to_owned(),
+            args: Vec::new(),
+        }
+    }
+
+    /// Adds an argument of the function, allocated empty on the device when measuring.
+    pub fn arg(mut self, shape: Vec<usize>, dtype: DataType) -> Self {
+        self.args.push((shape, dtype));
+        self
+    }
+}
+
+/// The outcome of measuring a [`MeasureInput`].
+///
+/// [`MeasureInput`]:struct.MeasureInput.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeasureResult {
+    /// The mean cost in seconds of every repeat, or empty if the measurement failed.
+    pub costs: Vec<f64>,
+    pub error_no: MeasureErrorNo,
+    /// The error of a failed measurement.
+    pub error_msg: Option<String>,
+    /// The seconds spent on the whole measurement, including uploading and loading.
+    pub all_cost: f64,
+    /// The seconds since the Unix epoch when the measurement finished.
+    pub timestamp: f64,
+}
+
+impl MeasureResult {
+    /// Returns the `[costs, error_no, all_cost, timestamp]` array written into tuning
+    /// logs, with the error message in place of the costs of a failed measurement.
+    pub fn to_json(&self) -> Value {
+        let costs = match self.error_msg {
+            Some(ref msg) => Value::from(vec![msg.clone()]),
+            None => Value::from(self.costs.clone()),
+        };
+        Value::Array(vec![
+            costs,
+            Value::from(self.error_no as i64),
+            Value::from(self.all_cost),
+            Value::from(self.timestamp),
+        ])
+    }
+}
+
+/// Measures candidate kernels with the time evaluator of the runtime.
+///
+/// Every candidate is timed with `repeat` measurements, each the mean of `number` runs
+/// taking together at least `min_repeat_ms` milliseconds.
+#[derive(Debug, Clone)]
+pub struct Runner {
+    session: Option<Module>,
+    ctx: TVMContext,
+    number: usize,
+    repeat: usize,
+    min_repeat_ms: usize,
+    timeout: Duration,
+}
+
+impl Runner {
+    /// Measures the candidates on a device of this process.
+    pub fn local(ctx: TVMContext) -> Self {
+        Runner {
+            session: None,
+            ctx,
+            number: 1,
+            repeat: 1,
+            min_repeat_ms: 0,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Connects to an RPC server, or a tracker forwarding to a server with the `key`, and
+    /// measures the candidates on its device `ctx`.
+    pub fn connect(host: &str, port: u16, key: &str, ctx: TVMContext) -> Result<Self> {
+        let connect_fn = function::get_first_global_func(&CONNECT_FUNCTIONS)?;
+        let session = call_packed!(connect_fn, host, &(port as i64), key)?.try_into_module()?;
+        let index_fn = function::get_first_global_func(&SESS_TABLE_INDEX_FUNCTIONS)?;
+        let index = call_packed!(index_fn, &session)?.to_int() as usize;
+        // the device types of a session are offset by the mask times its index plus one
+        let device_type = ctx.device_type.0 + (index + 1) * RPC_SESS_MASK;
+        let remote_ctx = TVMContext::new(TVMDeviceType(device_type), ctx.device_id);
+        let mut runner = Runner::local(remote_ctx);
+        runner.session = Some(session);
+        Ok(runner)
+    }
+
+    pub fn number(&mut self, number: usize) -> &mut Self {
+        self.number = number;
+        self
+    }
+
+    pub fn repeat(&mut self, repeat: usize) -> &mut Self {
+        self.repeat = repeat;
+        self
+    }
+
+    pub fn min_repeat_ms(&mut self, min_repeat_ms: usize) -> &mut Self {
+        self.min_repeat_ms = min_repeat_ms;
+        self
+    }
+
+    /// Sets how long the timing of a candidate may take before it fails with
+    /// `MeasureErrorNo::RunTimeoutError`.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the context the candidates run on, with the session encoded in the
+    /// device type when remote.
+    pub fn context(&self) -> &TVMContext {
+        &self.ctx
+    }
+
+    /// Measures the candidates one after another, in their order.
+    pub fn run(&self, inputs: &[MeasureInput]) -> Vec<MeasureResult> {
+        inputs.iter().map(|input| self.measure(input)).collect()
+    }
+
+    fn measure(&self, input: &MeasureInput) -> MeasureResult {
+        let start = Instant::now();
+        let outcome = match self.load(input) {
+            Ok(module) => self.time(&module, input).map_err(|err| {
+                let error_no = match err {
+                    Error(ErrorKind::Timeout(_), _) => MeasureErrorNo::RunTimeoutError,
+                    _ => MeasureErrorNo::RuntimeDeviceError,
+                };
+                (error_no, err)
+            }),
+            Err(err) => Err((MeasureErrorNo::InstantiationError, err)),
+        };
+        let elapsed = start.elapsed();
+        let all_cost = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs() as f64 + f64::from(since.subsec_nanos()) * 1e-9)
+            .unwrap_or(0.0);
+        match outcome {
+            Ok(costs) => MeasureResult {
+                costs,
+                error_no: MeasureErrorNo::NoError,
+                error_msg: None,
+                all_cost,
+                timestamp,
+            },
+            Err((error_no, err)) => MeasureResult {
+                costs: Vec::new(),
+                error_no,
+                error_msg: Some(err.to_string()),
+                all_cost,
+                timestamp,
+            },
+        }
+    }
+
+    // Loads the library of the candidate, uploading it first to a remote server.
+    fn load(&self, input: &MeasureInput) -> Result<Module> {
+        let session = match self.session {
+            Some(ref session) => session,
+            None => return Module::load(&input.lib),
+        };
+        let name = match input.lib.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => bail!("invalid library path: {}", input.lib.display()),
+        };
+        let blob = fs::read(&input.lib)?;
+        let upload_fn = session.get_function(UPLOAD, false)?;
+        call_packed!(upload_fn, &name, &TVMByteArray::from(&blob))?;
+        let load_fn = function::get_first_global_func(&LOAD_REMOTE_MODULE_FUNCTIONS)?;
+        call_packed!(load_fn, session, &name)?.try_into_module()
+    }
+
+    // Times the function of the loaded candidate, returning the cost of every repeat.
+    fn time(&self, module: &Module, input: &MeasureInput) -> Result<Vec<f64>> {
+        let evaluator_fn = function::get_first_global_func(&TIME_EVALUATOR_FUNCTIONS)?;
+        let timer = call_packed!(
+            evaluator_fn,
+            module,
+            &input.func_name,
+            &self.ctx.device_type.0,
+            &self.ctx.device_id,
+            &self.number,
+            &self.repeat,
+            &self.min_repeat_ms,
+            ""
+        )?
+        .try_into_function()?;
+        let mut arrays = Vec::with_capacity(input.args.len());
+        for &(ref shape, dtype) in &input.args {
+            let mut shape = shape.clone();
+            arrays.push(ndarray::empty(&mut shape, self.ctx.clone(), dtype));
+        }
+        // the watchdog thread holds the arrays until the call returns, even after a timeout
+        let arrays = Arc::new(arrays);
+        let mut builder = Builder::from(timer);
+        builder.args(&arrays[..]);
+        let ret = unsafe { builder.invoke_keeping(self.timeout, arrays.clone()) }?;
+        match ret.try_into_value()? {
+            RetValue::Bytes(bytes) => Ok(parse_costs(&bytes.data())),
+            other => Err(value::type_mismatch(TypeCode::kBytes, &other)),
+        }
+    }
+}
+
+// Reads the little-endian `f64`s the time evaluator returns as bytes.
+fn parse_costs(bytes: &[i8]) -> Vec<f64> {
+    bytes
+        .chunks(8)
+        .filter(|chunk| chunk.len() == 8)
+        .map(|chunk| {
+            let bits = chunk
+                .iter()
+                .rev()
+                .fold(0u64, |bits, &byte| bits << 8 | u64::from(byte as u8));
+            f64::from_bits(bits)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn costs() {
+        let mut bytes = Vec::new();
+        for cost in &[0.5f64, 0.25] {
+            let bits = cost.to_bits();
+            for i in 0..8 {
+                bytes.push((bits >> (8 * i)) as u8 as i8);
+            }
+        }
+        assert_eq!(parse_costs(&bytes), vec![0.5, 0.25]);
+    }
+
+    #[test]
+    fn result_json() {
+        let result = MeasureResult {
+            costs: vec![0.5],
+            error_no: MeasureErrorNo::NoError,
+            error_msg: None,
+            all_cost: 1.0,
+            timestamp: 2.0,
+        };
+        let expected: Value = serde_json::from_str("[[0.5], 0, 1.0, 2.0]").unwrap();
+        assert_eq!(result.to_json(), expected);
+    }
+
+    #[test]
+    fn missing_library() {
+        let runner = Runner::local(TVMContext::cpu(0));
+        let input = MeasureInput::new("missing.so", "default_function");
+        let results = runner.run(&[input]);
+        assert_eq!(results[0].error_no, MeasureErrorNo::InstantiationError);
+        assert!(results[0].costs.is_empty());
+    }
+}
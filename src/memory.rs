@@ -0,0 +1,241 @@
+//! Provides an opt-in tracker of the device memory allocated through the crate, reporting
+//! the current and peak usage per [`TVMContext`] with [`memory_stats`].
+//!
+//! Tracking is disabled by default. Once enabled with [`enable_tracking`], the storage of
+//! the NDArrays allocated with [`empty`] or [`empty_managed`], the NDArrays returned by
+//! packed functions or taken over with [`from_raw`], and the [`Workspace`] buffers is
+//! recorded when allocated and when freed. Memory allocated inside the runtime itself,
+//! e.g. the intermediate buffers of a graph runtime, is not seen by the tracker.
+//!
+//! # Example
+//!
+//! ```
+//! memory::enable_tracking();
+//! let ctx = TVMContext::gpu(0);
+//! let nd = empty(&mut [1024], ctx.clone(), DataType::from("float"));
+//! drop(nd);
+//! let stats = memory::memory_stats(&ctx);
+//! assert_eq!((stats.current, stats.peak), (0, 4096));
+//! ```
+//!
+//! [`empty`]:../ndarray/fn.empty.html
+//! [`empty_managed`]:../ndarray/struct.NDArray.html#method.empty_managed
+//! [`from_raw`]:../ndarray/struct.NDArray.html#method.from_raw
+
+use std::{
+    collections::HashMap,
+    os::raw::c_void,
+    ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering, ATOMIC_BOOL_INIT, ATOMIC_USIZE_INIT},
+        Mutex,
+    },
+};
+
+use ts;
+
+use ffi;
+use DataType;
+use TVMContext;
+
+static TRACKING: AtomicBool = ATOMIC_BOOL_INIT;
+// Number of the tracked allocations not freed yet, so that frees are cheap when none is.
+static LIVE: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Memory usage of a context in bytes, as recorded by the tracker.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Bytes allocated and not freed yet.
+    pub current: usize,
+    /// Largest `current` since tracking started or the last [`reset_peak`].
+    pub peak: usize,
+    /// Total bytes allocated.
+    pub allocated: usize,
+    /// Total bytes freed.
+    pub freed: usize,
+    /// Number of allocations not freed yet.
+    pub live_allocations: usize,
+}
+
+#[derive(Default)]
+struct Tracker {
+    stats: HashMap<TVMContext, MemoryStats>,
+    // Tracked allocations by address, so that an allocation made before tracking
+    // was enabled is never recorded as freed.
+    allocations: HashMap<usize, (TVMContext, usize)>,
+}
+
+lazy_static! {
+    static ref TRACKER: Mutex<Tracker> = Mutex::new(Tracker::default());
+}
+
+/// Starts recording the allocations and frees.
+pub fn enable_tracking() {
+    TRACKING.store(true, Ordering::SeqCst);
+}
+
+/// Stops recording new allocations. The allocations recorded so far are still
+/// recorded when freed.
+pub fn disable_tracking() {
+    TRACKING.store(false, Ordering::SeqCst);
+}
+
+pub fn is_tracking() -> bool {
+    TRACKING.load(Ordering::SeqCst)
+}
+
+/// Returns the recorded memory usage of a context.
+pub fn memory_stats(ctx: &TVMContext) -> MemoryStats {
+    let tracker = TRACKER.lock().unwrap();
+    tracker.stats.get(ctx).cloned().unwrap_or_default()
+}
+
+/// Resets the peak usage of a context to its current usage.
+pub fn reset_peak(ctx: &TVMContext) {
+    let mut tracker = TRACKER.lock().unwrap();
+    if let Some(stats) = tracker.stats.get_mut(ctx) {
+        stats.peak = stats.current;
+    }
+}
+
+/// Records an allocation of `nbytes` at the address `ptr` if tracking is enabled.
+pub(crate) fn record_alloc(ptr: *const c_void, ctx: &TVMContext, nbytes: usize) {
+    if !is_tracking() {
+        return;
+    }
+    let mut tracker = TRACKER.lock().unwrap();
+    tracker
+        .allocations
+        .insert(ptr as usize, (ctx.clone(), nbytes));
+    let stats = tracker.stats.entry(ctx.clone()).or_insert_with(Default::default);
+    stats.current += nbytes;
+    stats.allocated += nbytes;
+    stats.live_allocations += 1;
+    if stats.current > stats.peak {
+        stats.peak = stats.current;
+    }
+    LIVE.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Records the free of the allocation at the address `ptr` if it was recorded.
+pub(crate) fn record_free(ptr: *const c_void) {
+    if LIVE.load(Ordering::SeqCst) == 0 {
+        return;
+    }
+    let mut tracker = TRACKER.lock().unwrap();
+    if let Some((ctx, nbytes)) = tracker.allocations.remove(&(ptr as usize)) {
+        let stats = tracker.stats.get_mut(&ctx).expect("tracked contexts have stats");
+        stats.current -= nbytes;
+        stats.freed += nbytes;
+        stats.live_allocations -= 1;
+        LIVE.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Raw device memory, e.g. scratch space shared by custom packed functions, freed on drop.
+#[derive(Debug)]
+pub struct Workspace {
+    ctx: TVMContext,
+    data: *mut c_void,
+    nbytes: usize,
+    _runtime: ffi::RuntimeRef,
+}
+
+impl Workspace {
+    /// Allocates `nbytes` in the context, aligned to `alignment` bytes. The `type_hint`
+    /// is the dtype of the entries, which some devices use to choose the storage.
+    pub fn new(ctx: TVMContext, nbytes: usize, alignment: usize, type_hint: DataType) -> Self {
+        let mut data = ptr::null_mut();
+        check_call!(ffi::api().device_alloc_data_space(
+            ctx.clone().into(),
+            nbytes,
+            alignment,
+            type_hint.inner,
+            &mut data as *mut _,
+        ));
+        record_alloc(data, &ctx, nbytes);
+        Workspace {
+            ctx,
+            data,
+            nbytes,
+            _runtime: ffi::RuntimeRef::acquire(),
+        }
+    }
+
+    pub fn ctx(&self) -> &TVMContext {
+        &self.ctx
+    }
+
+    /// Returns the pointer to the device memory.
+    pub fn data(&self) -> *mut c_void {
+        self.data
+    }
+
+    pub fn nbytes(&self) -> usize {
+        self.nbytes
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        record_free(self.data);
+        let ctx: ts::DLContext = self.ctx.clone().into();
+        check_call!(ffi::api().device_free_data_space(ctx, self.data));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::rc::Rc;
+
+    use ffi::{set_api, MockApi};
+    use ndarray::empty;
+    use NDArray;
+
+    // Disables the tracking enabled by a test when dropped, even if the test fails.
+    struct Tracking;
+
+    impl Tracking {
+        fn enable() -> Self {
+            enable_tracking();
+            Tracking
+        }
+    }
+
+    impl Drop for Tracking {
+        fn drop(&mut self) {
+            disable_tracking();
+        }
+    }
+
+    #[test]
+    fn track_ndarrays_and_workspaces() {
+        let _guard = set_api(Rc::new(MockApi::default())).unwrap();
+        // a context no other test allocates in, as the stats are global
+        let ctx = TVMContext::cpu(41);
+        let _tracking = Tracking::enable();
+        let nd = empty(&mut [2, 4], ctx.clone(), DataType::from("float"));
+        let workspace = Workspace::new(ctx.clone(), 100, 64, DataType::from("float"));
+        assert_eq!(memory_stats(&ctx).current, 132);
+        assert_eq!(memory_stats(&ctx).live_allocations, 2);
+        drop(nd);
+        assert_eq!(memory_stats(&ctx).current, 100);
+        reset_peak(&ctx);
+        drop(workspace);
+        let stats = memory_stats(&ctx);
+        assert_eq!((stats.current, stats.peak), (0, 100));
+        assert_eq!((stats.allocated, stats.freed), (132, 132));
+        assert_eq!(memory_stats(&TVMContext::cpu(42)), MemoryStats::default());
+
+        let handle = empty(&mut [4], ctx.clone(), DataType::from("float"))
+            .into_raw()
+            .unwrap();
+        assert_eq!(memory_stats(&ctx).current, 0);
+        let nd = unsafe { NDArray::from_raw(handle) };
+        assert_eq!(memory_stats(&ctx).current, 16);
+        drop(nd);
+        assert_eq!(memory_stats(&ctx).current, 0);
+    }
+}
@@ -0,0 +1,399 @@
+//! Provides [`Metadata`], a json sidecar of the [`Artifacts`] recording the names, shapes
+//! and dtypes of the inputs and outputs of a model and the pre-processing of its inputs,
+//! so that deployments look tensors up by name instead of hard-coding their indices.
+//!
+//! The sidecar of `deploy_graph.json` is `deploy_graph.meta.json`, which
+//! [`Artifacts::from_files`] reads if it exists:
+//!
+//! ```json
+//! {
+//!     "version": 1,
+//!     "inputs": [{"name": "data", "shape": [1, 3, 224, 224], "dtype": "float32"}],
+//!     "outputs": [{"name": "scores", "shape": [1, 1000], "dtype": "float32"}],
+//!     "preprocess": {
+//!         "resize": [256, 256],
+//!         "center_crop": [224, 224],
+//!         "mean": [123.675, 116.28, 103.53],
+//!         "std": [58.395, 57.12, 57.375],
+//!         "layout": "NCHW"
+//!     }
+//! }
+//! ```
+//!
+//! # Example
+//!
+//! ```
+//! // when exporting the model
+//! let graph = GraphJson::parse(&artifacts.graph).unwrap();
+//! let mut metadata = Metadata::from_graph(&graph, &Params::parse(&artifacts.params).unwrap());
+//! metadata.outputs[0].name = "scores".to_owned();
+//! metadata.save(&Metadata::sidecar_path(Path::new("deploy_graph.json"))).unwrap();
+//!
+//! // when deploying it
+//! let artifacts = Artifacts::from_files(
+//!     "deploy_graph.json",
+//!     "deploy_lib.so",
+//!     "deploy_param.params",
+//! ).unwrap();
+//! let metadata = artifacts.metadata().unwrap();
+//! runtime.get_output(metadata.output_index("scores").unwrap(), &scores).unwrap();
+//! ```
+//!
+//! [`Metadata`]:struct.Metadata.html
+//! [`Artifacts`]:../graph_runtime/struct.Artifacts.html
+//! [`Artifacts::from_files`]:../graph_runtime/struct.Artifacts.html#method.from_files
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{de, Deserialize, Deserializer};
+use serde_json::{self, Value};
+
+use graph::{GraphJson, Params};
+#[cfg(feature = "image")]
+use preprocess::{Layout, Preprocessor};
+use DataType;
+use ErrorKind;
+use Result;
+
+/// The version of the format written by [`Metadata::to_json`].
+///
+/// [`Metadata::to_json`]:struct.Metadata.html#method.to_json
+pub const METADATA_VERSION: u64 = 1;
+
+fn invalid_metadata<S: Into<String>>(msg: S) -> ErrorKind {
+    ErrorKind::InvalidMetadata(msg.into())
+}
+
+/// The name, shape and dtype of an input or an output of a model.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TensorInfo {
+    pub name: String,
+    pub shape: Vec<usize>,
+    #[serde(deserialize_with = "deserialize_dtype")]
+    pub dtype: String,
+}
+
+/// The pre-processing of the image inputs of a model, see [`Preprocessor`].
+///
+/// [`Preprocessor`]:../preprocess/struct.Preprocessor.html
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Preprocessing {
+    /// The `(width, height)` images are resized to.
+    pub resize: Option<(u32, u32)>,
+    /// The `(width, height)` of the center crop of the resized images.
+    pub center_crop: Option<(u32, u32)>,
+    pub mean: [f32; 3],
+    pub std: [f32; 3],
+    /// Either `NCHW` or `NHWC`.
+    #[serde(deserialize_with = "deserialize_layout")]
+    pub layout: String,
+}
+
+// The metadata as written in the sidecar, whose version is checked first.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MetadataFile {
+    #[allow(dead_code)]
+    version: u64,
+    #[serde(default)]
+    inputs: Vec<TensorInfo>,
+    #[serde(default)]
+    outputs: Vec<TensorInfo>,
+    #[serde(default)]
+    preprocess: Option<Preprocessing>,
+}
+
+// Keeps the name of a dtype after checking that it parses.
+fn deserialize_dtype<'de, D>(deserializer: D) -> ::std::result::Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let dtype = String::deserialize(deserializer)?;
+    dtype.parse::<DataType>().map_err(de::Error::custom)?;
+    Ok(dtype)
+}
+
+fn deserialize_layout<'de, D>(deserializer: D) -> ::std::result::Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let layout = String::deserialize(deserializer)?;
+    if layout != "NCHW" && layout != "NHWC" {
+        return Err(de::Error::custom(format!(
+            "`layout` must be NCHW or NHWC, found `{}`",
+            layout
+        )));
+    }
+    Ok(layout)
+}
+
+impl Default for Preprocessing {
+    fn default() -> Self {
+        Preprocessing {
+            resize: None,
+            center_crop: None,
+            mean: [0.; 3],
+            std: [1.; 3],
+            layout: "NCHW".to_owned(),
+        }
+    }
+}
+
+impl Preprocessing {
+    /// Returns the [`Preprocessor`] applying the pre-processing.
+    ///
+    /// [`Preprocessor`]:../preprocess/struct.Preprocessor.html
+    #[cfg(feature = "image")]
+    pub fn to_preprocessor(&self) -> Preprocessor {
+        let mut preprocessor = Preprocessor::new();
+        if let Some((width, height)) = self.resize {
+            preprocessor.resize(width, height);
+        }
+        if let Some((width, height)) = self.center_crop {
+            preprocessor.center_crop(width, height);
+        }
+        let layout = if self.layout == "NHWC" {
+            Layout::NHWC
+        } else {
+            Layout::NCHW
+        };
+        preprocessor.normalize(self.mean, self.std).layout(layout);
+        preprocessor
+    }
+}
+
+/// The description of the inputs and outputs of a model, see the
+/// [module-level documentation].
+///
+/// [module-level documentation]:index.html
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    /// The inputs fed by the deployment, without the parameters.
+    pub inputs: Vec<TensorInfo>,
+    /// The outputs, in the order of their indices.
+    pub outputs: Vec<TensorInfo>,
+    pub preprocess: Option<Preprocessing>,
+}
+
+impl Metadata {
+    /// Describes the inputs of the graph which are not parameters and its outputs, named
+    /// after the nodes computing them, with a `:<index>` suffix for the outputs of nodes
+    /// with several ones.
+    pub fn from_graph(graph: &GraphJson, params: &Params) -> Metadata {
+        let inputs = graph
+            .inputs()
+            .into_iter()
+            .filter(|input| params.get(&input.name).is_none())
+            .map(|input| TensorInfo {
+                name: input.name,
+                shape: input.shape,
+                dtype: input.dtype,
+            })
+            .collect();
+        let outputs = graph
+            .heads
+            .iter()
+            .map(|head| {
+                let node = &graph.nodes[head.node].name;
+                let eid = graph.entry_id(head);
+                TensorInfo {
+                    name: match head.index {
+                        0 => node.clone(),
+                        index => format!("{}:{}", node, index),
+                    },
+                    shape: graph.shapes[eid].clone(),
+                    dtype: graph.dtypes[eid].clone(),
+                }
+            })
+            .collect();
+        Metadata {
+            inputs,
+            outputs,
+            preprocess: None,
+        }
+    }
+
+    /// Returns the path of the sidecar of a graph json, with the `meta.json` extension.
+    pub fn sidecar_path(graph: &Path) -> PathBuf {
+        graph.with_extension("meta.json")
+    }
+
+    /// Reads the sidecar of a graph json, or returns `None` if there is none.
+    pub fn load_sidecar(graph: &Path) -> Result<Option<Metadata>> {
+        let path = Metadata::sidecar_path(graph);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        Metadata::load(&path).map(Some)
+    }
+
+    pub fn load(path: &Path) -> Result<Metadata> {
+        Metadata::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_json())?;
+        Ok(())
+    }
+
+    /// Parses the metadata, failing with `ErrorKind::InvalidMetadata` for unknown versions
+    /// and invalid fields.
+    pub fn from_json(json: &str) -> Result<Metadata> {
+        let value: Value = serde_json::from_str(json)?;
+        match value["version"].as_u64() {
+            Some(METADATA_VERSION) => (),
+            Some(version) => bail!(invalid_metadata(format!("unknown version {}", version))),
+            None => bail!(invalid_metadata("missing `version`")),
+        }
+        let file: MetadataFile =
+            serde_json::from_value(value).map_err(|err| invalid_metadata(err.to_string()))?;
+        Ok(Metadata {
+            inputs: file.inputs,
+            outputs: file.outputs,
+            preprocess: file.preprocess,
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        let tensors = |tensors: &[TensorInfo]| {
+            tensors
+                .iter()
+                .map(|t| json!({"name": t.name, "shape": t.shape, "dtype": t.dtype}))
+                .collect::<Vec<_>>()
+        };
+        let mut value = json!({
+            "version": METADATA_VERSION,
+            "inputs": tensors(&self.inputs),
+            "outputs": tensors(&self.outputs),
+        });
+        if let Some(ref p) = self.preprocess {
+            value["preprocess"] = json!({
+                "resize": p.resize.map(|(width, height)| [width, height]),
+                "center_crop": p.center_crop.map(|(width, height)| [width, height]),
+                "mean": p.mean,
+                "std": p.std,
+                "layout": p.layout,
+            });
+        }
+        serde_json::to_string_pretty(&value).expect("metadata is valid json")
+    }
+
+    /// Returns the input by name.
+    pub fn input(&self, name: &str) -> Option<&TensorInfo> {
+        self.inputs.iter().find(|input| input.name == name)
+    }
+
+    /// Returns the index of the output by name, as expected by
+    /// [`GraphRuntime::get_output`].
+    ///
+    /// [`GraphRuntime::get_output`]:../graph_runtime/struct.GraphRuntime.html#method.get_output
+    pub fn output_index(&self, name: &str) -> Option<usize> {
+        self.outputs.iter().position(|output| output.name == name)
+    }
+
+    /// Returns the output by name.
+    pub fn output(&self, name: &str) -> Option<&TensorInfo> {
+        self.output_index(name).map(|index| &self.outputs[index])
+    }
+
+    /// Checks that the inputs are inputs of the graph and that it has as many outputs,
+    /// with the same shapes and dtypes. The outputs are matched by position, since their
+    /// names may differ from the ones of the nodes.
+    pub fn check(&self, graph: &GraphJson) -> Result<()> {
+        for input in &self.inputs {
+            match graph.input(&input.name) {
+                Some(found) => check_tensor(input, &found.shape, &found.dtype)?,
+                None => {
+                    bail!(invalid_metadata(format!("the graph has no input `{}`", input.name)))
+                }
+            }
+        }
+        let outputs = graph.outputs();
+        if self.outputs.len() != outputs.len() {
+            bail!(invalid_metadata(format!(
+                "{} outputs are described but the graph has {}",
+                self.outputs.len(),
+                outputs.len()
+            )));
+        }
+        for (output, &(ref shape, ref dtype)) in self.outputs.iter().zip(&outputs) {
+            check_tensor(output, shape, dtype)?;
+        }
+        Ok(())
+    }
+}
+
+// Checks that a described tensor has the shape and the dtype found in the graph.
+fn check_tensor(info: &TensorInfo, shape: &[usize], dtype: &str) -> Result<()> {
+    if info.shape[..] != shape[..] || info.dtype != dtype {
+        bail!(invalid_metadata(format!(
+            "`{}` is described as {} of shape {:?} but the graph has {} of shape {:?}",
+            info.name, info.dtype, info.shape, dtype, shape
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::{Param, TEST_GRAPH};
+    use Error;
+
+    #[test]
+    fn round_trip() {
+        let graph = GraphJson::parse(TEST_GRAPH).unwrap();
+        let params = Params {
+            params: vec![Param {
+                name: "weight".to_owned(),
+                shape: vec![2, 4],
+                dtype: DataType::from("float"),
+                nbytes: 32,
+            }],
+        };
+        let mut metadata = Metadata::from_graph(&graph, &params);
+        assert_eq!(metadata.inputs.len(), 1);
+        assert_eq!(metadata.outputs[0].name, "dense");
+        assert_eq!(metadata.outputs[0].shape, vec![1, 2]);
+        metadata.outputs[0].name = "scores".to_owned();
+        metadata.preprocess = Some(Preprocessing {
+            resize: Some((256, 256)),
+            center_crop: Some((224, 224)),
+            ..Preprocessing::default()
+        });
+        let parsed = Metadata::from_json(&metadata.to_json()).unwrap();
+        assert_eq!(parsed, metadata);
+        assert_eq!(parsed.output_index("scores"), Some(0));
+        assert_eq!(parsed.input("data").map(|input| &input.dtype[..]), Some("float32"));
+        assert!(parsed.check(&graph).is_ok());
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Metadata::from_json(r#"{"version": 2}"#).is_err());
+        let layout = r#"{"version": 1, "preprocess": {"layout": "CHWN"}}"#;
+        assert!(Metadata::from_json(layout).is_err());
+        let dtype = r#"{"version": 1, "inputs": [{"name": "x", "shape": [1], "dtype": "x"}]}"#;
+        assert!(Metadata::from_json(dtype).is_err());
+        let unknown = r#"{"version": 1, "preprocess": {"means": [0, 0, 0]}}"#;
+        match Metadata::from_json(unknown) {
+            Err(Error(ErrorKind::InvalidMetadata(msg), _)) => assert!(msg.contains("means")),
+            other => panic!("expected invalid metadata, but found {:?}", other),
+        }
+        let graph = GraphJson::parse(TEST_GRAPH).unwrap();
+        let metadata = Metadata::from_json(r#"{"version": 1}"#).unwrap();
+        assert!(metadata.check(&graph).is_err());
+        let mut stale = Metadata::from_graph(&graph, &Params::default());
+        assert!(stale.check(&graph).is_ok());
+        stale.outputs[0].shape = vec![1, 3];
+        assert!(stale.check(&graph).is_err());
+        let mut stale = Metadata::from_graph(&graph, &Params::default());
+        stale.inputs[0].dtype = "float16".to_owned();
+        assert!(stale.check(&graph).is_err());
+    }
+}
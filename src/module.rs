@@ -1,33 +1,149 @@
 //! Provides the [`Module`] type and methods for working with runtime TVM modules.
 
 use std::{
+    cell::RefCell,
+    collections::HashMap,
+    env::consts::DLL_EXTENSION,
     ffi::CString,
-    mem,
+    fs,
     os::raw::{c_char, c_int},
-    path::Path,
+    path::{Path, PathBuf},
     ptr,
+    sync::{Arc, Mutex},
 };
 
 use ts;
 
+use contrib::{self, Toolchain};
+use ffi;
 use function::Function;
 use internal_api;
+use replay;
 use ErrorKind;
 use Result;
 
 const ENTRY_FUNC: &'static str = "__tvm_main__";
 
+/// Options of looking up a function in a [`Module`].
+///
+/// [`Module`]:struct.Module.html
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FunctionOptions {
+    /// Whether to look the function up in the imported modules too, e.g. the device
+    /// module of a library with kernels.
+    pub query_imports: bool,
+    /// Whether a missing function is `None` instead of an error, see
+    /// [`Module::find_function`].
+    ///
+    /// [`Module::find_function`]:struct.Module.html#method.find_function
+    pub allow_missing: bool,
+}
+
+impl FunctionOptions {
+    /// The options looking the function up in the imported modules too.
+    pub fn query_imports() -> Self {
+        FunctionOptions {
+            query_imports: true,
+            ..Default::default()
+        }
+    }
+
+    /// The options returning `None` for a missing function.
+    pub fn allow_missing() -> Self {
+        FunctionOptions {
+            allow_missing: true,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<bool> for FunctionOptions {
+    fn from(query_imports: bool) -> Self {
+        FunctionOptions {
+            query_imports,
+            ..Default::default()
+        }
+    }
+}
+
+// Converts a path into the string passed to the runtime, in the native form of the host.
+fn path_to_string(path: &Path) -> Result<String> {
+    match path.to_str() {
+        Some(path) => Ok(normalize_path(path, cfg!(windows))),
+        None => bail!("the path {} is not valid unicode", path.display()),
+    }
+}
+
+// On Windows, strips the `\\?\` prefix of the verbatim paths returned by `fs::canonicalize`,
+// which the runtime does not understand, and uses backslashes as separators.
+fn normalize_path(path: &str, windows: bool) -> String {
+    if !windows {
+        return path.to_owned();
+    }
+    let path = if path.starts_with(r"\\?\UNC\") {
+        format!(r"\\{}", &path[8..])
+    } else if path.starts_with(r"\\?\") {
+        path[4..].to_owned()
+    } else {
+        path.to_owned()
+    };
+    path.replace('/', r"\")
+}
+
+// Returns the format of a module file from its extension. The shared library extensions
+// of all platforms, e.g. `.dll` on Windows, are loaded by the runtime as `so`.
+fn file_format(path: &Path) -> Result<String> {
+    let ext = match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ext.to_lowercase(),
+        None => bail!("cannot find the format of the module file {}", path.display()),
+    };
+    match ext.as_str() {
+        "dll" | "dylib" => Ok("so".to_owned()),
+        _ => Ok(ext),
+    }
+}
+
 /// Wrapper around TVM module handle which contains an entry function.
-/// The entry function can be applied to an imported module through [`entry_func`].
+/// The entry function can be obtained, also from an imported module, through [`entry`].
 /// Also [`is_released`] shows whether the module is dropped or not.
 ///
-/// [`entry_func`]:struct.Module.html#method.entry_func
+/// [`entry`]:struct.Module.html#method.entry
 /// [`is_released`]:struct.Module.html#method.is_released
+///
+/// Clones of a module and the functions obtained from it share the ownership of the
+/// module handle, which is freed once all of them are dropped, so a function never
+/// outlives its module. A module also keeps the modules it imported with
+/// [`import_module`] alive.
+///
+/// [`import_module`]:struct.Module.html#method.import_module
 #[derive(Debug, Clone)]
 pub struct Module {
     pub(crate) handle: ts::TVMModuleHandle,
-    is_released: bool,
-    pub(crate) entry: Option<Function>,
+    // `None` if the handle is not owned by the frontend.
+    owner: Option<Arc<ModuleHandle>>,
+    // the entry function, looked up on first use.
+    entry: RefCell<Option<Function>>,
+    // the modules imported with `import_module`, shared by the clones. Imports are
+    // acyclic, so dropping the last clone frees them.
+    imported: Arc<Mutex<Vec<Module>>>,
+}
+
+// Owner of a module handle, freeing it on drop.
+#[derive(Debug)]
+pub(crate) struct ModuleHandle(ts::TVMModuleHandle, ffi::RuntimeRef);
+
+// TVM modules are reference counted atomically, so the last reference can be
+// released from any thread.
+unsafe impl Send for ModuleHandle {}
+unsafe impl Sync for ModuleHandle {}
+
+impl Drop for ModuleHandle {
+    fn drop(&mut self) {
+        // null once the handle is moved out with `Module::into_raw`
+        if !self.0.is_null() {
+            check_call!(ffi::api().mod_free(self.0));
+        }
+    }
 }
 
 impl Module {
@@ -38,59 +154,326 @@ impl Module {
     ) -> Self {
         Self {
             handle,
-            is_released,
-            entry,
+            owner: if is_released {
+                None
+            } else {
+                Some(Arc::new(ModuleHandle(handle, ffi::RuntimeRef::acquire())))
+            },
+            entry: RefCell::new(entry),
+            imported: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    // Creates a module sharing the owned handle of another one, e.g. on another thread.
+    fn from_owner(owner: Arc<ModuleHandle>) -> Self {
+        Self {
+            handle: owner.0,
+            owner: Some(owner),
+            entry: RefCell::new(None),
+            imported: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Returns the entry function of the module, which is looked up once and cached,
+    /// so the module stays usable for fetching other functions.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let fadd = Module::load(&Path::new("add_cpu.so")).unwrap();
+    /// function::Builder::from(fadd.entry().unwrap())
+    ///     .arg(&arr)
+    ///     .arg(&arr)
+    ///     .set_output(&mut ret)
+    ///     .invoke()
+    ///     .unwrap();
+    /// ```
+    pub fn entry(&self) -> Result<Function> {
+        let mut entry = self.entry.borrow_mut();
+        if entry.is_none() {
+            *entry = Some(self.get_function(ENTRY_FUNC, false)?);
         }
+        Ok(entry.as_ref().expect("entry function is set").clone())
     }
 
     /// Sets the entry function of a module.
+    #[deprecated(since = "0.2.0", note = "use `entry` which does not need `&mut self`")]
     pub fn entry_func(&mut self) {
-        if self.entry.is_none() {
-            self.entry = self.get_function(ENTRY_FUNC, false).ok();
-        }
+        let _ = self.entry();
+    }
+
+    /// Gets a function by name from the module, failing with
+    /// `ErrorKind::FunctionNotFound` if it does not exist. A `bool` passed as the options
+    /// sets [`FunctionOptions::query_imports`].
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let run_fn = module.get_function("run", false).unwrap();
+    /// let kernel = lib.get_function("fused_add", FunctionOptions::query_imports()).unwrap();
+    /// ```
+    ///
+    /// [`FunctionOptions::query_imports`]:struct.FunctionOptions.html#structfield.query_imports
+    pub fn get_function<O>(&self, name: &str, options: O) -> Result<Function>
+    where
+        O: Into<FunctionOptions>,
+    {
+        let options = FunctionOptions {
+            allow_missing: false,
+            ..options.into()
+        };
+        Ok(self.find_function(name, options)?.expect("missing functions are not allowed"))
     }
 
-    /// Gets a function by name from a registered module.
-    pub fn get_function(&self, name: &str, query_import: bool) -> Result<Function> {
-        let name = CString::new(name)?;
+    /// Gets a function by name from the module like [`get_function`], returning `None`
+    /// instead of failing if it does not exist and `allow_missing` is set, e.g. to probe
+    /// for optional functions.
+    ///
+    /// [`get_function`]:struct.Module.html#method.get_function
+    pub fn find_function<O: Into<FunctionOptions>>(
+        &self,
+        name: &str,
+        options: O,
+    ) -> Result<Option<Function>> {
+        let options = options.into();
+        let c_name = CString::new(name)?;
         let mut fhandle = ptr::null_mut() as ts::TVMFunctionHandle;
-        check_call!(ts::TVMModGetFunction(
+        check_call!(ffi::api().mod_get_function(
             self.handle,
-            name.as_ptr() as *const c_char,
-            query_import as c_int,
+            c_name.as_ptr() as *const c_char,
+            options.query_imports as c_int,
             &mut fhandle as *mut _
         ));
-        if fhandle.is_null() {
-            bail!(ErrorKind::NullHandle(format!("{}", name.into_string()?)))
-        } else {
-            mem::forget(name);
-            Ok(Function::new(fhandle, false, false))
+        if !fhandle.is_null() {
+            replay::record_lookup(self.handle, name, options.query_imports, fhandle);
+            return Ok(Some(
+                Function::new(fhandle, false, false)
+                    .with_module(self.owner.clone())
+                    .with_name(Some(Arc::from(name))),
+            ));
+        }
+        if options.allow_missing {
+            return Ok(None);
         }
+        let module_type = self.type_key_or_unknown();
+        bail!(ErrorKind::FunctionNotFound(name.to_owned(), module_type))
+    }
+
+    /// Gets the functions by name, including the ones of the imported modules, e.g. to
+    /// check at startup that the library has every kernel a server calls.
+    ///
+    /// Fails with `ErrorKind::FunctionsNotFound` listing all the missing functions.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let kernels = lib.resolve_functions(&["preprocess", "fused_nn_dense"]).unwrap();
+    /// let dense = kernels["fused_nn_dense"].clone();
+    /// ```
+    pub fn resolve_functions(&self, names: &[&str]) -> Result<HashMap<String, Function>> {
+        let options = FunctionOptions {
+            query_imports: true,
+            allow_missing: true,
+        };
+        let mut functions = HashMap::with_capacity(names.len());
+        let mut missing = Vec::new();
+        for &name in names {
+            match self.find_function(name, options)? {
+                Some(function) => {
+                    functions.insert(name.to_owned(), function);
+                }
+                None => missing.push(name.to_owned()),
+            }
+        }
+        if !missing.is_empty() {
+            let module_type = self.type_key_or_unknown();
+            bail!(ErrorKind::FunctionsNotFound(missing, module_type));
+        }
+        Ok(functions)
     }
 
     /// Imports a dependent module such as `.ptx` for gpu.
-    pub fn import_module(&self, dependent_module: Module) {
-        check_call!(ts::TVMModImport(self.handle, dependent_module.handle))
+    ///
+    /// The runtime holds a reference to the dependent module, and the module keeps it
+    /// alive as well, so either can be dropped first. Fails if the dependent module is
+    /// the module itself or imports it, since cyclic imports would never be freed.
+    pub fn import_module(&self, dependent_module: Module) -> Result<()> {
+        if dependent_module.reaches(self)? {
+            bail!("importing the module would create an import cycle");
+        }
+        check_call!(ffi::api().mod_import(self.handle, dependent_module.handle));
+        self.imported.lock().unwrap().push(dependent_module);
+        Ok(())
+    }
+
+    /// Returns the modules imported by the module, including the ones imported by the
+    /// runtime when loading it, e.g. the device code of a library.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let lib = Module::load(Path::new("deploy_lib.so")).unwrap();
+    /// for import in lib.imports().unwrap() {
+    ///     println!("imports a `{}` module", import.type_key().unwrap());
+    /// }
+    /// ```
+    pub fn imports(&self) -> Result<Vec<Module>> {
+        (0..internal_api::imports_size(self)?)
+            .map(|i| internal_api::get_import(self, i))
+            .collect()
+    }
+
+    // Returns whether the module is `module` or imports it, transitively. The modules are
+    // compared by handle, since the runtime passes a module as the handle of the module
+    // object, so the imports returned by `imports` have the handles they were imported with.
+    fn reaches(&self, module: &Module) -> Result<bool> {
+        if self.handle == module.handle {
+            return Ok(true);
+        }
+        for import in self.imports()? {
+            if import.reaches(module)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
     }
 
     /// Loads a module shared library from path.
+    ///
+    /// Shared libraries have the extension of the platform, `.so`, `.dylib` or `.dll`,
+    /// and other files such as `.ptx` are loaded by the extension as the format.
+    ///
+    /// A `.tar` package produced by `export_library` is unpacked into a temporary
+    /// directory. Its object files are linked into a shared library with
+    /// [`contrib::create_shared`], and the other files, such as `.so` libraries or device
+    /// code like `.ptx`, are loaded as well, the first library importing the others.
+    ///
+    /// [`contrib::create_shared`]:../contrib/fn.create_shared.html
     pub fn load(path: &Path) -> Result<Module> {
-        let path = path.to_owned();
-        let path_str = path.to_str()?.to_owned();
-        let ext = path.extension()?.to_str()?.to_owned();
-        let func = internal_api::get_api("module._LoadFromFile".to_owned());
-        let ret = call_packed!(func, &path_str, &ext)?;
-        mem::forget(path);
-        Ok(ret.to_module())
+        if path.extension().map_or(false, |ext| ext == "tar") {
+            return Module::load_tar(path);
+        }
+        let path_str = path_to_string(path)?;
+        let ext = file_format(path)?;
+        internal_api::load_from_file(&path_str, &ext)
+    }
+
+    /// Returns a module file which is loaded like [`load`] on the first function lookup,
+    /// e.g. for an application bundling many optional models whose startup should not
+    /// load all of them.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let lib = Module::lazy(Path::new("deploy_lib.so"));
+    /// assert!(!lib.is_loaded());
+    /// let entry = lib.entry().unwrap();
+    /// ```
+    ///
+    /// [`load`]:struct.Module.html#method.load
+    pub fn lazy(path: &Path) -> LazyModule {
+        LazyModule {
+            path: path.to_owned(),
+            handle: Mutex::new(None),
+        }
+    }
+
+    fn load_tar(path: &Path) -> Result<Module> {
+        let dir = contrib::TempDir::new()?;
+        contrib::untar(path, dir.path())?;
+        let mut objects = Vec::new();
+        let mut others = Vec::new();
+        for entry in fs::read_dir(dir.path())? {
+            let file = entry?.path();
+            match file.extension().and_then(|ext| ext.to_str()) {
+                Some("o") => objects.push(file),
+                Some(_) => others.push(file),
+                None => (),
+            }
+        }
+        others.sort();
+        if !objects.is_empty() {
+            objects.sort();
+            let lib = dir.path().join(format!("lib.{}", DLL_EXTENSION));
+            contrib::create_shared(&lib, &objects, &Toolchain::new())?;
+            others.insert(0, lib);
+        }
+        let mut modules = others.iter().map(|file| Module::load(file));
+        let module = match modules.next() {
+            Some(module) => module?,
+            None => bail!("no module files in the package {}", path.display()),
+        };
+        for dependent in modules {
+            module.import_module(dependent?)?;
+        }
+        Ok(module)
+    }
+
+    /// Returns the type key of the module such as `llvm` or `cuda`.
+    pub fn type_key(&self) -> Result<String> {
+        internal_api::get_type_key(self)
     }
 
-    /// Checks if a target device is enabled for a module.
+    // Returns the type key for error messages, which must not fail themselves.
+    fn type_key_or_unknown(&self) -> String {
+        self.type_key().unwrap_or_else(|_| "unknown".to_owned())
+    }
+
+    /// Saves the module into a file with the given format such as `o` or `ptx`.
+    pub fn save(&self, path: &Path, format: &str) -> Result<()> {
+        internal_api::save_to_file(self, &path_to_string(path)?, format)
+    }
+
+    /// Exports the module as a deployable shared library which can be loaded back with
+    /// [`load`], like `export_library` in Python.
+    ///
+    /// The host code of the module, which must be an `llvm` or `c` module, is saved into a
+    /// temporary directory. The imported device modules are bundled as a C source, which
+    /// requires the full TVM library providing `codegen._PackImportsToC`. Then the files
+    /// are linked with [`contrib::create_shared`] using `compiler` or the default one.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// module.export_library(Path::new("deploy.so"), Some("clang++")).unwrap();
+    /// let module = Module::load(Path::new("deploy.so")).unwrap();
+    /// ```
+    ///
+    /// [`load`]:struct.Module.html#method.load
+    /// [`contrib::create_shared`]:../contrib/fn.create_shared.html
+    pub fn export_library(&self, path: &Path, compiler: Option<&str>) -> Result<()> {
+        let toolchain = compiler.map(Toolchain::from).unwrap_or_default();
+        self.export_library_with(path, &toolchain)
+    }
+
+    /// Exports the module as a shared library like [`export_library`], linking with the
+    /// given toolchain, e.g. a cross-compiler and a sysroot for an ARM board.
+    ///
+    /// [`export_library`]:struct.Module.html#method.export_library
+    pub fn export_library_with(&self, path: &Path, toolchain: &Toolchain) -> Result<()> {
+        let dir = contrib::TempDir::new()?;
+        let mut files = Vec::new();
+        let (file_name, format) = match self.type_key()?.as_str() {
+            "llvm" => ("lib.o", "o"),
+            "c" => ("lib.cc", "cc"),
+            type_key => bail!("cannot export the host code of a `{}` module", type_key),
+        };
+        let host = dir.path().join(file_name);
+        self.save(&host, format)?;
+        files.push(host);
+        if internal_api::imports_size(self)? > 0 {
+            let code = internal_api::pack_imports_to_c(self, false)?;
+            let devc = dir.path().join("devc.cc");
+            fs::write(&devc, code)?;
+            files.push(devc);
+        }
+        contrib::create_shared(path, &files, toolchain)
+    }
+
+    /// Checks if a target device is enabled for a module. Returns `false` if the runtime
+    /// cannot be queried.
     pub fn enabled(&self, target: &str) -> bool {
-        let func = internal_api::get_api("module._Enabled".to_owned());
-        // `unwrap` is safe here because if there is any error during the
-        // function call, it would occur in `call_packed!`.
-        let ret = call_packed!(func, target).unwrap();
-        ret.to_int() != 0
+        internal_api::enabled(target).unwrap_or(false)
     }
 
     /// Returns the underlying module handle.
@@ -98,17 +481,149 @@ impl Module {
         self.handle
     }
 
-    /// Returns true if the underlying module has been dropped and false otherwise.
+    /// Creates a module taking the ownership of a module handle, e.g. one loaded with
+    /// `TVMModLoadFromFile` by other code using the TVM C API or returned by
+    /// [`into_raw`].
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be a valid module handle which is not freed by anyone else, since
+    /// the module frees it with `TVMModFree` once it and its functions are dropped.
+    ///
+    /// [`into_raw`]:struct.Module.html#method.into_raw
+    pub unsafe fn from_raw(handle: ts::TVMModuleHandle) -> Module {
+        Module::new(handle, false, None)
+    }
+
+    /// Returns the module handle, transferring its ownership to the caller, who must free
+    /// it with `TVMModFree` or pass it back to [`from_raw`]. The modules imported with
+    /// [`import_module`] stay referenced by the runtime.
+    ///
+    /// Fails if the handle is not owned by the frontend, or is shared by clones of the
+    /// module or functions obtained from it other than the entry function.
+    ///
+    /// [`from_raw`]:struct.Module.html#method.from_raw
+    /// [`import_module`]:struct.Module.html#method.import_module
+    pub fn into_raw(self) -> Result<ts::TVMModuleHandle> {
+        let Module {
+            handle,
+            owner,
+            entry,
+            ..
+        } = self;
+        // the cached entry function shares the handle
+        drop(entry);
+        match owner.map(Arc::try_unwrap) {
+            Some(Ok(mut owner)) => {
+                owner.0 = ptr::null_mut();
+                Ok(handle)
+            }
+            Some(Err(_)) => bail!("the module handle is shared by clones or functions"),
+            None => bail!("the module handle is not owned by the frontend"),
+        }
+    }
+
+    /// Returns true if the underlying module handle is not owned by the frontend
+    /// and false otherwise.
     pub fn is_released(&self) -> bool {
-        self.is_released
+        self.owner.is_none()
     }
 }
 
-impl Drop for Module {
-    fn drop(&mut self) {
-        if !self.is_released {
-            check_call!(ts::TVMModFree(self.handle));
-            self.is_released = true;
+/// A module file loaded on first use, see [`Module::lazy`].
+///
+/// A failed load is not cached, so the next lookup tries to load the file again. It can
+/// be shared between threads, e.g. by the workers of a server, which load the file once.
+/// Only the module handle is shared, which is valid on every thread since the runtime
+/// library is opened once per process, while each lookup returns a new [`Module`] for the
+/// calling thread. The runtime keeps the imports of the module alive.
+///
+/// [`Module::lazy`]:struct.Module.html#method.lazy
+/// [`Module`]:struct.Module.html
+#[derive(Debug)]
+pub struct LazyModule {
+    path: PathBuf,
+    handle: Mutex<Option<Arc<ModuleHandle>>>,
+}
+
+impl LazyModule {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether the module file has been loaded.
+    pub fn is_loaded(&self) -> bool {
+        self.handle.lock().unwrap().is_some()
+    }
+
+    /// Loads the module file unless it has been loaded already, e.g. to load it before
+    /// the first request to a server, and returns the module.
+    pub fn preload(&self) -> Result<Module> {
+        let mut handle = self.handle.lock().unwrap();
+        if handle.is_none() {
+            let module = Module::load(&self.path)?;
+            *handle = module.owner.clone();
         }
+        let owner = handle.as_ref().expect("loaded modules own their handle").clone();
+        Ok(Module::from_owner(owner))
+    }
+
+    /// Loads the module if needed and gets a function by name like
+    /// [`Module::get_function`].
+    ///
+    /// [`Module::get_function`]:struct.Module.html#method.get_function
+    pub fn get_function<O>(&self, name: &str, options: O) -> Result<Function>
+    where
+        O: Into<FunctionOptions>,
+    {
+        self.preload()?.get_function(name, options)
+    }
+
+    /// Loads the module if needed and returns its entry function.
+    pub fn entry(&self) -> Result<Function> {
+        self.preload()?.entry()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths() {
+        assert_eq!(normalize_path("a/b.so", false), "a/b.so");
+        assert_eq!(normalize_path(r"\\?\C:\a/b.dll", true), r"C:\a\b.dll");
+        assert_eq!(
+            normalize_path(r"\\?\UNC\server\share\b.dll", true),
+            r"\\server\share\b.dll"
+        );
+        assert_eq!(file_format(Path::new(r"C:\models\add.DLL")).unwrap(), "so");
+        assert_eq!(file_format(Path::new("add.dylib")).unwrap(), "so");
+        assert_eq!(file_format(Path::new("add.ptx")).unwrap(), "ptx");
+        assert!(file_format(Path::new("add")).is_err());
+    }
+
+    #[test]
+    fn function_options() {
+        assert_eq!(FunctionOptions::from(true), FunctionOptions::query_imports());
+        let options = FunctionOptions {
+            allow_missing: true,
+            ..FunctionOptions::from(false)
+        };
+        assert_eq!(options, FunctionOptions::allow_missing());
+    }
+
+    #[test]
+    fn lazy() {
+        let lib = Module::lazy(Path::new("add"));
+        assert_eq!(lib.path(), Path::new("add"));
+        assert!(lib.preload().is_err());
+        assert!(!lib.is_loaded());
+    }
+
+    #[test]
+    fn lazy_is_sync() {
+        fn assert_sync<T: Send + Sync>() {}
+        assert_sync::<LazyModule>();
     }
 }
@@ -2,7 +2,7 @@
 //! coverting from a Rust's ndarray to TVM `NDArray`.
 //!
 //! One can create an empty NDArray given the shape, context and dtype using [`empty`].
-//! To create an NDArray from a mutable buffer in cpu use [`copy_from_buffer`].
+//! To copy the entries of a buffer into an NDArray on any context use [`copy_from_buffer`].
 //! To copy an NDArray to different context use [`copy_to_ctx`].
 //!
 //! Given a [`Rust's dynamic ndarray`], one can convert it to TVM NDArray as follows:
@@ -13,8 +13,8 @@
 //! let a = Array::from_shape_vec((2, 2), vec![1f32, 2., 3., 4.])
 //!     .unwrap()
 //!     .into_dyn(); // Rust's ndarray
-//! let nd = NDArray::from_rust_ndarray(&a, TVMContext::cpu(0), TVMType::from("float")).unwrap();
-//! assert_eq!(nd.shape(), Some(&mut [2, 2]));
+//! let nd = NDArray::from_rust_ndarray(&a, TVMContext::cpu(0), DataType::from("float")).unwrap();
+//! assert_eq!(nd.shape(), &[2, 2]);
 //! let rnd: ArrayD<f32> = ArrayD::try_from(&nd).unwrap();
 //! assert!(rnd.all_close(&a, 1e-8f32));
 //! ```
@@ -23,27 +23,49 @@
 //! [`copy_from_buffer`]:struct.NDArray.html#method.copy_from_buffer
 //! [`copy_to_ctx`]:struct.NDArray.html#method.copy_to_ctx
 
-use std::{convert::TryFrom, mem, os::raw::c_int, ptr, slice};
+use std::{
+    convert::TryFrom,
+    mem,
+    os::raw::{c_int, c_void},
+    ptr, slice,
+};
 
 use num_traits::Num;
 use rust_ndarray::{Array, ArrayD};
 
 use ts;
 
+#[cfg(feature = "cuda")]
+use context::{self, cuda};
+use ffi;
+use function;
+use memory;
+use ty::TypeCode;
+use value::TVMValue;
+use DataType;
 use Error;
 use ErrorKind;
 use Result;
+use TVMArgValue;
 use TVMByteArray;
 use TVMContext;
-use TVMType;
+use TVMDataType;
+use TVMDeviceType;
 
 /// See the [`module-level documentation`](../ndarray/index.html) for more details.
 ///
 /// Wrapper around TVM array handle.
+///
+/// An NDArray is not `Clone` since a second handle either aliases the storage or copies
+/// it. Use [`share`] for the former and [`copy`] for the latter.
+///
+/// [`share`]:struct.NDArray.html#method.share
+/// [`copy`]:struct.NDArray.html#method.copy
 #[derive(Debug)]
 pub struct NDArray {
     pub(crate) handle: ts::TVMArrayHandle,
     is_view: bool,
+    _runtime: ffi::RuntimeRef,
 }
 
 impl NDArray {
@@ -51,6 +73,7 @@ impl NDArray {
         NDArray {
             handle: handle,
             is_view: is_view,
+            _runtime: ffi::RuntimeRef::acquire(),
         }
     }
 
@@ -59,24 +82,60 @@ impl NDArray {
         self.handle
     }
 
+    /// Returns the array as a plain `DLTensor*` argument, e.g. when calling a function of
+    /// a module built with `tvm.build` directly. Passing the array itself passes its
+    /// NDArray container instead, unless it is a view.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// function::Builder::from(&fadd)
+    ///     .arg(&arr.as_dltensor_arg())
+    ///     .arg(&arr.as_dltensor_arg())
+    ///     .set_output(&mut ret)
+    ///     .invoke()
+    ///     .unwrap();
+    /// ```
+    pub fn as_dltensor_arg(&self) -> TVMArgValue {
+        TVMArgValue::new(TVMValue::from(self), TypeCode::kArrayHandle)
+    }
+
     pub fn is_view(&self) -> bool {
         self.is_view
     }
 
-    /// Returns the shape of the NDArray.
-    pub fn shape(&self) -> Option<&mut [usize]> {
-        let arr = unsafe { *(self.handle) };
-        if arr.shape.is_null() || arr.data.is_null() {
-            return None;
-        };
-        let slc = unsafe { slice::from_raw_parts_mut(arr.shape as *mut usize, arr.ndim as usize) };
-        Some(slc)
+    /// Returns the shape of the NDArray as stored in the `DLTensor`, empty for a scalar.
+    pub fn shape(&self) -> &[i64] {
+        unsafe {
+            let arr = &*self.handle;
+            if arr.shape.is_null() {
+                return &[];
+            }
+            slice::from_raw_parts(arr.shape, arr.ndim as usize)
+        }
+    }
+
+    // Returns the shape as `usize`s, e.g. to allocate an NDArray of the same shape.
+    pub(crate) fn dims(&self) -> Vec<usize> {
+        self.shape().iter().map(|&d| d as usize).collect()
     }
 
     /// Returns the total number of entries of the NDArray.
+    pub fn len(&self) -> usize {
+        self.shape().iter().product::<i64>() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the total number of entries of the NDArray, or `None` if it has no storage.
     pub fn size(&self) -> Option<usize> {
-        self.shape()
-            .map(|v| v.into_iter().fold(1, |acc, &mut e| acc * e))
+        if unsafe { (*self.handle).data.is_null() } {
+            None
+        } else {
+            Some(self.len())
+        }
     }
 
     /// Returns the context which the NDArray was defined.
@@ -85,7 +144,7 @@ impl NDArray {
     }
 
     /// Returns the type of the entries of the NDArray.
-    pub fn dtype(&self) -> TVMType {
+    pub fn dtype(&self) -> DataType {
         unsafe { (*self.handle).dtype.into() }
     }
 
@@ -94,12 +153,15 @@ impl NDArray {
         unsafe { (*self.handle).ndim as usize }
     }
 
-    /// Returns the strides of the underlying NDArray.
-    pub fn strides(&self) -> Option<&[usize]> {
+    /// Returns the strides of the underlying NDArray in entries, `None` for a compact
+    /// row-major array.
+    pub fn strides(&self) -> Option<&[i64]> {
         unsafe {
-            let sz = self.ndim() * mem::size_of::<usize>();
-            let slc = slice::from_raw_parts((*self.handle).strides as *const usize, sz);
-            Some(slc)
+            let strides = (*self.handle).strides;
+            if strides.is_null() {
+                return None;
+            }
+            Some(slice::from_raw_parts(strides, self.ndim()))
         }
     }
 
@@ -108,17 +170,13 @@ impl NDArray {
         Ok(match self.strides() {
             None => true,
             Some(strides) => {
-                // MissingShapeError in case shape is not determined
-                self.shape()?
+                self.shape()
                     .iter()
                     .zip(strides)
                     .rfold(
                         (true, 1),
-                        |(is_contig, expected_stride), (shape, stride)| {
-                            (
-                                is_contig && *stride == expected_stride,
-                                expected_stride * (*shape as usize),
-                            )
+                        |(is_contig, expected_stride), (&dim, &stride)| {
+                            (is_contig && stride == expected_stride, expected_stride * dim)
                         },
                     )
                     .0
@@ -136,18 +194,18 @@ impl NDArray {
     ///
     /// ```
     /// let shape = &mut [4];
-    /// let mut data = vec![1i32, 2, 3, 4];
+    /// let data = vec![1i32, 2, 3, 4];
     /// let ctx = TVMContext::cpu(0);
-    /// let mut ndarray = empty(shape, ctx, TVMType::from("int"));
-    /// ndarray.copy_from_buffer(&mut data);
-    /// assert_eq!(ndarray.shape(), Some(shape));
+    /// let mut ndarray = empty(shape, ctx, DataType::from("int"));
+    /// ndarray.copy_from_buffer(&data).unwrap();
+    /// assert_eq!(ndarray.shape(), &[4]);
     /// assert_eq!(ndarray.to_vec::<i32>().unwrap(), data);
     /// ```
     pub fn to_vec<T>(&self) -> Result<Vec<T>> {
-        if self.shape().is_none() {
+        if self.size().is_none() {
             bail!("{}", ErrorKind::EmptyArray);
         }
-        let earr = empty(self.shape()?, TVMContext::cpu(0), self.dtype());
+        let earr = empty(&mut self.dims(), TVMContext::cpu(0), self.dtype());
         let target = self.copy_to_ndarray(earr)?;
         let arr = unsafe { *(target.handle) };
         let sz = self.size()? as usize;
@@ -166,29 +224,111 @@ impl NDArray {
         Ok(TVMByteArray::from(&v))
     }
 
-    /// Creates an NDArray from a mutable buffer of types i32, u32 or f32 in cpu.
+    /// Copies the entries of a buffer into the NDArray, e.g. `f32`s into a `float` NDArray.
+    ///
+    /// Fails if the dtype of `T` is not the dtype of the NDArray or if the buffer does not
+    /// have as many entries as the NDArray. An NDArray outside the cpu memory is filled
+    /// through a staging NDArray in cpu.
     ///
     /// ## Example
     ///
     /// ```
     /// let shape = &mut [2];
-    /// let mut data = vec![1f32, 2];
+    /// let data = vec![1f32, 2.];
     /// let ctx = TVMContext::gpu(0);
-    /// let mut ndarray = empty(shape, ctx, TVMType::from("int"));
-    /// ndarray.copy_from_buffer(&mut data);
+    /// let mut ndarray = empty(shape, ctx, DataType::from("float"));
+    /// ndarray.copy_from_buffer(&data).unwrap();
+    /// assert!(ndarray.copy_from_buffer(&[1i32, 2]).is_err());
     /// ```
+    pub fn copy_from_buffer<T: TVMDataType>(&mut self, data: &[T]) -> Result<()> {
+        let dtype = self.dtype();
+        if T::data_type() != dtype {
+            bail!(ErrorKind::TypeMismatch(
+                dtype.to_string(),
+                T::data_type().to_string()
+            ));
+        }
+        let nbytes = data.len() * mem::size_of::<T>();
+        self.check_nbytes(nbytes)?;
+        match self.ctx().device_type {
+            // cpu and cpu_pinned memory
+            TVMDeviceType(1) | TVMDeviceType(3) => check_call!(ffi::api().array_copy_from_bytes(
+                self.handle,
+                data.as_ptr() as *mut _,
+                nbytes
+            )),
+            _ => {
+                let mut staging = empty(&mut self.dims(), TVMContext::cpu(0), dtype);
+                staging.copy_from_buffer(data)?;
+                check_call!(ffi::api().array_copy_from_to(
+                    staging.handle,
+                    self.handle,
+                    ptr::null_mut() as ts::TVMStreamHandle
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies raw bytes into the NDArray on any context, e.g. a tensor payload received
+    /// from the network. The length of `data` must be the size of the NDArray in bytes.
+    ///
+    /// ## Example
     ///
-    /// *Note*: if something goes wrong during the copy, it will panic
-    /// from TVM side. See `TVMArrayCopyFromBytes` in `c_runtime_api.h`.
-    pub fn copy_from_buffer<T: Num32>(&mut self, data: &mut [T]) {
-        check_call!(ts::TVMArrayCopyFromBytes(
+    /// ```
+    /// let mut nd = empty(&mut [2], TVMContext::gpu(0), DataType::from("int"));
+    /// nd.copy_from_bytes(&[1, 0, 0, 0, 2, 0, 0, 0]).unwrap();
+    /// ```
+    pub fn copy_from_bytes(&mut self, data: &[u8]) -> Result<()> {
+        self.check_nbytes(data.len())?;
+        check_call!(ffi::api().array_copy_from_bytes(
             self.handle,
             data.as_ptr() as *mut _,
-            data.len() * mem::size_of::<T>()
+            data.len()
         ));
+        Ok(())
+    }
+
+    /// Copies the entries of the NDArray as raw bytes into `data`, whose length must be
+    /// the size of the NDArray in bytes.
+    pub fn copy_to_bytes(&self, data: &mut [u8]) -> Result<()> {
+        self.check_nbytes(data.len())?;
+        check_call!(ffi::api().array_copy_to_bytes(
+            self.handle,
+            data.as_mut_ptr() as *mut _,
+            data.len()
+        ));
+        Ok(())
+    }
+
+    // Returns the size of the entries of the NDArray in bytes.
+    pub(crate) fn nbytes(&self) -> Result<usize> {
+        let dtype = self.dtype();
+        let bits = self.size()? * dtype.bits as usize * dtype.lanes as usize;
+        Ok((bits + 7) / 8)
+    }
+
+    // Fails if `nbytes` is not the size of the NDArray in bytes.
+    fn check_nbytes(&self, nbytes: usize) -> Result<()> {
+        let expected = self.nbytes()?;
+        if nbytes != expected {
+            bail!(
+                "expected {} bytes for the array but found {} bytes",
+                expected,
+                nbytes
+            );
+        }
+        Ok(())
     }
 
     /// Copies the NDArray to another target NDArray.
+    ///
+    /// Copies of contiguous arrays between two gpu contexts go directly from device to
+    /// device, without bouncing through the host memory, once [`enable_peer_access`] is
+    /// called for them. The source context of copies between gpus is synchronized so
+    /// that the target is ready on return.
+    ///
+    /// [`enable_peer_access`]:../context/fn.enable_peer_access.html
     pub fn copy_to_ndarray(&self, target: NDArray) -> Result<NDArray> {
         if self.dtype() != target.dtype() {
             bail!(
@@ -199,39 +339,638 @@ impl NDArray {
                 )
             );
         }
-        check_call!(ts::TVMArrayCopyFromTo(
-            self.handle,
-            target.handle,
-            ptr::null_mut() as ts::TVMStreamHandle
-        ));
+        if !copy_peer(self, &target)? {
+            check_call!(ffi::api().array_copy_from_to(
+                self.handle,
+                target.handle,
+                ptr::null_mut() as ts::TVMStreamHandle
+            ));
+        }
+        let (from, to) = (self.ctx(), target.ctx());
+        let gpu = TVMDeviceType(2);
+        if from.device_type == gpu && to.device_type == gpu && from.device_id != to.device_id {
+            // peer copies are asynchronous on the stream of the source device
+            from.sync()?;
+        }
         Ok(target)
     }
 
+    /// Copies the entries of the NDArray into a new NDArray on the same context, on the
+    /// device for device contexts.
+    pub fn copy(&self) -> Result<NDArray> {
+        self.copy_to_ctx(&self.ctx())
+    }
+
+    /// Returns a second handle to the storage of the NDArray, which the runtime keeps
+    /// alive until both handles are dropped. Writes through either handle are visible
+    /// through the other.
+    ///
+    /// Fails for views, e.g. the arrays passed to Rust callbacks, whose storage is not
+    /// reference counted by the runtime.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let shared = nd.share().unwrap();
+    /// drop(nd);
+    /// println!("{:?}", shared.to_vec::<f32>().unwrap());
+    /// ```
+    pub fn share(&self) -> Result<NDArray> {
+        if self.is_view {
+            bail!("a view cannot be shared, copy it instead");
+        }
+        // the exported tensor holds a reference on the storage, released by its deleter
+        // once the new handle is freed
+        Ok(unsafe { NDArray::from_dlpack(self.to_dlpack()) })
+    }
+
     /// Copies the NDArray to a target context.
     pub fn copy_to_ctx(&self, target: &TVMContext) -> Result<NDArray> {
-        let tmp = empty(self.shape()?, target.clone(), self.dtype());
+        let tmp = empty(&mut self.dims(), target.clone(), self.dtype());
         let copy = self.copy_to_ndarray(tmp)?;
         Ok(copy)
     }
 
+    /// Casts the entries of the NDArray to the given dtype into a new NDArray on the
+    /// same context.
+    ///
+    /// If a global packed function named [`ASTYPE_FUNCTION`] is registered, it is called
+    /// with the source and the allocated target NDArrays to cast on the device. Otherwise
+    /// the entries are cast in cpu and copied back to the context. The fallback supports
+    /// single lane `int`, `uint` and `float` dtypes of the standard widths.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut nd = empty(&mut [2], TVMContext::cpu(0), DataType::from("int"));
+    /// nd.copy_from_buffer(&[1i32, 2]).unwrap();
+    /// let nd = nd.astype(DataType::from("float")).unwrap();
+    /// assert_eq!(nd.to_vec::<f32>().unwrap(), vec![1f32, 2.]);
+    /// ```
+    ///
+    /// [`ASTYPE_FUNCTION`]:constant.ASTYPE_FUNCTION.html
+    pub fn astype(&self, dtype: DataType) -> Result<NDArray> {
+        let ctx = self.ctx();
+        if let Some(astype_fn) = function::get_global_func(ASTYPE_FUNCTION, true) {
+            let target = empty(&mut self.dims(), ctx, dtype);
+            call_packed!(astype_fn, self, &target)?;
+            return Ok(target);
+        }
+        let cpu = TVMContext::cpu(0);
+        let source = self.copy_to_ctx(&cpu)?;
+        let target = empty(&mut self.dims(), cpu, dtype);
+        let src_dtype = self.dtype();
+        unsafe {
+            let src = (*source.handle).data as *const u8;
+            let dst = (*target.handle).data as *mut u8;
+            for index in 0..self.size()? {
+                write_scalar(dst, &dtype, index, read_scalar(src, &src_dtype, index)?)?;
+            }
+        }
+        if ctx == cpu {
+            Ok(target)
+        } else {
+            target.copy_to_ctx(&ctx)
+        }
+    }
+
+    /// Returns the sum of the entries, computed in `f64` on a cpu copy, e.g. to check the
+    /// output of a kernel in a test. Supports the dtypes supported by [`astype`].
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut nd = empty(&mut [3], TVMContext::gpu(0), DataType::from("float"));
+    /// nd.copy_from_buffer(&[1f32, 2., 6.]).unwrap();
+    /// assert_eq!(nd.sum().unwrap(), 9.);
+    /// assert_eq!(nd.mean().unwrap(), 3.);
+    /// assert_eq!(nd.max().unwrap(), 6.);
+    /// ```
+    ///
+    /// [`astype`]:struct.NDArray.html#method.astype
+    pub fn sum(&self) -> Result<f64> {
+        Ok(self.host_values()?.iter().sum())
+    }
+
+    /// Returns the mean of the entries like [`sum`], failing for an empty NDArray.
+    ///
+    /// [`sum`]:struct.NDArray.html#method.sum
+    pub fn mean(&self) -> Result<f64> {
+        let values = self.host_values()?;
+        if values.is_empty() {
+            bail!(ErrorKind::EmptyArray);
+        }
+        Ok(values.iter().sum::<f64>() / values.len() as f64)
+    }
+
+    /// Returns the largest entry like [`sum`], failing for an empty NDArray. NaNs are
+    /// ignored unless all entries are NaN.
+    ///
+    /// [`sum`]:struct.NDArray.html#method.sum
+    pub fn max(&self) -> Result<f64> {
+        self.fold_values(f64::max)
+    }
+
+    /// Returns the smallest entry like [`max`].
+    ///
+    /// [`max`]:struct.NDArray.html#method.max
+    pub fn min(&self) -> Result<f64> {
+        self.fold_values(f64::min)
+    }
+
+    fn fold_values(&self, f: fn(f64, f64) -> f64) -> Result<f64> {
+        let values = self.host_values()?;
+        match values.split_first() {
+            Some((&first, rest)) => Ok(rest.iter().cloned().fold(first, f)),
+            None => bail!(ErrorKind::EmptyArray),
+        }
+    }
+
+    // Reads the entries on the cpu as `f64`s.
+    pub(crate) fn host_values(&self) -> Result<Vec<f64>> {
+        let host = self.copy_to_ctx(&TVMContext::cpu(0))?;
+        let data = unsafe { (*host.handle).data as *const u8 };
+        let dtype = self.dtype();
+        (0..self.size()?)
+            .map(|index| unsafe { read_scalar(data, &dtype, index) }.map(Scalar::to_f64))
+            .collect()
+    }
+
     /// Converts a Rust's ndarray to TVM NDArray.
     pub fn from_rust_ndarray<T: Num32 + Copy>(
         rnd: &ArrayD<T>,
         ctx: TVMContext,
-        dtype: TVMType,
+        dtype: DataType,
     ) -> Result<Self> {
         let mut shape = rnd.shape().to_vec();
         let mut nd = empty(&mut shape, ctx, dtype);
-        let mut buf = Array::from_iter(rnd.into_iter().map(|&v| v as T));
-        nd.copy_from_buffer(buf.as_slice_mut()?);
+        let buf = Array::from_iter(rnd.into_iter().map(|&v| v as T));
+        nd.copy_from_buffer(buf.as_slice()?)?;
         Ok(nd)
     }
+
+    /// Returns a view of the NDArray with the given shape sharing the same storage.
+    ///
+    /// The number of entries must not change and the NDArray must be contiguous,
+    /// otherwise an error is returned.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut nd = empty(&mut [2, 3], TVMContext::cpu(0), DataType::from("int"));
+    /// nd.copy_from_buffer(&[1i32, 2, 3, 4, 5, 6]).unwrap();
+    /// let view = nd.reshape(&[3, 2]).unwrap();
+    /// assert_eq!(view.shape(), &[3, 2]);
+    /// ```
+    pub fn reshape(&self, new_shape: &[usize]) -> Result<NDArray> {
+        let size = self.size()?;
+        if new_shape.iter().product::<usize>() != size {
+            bail!(
+                "cannot reshape an array of size {} into shape {:?}",
+                size,
+                new_shape
+            );
+        }
+        if !self.is_contiguous()? {
+            bail!("cannot reshape a non-contiguous array without copying");
+        }
+        Ok(self.view(new_shape.iter().map(|&d| d as i64).collect()))
+    }
+
+    /// Returns a view of the NDArray with a new dimension of size one inserted at `axis`.
+    pub fn expand_dims(&self, axis: usize) -> Result<NDArray> {
+        let mut shape = self.dims();
+        if axis > shape.len() {
+            bail!(
+                "axis {} is out of bounds for an array of dimension {}",
+                axis,
+                shape.len()
+            );
+        }
+        shape.insert(axis, 1);
+        self.reshape(&shape)
+    }
+
+    /// Returns a view of the NDArray without the dimension at `axis` if given, or without
+    /// all the dimensions of size one otherwise. The squeezed dimension must have size one.
+    pub fn squeeze(&self, axis: Option<usize>) -> Result<NDArray> {
+        let mut shape = self.dims();
+        match axis {
+            Some(axis) => {
+                if axis >= shape.len() || shape[axis] != 1 {
+                    bail!("cannot squeeze axis {} of an array of shape {:?}", axis, shape);
+                }
+                shape.remove(axis);
+            }
+            None => shape.retain(|&d| d != 1),
+        }
+        self.reshape(&shape)
+    }
+
+    // Creates an NDArray sharing the storage of `self` with the given compact shape.
+    fn view(&self, mut shape: Vec<i64>) -> NDArray {
+        let source = self.to_dlpack();
+        let mut dl_tensor = unsafe { (*source).dl_tensor };
+        dl_tensor.ndim = shape.len() as c_int;
+        dl_tensor.shape = shape.as_mut_ptr();
+        dl_tensor.strides = ptr::null_mut();
+        let manager = Box::new(ViewManager { source, shape });
+        let managed = Box::new(ts::DLManagedTensor {
+            dl_tensor,
+            manager_ctx: Box::into_raw(manager) as *mut c_void,
+            deleter: Some(view_deleter),
+        });
+        unsafe { NDArray::from_dlpack(Box::into_raw(managed)) }
+    }
+
+    /// Allocates an NDArray in CUDA managed memory, which the host and the gpu of `ctx`
+    /// access through the same pointer, e.g. to preprocess on the host in place of the
+    /// inputs of a graph running on the gpu. The NDArray is on `ctx` for the kernels.
+    ///
+    /// Requires the `cuda` feature. The pages migrate on access, or ahead of it with
+    /// [`prefetch`]. Accessing the entries on the host with [`managed_slice`] or
+    /// [`managed_slice_mut`] waits for the kernels running on `ctx` first.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let ctx = TVMContext::gpu(0);
+    /// let mut input = NDArray::empty_managed(&[1, 3, 224, 224], &ctx, dtype).unwrap();
+    /// preprocess(input.managed_slice_mut::<f32>().unwrap());
+    /// input.prefetch(&ctx).unwrap();
+    /// runtime.set_input("data", &input).unwrap();
+    /// ```
+    ///
+    /// [`prefetch`]:struct.NDArray.html#method.prefetch
+    /// [`managed_slice`]:struct.NDArray.html#method.managed_slice
+    /// [`managed_slice_mut`]:struct.NDArray.html#method.managed_slice_mut
+    pub fn empty_managed(shape: &[usize], ctx: &TVMContext, dtype: DataType) -> Result<NDArray> {
+        if ctx.device_type != TVMDeviceType(2) {
+            bail!("managed memory is only supported on gpu contexts, found {}", ctx);
+        }
+        let mut manager = Box::new(ManagedMemory {
+            data: ptr::null_mut(),
+            shape: shape.iter().map(|&d| d as i64).collect(),
+        });
+        let bits = shape.iter().product::<usize>() * dtype.bits as usize * dtype.lanes as usize;
+        manager.data = alloc_managed((bits + 7) / 8)?;
+        let dl_tensor = ts::DLTensor {
+            data: manager.data,
+            ctx: ctx.clone().into(),
+            ndim: shape.len() as c_int,
+            dtype: dtype.into(),
+            shape: manager.shape.as_mut_ptr(),
+            strides: ptr::null_mut(),
+            byte_offset: 0,
+        };
+        let managed = Box::new(ts::DLManagedTensor {
+            dl_tensor,
+            manager_ctx: Box::into_raw(manager) as *mut c_void,
+            deleter: Some(managed_deleter),
+        });
+        Ok(unsafe { NDArray::from_dlpack(Box::into_raw(managed)) }.tracked())
+    }
+
+    /// Returns whether the storage of the NDArray is CUDA managed memory, see
+    /// [`empty_managed`].
+    ///
+    /// [`empty_managed`]:struct.NDArray.html#method.empty_managed
+    pub fn is_managed(&self) -> bool {
+        is_managed_ptr(self.data_ptr())
+    }
+
+    /// Migrates the pages of a managed NDArray to the memory of `target`, the host for a
+    /// cpu context, ahead of the accesses. The migration is asynchronous.
+    pub fn prefetch(&self, target: &TVMContext) -> Result<()> {
+        let nbytes = self.nbytes()?;
+        if nbytes == 0 {
+            return Ok(());
+        }
+        if !self.is_managed() {
+            bail!("only managed NDArrays can be prefetched");
+        }
+        let device = match target.device_type {
+            TVMDeviceType(1) => None,
+            TVMDeviceType(2) => Some(target.device_id),
+            _ => bail!("managed memory cannot be prefetched to {}", target),
+        };
+        prefetch_managed(self.data_ptr(), nbytes, device)
+    }
+
+    /// Returns the entries of a managed NDArray, accessed from the host in place.
+    ///
+    /// Synchronizes the context of the NDArray first, so that the kernels writing the
+    /// entries are done.
+    pub fn managed_slice<T: TVMDataType>(&self) -> Result<&[T]> {
+        if !self.check_managed_access::<T>()? {
+            return Ok(&[]);
+        }
+        self.ctx().sync()?;
+        Ok(unsafe { slice::from_raw_parts(self.data_ptr() as *const T, self.len()) })
+    }
+
+    /// Returns the entries of a managed NDArray, written from the host in place.
+    ///
+    /// Synchronizes the context of the NDArray first, so that the kernels accessing the
+    /// entries are done.
+    pub fn managed_slice_mut<T: TVMDataType>(&mut self) -> Result<&mut [T]> {
+        if !self.check_managed_access::<T>()? {
+            return Ok(&mut []);
+        }
+        self.ctx().sync()?;
+        Ok(unsafe { slice::from_raw_parts_mut(self.data_ptr() as *mut T, self.len()) })
+    }
+
+    // Checks that the entries can be accessed as a slice of `T`, returning `false` if
+    // there are none, e.g. for a managed NDArray without storage.
+    fn check_managed_access<T: TVMDataType>(&self) -> Result<bool> {
+        if T::data_type() != self.dtype() {
+            bail!(ErrorKind::TypeMismatch(
+                T::data_type().to_string(),
+                self.dtype().to_string()
+            ));
+        }
+        if self.is_empty() {
+            return Ok(false);
+        }
+        if !self.is_managed() {
+            bail!("only managed NDArrays can be accessed from the host in place");
+        }
+        if !self.is_contiguous()? {
+            bail!("only contiguous NDArrays can be accessed as slices");
+        }
+        Ok(true)
+    }
+
+    // Returns the address of the first entry.
+    fn data_ptr(&self) -> *mut c_void {
+        unsafe { ((*self.handle).data as *mut u8).offset(self.byte_offset()) as *mut c_void }
+    }
+
+    /// Creates an NDArray from a DLPack managed tensor without copying and takes
+    /// the ownership of it.
+    ///
+    /// ## Safety
+    ///
+    /// `managed` must point to a valid `DLManagedTensor` whose `deleter` releases the
+    /// underlying data once the NDArray is dropped.
+    pub unsafe fn from_dlpack(managed: *mut ts::DLManagedTensor) -> NDArray {
+        let mut handle = ptr::null_mut() as ts::TVMArrayHandle;
+        check_call!(ffi::api().array_from_dlpack(managed, &mut handle as *mut _));
+        NDArray::new(handle, false)
+    }
+
+    /// Creates an NDArray taking the ownership of an array handle, e.g. one allocated with
+    /// `TVMArrayAlloc` by other code using the TVM C API or returned by [`into_raw`].
+    ///
+    /// ## Safety
+    ///
+    /// `handle` must be a valid array handle which is not freed by anyone else, since the
+    /// NDArray frees it with `TVMArrayFree` on drop.
+    ///
+    /// [`into_raw`]:struct.NDArray.html#method.into_raw
+    pub unsafe fn from_raw(handle: ts::TVMArrayHandle) -> NDArray {
+        NDArray::new(handle, false).tracked()
+    }
+
+    // Records the storage of an owned NDArray allocated elsewhere than in `empty` with the
+    // memory tracker, which ignores the arrays not recorded when they are freed.
+    pub(crate) fn tracked(self) -> Self {
+        if !self.is_view && memory::is_tracking() {
+            if let Ok(nbytes) = self.nbytes() {
+                memory::record_alloc(self.handle as *const c_void, &self.ctx(), nbytes);
+            }
+        }
+        self
+    }
+
+    /// Returns the array handle, transferring its ownership to the caller, who must free
+    /// it with `TVMArrayFree` or pass it back to [`from_raw`]. Fails for views, e.g. the
+    /// arguments of a callback, which do not own their handle. The arrays returned by
+    /// [`reshape`] own theirs and keep the storage they share alive until it is freed.
+    ///
+    /// [`from_raw`]:struct.NDArray.html#method.from_raw
+    /// [`reshape`]:struct.NDArray.html#method.reshape
+    pub fn into_raw(mut self) -> Result<ts::TVMArrayHandle> {
+        if self.is_view {
+            bail!("a view does not own its array handle");
+        }
+        memory::record_free(self.handle as *const c_void);
+        // not freed on drop anymore
+        self.is_view = true;
+        Ok(self.handle)
+    }
+
+    /// Exports the NDArray as a DLPack managed tensor sharing the same storage.
+    ///
+    /// The caller is responsible for calling the `deleter` of the returned tensor.
+    pub fn to_dlpack(&self) -> *mut ts::DLManagedTensor {
+        let mut managed = ptr::null_mut() as *mut ts::DLManagedTensor;
+        check_call!(ffi::api().array_to_dlpack(self.handle, &mut managed as *mut _));
+        managed
+    }
+}
+
+/// Name of the global packed function used by [`NDArray::astype`] to cast on the device,
+/// if registered. It is called with the source and the target NDArrays.
+///
+/// [`NDArray::astype`]:struct.NDArray.html#method.astype
+pub const ASTYPE_FUNCTION: &'static str = "tvm_frontend.astype";
+
+// An entry of an NDArray widened to the largest type of its kind.
+#[derive(Debug, Clone, Copy)]
+enum Scalar {
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+}
+
+macro_rules! cast_scalar {
+    ($value:expr, $type:ty) => {
+        match $value {
+            Scalar::Int(v) => v as $type,
+            Scalar::UInt(v) => v as $type,
+            Scalar::Float(v) => v as $type,
+        }
+    };
+}
+
+impl Scalar {
+    fn to_f64(self) -> f64 {
+        cast_scalar!(self, f64)
+    }
+}
+
+fn unsupported_cast(dtype: &DataType) -> Error {
+    format!(
+        "astype does not support the dtype (code: {}, bits: {}, lanes: {})",
+        dtype.code, dtype.bits, dtype.lanes
+    ).into()
+}
+
+// Reads the entry at `index` of a compact host buffer of the given dtype.
+unsafe fn read_scalar(data: *const u8, dtype: &DataType, index: usize) -> Result<Scalar> {
+    Ok(match (dtype.code, dtype.bits, dtype.lanes) {
+        (0, 8, 1) => Scalar::Int(*(data as *const i8).add(index) as i64),
+        (0, 16, 1) => Scalar::Int(*(data as *const i16).add(index) as i64),
+        (0, 32, 1) => Scalar::Int(*(data as *const i32).add(index) as i64),
+        (0, 64, 1) => Scalar::Int(*(data as *const i64).add(index)),
+        (1, 8, 1) => Scalar::UInt(*(data as *const u8).add(index) as u64),
+        (1, 16, 1) => Scalar::UInt(*(data as *const u16).add(index) as u64),
+        (1, 32, 1) => Scalar::UInt(*(data as *const u32).add(index) as u64),
+        (1, 64, 1) => Scalar::UInt(*(data as *const u64).add(index)),
+        (2, 32, 1) => Scalar::Float(*(data as *const f32).add(index) as f64),
+        (2, 64, 1) => Scalar::Float(*(data as *const f64).add(index)),
+        _ => return Err(unsupported_cast(dtype)),
+    })
+}
+
+// Writes the entry at `index` of a compact host buffer of the given dtype.
+unsafe fn write_scalar(data: *mut u8, dtype: &DataType, index: usize, value: Scalar) -> Result<()> {
+    match (dtype.code, dtype.bits, dtype.lanes) {
+        (0, 8, 1) => *(data as *mut i8).add(index) = cast_scalar!(value, i8),
+        (0, 16, 1) => *(data as *mut i16).add(index) = cast_scalar!(value, i16),
+        (0, 32, 1) => *(data as *mut i32).add(index) = cast_scalar!(value, i32),
+        (0, 64, 1) => *(data as *mut i64).add(index) = cast_scalar!(value, i64),
+        (1, 8, 1) => *(data as *mut u8).add(index) = cast_scalar!(value, u8),
+        (1, 16, 1) => *(data as *mut u16).add(index) = cast_scalar!(value, u16),
+        (1, 32, 1) => *(data as *mut u32).add(index) = cast_scalar!(value, u32),
+        (1, 64, 1) => *(data as *mut u64).add(index) = cast_scalar!(value, u64),
+        (2, 32, 1) => *(data as *mut f32).add(index) = cast_scalar!(value, f32),
+        (2, 64, 1) => *(data as *mut f64).add(index) = cast_scalar!(value, f64),
+        _ => return Err(unsupported_cast(dtype)),
+    }
+    Ok(())
+}
+
+// Keeps the storage of the viewed NDArray alive as long as TVM holds the view.
+struct ViewManager {
+    source: *mut ts::DLManagedTensor,
+    shape: Vec<i64>,
+}
+
+unsafe extern "C" fn view_deleter(managed: *mut ts::DLManagedTensor) {
+    let managed = Box::from_raw(managed);
+    let manager = Box::from_raw(managed.manager_ctx as *mut ViewManager);
+    if let Some(deleter) = (*manager.source).deleter {
+        deleter(manager.source);
+    }
+}
+
+// Frees the managed memory of an NDArray allocated with `NDArray::empty_managed`.
+struct ManagedMemory {
+    data: *mut c_void,
+    shape: Vec<i64>,
+}
+
+unsafe extern "C" fn managed_deleter(managed: *mut ts::DLManagedTensor) {
+    let managed = Box::from_raw(managed);
+    let manager = Box::from_raw(managed.manager_ctx as *mut ManagedMemory);
+    free_managed(manager.data);
+}
+
+// Copies the entries of `from` directly to `to` if both are contiguous arrays of the same
+// size on two gpus with peer access enabled, returning whether it did.
+#[cfg(feature = "cuda")]
+fn copy_peer(from: &NDArray, to: &NDArray) -> Result<bool> {
+    let (src, dst) = (from.ctx(), to.ctx());
+    let gpu = TVMDeviceType(2);
+    if src.device_type != gpu
+        || dst.device_type != gpu
+        || src.device_id == dst.device_id
+        || !context::peer_access_enabled(&src, &dst)
+        || !from.is_contiguous()?
+        || !to.is_contiguous()?
+        || from.nbytes()? != to.nbytes()?
+    {
+        return Ok(false);
+    }
+    let ret = unsafe {
+        cuda::cudaMemcpyPeer(
+            to.data_ptr(),
+            dst.device_id as c_int,
+            from.data_ptr(),
+            src.device_id as c_int,
+            from.nbytes()?,
+        )
+    };
+    if ret != 0 {
+        bail!("failed to copy from {} to {}, cuda error {}", src, dst, ret);
+    }
+    Ok(true)
+}
+
+#[cfg(not(feature = "cuda"))]
+fn copy_peer(_from: &NDArray, _to: &NDArray) -> Result<bool> {
+    Ok(false)
+}
+
+#[cfg(feature = "cuda")]
+fn alloc_managed(nbytes: usize) -> Result<*mut c_void> {
+    let mut data = ptr::null_mut();
+    // `cudaMallocManaged` fails for 0 bytes, which need no storage
+    if nbytes == 0 {
+        return Ok(data);
+    }
+    let ret = unsafe { cuda::cudaMallocManaged(&mut data, nbytes, cuda::MEM_ATTACH_GLOBAL) };
+    if ret != 0 {
+        bail!("failed to allocate {} bytes of managed memory, cuda error {}", nbytes, ret);
+    }
+    Ok(data)
+}
+
+#[cfg(not(feature = "cuda"))]
+fn alloc_managed(_nbytes: usize) -> Result<*mut c_void> {
+    bail!("managed memory requires the `cuda` feature")
+}
+
+#[cfg(feature = "cuda")]
+fn free_managed(data: *mut c_void) {
+    unsafe {
+        cuda::cudaFree(data);
+    }
+}
+
+#[cfg(not(feature = "cuda"))]
+fn free_managed(_data: *mut c_void) {}
+
+#[cfg(feature = "cuda")]
+fn is_managed_ptr(data: *const c_void) -> bool {
+    let mut attrs = cuda::PointerAttributes {
+        memory_type: 0,
+        device: 0,
+        device_pointer: ptr::null_mut(),
+        host_pointer: ptr::null_mut(),
+    };
+    let ret = unsafe { cuda::cudaPointerGetAttributes(&mut attrs, data) };
+    ret == 0 && attrs.memory_type == cuda::MEMORY_TYPE_MANAGED
+}
+
+#[cfg(not(feature = "cuda"))]
+fn is_managed_ptr(_data: *const c_void) -> bool {
+    false
+}
+
+// Prefetches to the gpu `device`, or the host for `None`, on the default stream.
+#[cfg(feature = "cuda")]
+fn prefetch_managed(data: *const c_void, nbytes: usize, device: Option<usize>) -> Result<()> {
+    let device = device.map_or(cuda::CPU_DEVICE_ID, |device| device as c_int);
+    let ret = unsafe { cuda::cudaMemPrefetchAsync(data, nbytes, device, ptr::null_mut()) };
+    if ret != 0 {
+        bail!("failed to prefetch managed memory, cuda error {}", ret);
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "cuda"))]
+fn prefetch_managed(_data: *const c_void, _nbytes: usize, _device: Option<usize>) -> Result<()> {
+    bail!("managed memory requires the `cuda` feature")
 }
 
 /// Allocates and creates an empty NDArray given the shape, context and dtype.
-pub fn empty(shape: &mut [usize], ctx: TVMContext, dtype: TVMType) -> NDArray {
+pub fn empty(shape: &mut [usize], ctx: TVMContext, dtype: DataType) -> NDArray {
     let mut handle = ptr::null_mut() as ts::TVMArrayHandle;
-    check_call!(ts::TVMArrayAlloc(
+    check_call!(ffi::api().array_alloc(
         shape.as_ptr() as *const i64,
         shape.len() as c_int,
         dtype.inner.code as c_int,
@@ -241,30 +980,150 @@ pub fn empty(shape: &mut [usize], ctx: TVMContext, dtype: TVMType) -> NDArray {
         ctx.device_id as c_int,
         &mut handle as *mut _,
     ));
+    let bits = shape.iter().product::<usize>() * dtype.bits as usize * dtype.lanes as usize;
+    memory::record_alloc(handle as *const c_void, &ctx, (bits + 7) / 8);
     NDArray::new(handle, false)
 }
 
+// Returns the size of an entry of `dtype` in bytes, failing for sub-byte dtypes.
+fn entry_bytes(dtype: DataType) -> Result<usize> {
+    let bits = dtype.bits as usize * dtype.lanes as usize;
+    if bits % 8 != 0 {
+        bail!("cannot split entries of {} bits", bits);
+    }
+    Ok(bits / 8)
+}
+
+// Returns the lengths of `n` shards of `len` entries, the first ones one longer.
+fn shard_lens(len: usize, n: usize) -> Vec<usize> {
+    (0..n).map(|i| len / n + (i < len % n) as usize).collect()
+}
+
+/// Splits an NDArray along `axis` into one shard per context, e.g. a batch across the
+/// devices of a data parallel setup. The shards are as even as possible, the first ones
+/// being one longer if the length of the axis is not a multiple of the number of contexts.
+///
+/// The entries go through the host memory.
+///
+/// ## Example
+///
+/// ```
+/// let ctxs = [TVMContext::gpu(0), TVMContext::gpu(1)];
+/// let shards = ndarray::scatter(&batch, &ctxs, 0).unwrap();
+/// let outputs = run_on_each(&shards);
+/// let output = ndarray::gather(&outputs, TVMContext::cpu(0), 0).unwrap();
+/// ```
+pub fn scatter(array: &NDArray, ctxs: &[TVMContext], axis: usize) -> Result<Vec<NDArray>> {
+    let shape = array.dims();
+    if axis >= shape.len() {
+        bail!("axis {} is out of the {} dimensions", axis, shape.len());
+    }
+    if ctxs.is_empty() || shape[axis] < ctxs.len() {
+        bail!(
+            "cannot split {} entries along axis {} into {} shards",
+            shape[axis],
+            axis,
+            ctxs.len()
+        );
+    }
+    let dtype = array.dtype();
+    let inner = shape[axis + 1..].iter().product::<usize>() * entry_bytes(dtype)?;
+    let mut bytes = vec![0u8; array.nbytes()?];
+    array.copy_to_bytes(&mut bytes)?;
+    let mut offset = 0;
+    shard_lens(shape[axis], ctxs.len())
+        .into_iter()
+        .zip(ctxs)
+        .map(|(len, ctx)| {
+            let mut shard_shape = shape.clone();
+            shard_shape[axis] = len;
+            let mut shard = empty(&mut shard_shape, ctx.clone(), dtype);
+            // an empty array has no rows to chunk, e.g. with a zero-size trailing dimension
+            if inner > 0 {
+                let shard_bytes = bytes
+                    .chunks(shape[axis] * inner)
+                    .flat_map(|outer| &outer[offset * inner..(offset + len) * inner])
+                    .cloned()
+                    .collect::<Vec<_>>();
+                shard.copy_from_bytes(&shard_bytes)?;
+            }
+            offset += len;
+            Ok(shard)
+        })
+        .collect()
+}
+
+/// Concatenates shards along `axis` into an NDArray on `ctx`, e.g. the outputs of the
+/// devices of a data parallel setup. It reverses [`scatter`].
+///
+/// The shards must have the same dtype and the same dimensions except along `axis`.
+///
+/// [`scatter`]:fn.scatter.html
+pub fn gather(shards: &[NDArray], ctx: TVMContext, axis: usize) -> Result<NDArray> {
+    let first = match shards.first() {
+        Some(first) => first,
+        None => bail!("cannot gather no shards"),
+    };
+    let (mut shape, dtype) = (first.dims(), first.dtype());
+    if axis >= shape.len() {
+        bail!("axis {} is out of the {} dimensions", axis, shape.len());
+    }
+    let inner = shape[axis + 1..].iter().product::<usize>() * entry_bytes(dtype)?;
+    let mut shard_bytes = Vec::with_capacity(shards.len());
+    for shard in shards {
+        if shard.dtype() != dtype {
+            bail!(ErrorKind::TypeMismatch(dtype.to_string(), shard.dtype().to_string()));
+        }
+        let mut dims = shard.dims();
+        let len = dims.get(axis).cloned().unwrap_or(0);
+        if dims.len() == shape.len() {
+            dims[axis] = shape[axis];
+        }
+        if dims != shape {
+            bail!(
+                "cannot gather a shard of shape {:?} with shards of shape {:?}",
+                shard.dims(),
+                first.dims()
+            );
+        }
+        let mut bytes = vec![0u8; shard.nbytes()?];
+        shard.copy_to_bytes(&mut bytes)?;
+        shard_bytes.push((len, bytes));
+    }
+    let outer = shape[..axis].iter().product::<usize>();
+    let mut bytes = Vec::with_capacity(shard_bytes.iter().map(|(_, b)| b.len()).sum());
+    for i in 0..outer {
+        for (len, shard) in &shard_bytes {
+            bytes.extend_from_slice(&shard[i * len * inner..(i + 1) * len * inner]);
+        }
+    }
+    shape[axis] = shard_bytes.iter().map(|(len, _)| len).sum();
+    let mut array = empty(&mut shape, ctx, dtype);
+    array.copy_from_bytes(&bytes)?;
+    Ok(array)
+}
+
 macro_rules! impl_from_ndarray_rustndarray {
     ($type:ty, $type_name:tt) => {
         impl<'a> TryFrom<&'a NDArray> for ArrayD<$type> {
             type Error = Error;
             fn try_from(nd: &NDArray) -> Result<ArrayD<$type>> {
-                if nd.shape().is_none() {
+                if nd.size().is_none() {
                     bail!("{}", ErrorKind::EmptyArray);
                 }
-                assert_eq!(nd.dtype(), TVMType::from($type_name), "Type mismatch");
-                Ok(Array::from_shape_vec(&*nd.shape()?, nd.to_vec::<$type>()?)?)
+                assert_eq!(nd.dtype(), DataType::from($type_name), "Type mismatch");
+                Ok(Array::from_shape_vec(nd.dims(), nd.to_vec::<$type>()?)?)
             }
         }
 
         impl<'a> TryFrom<&'a mut NDArray> for ArrayD<$type> {
             type Error = Error;
             fn try_from(nd: &mut NDArray) -> Result<ArrayD<$type>> {
-                if nd.shape().is_none() {
+                if nd.size().is_none() {
                     bail!("{}", ErrorKind::EmptyArray);
                 }
-                assert_eq!(nd.dtype(), TVMType::from($type_name), "Type mismatch");
-                Ok(Array::from_shape_vec(&*nd.shape()?, nd.to_vec::<$type>()?)?)
+                assert_eq!(nd.dtype(), DataType::from($type_name), "Type mismatch");
+                Ok(Array::from_shape_vec(nd.dims(), nd.to_vec::<$type>()?)?)
             }
         }
     };
@@ -277,13 +1136,14 @@ impl_from_ndarray_rustndarray!(f32, "float");
 impl Drop for NDArray {
     fn drop(&mut self) {
         if !self.is_view {
-            check_call!(ts::TVMArrayFree(self.handle));
+            memory::record_free(self.handle as *const c_void);
+            check_call!(ffi::api().array_free(self.handle));
         }
     }
 }
 
 /// A trait for the supported 32bits numerical types in frontend.
-pub trait Num32: Num {
+pub trait Num32: Num + TVMDataType {
     const BITS: u8 = 32;
 }
 
@@ -305,13 +1165,13 @@ mod tests {
     fn basics() {
         let shape = &mut [1, 2, 3];
         let ctx = TVMContext::cpu(0);
-        let ndarray = empty(shape, ctx, TVMType::from("int"));
-        assert_eq!(ndarray.shape().unwrap(), shape);
-        assert_eq!(
-            ndarray.size().unwrap(),
-            shape.to_vec().into_iter().product()
-        );
+        let ndarray = empty(shape, ctx, DataType::from("int"));
+        assert_eq!(ndarray.shape(), &[1, 2, 3]);
+        assert_eq!(ndarray.len(), 6);
+        assert_eq!(ndarray.size(), Some(6));
         assert_eq!(ndarray.ndim(), 3);
+        assert_eq!(ndarray.dtype(), DataType::from("int"));
+        assert_eq!(ndarray.ctx(), TVMContext::cpu(0));
         assert!(ndarray.strides().is_none());
         assert_eq!(ndarray.byte_offset(), 0);
     }
@@ -319,44 +1179,200 @@ mod tests {
     #[test]
     fn copy() {
         let shape = &mut [4];
-        let mut data = vec![1i32, 2, 3, 4];
+        let data = vec![1i32, 2, 3, 4];
         let ctx = TVMContext::cpu(0);
-        let mut ndarray = empty(shape, ctx, TVMType::from("int"));
+        let mut ndarray = empty(shape, ctx, DataType::from("int"));
         assert!(ndarray.to_vec::<i32>().is_ok());
-        ndarray.copy_from_buffer(&mut data);
-        assert_eq!(ndarray.shape().unwrap(), shape);
+        ndarray.copy_from_buffer(&data).unwrap();
+        assert_eq!(ndarray.shape(), &[4]);
         assert_eq!(ndarray.to_vec::<i32>().unwrap(), data);
         assert_eq!(ndarray.ndim(), 1);
         assert!(ndarray.is_contiguous().is_ok());
         assert_eq!(ndarray.byte_offset(), 0);
         let mut shape = vec![4];
-        let e = empty(&mut shape, TVMContext::cpu(0), TVMType::from("int"));
+        let e = empty(&mut shape, TVMContext::cpu(0), DataType::from("int"));
         let nd = ndarray.copy_to_ndarray(e);
         assert!(nd.is_ok());
         assert_eq!(nd.unwrap().to_vec::<i32>().unwrap(), data);
     }
 
+    #[test]
+    fn copy_and_share() {
+        let mut nd = empty(&mut [2], TVMContext::cpu(0), DataType::from("int"));
+        nd.copy_from_buffer(&[1i32, 2]).unwrap();
+        let copy = nd.copy().unwrap();
+        let shared = nd.share().unwrap();
+        nd.copy_from_buffer(&[3i32, 4]).unwrap();
+        assert_eq!(copy.to_vec::<i32>().unwrap(), vec![1, 2]);
+        let view = NDArray::new(nd.handle, true);
+        assert!(view.share().is_err());
+        drop(view);
+        drop(nd);
+        assert_eq!(shared.to_vec::<i32>().unwrap(), vec![3, 4]);
+    }
+
+    #[test]
+    fn raw_parts() {
+        let mut nd = empty(&mut [2], TVMContext::cpu(0), DataType::from("int"));
+        nd.copy_from_buffer(&[1i32, 2]).unwrap();
+        let handle = nd.into_raw().unwrap();
+        let nd = unsafe { NDArray::from_raw(handle) };
+        assert_eq!(nd.to_vec::<i32>().unwrap(), vec![1, 2]);
+        let reshaped = nd.reshape(&[2, 1]).unwrap();
+        assert!(!reshaped.is_view());
+        let reshaped = unsafe { NDArray::from_raw(reshaped.into_raw().unwrap()) };
+        drop(nd);
+        assert_eq!(reshaped.to_vec::<i32>().unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn copy_bytes() {
+        let mut shape = vec![2];
+        let mut nd = empty(&mut shape, TVMContext::cpu(0), DataType::from("int"));
+        let bytes = [1u8, 0, 0, 0, 2, 0, 0, 0];
+        nd.copy_from_bytes(&bytes).unwrap();
+        assert_eq!(nd.to_vec::<i32>().unwrap(), vec![1, 2]);
+        let mut out = [0u8; 8];
+        nd.copy_to_bytes(&mut out).unwrap();
+        assert_eq!(out, bytes);
+        assert!(nd.copy_from_bytes(&bytes[..4]).is_err());
+        assert!(nd.copy_to_bytes(&mut [0u8; 12]).is_err());
+    }
+
+    #[test]
+    fn copy_checked_buffer() {
+        let mut nd = empty(&mut [2], TVMContext::cpu(0), DataType::from("float"));
+        match nd.copy_from_buffer(&[1i32, 2]) {
+            Err(Error(ErrorKind::TypeMismatch(expected, found), _)) => {
+                assert_eq!((expected.as_str(), found.as_str()), ("float", "int"))
+            }
+            _ => panic!("expected a type mismatch"),
+        }
+        assert!(nd.copy_from_buffer(&[1f32, 2., 3.]).is_err());
+        nd.copy_from_buffer(&[1f32, 2.]).unwrap();
+        assert_eq!(nd.to_vec::<f32>().unwrap(), vec![1f32, 2.]);
+    }
+
     #[test]
     #[should_panic(expected = "called `Result::unwrap()` on an `Err`")]
     fn copy_wrong_dtype() {
         let mut shape = vec![4];
-        let mut data = vec![1f32, 2., 3., 4.];
+        let data = vec![1f32, 2., 3., 4.];
         let ctx = TVMContext::cpu(0);
-        let mut nd_float = empty(&mut shape, ctx.clone(), TVMType::from("float"));
-        nd_float.copy_from_buffer(&mut data);
-        let empty_int = empty(&mut shape, ctx, TVMType::from("int"));
+        let mut nd_float = empty(&mut shape, ctx.clone(), DataType::from("float"));
+        nd_float.copy_from_buffer(&data).unwrap();
+        let empty_int = empty(&mut shape, ctx, DataType::from("int"));
         nd_float.copy_to_ndarray(empty_int).unwrap();
     }
 
+    #[test]
+    fn reshape() {
+        let mut shape = vec![2, 3];
+        let data = vec![1i32, 2, 3, 4, 5, 6];
+        let mut nd = empty(&mut shape, TVMContext::cpu(0), DataType::from("int"));
+        nd.copy_from_buffer(&data).unwrap();
+        let view = nd.reshape(&[3, 2]).unwrap();
+        assert_eq!(view.shape(), &[3, 2]);
+        assert_eq!(view.to_vec::<i32>().unwrap(), data);
+        nd.copy_from_buffer(&[0i32; 6]).unwrap();
+        assert_eq!(view.to_vec::<i32>().unwrap(), vec![0; 6]);
+        assert!(nd.reshape(&[4, 2]).is_err());
+    }
+
+    #[test]
+    fn expand_and_squeeze() {
+        let mut shape = vec![2, 1, 3];
+        let nd = empty(&mut shape, TVMContext::cpu(0), DataType::from("float"));
+        assert_eq!(nd.expand_dims(0).unwrap().shape(), &[1, 2, 1, 3]);
+        assert_eq!(nd.expand_dims(3).unwrap().shape(), &[2, 1, 3, 1]);
+        assert!(nd.expand_dims(4).is_err());
+        assert_eq!(nd.squeeze(None).unwrap().shape(), &[2, 3]);
+        assert_eq!(nd.squeeze(Some(1)).unwrap().shape(), &[2, 3]);
+        assert!(nd.squeeze(Some(0)).is_err());
+    }
+
+    #[test]
+    fn astype() {
+        let mut shape = vec![3];
+        let mut nd = empty(&mut shape, TVMContext::cpu(0), DataType::from("float"));
+        nd.copy_from_buffer(&[1.5f32, -2., 3.]).unwrap();
+        let nd_int = nd.astype(DataType::from("int")).unwrap();
+        assert_eq!(nd_int.dtype(), DataType::from("int"));
+        assert_eq!(nd_int.to_vec::<i32>().unwrap(), vec![1, -2, 3]);
+        let nd_float = nd_int.astype(DataType::from("float")).unwrap();
+        assert_eq!(nd_float.to_vec::<f32>().unwrap(), vec![1f32, -2., 3.]);
+        assert!(nd.astype(DataType::new(2, 16, 1)).is_err());
+    }
+
+    #[test]
+    fn reductions() {
+        let mut nd = empty(&mut [2, 2], TVMContext::cpu(0), DataType::from("int"));
+        nd.copy_from_buffer(&[3i32, -1, 4, 2]).unwrap();
+        assert_eq!(nd.sum().unwrap(), 8.);
+        assert_eq!(nd.mean().unwrap(), 2.);
+        assert_eq!(nd.max().unwrap(), 4.);
+        assert_eq!(nd.min().unwrap(), -1.);
+        let empty = empty(&mut [0], TVMContext::cpu(0), DataType::from("float"));
+        assert!(empty.max().is_err());
+    }
+
     #[test]
     fn rust_ndarray() {
         let a = Array::from_shape_vec((2, 2), vec![1f32, 2., 3., 4.])
             .unwrap()
             .into_dyn();
         let nd =
-            NDArray::from_rust_ndarray(&a, TVMContext::cpu(0), TVMType::from("float")).unwrap();
-        assert_eq!(nd.shape().unwrap(), &mut [2, 2]);
+            NDArray::from_rust_ndarray(&a, TVMContext::cpu(0), DataType::from("float")).unwrap();
+        assert_eq!(nd.shape(), &[2, 2]);
         let rnd: ArrayD<f32> = ArrayD::try_from(&nd).unwrap();
         assert!(rnd.all_close(&a, 1e-8f32));
     }
+
+    #[test]
+    fn managed() {
+        let dtype = DataType::from("float");
+        assert!(NDArray::empty_managed(&[2], &TVMContext::cpu(0), dtype).is_err());
+        let mut nd = empty(&mut [2], TVMContext::cpu(0), dtype);
+        assert!(!nd.is_managed());
+        assert!(nd.prefetch(&TVMContext::cpu(0)).is_err());
+        assert!(nd.managed_slice_mut::<f32>().is_err());
+        let mut nothing = empty(&mut [0, 3], TVMContext::cpu(0), dtype);
+        assert!(nothing.managed_slice_mut::<f32>().unwrap().is_empty());
+        assert!(nothing.managed_slice::<i32>().is_err());
+    }
+
+    #[cfg(feature = "cuda")]
+    #[test]
+    fn empty_managed() {
+        let dtype = DataType::from("float");
+        let mut nd = NDArray::empty_managed(&[0, 3], &TVMContext::gpu(0), dtype).unwrap();
+        assert!(nd.prefetch(&TVMContext::cpu(0)).is_ok());
+        assert!(nd.managed_slice_mut::<f32>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn scatter_gather() {
+        let mut nd = empty(&mut [2, 5], TVMContext::cpu(0), DataType::from("int"));
+        nd.copy_from_buffer(&(0..10).collect::<Vec<i32>>()).unwrap();
+        let ctxs = vec![TVMContext::cpu(0); 2];
+        let shards = scatter(&nd, &ctxs, 1).unwrap();
+        assert_eq!(shards[0].shape(), &[2, 3]);
+        assert_eq!(shards[0].to_vec::<i32>().unwrap(), vec![0, 1, 2, 5, 6, 7]);
+        assert_eq!(shards[1].to_vec::<i32>().unwrap(), vec![3, 4, 8, 9]);
+        let gathered = gather(&shards, TVMContext::cpu(0), 1).unwrap();
+        assert_eq!(gathered.shape(), &[2, 5]);
+        assert_eq!(gathered.to_vec::<i32>().unwrap(), nd.to_vec::<i32>().unwrap());
+        assert!(scatter(&nd, &vec![TVMContext::cpu(0); 3], 0).is_err());
+        assert!(gather(&[nd, shards.into_iter().next().unwrap()], TVMContext::cpu(0), 0).is_err());
+    }
+
+    #[test]
+    fn scatter_empty() {
+        let nd = empty(&mut [2, 4, 0], TVMContext::cpu(0), DataType::from("int"));
+        let shards = scatter(&nd, &vec![TVMContext::cpu(0); 2], 1).unwrap();
+        assert_eq!(shards[0].shape(), &[2, 2, 0]);
+        assert_eq!(shards[1].shape(), &[2, 2, 0]);
+        let gathered = gather(&shards, TVMContext::cpu(0), 1).unwrap();
+        assert_eq!(gathered.shape(), &[2, 4, 0]);
+    }
 }
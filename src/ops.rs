@@ -0,0 +1,134 @@
+//! Provides cpu implementations of the common post-processing operations on [`NDArray`]
+//! outputs such as [`argmax`], [`top_k`] and [`softmax`].
+//!
+//! The operations work along the last axis of NDArrays of any `int`, `uint` or `float`
+//! dtype, treating the leading axes as a batch, e.g. the `[batch, classes]` output of a
+//! classifier.
+//!
+//! # Example
+//!
+//! ```
+//! runtime.get_output(0, &output).unwrap();
+//! let probs = ops::softmax(&output).unwrap();
+//! let best = ops::top_k(&probs, 5).unwrap();
+//! println!("best class: {} with probability {}", best[0][0].0, best[0][0].1);
+//! ```
+
+use std::cmp::Ordering;
+
+use ndarray::empty;
+use DataType;
+use NDArray;
+use Result;
+use TVMContext;
+
+// Returns the entries of the NDArray as `f64` and the length of the last axis.
+fn rows(nd: &NDArray) -> Result<(Vec<f64>, usize)> {
+    let width = match nd.shape().last() {
+        Some(&width) if width > 0 => width as usize,
+        _ => bail!("expected an NDArray with a non-empty last axis"),
+    };
+    let values = nd.astype(DataType::new(2, 64, 1))?.to_vec::<f64>()?;
+    Ok((values, width))
+}
+
+fn descending(a: &f64, b: &f64) -> Ordering {
+    b.partial_cmp(a).unwrap_or(Ordering::Equal)
+}
+
+/// Returns the index of the largest entry of each row along the last axis. Ties resolve
+/// to the lowest index.
+pub fn argmax(nd: &NDArray) -> Result<Vec<usize>> {
+    let (values, width) = rows(nd)?;
+    Ok(values
+        .chunks(width)
+        .map(|row| {
+            row.iter()
+                .enumerate()
+                .fold(0, |best, (i, v)| if *v > row[best] { i } else { best })
+        })
+        .collect())
+}
+
+/// Returns the `k` largest entries of each row along the last axis as `(index, value)`
+/// pairs in descending order. All the entries are returned if a row has less than `k`.
+pub fn top_k(nd: &NDArray, k: usize) -> Result<Vec<Vec<(usize, f64)>>> {
+    let (values, width) = rows(nd)?;
+    Ok(values
+        .chunks(width)
+        .map(|row| {
+            let mut entries = row.iter().cloned().enumerate().collect::<Vec<_>>();
+            // the sort is stable, so ties keep the lowest index first
+            entries.sort_by(|a, b| descending(&a.1, &b.1));
+            entries.truncate(k);
+            entries
+        })
+        .collect())
+}
+
+/// Computes the softmax of each row along the last axis into a new NDArray in cpu.
+///
+/// The output has the dtype of `nd` if it is a `float`, or `float32` otherwise.
+pub fn softmax(nd: &NDArray) -> Result<NDArray> {
+    let (mut values, width) = rows(nd)?;
+    for row in values.chunks_mut(width) {
+        let max = row.iter().cloned().fold(::std::f64::NEG_INFINITY, f64::max);
+        let mut sum = 0.;
+        for v in row.iter_mut() {
+            *v = (*v - max).exp();
+            sum += *v;
+        }
+        for v in row.iter_mut() {
+            *v /= sum;
+        }
+    }
+    let mut shape = nd.dims();
+    let mut out = empty(&mut shape, TVMContext::cpu(0), DataType::new(2, 64, 1));
+    let bytes = unsafe {
+        ::std::slice::from_raw_parts(values.as_ptr() as *const u8, values.len() * 8)
+    };
+    out.copy_from_bytes(bytes)?;
+    let dtype = nd.dtype();
+    if dtype.code == 2 {
+        out.astype(dtype)
+    } else {
+        out.astype(DataType::from("float"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ndarray(shape: &mut [usize], data: &mut [f32]) -> NDArray {
+        let mut nd = empty(shape, TVMContext::cpu(0), DataType::from("float"));
+        nd.copy_from_buffer(data).unwrap();
+        nd
+    }
+
+    #[test]
+    fn argmax_and_top_k() {
+        let nd = ndarray(&mut [2, 3], &mut [0.1, 0.7, 0.2, 0.5, 0.5, 0.]);
+        assert_eq!(argmax(&nd).unwrap(), vec![1, 0]);
+        let top = top_k(&nd, 2).unwrap();
+        assert_eq!(top[0].iter().map(|e| e.0).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(top[1].iter().map(|e| e.0).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(top_k(&nd, 10).unwrap()[0].len(), 3);
+    }
+
+    #[test]
+    fn softmax_rows() {
+        let nd = ndarray(&mut [2, 2], &mut [1., 1., 0., 1000.]);
+        let probs = softmax(&nd).unwrap();
+        assert_eq!(probs.dtype(), DataType::from("float"));
+        assert_eq!(probs.to_vec::<f32>().unwrap(), vec![0.5, 0.5, 0., 1.]);
+    }
+
+    #[test]
+    fn int_inputs() {
+        let mut nd = empty(&mut [3], TVMContext::cpu(0), DataType::from("int"));
+        nd.copy_from_buffer(&[3i32, -1, 7]).unwrap();
+        assert_eq!(argmax(&nd).unwrap(), vec![2]);
+        assert_eq!(softmax(&nd).unwrap().dtype(), DataType::from("float"));
+    }
+}
@@ -0,0 +1,136 @@
+//! Builds a Rust crate as a TVM plugin, a `cdylib` registering its packed functions in
+//! the global registry of the TVM process loading it, e.g. with `ctypes.CDLL` or
+//! `tvm.runtime.load_module` in Python or `dlopen` in C++.
+//!
+//! The [`tvm_plugin`] macro exports the [`INIT_SYMBOL`] registering the functions and
+//! calls it when the library is loaded, like the static initializers of
+//! `TVM_REGISTER_GLOBAL` in C++ plugins. The plugin links against `libtvm_runtime`, which
+//! must be the one of the loading process.
+//!
+//! # Example
+//!
+//! ```toml
+//! [lib]
+//! crate-type = ["cdylib"]
+//! ```
+//!
+//! ```
+//! #[macro_use]
+//! extern crate tvm_frontend as tvm;
+//!
+//! fn register() -> tvm::Result<()> {
+//!     tvm::function::register_typed("my_plugin.scale", |x: f64, factor: f64| Ok(x * factor))
+//! }
+//!
+//! tvm_plugin!(register);
+//! ```
+//!
+//! ```python
+//! ctypes.CDLL("libmy_plugin.so", ctypes.RTLD_GLOBAL)
+//! scale = tvm.get_global_func("my_plugin.scale")
+//! ```
+//!
+//! [`tvm_plugin`]:../macro.tvm_plugin.html
+//! [`INIT_SYMBOL`]:constant.INIT_SYMBOL.html
+
+use std::{
+    os::raw::c_int,
+    panic::{self, AssertUnwindSafe},
+};
+
+use function::panic_message;
+use Result;
+
+/// The symbol exported by plugins, `int tvm_plugin_init()`, registering their functions
+/// once and returning `0`, or `-1` with the last error set if the registration failed.
+/// Loaders which do not run the static initializers of libraries call it explicitly.
+pub const INIT_SYMBOL: &'static str = "tvm_plugin_init";
+
+/// Runs the registration function of a plugin, see [`tvm_plugin`], returning the status
+/// of the [`INIT_SYMBOL`]. Errors and panics set the last error.
+///
+/// [`tvm_plugin`]:../macro.tvm_plugin.html
+/// [`INIT_SYMBOL`]:constant.INIT_SYMBOL.html
+#[doc(hidden)]
+pub fn register(register: fn() -> Result<()>) -> c_int {
+    // panics must not unwind into the loader
+    match panic::catch_unwind(AssertUnwindSafe(register)) {
+        Ok(Ok(())) => 0,
+        Ok(Err(err)) => {
+            ::set_last_error(&err);
+            -1
+        }
+        Err(cause) => {
+            ::set_last_error(&panic_message(&*cause));
+            -1
+        }
+    }
+}
+
+/// Exports the [`INIT_SYMBOL`] of a plugin calling the registration function
+/// `fn() -> Result<()>` once, and calls it when the library is loaded. A registration
+/// failing on load is reported on stderr, since the loader cannot observe it.
+///
+/// See the [`plugin`] module for an example.
+///
+/// [`INIT_SYMBOL`]:plugin/constant.INIT_SYMBOL.html
+/// [`plugin`]:plugin/index.html
+#[macro_export]
+macro_rules! tvm_plugin {
+    ($register:path) => {
+        #[no_mangle]
+        pub extern "C" fn tvm_plugin_init() -> ::std::os::raw::c_int {
+            use std::sync::{
+                atomic::{AtomicIsize, Ordering, ATOMIC_ISIZE_INIT},
+                Once, ONCE_INIT,
+            };
+            static INIT: Once = ONCE_INIT;
+            static STATUS: AtomicIsize = ATOMIC_ISIZE_INIT;
+            INIT.call_once(|| {
+                let status = $crate::plugin::register($register);
+                STATUS.store(status as isize, Ordering::SeqCst);
+            });
+            STATUS.load(Ordering::SeqCst) as ::std::os::raw::c_int
+        }
+
+        #[doc(hidden)]
+        #[used]
+        #[cfg_attr(any(target_os = "linux", target_os = "android"), link_section = ".init_array")]
+        #[cfg_attr(target_os = "macos", link_section = "__DATA,__mod_init_func")]
+        #[cfg_attr(windows, link_section = ".CRT$XCU")]
+        pub static __TVM_PLUGIN_CONSTRUCTOR: extern "C" fn() = {
+            extern "C" fn constructor() {
+                if tvm_plugin_init() != 0 {
+                    eprintln!(
+                        "failed to register the functions of the TVM plugin: {}",
+                        $crate::get_last_error()
+                    );
+                }
+            }
+            constructor
+        };
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_status() {
+        fn ok() -> Result<()> {
+            Ok(())
+        }
+        fn failing() -> Result<()> {
+            bail!("no device")
+        }
+        fn panicking() -> Result<()> {
+            panic!("bad plugin")
+        }
+        assert_eq!(register(ok), 0);
+        assert_eq!(register(failing), -1);
+        assert!(::get_last_error().contains("no device"));
+        assert_eq!(register(panicking), -1);
+        assert!(::get_last_error().contains("bad plugin"));
+    }
+}
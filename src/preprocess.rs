@@ -0,0 +1,197 @@
+//! Image pre-processing helpers converting [`image::DynamicImage`] into `float` NDArrays,
+//! enabled by the `image` feature.
+//!
+//! Resizing, center-cropping, mean/std normalization and the output layout are configured
+//! through [`Preprocessor`]. The output has a leading batch dimension of size one.
+//!
+//! # Example
+//!
+//! ```
+//! let img = image::open("cat.png").unwrap();
+//! let input = Preprocessor::new()
+//!     .resize(256, 256)
+//!     .center_crop(224, 224)
+//!     .normalize(IMAGENET_MEAN, IMAGENET_STD)
+//!     .layout(Layout::NCHW)
+//!     .to_ndarray(&img, TVMContext::cpu(0))
+//!     .unwrap();
+//! assert_eq!(input.shape(), &[1, 3, 224, 224]);
+//! ```
+//!
+//! [`image::DynamicImage`]:https://docs.rs/image/0.20.1/image/enum.DynamicImage.html
+
+use image::{DynamicImage, FilterType, GenericImageView};
+
+use ndarray::empty;
+use DataType;
+use NDArray;
+use Result;
+use TVMContext;
+
+/// Mean of the RGB channels of ImageNet1k.
+pub const IMAGENET_MEAN: [f32; 3] = [123.675, 116.28, 103.53];
+/// Standard deviation of the RGB channels of ImageNet1k.
+pub const IMAGENET_STD: [f32; 3] = [58.395, 57.12, 57.375];
+
+/// Memory layout of the output NDArray.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// `[batch, height, width, channel]`
+    NHWC,
+    /// `[batch, channel, height, width]`
+    NCHW,
+}
+
+/// Converts images into RGB `float` NDArrays. Default is no resizing, no cropping,
+/// no normalization and `NCHW` layout.
+#[derive(Debug, Clone)]
+pub struct Preprocessor {
+    resize: Option<(u32, u32)>,
+    filter: FilterType,
+    crop: Option<(u32, u32)>,
+    mean: [f32; 3],
+    std: [f32; 3],
+    layout: Layout,
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Preprocessor {
+            resize: None,
+            filter: FilterType::Triangle,
+            crop: None,
+            mean: [0.; 3],
+            std: [1.; 3],
+            layout: Layout::NCHW,
+        }
+    }
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resizes the image to exactly `width` x `height`, ignoring the aspect ratio.
+    pub fn resize(&mut self, width: u32, height: u32) -> &mut Self {
+        self.resize = Some((width, height));
+        self
+    }
+
+    /// Sets the filter used for resizing. Default is `FilterType::Triangle`.
+    pub fn filter(&mut self, filter: FilterType) -> &mut Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Crops the center `width` x `height` region of the (resized) image.
+    pub fn center_crop(&mut self, width: u32, height: u32) -> &mut Self {
+        self.crop = Some((width, height));
+        self
+    }
+
+    /// Normalizes the RGB channels of each pixel as `(pixel - mean) / std`.
+    pub fn normalize(&mut self, mean: [f32; 3], std: [f32; 3]) -> &mut Self {
+        self.mean = mean;
+        self.std = std;
+        self
+    }
+
+    /// Sets the layout of the output NDArray.
+    pub fn layout(&mut self, layout: Layout) -> &mut Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Pre-processes the image into a `float` NDArray on the given context.
+    pub fn to_ndarray(&self, img: &DynamicImage, ctx: TVMContext) -> Result<NDArray> {
+        let mut img = match self.resize {
+            Some((width, height)) => img.resize_exact(width, height, self.filter),
+            None => img.clone(),
+        };
+        if let Some((width, height)) = self.crop {
+            let (img_width, img_height) = img.dimensions();
+            if width > img_width || height > img_height {
+                bail!(
+                    "cannot crop {}x{} out of an image of size {}x{}",
+                    width,
+                    height,
+                    img_width,
+                    img_height
+                );
+            }
+            img = img.crop(
+                (img_width - width) / 2,
+                (img_height - height) / 2,
+                width,
+                height,
+            );
+        }
+        let img = img.to_rgb();
+        let (width, height) = img.dimensions();
+        let (w, h) = (width as usize, height as usize);
+        let mut data = vec![0f32; 3 * w * h];
+        for (x, y, pixel) in img.enumerate_pixels() {
+            let (x, y) = (x as usize, y as usize);
+            for c in 0..3 {
+                let index = match self.layout {
+                    Layout::NHWC => (y * w + x) * 3 + c,
+                    Layout::NCHW => (c * h + y) * w + x,
+                };
+                data[index] = (pixel.data[c] as f32 - self.mean[c]) / self.std[c];
+            }
+        }
+        let mut shape = match self.layout {
+            Layout::NHWC => vec![1, h, w, 3],
+            Layout::NCHW => vec![1, 3, h, w],
+        };
+        let mut nd = empty(&mut shape, TVMContext::cpu(0), DataType::from("float"));
+        nd.copy_from_buffer(&data)?;
+        if ctx == TVMContext::cpu(0) {
+            Ok(nd)
+        } else {
+            nd.copy_to_ctx(&ctx)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn layouts() {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 2, Rgb { data: [1, 2, 3] }));
+        let nchw = Preprocessor::new()
+            .to_ndarray(&img, TVMContext::cpu(0))
+            .unwrap();
+        assert_eq!(nchw.shape(), &[1, 3, 2, 4]);
+        assert_eq!(nchw.to_vec::<f32>().unwrap()[..8], [1f32; 8]);
+        let nhwc = Preprocessor::new()
+            .layout(Layout::NHWC)
+            .to_ndarray(&img, TVMContext::cpu(0))
+            .unwrap();
+        assert_eq!(nhwc.shape(), &[1, 2, 4, 3]);
+        assert_eq!(nhwc.to_vec::<f32>().unwrap()[..3], [1f32, 2., 3.]);
+    }
+
+    #[test]
+    fn resize_and_crop() {
+        let img = DynamicImage::new_rgb8(10, 8);
+        let nd = Preprocessor::new()
+            .resize(6, 6)
+            .center_crop(4, 4)
+            .normalize(IMAGENET_MEAN, IMAGENET_STD)
+            .to_ndarray(&img, TVMContext::cpu(0))
+            .unwrap();
+        assert_eq!(nd.shape(), &[1, 3, 4, 4]);
+        assert!(
+            Preprocessor::new()
+                .center_crop(20, 20)
+                .to_ndarray(&img, TVMContext::cpu(0))
+                .is_err()
+        );
+    }
+}
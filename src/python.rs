@@ -0,0 +1,105 @@
+//! Exchanging [`NDArray`]s with Python through the
+//! [DLPack](https://github.com/dmlc/dlpack) protocol, enabled by the `pyo3` feature.
+//!
+//! An NDArray is converted to a `"dltensor"` capsule, which can be consumed by any
+//! DLPack-aware library such as `torch.utils.dlpack.from_dlpack` or `tvm.nd.from_dlpack`.
+//! In the other direction, objects implementing `__dlpack__` as well as raw `"dltensor"`
+//! capsules are accepted. In both cases the storage is shared without copies.
+//!
+//! # Example
+//!
+//! ```
+//! let gil = Python::acquire_gil();
+//! let py = gil.python();
+//! let dlpack = py.import("torch.utils.dlpack").unwrap();
+//! let tensor = dlpack.call1("from_dlpack", (ndarray.into_py(py),)).unwrap();
+//! let ndarray: NDArray = tensor.extract().unwrap();
+//! ```
+
+use std::{ffi::CStr, os::raw::c_void};
+
+use pyo3::{exceptions, ffi, prelude::*, AsPyPointer};
+
+use ts;
+
+use NDArray;
+
+const DLTENSOR_NAME: &'static [u8] = b"dltensor\0";
+const USED_DLTENSOR_NAME: &'static [u8] = b"used_dltensor\0";
+
+fn dltensor_name() -> &'static CStr {
+    CStr::from_bytes_with_nul(DLTENSOR_NAME).unwrap()
+}
+
+// Calls the deleter of a capsule which has not been consumed by any library.
+unsafe extern "C" fn capsule_destructor(capsule: *mut ffi::PyObject) {
+    let name = dltensor_name().as_ptr();
+    if ffi::PyCapsule_IsValid(capsule, name) == 1 {
+        let managed = ffi::PyCapsule_GetPointer(capsule, name) as *mut ts::DLManagedTensor;
+        if let Some(deleter) = (*managed).deleter {
+            deleter(managed);
+        }
+    }
+}
+
+impl IntoPy<PyObject> for NDArray {
+    fn into_py(self, py: Python) -> PyObject {
+        let managed = self.to_dlpack();
+        unsafe {
+            let capsule = ffi::PyCapsule_New(
+                managed as *mut c_void,
+                dltensor_name().as_ptr(),
+                Some(capsule_destructor),
+            );
+            PyObject::from_owned_ptr(py, capsule)
+        }
+    }
+}
+
+impl<'source> FromPyObject<'source> for NDArray {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        let capsule = if ob.hasattr("__dlpack__")? {
+            ob.call_method0("__dlpack__")?
+        } else {
+            ob
+        };
+        let name = dltensor_name().as_ptr();
+        unsafe {
+            if ffi::PyCapsule_IsValid(capsule.as_ptr(), name) != 1 {
+                return Err(PyErr::new::<exceptions::TypeError, _>(
+                    "expected an object implementing `__dlpack__` or a `dltensor` capsule",
+                ));
+            }
+            let managed =
+                ffi::PyCapsule_GetPointer(capsule.as_ptr(), name) as *mut ts::DLManagedTensor;
+            // mark the capsule as consumed so that its destructor does not free the tensor.
+            ffi::PyCapsule_SetName(
+                capsule.as_ptr(),
+                USED_DLTENSOR_NAME.as_ptr() as *const _,
+            );
+            Ok(NDArray::from_dlpack(managed))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use DataType;
+    use TVMContext;
+
+    #[test]
+    fn roundtrip() {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let shape = &mut [2];
+        let data = vec![3f32, 4.0];
+        let mut arr = ::empty(shape, TVMContext::cpu(0), DataType::from("float"));
+        arr.copy_from_buffer(&data).unwrap();
+        let capsule = arr.into_py(py);
+        let nd: NDArray = capsule.extract(py).unwrap();
+        assert_eq!(nd.to_vec::<f32>().unwrap(), data);
+        assert!(capsule.extract::<NDArray>(py).is_err());
+    }
+}
@@ -0,0 +1,256 @@
+//! Provides [`ModelRegistry`], an in-process registry of named and versioned models.
+//!
+//! Models are registered as [`Artifacts`] and instantiated as [`GraphRuntime`]s lazily,
+//! once per context, on first lookup. Instances can be evicted explicitly or
+//! automatically by bounding the number of live instances, in which case the least
+//! recently used one is dropped first.
+//!
+//! # Example
+//!
+//! ```
+//! let registry = ModelRegistry::with_max_instances(2);
+//! registry.register("resnet18", 1, artifacts);
+//! let runtime = registry.get("resnet18", 1, &TVMContext::cpu(0)).unwrap();
+//! runtime.lock().unwrap().run().unwrap();
+//! ```
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use Artifacts;
+use ErrorKind;
+use GraphRuntime;
+use Result;
+use TVMContext;
+
+type ModelKey = (String, u32);
+type InstanceKey = (ModelKey, TVMContext);
+
+#[derive(Debug)]
+struct Instance {
+    runtime: Arc<Mutex<GraphRuntime>>,
+    last_used: u64,
+}
+
+#[derive(Debug)]
+struct Registered {
+    artifacts: Arc<Artifacts>,
+    // distinguishes the registrations of the same key
+    generation: u64,
+}
+
+#[derive(Debug, Default)]
+struct Models {
+    artifacts: HashMap<ModelKey, Registered>,
+    instances: HashMap<InstanceKey, Instance>,
+    clock: u64,
+    generation: u64,
+}
+
+impl Models {
+    // Registers or removes the artifacts of the key with its instances at once, so that no
+    // instance of other artifacts is looked up in between.
+    fn replace(&mut self, key: ModelKey, artifacts: Option<Artifacts>) -> Option<Artifacts> {
+        self.instances.retain(|(model, _), _| *model != key);
+        let previous = match artifacts {
+            Some(artifacts) => {
+                self.generation += 1;
+                let registered = Registered {
+                    artifacts: Arc::new(artifacts),
+                    generation: self.generation,
+                };
+                self.artifacts.insert(key, registered)
+            }
+            None => self.artifacts.remove(&key),
+        };
+        previous.map(|registered| unwrap_artifacts(registered.artifacts))
+    }
+
+    // Returns the instance of the key if any, marking it as used.
+    fn touch(&mut self, key: &InstanceKey) -> Option<Arc<Mutex<GraphRuntime>>> {
+        self.clock += 1;
+        let now = self.clock;
+        self.instances.get_mut(key).map(|instance| {
+            instance.last_used = now;
+            instance.runtime.clone()
+        })
+    }
+
+    fn evict_lru(&mut self) {
+        let lru = self
+            .instances
+            .iter()
+            .min_by_key(|(_, instance)| instance.last_used)
+            .map(|(key, _)| key.clone());
+        if let Some(key) = lru {
+            self.instances.remove(&key);
+        }
+    }
+}
+
+/// Thread-safe registry of models looked up by `(name, version)`.
+///
+/// See the [module-level documentation](../registry/index.html) for more details.
+#[derive(Debug, Default)]
+pub struct ModelRegistry {
+    models: Mutex<Models>,
+    max_instances: Option<usize>,
+}
+
+impl ModelRegistry {
+    /// Creates a registry with no bound on the number of live instances.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry keeping at most `max_instances` live instances.
+    pub fn with_max_instances(max_instances: usize) -> Self {
+        ModelRegistry {
+            models: Mutex::new(Models::default()),
+            max_instances: Some(max_instances),
+        }
+    }
+
+    /// Registers the artifacts of a model and returns the previously registered ones, if any.
+    /// Existing instances of the same `(name, version)` are evicted.
+    pub fn register(&self, name: &str, version: u32, artifacts: Artifacts) -> Option<Artifacts> {
+        let mut models = self.models.lock().unwrap();
+        models.replace((name.to_owned(), version), Some(artifacts))
+    }
+
+    /// Removes a model from the registry together with all of its instances.
+    pub fn unregister(&self, name: &str, version: u32) -> Option<Artifacts> {
+        let mut models = self.models.lock().unwrap();
+        models.replace((name.to_owned(), version), None)
+    }
+
+    /// Returns the instance of a model on the given context, creating it if needed.
+    ///
+    /// The instance is created without locking the registry, so that the other models
+    /// are served meanwhile. If several threads create the same instance at once, the
+    /// first one created is kept and returned to all of them.
+    pub fn get(
+        &self,
+        name: &str,
+        version: u32,
+        ctx: &TVMContext,
+    ) -> Result<Arc<Mutex<GraphRuntime>>> {
+        let key = ((name.to_owned(), version), ctx.clone());
+        let (artifacts, generation) = {
+            let mut models = self.models.lock().unwrap();
+            if let Some(runtime) = models.touch(&key) {
+                return Ok(runtime);
+            }
+            match models.artifacts.get(&key.0) {
+                Some(registered) => (registered.artifacts.clone(), registered.generation),
+                None => bail!(ErrorKind::ModelNotFound(name.to_owned(), version)),
+            }
+        };
+        let runtime = Arc::new(Mutex::new(GraphRuntime::new(&artifacts, ctx.clone())?));
+        let mut models = self.models.lock().unwrap();
+        if let Some(runtime) = models.touch(&key) {
+            return Ok(runtime);
+        }
+        // the instance is not cached if the model was registered again or removed meanwhile
+        let current = match models.artifacts.get(&key.0) {
+            Some(registered) => registered.generation == generation,
+            None => false,
+        };
+        if !current {
+            return Ok(runtime);
+        }
+        if let Some(max_instances) = self.max_instances {
+            while !models.instances.is_empty() && models.instances.len() >= max_instances {
+                models.evict_lru();
+            }
+        }
+        let now = models.clock;
+        models.instances.insert(
+            key,
+            Instance {
+                runtime: runtime.clone(),
+                last_used: now,
+            },
+        );
+        Ok(runtime)
+    }
+
+    /// Returns the latest registered version of a model.
+    pub fn latest_version(&self, name: &str) -> Option<u32> {
+        let models = self.models.lock().unwrap();
+        models
+            .artifacts
+            .keys()
+            .filter(|(n, _)| n == name)
+            .map(|&(_, v)| v)
+            .max()
+    }
+
+    /// Drops the instances of a model on every context while keeping it registered.
+    /// Instances still in use are destroyed once released.
+    pub fn evict(&self, name: &str, version: u32) {
+        let mut models = self.models.lock().unwrap();
+        models
+            .instances
+            .retain(|((n, v), _), _| !(n == name && *v == version));
+    }
+
+    /// Drops the least recently used instances until at most `max_instances` are alive.
+    /// Useful to release device memory under memory pressure.
+    pub fn shrink_to(&self, max_instances: usize) {
+        let mut models = self.models.lock().unwrap();
+        while models.instances.len() > max_instances {
+            models.evict_lru();
+        }
+    }
+
+    /// Returns the number of live instances.
+    pub fn num_instances(&self) -> usize {
+        self.models.lock().unwrap().instances.len()
+    }
+}
+
+// Returns the artifacts removed from the registry, which the instances being created
+// from them may still share.
+fn unwrap_artifacts(artifacts: Arc<Artifacts>) -> Artifacts {
+    Arc::try_unwrap(artifacts).unwrap_or_else(|artifacts| (*artifacts).clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register() {
+        let registry = ModelRegistry::new();
+        let artifacts = Artifacts::new(String::new(), "lib.so".into(), vec![]);
+        assert!(registry.register("model", 1, artifacts.clone()).is_none());
+        assert!(registry.register("model", 2, artifacts.clone()).is_none());
+        assert!(registry.register("model", 2, artifacts).is_some());
+        assert_eq!(registry.latest_version("model"), Some(2));
+        assert!(registry.unregister("model", 2).is_some());
+        assert_eq!(registry.latest_version("model"), Some(1));
+        assert_eq!(registry.num_instances(), 0);
+    }
+
+    #[test]
+    fn generations() {
+        let mut models = Models::default();
+        let key = ("model".to_owned(), 1);
+        let artifacts = Artifacts::new(String::new(), "lib.so".into(), vec![]);
+        assert!(models.replace(key.clone(), Some(artifacts.clone())).is_none());
+        let first = models.artifacts[&key].generation;
+        assert!(models.replace(key.clone(), Some(artifacts)).is_some());
+        assert!(models.artifacts[&key].generation != first);
+        assert!(models.replace(key.clone(), None).is_some());
+        assert!(models.artifacts.is_empty());
+    }
+
+    #[test]
+    fn model_not_found() {
+        let registry = ModelRegistry::new();
+        assert!(registry.get("model", 1, &TVMContext::cpu(0)).is_err());
+    }
+}
@@ -0,0 +1,165 @@
+//! Parses Relay programs in the text format and compiles them into graph runtime artifacts
+//! through the global functions of the TVM compiler, without Python.
+//!
+//! This module requires the `compiler` feature. The functions are only registered when the
+//! full `libtvm` is loaded instead of `libtvm_runtime`, e.g. with the `dynamic` feature and
+//! [`ffi::set_library_loader`]. Otherwise parsing and building fail with
+//! `ErrorKind::NullHandle`.
+//!
+//! # Example
+//!
+//! ```
+//! let expr = relay::parse_expr("fn (%x: Tensor[(2), float32]) { add(%x, %x) }").unwrap();
+//! let built = relay::build(&expr, "llvm").unwrap();
+//! built.lib.export_library(Path::new("add.so"), None).unwrap();
+//! let artifacts = Artifacts::new(built.graph, PathBuf::from("add.so"), Vec::new());
+//! let mut runtime = GraphRuntime::new(&artifacts, TVMContext::cpu(0)).unwrap();
+//! ```
+//!
+//! [`ffi::set_library_loader`]:../ffi/fn.set_library_loader.html
+
+use std::rc::Rc;
+
+use function;
+use ty::TypeCode;
+use value::{self, ObjectHandle, TVMValue};
+use DataType;
+use ErrorKind;
+use Function;
+use Module;
+use Result;
+use RetValue;
+use TVMRetValue;
+
+/// The global functions parsing a Relay expression, by TVM version.
+pub const PARSE_EXPR_FUNCTIONS: [&'static str; 2] =
+    ["parser.ParseExpr", "relay._parser.ParseExpr"];
+
+const BUILD_MODULE: &'static str = "relay.build_module._BuildModule";
+const MAKE_INT_IMM: &'static str = "make.IntImm";
+const CREATE_TARGET: &'static str = "_TargetCreate";
+const MAKE_MAP: &'static str = "_Map";
+
+/// A node of the compiler, such as a Relay expression or a target.
+///
+/// The node is released once the last clone is dropped.
+#[derive(Debug, Clone)]
+pub struct Node {
+    handle: Rc<ObjectHandle>,
+}
+
+impl Node {
+    // Takes over a returned node, failing if the value is not a node.
+    pub(crate) fn from_ret(ret: TVMRetValue, name: &str) -> Result<Node> {
+        match ret.try_into_value()? {
+            RetValue::Node(handle) => Ok(Node {
+                handle: Rc::new(ObjectHandle::new(handle)),
+            }),
+            other => bail!(ErrorKind::TypeMismatch(
+                format!("{} of `{}`", TypeCode::kNodeHandle, name),
+                other.type_code().to_string()
+            )),
+        }
+    }
+}
+
+impl<'a> From<&'a Node> for TVMValue {
+    fn from(node: &Node) -> Self {
+        TVMValue::from(&node.handle.as_arg())
+    }
+}
+
+impl<'a> From<&'a Node> for TypeCode {
+    fn from(_node: &Node) -> Self {
+        TypeCode::kNodeHandle
+    }
+}
+
+/// The graph json and the library of a Relay program built with [`build`].
+///
+/// [`build`]:fn.build.html
+#[derive(Debug, Clone)]
+pub struct BuiltModule {
+    pub graph: String,
+    pub lib: Module,
+}
+
+// Returns the global function, failing if the compiler does not register it.
+fn get_compiler_func(name: &str) -> Result<Function> {
+    match function::get_global_func(name, true) {
+        Some(func) => Ok(func),
+        None => bail!(ErrorKind::NullHandle(name.to_owned())),
+    }
+}
+
+/// Parses a Relay expression in the text format, e.g. a function.
+pub fn parse_expr(source: &str) -> Result<Node> {
+    let name = PARSE_EXPR_FUNCTIONS
+        .iter()
+        .find(|name| Function::get_function(name, true).is_some())
+        .ok_or_else(|| ErrorKind::NullHandle(PARSE_EXPR_FUNCTIONS[0].to_owned()))?;
+    let parse_fn = get_compiler_func(name)?;
+    Node::from_ret(call_packed!(parse_fn, "from_string", source)?, name)
+}
+
+/// Compiles a Relay function for the `target`, e.g. `llvm` or `cuda`, with the host code
+/// compiled for `llvm`.
+pub fn build(func: &Node, target: &str) -> Result<BuiltModule> {
+    let create_target_fn = get_compiler_func(CREATE_TARGET)?;
+    let device_target = Node::from_ret(call_packed!(create_target_fn, target)?, CREATE_TARGET)?;
+    let create_target_fn = get_compiler_func(CREATE_TARGET)?;
+    let host_target = Node::from_ret(call_packed!(create_target_fn, "llvm")?, CREATE_TARGET)?;
+    let device_type = device_target_type(target) as i64;
+    let int_imm_fn = get_compiler_func(MAKE_INT_IMM)?;
+    let key = Node::from_ret(
+        call_packed!(int_imm_fn, &DataType::from("int"), &device_type)?,
+        MAKE_INT_IMM,
+    )?;
+    let map_fn = get_compiler_func(MAKE_MAP)?;
+    let targets = Node::from_ret(call_packed!(map_fn, &key, &device_target)?, MAKE_MAP)?;
+
+    let build_module_fn = get_compiler_func(BUILD_MODULE)?;
+    let build_module = call_packed!(build_module_fn,)?.try_into_module()?;
+    let build_fn = build_module.get_function("build", false)?;
+    call_packed!(build_fn, func, &targets, &host_target)?;
+    let get_graph_fn = build_module.get_function("get_graph_json", false)?;
+    let graph = value::into_string(call_packed!(get_graph_fn,)?)?;
+    let get_module_fn = build_module.get_function("get_module", false)?;
+    let lib = call_packed!(get_module_fn,)?.try_into_module()?;
+    Ok(BuiltModule { graph, lib })
+}
+
+// Returns the device type of the target the compiler maps it to, e.g. `2` for `cuda`.
+fn device_target_type(target: &str) -> usize {
+    let kind = target.split_whitespace().next().unwrap_or("");
+    match kind {
+        "cuda" | "nvptx" => 2,
+        "opencl" | "sdaccel" | "aocl" => 4,
+        "vulkan" => 7,
+        "metal" => 8,
+        "rocm" => 10,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_types() {
+        assert_eq!(device_target_type("llvm -mcpu=skylake"), 1);
+        assert_eq!(device_target_type("cuda"), 2);
+        assert_eq!(device_target_type("opencl -device=mali"), 4);
+    }
+
+    #[test]
+    fn without_compiler() {
+        if PARSE_EXPR_FUNCTIONS
+            .iter()
+            .all(|name| Function::get_function(name, true).is_none())
+        {
+            assert!(parse_expr("fn () { 1 }").is_err());
+        }
+    }
+}
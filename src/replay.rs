@@ -0,0 +1,599 @@
+//! Records the packed calls made through `function::Builder` into a file and replays
+//! them, e.g. to reproduce a crash of the runtime reported from the field.
+//!
+//! Every call is written as a json line before crossing the FFI, so the log of a crashed
+//! process ends with the call that crashed it. A line has the name of the function, if
+//! it was looked up by name, its handle and its arguments: the values of scalars and
+//! strings, the shape, dtype and context of arrays, and the handles of other objects.
+//! The handles returned by calls and the functions looked up in modules are recorded on
+//! their own lines.
+//!
+//! Replaying calls the global functions again with the recorded scalars and strings and
+//! with new arrays of the recorded shapes, whose content is not recorded. The recorded
+//! handles are mapped to the objects returned and looked up while replaying, so modules,
+//! e.g. a graph runtime, and their functions are replayed too. Calls of functions or with
+//! handles which were not replayed, e.g. Rust callbacks, are skipped.
+//!
+//! # Example
+//!
+//! ```
+//! replay::start_recording(Path::new("calls.log")).unwrap();
+//! let mut runtime = GraphRuntime::new(&artifacts, TVMContext::cpu(0)).unwrap();
+//! runtime.run().unwrap();
+//! replay::stop_recording();
+//!
+//! // later, from the log sent with the crash report
+//! let report = replay::replay(Path::new("calls.log")).unwrap();
+//! println!("{} calls replayed, {} skipped", report.replayed, report.skipped.len());
+//! ```
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{LineWriter, Write},
+    path::Path,
+    result,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering, ATOMIC_BOOL_INIT, ATOMIC_USIZE_INIT},
+        Mutex,
+    },
+};
+
+use serde_json::{self, Value};
+
+use ts;
+
+use function::{self, Builder};
+use graph::dtype_name;
+use module::FunctionOptions;
+use ndarray::empty;
+use ty::TypeCode;
+use value::ArgValue;
+use DataType;
+use Error;
+use Function;
+use NDArray;
+use Result;
+use TVMArgValue;
+use TVMByteArray;
+use TVMContext;
+use TVMDeviceType;
+use TVMRetValue;
+
+// Whether a recorder is set, so that unrecorded calls do not lock it.
+static RECORDING: AtomicBool = ATOMIC_BOOL_INIT;
+
+// The id of the next recorded call, matching the calls with their returned handles.
+static NEXT_CALL: AtomicUsize = ATOMIC_USIZE_INIT;
+
+lazy_static! {
+    static ref RECORDER: Mutex<Option<LineWriter<File>>> = Mutex::new(None);
+}
+
+/// Records the calls made on any thread into the file at `path`, replacing it and the
+/// previous recording if any.
+pub fn start_recording(path: &Path) -> Result<()> {
+    let file = File::create(path)?;
+    *RECORDER.lock().unwrap() = Some(LineWriter::new(file));
+    RECORDING.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Stops recording the calls.
+pub fn stop_recording() {
+    RECORDING.store(false, Ordering::SeqCst);
+    *RECORDER.lock().unwrap() = None;
+}
+
+// Writes a line if recording. Recording failures are ignored so that they do not change
+// the behavior of the calls.
+fn write_line(line: &Value) {
+    if let Some(ref mut recorder) = *RECORDER.lock().unwrap() {
+        let _ = writeln!(recorder, "{}", line);
+    }
+}
+
+// Records a call of the function if recording, returning the id of the call.
+pub(crate) fn record(func: &Function, args: &[TVMArgValue]) -> Option<usize> {
+    if !RECORDING.load(Ordering::SeqCst) {
+        return None;
+    }
+    let id = NEXT_CALL.fetch_add(1, Ordering::SeqCst);
+    write_line(&json!({
+        "kind": "call",
+        "id": id,
+        "name": func.name(),
+        "global": func.is_global(),
+        "func": func.handle as usize,
+        "args": args.iter().map(record_arg).collect::<Vec<_>>(),
+    }));
+    Some(id)
+}
+
+// Records the handle returned by the recorded call `id`, if it returned one.
+pub(crate) fn record_return(id: Option<usize>, ret: &TVMRetValue) {
+    let id = match id {
+        Some(id) if RECORDING.load(Ordering::SeqCst) => id,
+        _ => return,
+    };
+    if let Some(handle) = handle_of(&ret.value.inner, ret.type_code) {
+        write_line(&json!({"kind": "return", "call": id, "handle": handle}));
+    }
+}
+
+// Records the function looked up by name in a module if recording.
+pub(crate) fn record_lookup(
+    module: ts::TVMModuleHandle,
+    name: &str,
+    query_imports: bool,
+    func: ts::TVMFunctionHandle,
+) {
+    if !RECORDING.load(Ordering::SeqCst) {
+        return;
+    }
+    write_line(&json!({
+        "kind": "lookup",
+        "module": module as usize,
+        "name": name,
+        "query_imports": query_imports,
+        "func": func as usize,
+    }));
+}
+
+// Returns the address identifying a non-null handle in a recording.
+fn handle_of(value: &ts::TVMValue, type_code: TypeCode) -> Option<usize> {
+    match type_code {
+        TypeCode::kArrayHandle
+        | TypeCode::kNDArrayContainer
+        | TypeCode::kModuleHandle
+        | TypeCode::kFuncHandle
+        | TypeCode::kNodeHandle
+        | TypeCode::kHandle => {
+            let handle = unsafe { value.v_handle };
+            if handle.is_null() {
+                None
+            } else {
+                Some(handle as usize)
+            }
+        }
+        _ => None,
+    }
+}
+
+// Returns the dtype with its lanes if any, e.g. `float32x4`.
+fn dtype_spec(dtype: &DataType) -> String {
+    if dtype.lanes > 1 {
+        format!("{}x{}", dtype_name(dtype), dtype.lanes)
+    } else {
+        dtype_name(dtype)
+    }
+}
+
+fn record_arg(arg: &TVMArgValue) -> Value {
+    let type_code = arg.type_code as i64;
+    let handle = handle_of(&arg.value.inner, arg.type_code);
+    match arg.as_value() {
+        ArgValue::Null => json!({"kind": "null", "type_code": type_code}),
+        ArgValue::Int(v) => json!({"kind": "int", "type_code": type_code, "value": v}),
+        ArgValue::UInt(v) => json!({"kind": "uint", "type_code": type_code, "value": v}),
+        ArgValue::Float(v) => json!({"kind": "float", "type_code": type_code, "value": v}),
+        ArgValue::Str(s) => json!({
+            "kind": "str",
+            "type_code": type_code,
+            "value": s.to_string_lossy(),
+        }),
+        ArgValue::Bytes(barr) => json!({
+            "kind": "bytes",
+            "type_code": type_code,
+            "len": barr.len(),
+        }),
+        ArgValue::Type(dtype) => json!({
+            "kind": "dtype",
+            "type_code": type_code,
+            "value": dtype_spec(&dtype),
+        }),
+        ArgValue::Context(ctx) => json!({
+            "kind": "ctx",
+            "type_code": type_code,
+            "value": [ctx.device_type.0, ctx.device_id],
+        }),
+        ArgValue::NDArray(nd) => {
+            let ctx = nd.ctx();
+            json!({
+                "kind": "array",
+                "type_code": type_code,
+                "shape": nd.shape(),
+                "dtype": dtype_spec(&nd.dtype()),
+                "ctx": [ctx.device_type.0, ctx.device_id],
+                "handle": handle,
+            })
+        }
+        ArgValue::Module(_) => json!({"kind": "module", "type_code": type_code, "handle": handle}),
+        ArgValue::Function(_) => {
+            json!({"kind": "function", "type_code": type_code, "handle": handle})
+        }
+        ArgValue::Node(_) => json!({"kind": "node", "type_code": type_code, "handle": handle}),
+        ArgValue::Handle(_) => json!({"kind": "handle", "type_code": type_code, "handle": handle}),
+    }
+}
+
+/// A call read from a recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedCall {
+    /// The id matching the call with the handle it returned, if any.
+    pub id: u64,
+    pub name: Option<String>,
+    /// Whether the function is a global function, looked up by its name when replayed.
+    pub global: bool,
+    /// The recorded handle of the function.
+    pub func: u64,
+    /// The recorded arguments, see the [module-level documentation].
+    ///
+    /// [module-level documentation]:index.html
+    pub args: Vec<Value>,
+}
+
+/// A line of a recording.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Recorded {
+    Call(RecordedCall),
+    /// A function looked up by name in a module.
+    Lookup {
+        module: u64,
+        name: String,
+        query_imports: bool,
+        func: u64,
+    },
+    /// The handle returned by the call `call`.
+    Return { call: u64, handle: u64 },
+}
+
+fn parse_recorded(line: &str) -> Option<Recorded> {
+    let record: Value = serde_json::from_str(line).ok()?;
+    Some(match record["kind"].as_str().unwrap_or("call") {
+        "call" => {
+            let name = record["name"].as_str().map(str::to_owned);
+            Recorded::Call(RecordedCall {
+                id: record["id"].as_u64().unwrap_or(0),
+                global: record["global"].as_bool().unwrap_or_else(|| name.is_some()),
+                name,
+                func: record["func"].as_u64().unwrap_or(0),
+                args: record["args"].as_array()?.clone(),
+            })
+        }
+        "lookup" => Recorded::Lookup {
+            module: record["module"].as_u64()?,
+            name: record["name"].as_str()?.to_owned(),
+            query_imports: record["query_imports"].as_bool().unwrap_or(false),
+            func: record["func"].as_u64()?,
+        },
+        "return" => Recorded::Return {
+            call: record["call"].as_u64()?,
+            handle: record["handle"].as_u64()?,
+        },
+        _ => return None,
+    })
+}
+
+/// Reads a recording, with the line number of each recorded line.
+pub fn load(path: &Path) -> Result<Vec<(usize, Recorded)>> {
+    fs::read_to_string(path)?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| match parse_recorded(line) {
+            Some(recorded) => Ok((i + 1, recorded)),
+            None => bail!("invalid recorded line {}", i + 1),
+        })
+        .collect()
+}
+
+/// The outcome of [`replay`], with the line numbers and the reasons of the calls that
+/// were skipped or failed.
+///
+/// [`replay`]:fn.replay.html
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    pub replayed: usize,
+    pub skipped: Vec<(usize, String)>,
+    pub failed: Vec<(usize, String)>,
+}
+
+// An object returned or looked up while replaying, mapped from its recorded handle.
+enum Replayed {
+    Value(TVMRetValue),
+    Function(Function),
+}
+
+impl Replayed {
+    // Returns the object as an argument, borrowing its handle.
+    fn as_value(&self) -> ArgValue {
+        match *self {
+            Replayed::Value(ref value) => value.as_value(),
+            Replayed::Function(ref func) => ArgValue::Function(func.clone()),
+        }
+    }
+}
+
+// An argument of a replayed call, owned or borrowed from a replayed object.
+enum ReplayArg<'r> {
+    Null,
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Bytes(Vec<u8>),
+    Type(DataType),
+    Context(TVMContext),
+    Array(NDArray),
+    Object(ArgValue<'r>),
+}
+
+fn replay_ctx(value: &Value) -> Option<TVMContext> {
+    let device_type = value[0].as_u64()? as usize;
+    let device_id = value[1].as_u64()? as usize;
+    Some(TVMContext::new(TVMDeviceType(device_type), device_id))
+}
+
+// Replays the recorded calls, mapping the recorded handles to the replayed objects.
+#[derive(Default)]
+struct Replayer {
+    objects: HashMap<u64, Replayed>,
+    // the handles returned by replayed calls, until their recorded handle is read.
+    returned: HashMap<u64, TVMRetValue>,
+}
+
+impl Replayer {
+    fn replay_arg(&self, arg: &Value) -> result::Result<ReplayArg, String> {
+        let kind = arg["kind"].as_str().unwrap_or("");
+        if let Some(object) = arg["handle"].as_u64().and_then(|handle| self.objects.get(&handle))
+        {
+            return Ok(ReplayArg::Object(object.as_value()));
+        }
+        let invalid = || format!("invalid recorded {} argument", kind);
+        let dtype = |value: &Value| -> result::Result<DataType, String> {
+            value
+                .as_str()
+                .ok_or_else(invalid)?
+                .parse()
+                .map_err(|err: Error| err.to_string())
+        };
+        Ok(match kind {
+            "null" => ReplayArg::Null,
+            "int" | "uint" => ReplayArg::Int(arg["value"].as_i64().ok_or_else(invalid)?),
+            "float" => ReplayArg::Float(arg["value"].as_f64().ok_or_else(invalid)?),
+            "str" => ReplayArg::Str(arg["value"].as_str().ok_or_else(invalid)?.to_owned()),
+            "bytes" => {
+                ReplayArg::Bytes(vec![0; arg["len"].as_u64().ok_or_else(invalid)? as usize])
+            }
+            "dtype" => ReplayArg::Type(dtype(&arg["value"])?),
+            "ctx" => ReplayArg::Context(replay_ctx(&arg["value"]).ok_or_else(invalid)?),
+            "array" => {
+                let mut shape = arg["shape"]
+                    .as_array()
+                    .and_then(|dims| {
+                        dims.iter()
+                            .map(|dim| dim.as_u64().map(|dim| dim as usize))
+                            .collect::<Option<Vec<_>>>()
+                    })
+                    .ok_or_else(invalid)?;
+                let ctx = replay_ctx(&arg["ctx"]).ok_or_else(invalid)?;
+                ReplayArg::Array(empty(&mut shape, ctx, dtype(&arg["dtype"])?))
+            }
+            other => return Err(format!("the {} argument was not replayed", other)),
+        })
+    }
+
+    // Replays a call, returning why it was skipped or failed.
+    fn replay_call(&mut self, call: &RecordedCall) -> result::Result<(), (bool, String)> {
+        let func = if call.global {
+            let name = call.name.as_ref().map_or("", String::as_str);
+            function::get_global_func(name, true)
+                .ok_or_else(|| (true, format!("`{}` is not a global function", name)))?
+        } else {
+            match self.objects.get(&call.func).map(Replayed::as_value) {
+                Some(ArgValue::Function(func)) => func,
+                _ => {
+                    let name = call.name.as_ref().map_or("anonymous function", String::as_str);
+                    return Err((true, format!("{} was not replayed", name)));
+                }
+            }
+        };
+        let ret = {
+            let args = call
+                .args
+                .iter()
+                .map(|arg| self.replay_arg(arg))
+                .collect::<result::Result<Vec<_>, _>>()
+                .map_err(|reason| (true, reason))?;
+            let barrs = args
+                .iter()
+                .map(|arg| match *arg {
+                    ReplayArg::Bytes(ref bytes) => Some(TVMByteArray::from(bytes)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            let mut builder = Builder::from(func);
+            for (arg, barr) in args.iter().zip(&barrs) {
+                match (arg, barr) {
+                    (&ReplayArg::Null, _) => builder.arg(&ArgValue::Null),
+                    (&ReplayArg::Int(ref v), _) => builder.arg(v),
+                    (&ReplayArg::Float(ref v), _) => builder.arg(v),
+                    (&ReplayArg::Str(ref s), _) => builder.arg(s),
+                    (&ReplayArg::Bytes(_), &Some(ref barr)) => builder.arg(barr),
+                    (&ReplayArg::Bytes(_), &None) => unreachable!(),
+                    (&ReplayArg::Type(ref dtype), _) => builder.arg(dtype),
+                    (&ReplayArg::Context(ref ctx), _) => builder.arg(ctx),
+                    (&ReplayArg::Array(ref nd), _) => builder.arg(nd),
+                    (&ReplayArg::Object(ref value), _) => builder.arg(value),
+                };
+            }
+            builder
+                .invoke_catching()
+                .map_err(|err| (false, err.to_string()))?
+        };
+        if handle_of(&ret.value.inner, ret.type_code).is_some() {
+            self.returned.insert(call.id, ret);
+        }
+        Ok(())
+    }
+
+    // Maps the recorded handle of a module function to the function looked up again.
+    fn replay_lookup(
+        &mut self,
+        module: u64,
+        name: &str,
+        query_imports: bool,
+        func: u64,
+    ) -> result::Result<(), (bool, String)> {
+        let found = match self.objects.get(&module).map(Replayed::as_value) {
+            Some(ArgValue::Module(module)) => {
+                let options = FunctionOptions {
+                    query_imports,
+                    ..FunctionOptions::default()
+                };
+                module
+                    .get_function(name, options)
+                    .map_err(|err| (false, err.to_string()))?
+            }
+            _ => return Err((true, format!("the module of `{}` was not replayed", name))),
+        };
+        self.objects.insert(func, Replayed::Function(found));
+        Ok(())
+    }
+
+    // Maps the recorded handle returned by a call to the one returned when replayed.
+    fn replay_return(&mut self, call: u64, handle: u64) -> result::Result<(), (bool, String)> {
+        match self.returned.remove(&call) {
+            Some(ret) => {
+                self.objects.insert(handle, Replayed::Value(ret));
+                Ok(())
+            }
+            None => Err((true, format!("call {} did not return a handle", call))),
+        }
+    }
+}
+
+/// Replays the calls of a recording in their order, see the [module-level
+/// documentation]. A call crashing the runtime also crashes the replay, which is how it
+/// is reproduced.
+///
+/// The replayed calls are recorded too if recording, so stop it first.
+///
+/// [module-level documentation]:index.html
+pub fn replay(path: &Path) -> Result<ReplayReport> {
+    let mut report = ReplayReport::default();
+    let mut replayer = Replayer::default();
+    for (line, recorded) in load(path)? {
+        let outcome = match recorded {
+            Recorded::Call(ref call) => replayer.replay_call(call).map(|()| true),
+            Recorded::Lookup {
+                module,
+                ref name,
+                query_imports,
+                func,
+            } => replayer
+                .replay_lookup(module, name, query_imports, func)
+                .map(|()| false),
+            Recorded::Return { call, handle } => {
+                replayer.replay_return(call, handle).map(|()| false)
+            }
+        };
+        match outcome {
+            Ok(true) => report.replayed += 1,
+            Ok(false) => (),
+            Err((true, reason)) => report.skipped.push((line, reason)),
+            Err((false, reason)) => report.failed.push((line, reason)),
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        env,
+        panic::{self, AssertUnwindSafe},
+    };
+
+    #[test]
+    fn record_and_replay() {
+        function::register_typed("test_replayed", |x: i64, s: String| Ok(x + s.len() as i64))
+            .unwrap();
+        let path = env::temp_dir().join("tvm_replay_test.log");
+        start_recording(&path).unwrap();
+        let func = function::get_global_func("test_replayed", true).unwrap();
+        call_packed!(func, &1i64, "ab").unwrap();
+        let nd = empty(&mut [2, 3], TVMContext::cpu(0), DataType::from("float"));
+        let func = function::get_global_func("test_replayed", true).unwrap();
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| call_packed!(func, &nd))).is_err());
+        stop_recording();
+
+        let calls = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let recorded = calls
+            .into_iter()
+            .filter_map(|(_, recorded)| match recorded {
+                Recorded::Call(call) => Some(call),
+                _ => None,
+            })
+            .filter(|call| call.name.as_ref().map(String::as_str) == Some("test_replayed"))
+            .collect::<Vec<_>>();
+        assert_eq!(recorded.len(), 2);
+        assert!(recorded[0].global);
+        assert_eq!(recorded[0].args[1]["value"], "ab");
+        assert_eq!(recorded[1].args[0]["shape"], json!([2, 3]));
+        assert_eq!(recorded[1].args[0]["dtype"], "float32");
+        let mut replayer = Replayer::default();
+        assert!(replayer.replay_call(&recorded[0]).is_ok());
+        match replayer.replay_call(&recorded[1]) {
+            Err((false, _)) => (),
+            ret => panic!("expected a failed call, found {:?}", ret),
+        }
+    }
+
+    #[test]
+    fn mapped_handles() {
+        function::register_typed("test_replay_mapped", |x: i64| Ok(x + 1)).unwrap();
+        let func = function::get_global_func("test_replay_mapped", true).unwrap();
+        let mut replayer = Replayer::default();
+        replayer.objects.insert(7, Replayed::Function(func));
+        let call = RecordedCall {
+            id: 0,
+            name: Some("run".to_owned()),
+            global: false,
+            func: 7,
+            args: vec![json!({"kind": "int", "value": 1})],
+        };
+        assert!(replayer.replay_call(&call).is_ok());
+        let unmapped = RecordedCall {
+            func: 8,
+            ..call.clone()
+        };
+        match replayer.replay_call(&unmapped) {
+            Err((true, reason)) => assert!(reason.contains("run")),
+            ret => panic!("expected a skipped call, found {:?}", ret),
+        }
+        assert!(replayer.replay_lookup(9, "run", false, 10).is_err());
+        // the call returned an integer, which is not mapped
+        assert!(replayer.replay_return(0, 11).is_err());
+    }
+
+    #[test]
+    fn replay_report() {
+        function::register_typed("test_replay_report", |x: i64| Ok(x)).unwrap();
+        let path = env::temp_dir().join("tvm_replay_report.log");
+        let log = [
+            r#"{"name": "test_replay_report", "args": [{"kind": "int", "value": 1}]}"#,
+            r#"{"name": null, "args": []}"#,
+            r#"{"name": "test_replay_report", "args": [{"kind": "module"}]}"#,
+        ];
+        fs::write(&path, log.join("\n")).unwrap();
+        let report = replay(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(report.replayed, 1);
+        assert_eq!(report.skipped.iter().map(|s| s.0).collect::<Vec<_>>(), vec![2, 3]);
+        assert!(report.failed.is_empty());
+    }
+}
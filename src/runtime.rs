@@ -0,0 +1,458 @@
+//! Provides [`init`] for probing the TVM runtime library and the [`Runtime`] handle.
+//!
+//! Most of the crate lazily talks to the runtime and panics if it is not usable.
+//! Calling [`init`] first turns such failures into errors at a single place.
+//!
+//! With the `dynamic` feature, the runtime library is opened on first use from the
+//! locations configured with [`Config`], or else given by the environment.
+//!
+//! The code generation targets supported by the runtime are listed by [`enabled_targets`].
+//!
+//! Long-running hosts and plugins unloading the crate call [`shutdown`] to release the
+//! resources cached by the crate once they are done with the runtime.
+//!
+//! # Example
+//!
+//! ```
+//! let runtime = tvm::init().unwrap();
+//! println!("TVM version: {}", runtime.version());
+//! let create = runtime.get_function("tvm.graph_runtime.create").unwrap();
+//! ```
+
+use std::{
+    env,
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+};
+
+use context::TVMDeviceType;
+use ffi;
+use function::{self, Function};
+use internal_api;
+use value;
+use Error;
+use ErrorKind;
+use Result;
+use TVMContext;
+
+// Global functions the crate itself relies on.
+const REQUIRED_FUNCTIONS: [&'static str; 3] =
+    ["module._LoadFromFile", "module._Enabled", "_GetDeviceAttr"];
+
+/// Global functions queried for the version of the runtime, in order. The runtime reports
+/// its version if it registers one of them returning `TVM_VERSION`, e.g. with
+/// `TVM_REGISTER_GLOBAL("runtime._GetVersion")` in the runtime build. Stock TVM builds
+/// register none of them, see [`Config::require_version`].
+///
+/// [`Config::require_version`]:struct.Config.html#method.require_version
+pub const VERSION_FUNCTIONS: [&'static str; 1] = ["runtime._GetVersion"];
+
+/// Handle to an initialized TVM runtime, obtained with [`init`].
+#[derive(Debug, Clone)]
+pub struct Runtime {
+    global_names: Vec<String>,
+    runtime_version: Option<String>,
+}
+
+/// Verifies that the runtime library is usable and provides the global functions
+/// the crate relies on. With the `dynamic` feature, it also opens the library.
+///
+/// If the runtime reports its version through one of the [`VERSION_FUNCTIONS`], it must
+/// have the same major and minor version as the bindings, otherwise this fails with
+/// `ErrorKind::VersionMismatch` rather than on an unknown type code later on. A runtime
+/// reporting no version, e.g. a stock TVM build, is not checked unless it is required
+/// with [`Config::require_version`].
+///
+/// [`Config::require_version`]:struct.Config.html#method.require_version
+pub fn init() -> Result<Runtime> {
+    init_runtime(false)
+}
+
+fn init_runtime(require_version: bool) -> Result<Runtime> {
+    // fails instead of panicking on the first call if the library cannot be opened
+    #[cfg(feature = "dynamic")]
+    ffi::Dylib::shared()?;
+    let global_names = function::list_global_names()?;
+    let runtime_version = query_version(&global_names)?;
+    match runtime_version {
+        Some(ref runtime_version) => check_version(::version(), runtime_version)?,
+        None if require_version => {
+            bail!(ErrorKind::UnknownVersion(VERSION_FUNCTIONS.join(", ")))
+        }
+        None => (),
+    }
+    for name in REQUIRED_FUNCTIONS.iter() {
+        if !global_names.iter().any(|global| global == name) {
+            bail!(ErrorKind::RuntimeUnavailable(format!(
+                "missing global function `{}`",
+                name
+            )));
+        }
+    }
+    Ok(Runtime {
+        global_names,
+        runtime_version,
+    })
+}
+
+// Devices probed per device type by `shutdown`.
+const MAX_DEVICES: usize = 16;
+
+/// Waits for the pending work of the devices of the enabled targets and releases the
+/// resources cached by the crate: the handles of the global functions and the runtime API
+/// of every thread. The other threads release their API on their next call into the
+/// runtime or when they exit. With the `dynamic` feature, the runtime library is unloaded
+/// once all of them have.
+///
+/// The crate can be used again afterwards, looking up the functions and opening the
+/// runtime library on first use. The values obtained from the runtime before, e.g.
+/// NDArrays, modules or functions, must be dropped first, otherwise this fails without
+/// releasing anything.
+///
+/// ## Example
+///
+/// ```
+/// let runtime = tvm::init().unwrap();
+/// serve(&runtime);
+/// tvm::shutdown().unwrap();
+/// ```
+pub fn shutdown() -> Result<()> {
+    let live = ffi::live_resources();
+    if live > 0 {
+        bail!(
+            "{} NDArrays, modules or functions obtained from the runtime are still alive",
+            live
+        );
+    }
+    let mut device_types = enabled_targets()
+        .iter()
+        .filter_map(Target::device_type)
+        .collect::<Vec<_>>();
+    device_types.dedup();
+    for device_type in device_types {
+        // the cpu has no pending work
+        if device_type == TVMDeviceType(1) {
+            continue;
+        }
+        for device_id in 0..MAX_DEVICES {
+            let ctx = TVMContext::new(device_type, device_id);
+            if !ctx.exist().unwrap_or(false) {
+                break;
+            }
+            ctx.sync()?;
+        }
+    }
+    function::clear_global_handles();
+    ffi::release_api();
+    Ok(())
+}
+
+// Returns the version reported by the first registered of the `VERSION_FUNCTIONS`.
+fn query_version(global_names: &[String]) -> Result<Option<String>> {
+    let name = match VERSION_FUNCTIONS
+        .iter()
+        .find(|name| global_names.iter().any(|global| global == *name))
+    {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    let func = Function::get_function(name, true)
+        .ok_or_else(|| Error::from(ErrorKind::NullHandle(name.to_string())))?;
+    value::into_string(call_packed!(func,)?).map(Some)
+}
+
+// Returns the major and minor versions, e.g. `[0, 5]` for `0.5.dev`.
+fn major_minor(version: &str) -> Vec<u32> {
+    version
+        .split('.')
+        .take(2)
+        .map(|part| {
+            let digits = part.chars().take_while(|c| c.is_digit(10)).collect::<String>();
+            digits.parse().unwrap_or(0)
+        })
+        .collect()
+}
+
+// Fails if the versions have different major or minor versions.
+fn check_version(bindings: &str, runtime: &str) -> Result<()> {
+    if major_minor(bindings) != major_minor(runtime) {
+        bail!(ErrorKind::VersionMismatch(
+            bindings.to_owned(),
+            runtime.to_owned()
+        ));
+    }
+    Ok(())
+}
+
+/// Configures the initialization of the runtime, e.g. where the runtime library is found.
+///
+/// ## Example
+///
+/// ```
+/// let runtime = Config::new().with_library_path("/opt/tvm/lib").init().unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    library_paths: Vec<PathBuf>,
+    require_version: bool,
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a location of the runtime library, either the library file or a directory
+    /// containing it. The locations are searched in the order they are added.
+    ///
+    /// The runtime library is only searched with the `dynamic` feature, as it is
+    /// linked otherwise.
+    pub fn with_library_path<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.library_paths.push(path.as_ref().to_owned());
+        self
+    }
+
+    /// Sets whether the runtime must report its version through one of the
+    /// [`VERSION_FUNCTIONS`], failing with `ErrorKind::UnknownVersion` otherwise, e.g. for
+    /// a stock TVM build. Default is `false`, skipping the check for such runtimes.
+    ///
+    /// [`VERSION_FUNCTIONS`]:constant.VERSION_FUNCTIONS.html
+    pub fn require_version(&mut self, require: bool) -> &mut Self {
+        self.require_version = require;
+        self
+    }
+
+    /// Initializes the runtime like [`init`], opening the runtime library from the first
+    /// location where it can be opened, see [`library_candidates`]. The library is opened
+    /// once per process, so the locations have no effect after the first initialization.
+    ///
+    /// Fails listing the searched locations if the library cannot be opened, or if
+    /// locations are given without the `dynamic` feature.
+    pub fn init(&self) -> Result<Runtime> {
+        #[cfg(feature = "dynamic")]
+        {
+            let candidates = library_candidates(&self.library_paths);
+            ffi::set_library_loader(move || ffi::open_first(&candidates));
+        }
+        #[cfg(not(feature = "dynamic"))]
+        {
+            if !self.library_paths.is_empty() {
+                bail!(ErrorKind::RuntimeUnavailable(
+                    "library paths require the `dynamic` feature as the runtime is linked"
+                        .to_owned()
+                ));
+            }
+        }
+        init_runtime(self.require_version)
+    }
+}
+
+/// Returns the locations where the runtime library is searched, in order: the given paths,
+/// the paths listed in the `TVM_LIBRARY_PATH` environment variable, the `TVM_RUNTIME_LIBRARY`
+/// file, `$TVM_HOME/build` and finally the bare file name searched by the system loader.
+/// The directories are joined with the file name of the runtime library.
+pub fn library_candidates(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut locations = paths.to_vec();
+    if let Some(paths) = env::var_os("TVM_LIBRARY_PATH") {
+        locations.extend(env::split_paths(&paths));
+    }
+    if let Some(path) = env::var_os("TVM_RUNTIME_LIBRARY") {
+        locations.push(PathBuf::from(path));
+    }
+    if let Some(home) = env::var_os("TVM_HOME") {
+        locations.push(Path::new(&home).join("build"));
+    }
+    let mut candidates = locations
+        .into_iter()
+        .map(|path| {
+            if path.is_dir() {
+                path.join(ffi::RUNTIME_LIBRARY)
+            } else {
+                path
+            }
+        })
+        .collect::<Vec<_>>();
+    candidates.push(PathBuf::from(ffi::RUNTIME_LIBRARY));
+    candidates
+}
+
+impl Runtime {
+    /// Returns the TVM version the bindings were generated for.
+    pub fn version(&self) -> &'static str {
+        ::version()
+    }
+
+    /// Returns the version reported by the runtime, if any, see [`VERSION_FUNCTIONS`].
+    pub fn runtime_version(&self) -> Option<&str> {
+        self.runtime_version.as_ref().map(|version| version.as_str())
+    }
+
+    /// Returns the names of the global functions provided by the runtime at initialization.
+    pub fn global_function_names(&self) -> &[String] {
+        &self.global_names
+    }
+
+    /// Returns a global function by name.
+    pub fn get_function(&self, name: &str) -> Result<Function> {
+        Function::get_function(name, true)
+            .ok_or_else(|| ErrorKind::NullHandle(name.to_owned()).into())
+    }
+}
+
+/// Code generation targets whose runtime support can be queried with
+/// [`is_target_enabled`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Target {
+    Llvm,
+    StackVM,
+    Cuda,
+    OpenCL,
+    Metal,
+    Vulkan,
+    Rocm,
+    Vpi,
+    Rpc,
+}
+
+impl Target {
+    /// The standard targets probed by [`enabled_targets`].
+    pub const ALL: [Target; 9] = [
+        Target::Llvm,
+        Target::StackVM,
+        Target::Cuda,
+        Target::OpenCL,
+        Target::Metal,
+        Target::Vulkan,
+        Target::Rocm,
+        Target::Vpi,
+        Target::Rpc,
+    ];
+
+    /// Returns the name of the target as understood by the runtime.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Target::Llvm => "llvm",
+            Target::StackVM => "stackvm",
+            Target::Cuda => "cuda",
+            Target::OpenCL => "opencl",
+            Target::Metal => "metal",
+            Target::Vulkan => "vulkan",
+            Target::Rocm => "rocm",
+            Target::Vpi => "vpi",
+            Target::Rpc => "rpc",
+        }
+    }
+
+    // Returns the device type running the code of the target, if the target has one.
+    fn device_type(&self) -> Option<TVMDeviceType> {
+        match self {
+            Target::Llvm | Target::StackVM => Some(TVMDeviceType(1)),
+            Target::Cuda => Some(TVMDeviceType(2)),
+            Target::OpenCL => Some(TVMDeviceType(4)),
+            Target::Vulkan => Some(TVMDeviceType(7)),
+            Target::Metal => Some(TVMDeviceType(8)),
+            Target::Vpi => Some(TVMDeviceType(9)),
+            Target::Rocm => Some(TVMDeviceType(10)),
+            Target::Rpc => None,
+        }
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+lazy_static! {
+    static ref ENABLED_TARGETS: Vec<Target> = Target::ALL
+        .iter()
+        .cloned()
+        .filter(|target| internal_api::enabled(target.name()).unwrap_or(false))
+        .collect();
+}
+
+/// Returns the standard targets enabled in the runtime library.
+///
+/// The targets are probed once on the first call and cached afterwards.
+pub fn enabled_targets() -> &'static [Target] {
+    &ENABLED_TARGETS
+}
+
+/// Checks whether a target is enabled in the runtime library using the cached
+/// [`enabled_targets`].
+pub fn is_target_enabled(target: Target) -> bool {
+    enabled_targets().contains(&target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use contrib;
+
+    #[test]
+    fn initialize() {
+        let runtime = init().unwrap();
+        assert!(
+            runtime
+                .global_function_names()
+                .iter()
+                .any(|name| name == "tvm.graph_runtime.create")
+        );
+        assert!(runtime.get_function("tvm.graph_runtime.create").is_ok());
+        assert!(runtime.get_function("does not exists!").is_err());
+    }
+
+    #[test]
+    fn shutdown_and_reinit() {
+        let runtime = init().unwrap();
+        let func = runtime.get_function("tvm.graph_runtime.create").unwrap();
+        assert!(shutdown().is_err());
+        drop(func);
+        // the tests running in parallel may hold resources for a while
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while let Err(e) = shutdown() {
+            assert!(Instant::now() < deadline, "{}", e);
+            thread::sleep(Duration::from_millis(10));
+        }
+        let runtime = init().unwrap();
+        assert!(runtime.get_function("tvm.graph_runtime.create").is_ok());
+    }
+
+    #[test]
+    fn library_paths() {
+        let dir = contrib::TempDir::new().unwrap();
+        let file = dir.path().join("libcustom_runtime.so");
+        let candidates = library_candidates(&[dir.path().to_owned(), file.clone()]);
+        assert_eq!(candidates[0], dir.path().join(ffi::RUNTIME_LIBRARY));
+        assert_eq!(candidates[1], file);
+        assert_eq!(candidates.last().unwrap(), Path::new(ffi::RUNTIME_LIBRARY));
+        #[cfg(not(feature = "dynamic"))]
+        assert!(Config::new().with_library_path(dir.path()).init().is_err());
+    }
+
+    #[test]
+    fn versions() {
+        assert!(check_version("0.5.dev", "0.5").is_ok());
+        assert!(check_version("0.5.dev", "0.5.1").is_ok());
+        match check_version("0.5.dev", "0.6.dev") {
+            Err(Error(ErrorKind::VersionMismatch(bindings, runtime), _)) => {
+                assert_eq!((bindings.as_str(), runtime.as_str()), ("0.5.dev", "0.6.dev"))
+            }
+            _ => panic!("expected a version mismatch"),
+        }
+    }
+
+    #[test]
+    fn targets() {
+        assert!(is_target_enabled(Target::Llvm));
+        assert!(enabled_targets().iter().all(|target| Target::ALL.contains(target)));
+        assert_eq!(Target::OpenCL.to_string(), "opencl");
+    }
+}
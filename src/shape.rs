@@ -0,0 +1,309 @@
+//! Provides [`Shape`] for passing shapes to packed functions.
+//!
+//! Runtime functions take shapes either as repeated integer arguments, pushed with
+//! [`Builder::shape_args`], or packed into an `int64` NDArray, created with
+//! [`Shape::to_ndarray`]. A shape can be built from the dims of any integer type.
+//!
+//! # Example
+//!
+//! ```
+//! let shape = Shape::from(&[1usize, 3, 224, 224][..]);
+//! let mut alloc = function::Builder::from(alloc_fn);
+//! alloc.shape_args(&shape).arg(&dtype);
+//! let packed = shape.to_ndarray().unwrap();
+//! assert_eq!(packed.to_vec::<i64>().unwrap(), vec![1, 3, 224, 224]);
+//! ```
+//!
+//! Newer runtime functions, such as `set_input` of the VM or reshapes, take and return
+//! shapes as `runtime.ShapeTuple` objects, wrapped by [`ShapeTuple`].
+//!
+//! ```
+//! let shape = ShapeTuple::new(&[1, 3, 224, 224]).unwrap();
+//! let ret = call_packed!(reshape_fn, &arr, &shape).unwrap();
+//! for dim in &ShapeTuple::from_ret(ret).unwrap() {
+//!     println!("{}", dim);
+//! }
+//! ```
+//!
+//! [`Builder::shape_args`]:../function/struct.Builder.html#method.shape_args
+//! [`Shape::to_ndarray`]:struct.Shape.html#method.to_ndarray
+//! [`ShapeTuple`]:struct.ShapeTuple.html
+
+use std::{
+    fmt::{self, Display, Formatter},
+    ops::Deref,
+    slice,
+};
+
+use function::{self, Builder};
+use ndarray::empty;
+use ty::TypeCode;
+use value::{self, ArgValue, FromArgValue, IntoRetValue, ObjectHandle, TVMValue};
+use ErrorKind;
+use NDArray;
+use Result;
+use RetValue;
+use TVMArgValue;
+use TVMContext;
+use TVMDataType;
+use TVMRetValue;
+
+const CREATE_SHAPE_TUPLE: &'static str = "runtime.ShapeTuple";
+const SHAPE_TUPLE_SIZE: &'static str = "runtime.GetShapeTupleSize";
+const SHAPE_TUPLE_ELEM: &'static str = "runtime.GetShapeTupleElem";
+
+/// The dims of a shape as `i64`s, like the shapes of `DLTensor`s.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Shape {
+    dims: Vec<i64>,
+}
+
+impl Shape {
+    pub fn new(dims: Vec<i64>) -> Self {
+        Shape { dims }
+    }
+
+    pub fn dims(&self) -> &[i64] {
+        &self.dims
+    }
+
+    pub fn ndim(&self) -> usize {
+        self.dims.len()
+    }
+
+    /// Returns the number of entries of an array of the shape.
+    pub fn size(&self) -> i64 {
+        self.dims.iter().product()
+    }
+
+    /// Packs the dims into a one-dimensional `int64` NDArray in cpu.
+    pub fn to_ndarray(&self) -> Result<NDArray> {
+        let mut nd = empty(&mut [self.ndim()], TVMContext::cpu(0), i64::data_type());
+        nd.copy_from_buffer(&self.dims)?;
+        Ok(nd)
+    }
+}
+
+impl Deref for Shape {
+    type Target = [i64];
+
+    fn deref(&self) -> &[i64] {
+        &self.dims
+    }
+}
+
+impl From<Vec<i64>> for Shape {
+    fn from(dims: Vec<i64>) -> Self {
+        Shape::new(dims)
+    }
+}
+
+macro_rules! impl_shape_from_dims {
+    ($($type:ty),+) => {
+        $(
+            impl<'a> From<&'a [$type]> for Shape {
+                fn from(dims: &[$type]) -> Self {
+                    Shape::new(dims.iter().map(|&d| d as i64).collect())
+                }
+            }
+        )+
+    };
+}
+
+impl_shape_from_dims!(i64, i32, usize, u32);
+
+/// Copies the shape of the NDArray.
+impl<'a> From<&'a NDArray> for Shape {
+    fn from(nd: &NDArray) -> Self {
+        Shape::from(nd.shape())
+    }
+}
+
+/// Prints the shape as a tuple, e.g. `(1, 3, 224, 224)`.
+impl Display for Shape {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "(")?;
+        for (i, dim) in self.dims.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", dim)?;
+        }
+        if self.dims.len() == 1 {
+            write!(f, ",")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// A `runtime.ShapeTuple` object of the runtime with its dims read once.
+///
+/// Runtimes before TVM 0.8 have no shape tuples, so creating and reading them fails with
+/// `ErrorKind::NullHandle`. The object is released once the last clone is dropped.
+#[derive(Debug, Clone)]
+pub struct ShapeTuple {
+    // owns the object.
+    ret: TVMRetValue,
+    shape: Shape,
+}
+
+impl ShapeTuple {
+    /// Creates a shape tuple object with the dims.
+    pub fn new(dims: &[i64]) -> Result<ShapeTuple> {
+        let create_fn = function::get_first_global_func(&[CREATE_SHAPE_TUPLE])?;
+        let ret = Builder::from(create_fn).shape_args(&Shape::from(dims)).invoke()?;
+        ShapeTuple::from_ret(ret)
+    }
+
+    /// Takes over a shape tuple returned by a call, failing for values of other types.
+    pub fn from_ret(ret: TVMRetValue) -> Result<ShapeTuple> {
+        let object = match ret.try_into_value()? {
+            RetValue::Node(handle) => ObjectHandle::new(handle),
+            other => return Err(value::type_mismatch(TypeCode::kNodeHandle, &other)),
+        };
+        let shape = Shape::new(read_dims(&object.as_arg())?);
+        Ok(ShapeTuple {
+            ret: TVMRetValue::from_object(object),
+            shape,
+        })
+    }
+
+    pub fn shape(&self) -> &Shape {
+        &self.shape
+    }
+
+    pub fn dims(&self) -> &[i64] {
+        self.shape.dims()
+    }
+
+    pub fn len(&self) -> usize {
+        self.shape.ndim()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> slice::Iter<i64> {
+        self.dims().iter()
+    }
+}
+
+// Reads the dims of a shape tuple object, failing for objects of other types.
+fn read_dims(object: &ArgValue) -> Result<Vec<i64>> {
+    let size_fn = function::get_first_global_func(&[SHAPE_TUPLE_SIZE])?;
+    let size = Builder::from(size_fn).arg(object).invoke_catching()?.to_int();
+    (0..size)
+        .map(|i| {
+            let elem_fn = function::get_first_global_func(&[SHAPE_TUPLE_ELEM])?;
+            Ok(Builder::from(elem_fn).arg(object).arg(&i).invoke_catching()?.to_int())
+        })
+        .collect()
+}
+
+/// Creates a shape tuple object, panicking if the runtime has no shape tuples. See
+/// [`ShapeTuple::new`] for the fallible version.
+///
+/// [`ShapeTuple::new`]:struct.ShapeTuple.html#method.new
+impl<'a> From<&'a [i64]> for ShapeTuple {
+    fn from(dims: &[i64]) -> Self {
+        ShapeTuple::new(dims).unwrap_or_else(|err| panic!("cannot create shape tuple: {}", err))
+    }
+}
+
+impl<'a> IntoIterator for &'a ShapeTuple {
+    type Item = &'a i64;
+    type IntoIter = slice::Iter<'a, i64>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a> From<&'a ShapeTuple> for TVMValue {
+    fn from(shape: &ShapeTuple) -> Self {
+        shape.ret.value.clone()
+    }
+}
+
+impl<'a> From<&'a ShapeTuple> for TypeCode {
+    fn from(_shape: &ShapeTuple) -> Self {
+        TypeCode::kNodeHandle
+    }
+}
+
+/// Copies the dims of the argument into a new shape tuple, since the argument is only
+/// borrowed for the duration of the call.
+impl FromArgValue for ShapeTuple {
+    fn from_arg_value(arg: &TVMArgValue) -> Result<Self> {
+        let dims = match arg.as_value() {
+            node @ ArgValue::Node(_) => read_dims(&node)?,
+            other => bail!(ErrorKind::TypeMismatch(
+                TypeCode::kNodeHandle.to_string(),
+                TypeCode::from(&other).to_string()
+            )),
+        };
+        ShapeTuple::new(&dims)
+    }
+}
+
+impl IntoRetValue for ShapeTuple {
+    fn into_ret_value(self) -> TVMRetValue {
+        self.ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use function::Builder;
+    use Error;
+
+    #[test]
+    fn shapes() {
+        let shape = Shape::from(&[2usize, 3][..]);
+        assert_eq!(shape.dims(), &[2, 3]);
+        assert_eq!((shape.ndim(), shape.size()), (2, 6));
+        assert_eq!(shape.to_string(), "(2, 3)");
+        assert_eq!(Shape::from(vec![4]).to_string(), "(4,)");
+        assert_eq!(shape.to_ndarray().unwrap().to_vec::<i64>().unwrap(), vec![2, 3]);
+        let nd = empty(&mut [2, 3], TVMContext::cpu(0), i64::data_type());
+        assert_eq!(Shape::from(&nd), shape);
+        let mut builder = Builder::default();
+        builder.shape_args(&shape).arg(&1i64);
+        let args = builder.arg_values().iter().map(|arg| arg.to_int()).collect::<Vec<_>>();
+        assert_eq!(args, vec![2, 3, 1]);
+    }
+
+    #[test]
+    fn shape_tuple_errors() {
+        match ShapeTuple::from_ret(TVMRetValue::from(&1i64)) {
+            Err(Error(ErrorKind::TypeMismatch(..), _)) => (),
+            other => panic!("expected a type mismatch, found {:?}", other),
+        }
+        let arg = TVMArgValue::from("(2, 3)");
+        assert!(ShapeTuple::from_arg_value(&arg).is_err());
+        if function::get_global_func(CREATE_SHAPE_TUPLE, true).is_none() {
+            match ShapeTuple::new(&[2, 3]) {
+                Err(Error(ErrorKind::NullHandle(name), _)) => {
+                    assert_eq!(name, CREATE_SHAPE_TUPLE)
+                }
+                other => panic!("expected a missing function, found {:?}", other),
+            }
+        }
+    }
+
+    // needs a runtime with shape tuples, i.e. TVM 0.8 or later, run with
+    // `cargo test shape_tuples -- --ignored`
+    #[test]
+    #[ignore]
+    fn shape_tuples() {
+        let shape = ShapeTuple::from(&[2i64, 3][..]);
+        assert_eq!(shape.dims(), &[2, 3]);
+        assert_eq!(shape.iter().product::<i64>(), 6);
+        let arg = TVMArgValue::from(&shape);
+        let copy = ShapeTuple::from_arg_value(&arg).unwrap();
+        assert_eq!(copy.shape(), shape.shape());
+    }
+}
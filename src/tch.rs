@@ -0,0 +1,143 @@
+//! Interoperability with [tch-rs](https://github.com/LaurentMazare/tch-rs) (libtorch)
+//! tensors, enabled by the `tch` feature.
+//!
+//! [`NDArray::from_tch`] shares the storage of a libtorch tensor with TVM through a DLPack
+//! managed tensor, so pre-processing can run in libtorch and the model body in TVM
+//! without copies. [`NDArray::to_tch`] copies the data into a new tensor on the same
+//! device since libtorch Rust bindings cannot take the ownership of foreign storage.
+//!
+//! # Example
+//!
+//! ```
+//! let tensor = tch::Tensor::of_slice(&[1f32, 2., 3., 4.]).view(&[2, 2]);
+//! let nd = NDArray::from_tch(&tensor).unwrap();
+//! assert_eq!(nd.shape(), &[2, 2]);
+//! let back = nd.to_tch().unwrap();
+//! ```
+//!
+//! [`NDArray::from_tch`]:../ndarray/struct.NDArray.html#method.from_tch
+//! [`NDArray::to_tch`]:../ndarray/struct.NDArray.html#method.to_tch
+
+use std::{mem, os::raw::c_void};
+
+use rust_tch::{Device, Kind, Tensor};
+
+use ts;
+
+use DataType;
+use ErrorKind;
+use NDArray;
+use Result;
+use TVMContext;
+
+// Keeps a shallow clone of the libtorch tensor alive as long as TVM holds its storage.
+struct TchManager {
+    tensor: Tensor,
+    shape: Vec<i64>,
+    strides: Vec<i64>,
+}
+
+unsafe extern "C" fn tch_deleter(managed: *mut ts::DLManagedTensor) {
+    let managed = Box::from_raw(managed);
+    mem::drop(Box::from_raw(managed.manager_ctx as *mut TchManager));
+}
+
+fn kind_to_type(kind: Kind) -> Result<DataType> {
+    Ok(match kind {
+        Kind::Uint8 => DataType::new(1, 8, 1),
+        Kind::Int8 => DataType::new(0, 8, 1),
+        Kind::Int16 => DataType::new(0, 16, 1),
+        Kind::Int => DataType::new(0, 32, 1),
+        Kind::Int64 => DataType::new(0, 64, 1),
+        Kind::Float => DataType::new(2, 32, 1),
+        Kind::Double => DataType::new(2, 64, 1),
+        _ => bail!("unsupported libtorch tensor kind {:?}", kind),
+    })
+}
+
+fn device_to_ctx(device: Device) -> TVMContext {
+    match device {
+        Device::Cpu => TVMContext::cpu(0),
+        Device::Cuda(id) => TVMContext::gpu(id),
+    }
+}
+
+impl NDArray {
+    /// Creates an NDArray sharing the storage of a libtorch tensor.
+    pub fn from_tch(tensor: &Tensor) -> Result<NDArray> {
+        let dtype = kind_to_type(tensor.kind())?;
+        let mut manager = Box::new(TchManager {
+            tensor: tensor.shallow_clone(),
+            shape: tensor.size(),
+            strides: tensor.stride(),
+        });
+        let dl_tensor = ts::DLTensor {
+            data: manager.tensor.data_ptr() as *mut c_void,
+            ctx: device_to_ctx(tensor.device()).into(),
+            ndim: manager.shape.len() as i32,
+            dtype: dtype.into(),
+            shape: manager.shape.as_mut_ptr(),
+            strides: manager.strides.as_mut_ptr(),
+            byte_offset: 0,
+        };
+        let managed = Box::new(ts::DLManagedTensor {
+            dl_tensor,
+            manager_ctx: Box::into_raw(manager) as *mut c_void,
+            deleter: Some(tch_deleter),
+        });
+        Ok(unsafe { NDArray::from_dlpack(Box::into_raw(managed)) })
+    }
+
+    /// Copies the NDArray into a new libtorch tensor on the same device, supporting the
+    /// same dtypes as [`from_tch`].
+    ///
+    /// [`from_tch`]:#method.from_tch
+    pub fn to_tch(&self) -> Result<Tensor> {
+        let shape = self.shape().to_vec();
+        let dtype = self.dtype();
+        let tensor = match (dtype.code, dtype.bits, dtype.lanes) {
+            (1, 8, 1) => Tensor::of_slice(&self.to_vec::<u8>()?),
+            (0, 8, 1) => Tensor::of_slice(&self.to_vec::<i8>()?),
+            (0, 16, 1) => Tensor::of_slice(&self.to_vec::<i16>()?),
+            (0, 32, 1) => Tensor::of_slice(&self.to_vec::<i32>()?),
+            (0, 64, 1) => Tensor::of_slice(&self.to_vec::<i64>()?),
+            (2, 32, 1) => Tensor::of_slice(&self.to_vec::<f32>()?),
+            (2, 64, 1) => Tensor::of_slice(&self.to_vec::<f64>()?),
+            _ => bail!(ErrorKind::TypeMismatch(
+                "a dtype of libtorch".to_owned(),
+                dtype.to_string()
+            )),
+        };
+        let ctx = self.ctx();
+        let device = match ctx.device_type.0 {
+            1 => Device::Cpu,
+            2 => Device::Cuda(ctx.device_id),
+            _ => bail!("libtorch does not support the context {}", ctx),
+        };
+        Ok(tensor.view(&shape).to_device(device))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let tensor = Tensor::of_slice(&[1f32, 2., 3., 4.]).view(&[2, 2]);
+        let nd = NDArray::from_tch(&tensor).unwrap();
+        assert_eq!(nd.shape(), &[2, 2]);
+        assert_eq!(nd.to_vec::<f32>().unwrap(), vec![1f32, 2., 3., 4.]);
+        let back = nd.to_tch().unwrap();
+        assert_eq!(back.size(), vec![2, 2]);
+    }
+
+    #[test]
+    fn kinds() {
+        for &kind in &[Kind::Uint8, Kind::Int8, Kind::Int16, Kind::Int64, Kind::Double] {
+            let tensor = Tensor::of_slice(&[1u8, 2, 3]).to_kind(kind);
+            let back = NDArray::from_tch(&tensor).unwrap().to_tch().unwrap();
+            assert_eq!(back.kind(), kind);
+        }
+    }
+}
@@ -0,0 +1,410 @@
+//! A golden-file harness for compiled kernels: [`GoldenTest`] runs a function of a module
+//! on inputs saved with `numpy.save` and compares its outputs with the expected ones
+//! within tolerances, reporting the entries which differ.
+//!
+//! The function is called like the kernels built with `tvm.build`, with the inputs then
+//! the outputs as `DLTensor*` arguments. The outputs are allocated with the shapes and
+//! dtypes of the expected arrays.
+//!
+//! # Example
+//!
+//! ```
+//! let module = Module::load(Path::new("add_cpu.so")).unwrap();
+//! GoldenTest::new(module, "myadd")
+//!     .input(Path::new("golden/a.npy"))
+//!     .input(Path::new("golden/b.npy"))
+//!     .expected(Path::new("golden/sum.npy"))
+//!     .tolerance(Tolerance { rtol: 1e-4, atol: 1e-6 })
+//!     .check()
+//!     .unwrap();
+//! ```
+//!
+//! [`GoldenTest`]:struct.GoldenTest.html
+
+use std::{
+    fmt::{self, Display, Formatter},
+    fs,
+    path::{Path, PathBuf},
+    str,
+};
+
+use function::Builder;
+use ndarray::empty;
+use DataType;
+use ErrorKind;
+use Module;
+use NDArray;
+use Result;
+use TVMContext;
+
+const NPY_MAGIC: &'static [u8] = b"\x93NUMPY";
+
+fn invalid_npy<S: Into<String>>(msg: S) -> ErrorKind {
+    ErrorKind::InvalidNpy(msg.into())
+}
+
+/// Loads an array saved with `numpy.save` into an NDArray on the context.
+///
+/// Supports C-ordered arrays of little-endian `int`, `uint` and `float` dtypes, and
+/// `bool` arrays, loaded as `uint8`.
+pub fn load_npy(path: &Path, ctx: TVMContext) -> Result<NDArray> {
+    let bytes = fs::read(path)?;
+    let (dtype, mut shape, data) = parse_npy(&bytes)?;
+    let mut array = empty(&mut shape, ctx, dtype);
+    array.copy_from_bytes(data)?;
+    Ok(array)
+}
+
+// Splits an npy file into the dtype, the shape and the data of the array.
+fn parse_npy(bytes: &[u8]) -> Result<(DataType, Vec<usize>, &[u8])> {
+    if bytes.len() < 10 || &bytes[..6] != NPY_MAGIC {
+        bail!(invalid_npy("missing the magic string"));
+    }
+    let (header_len, start) = match bytes[6] {
+        1 => ((bytes[8] as usize) | ((bytes[9] as usize) << 8), 10),
+        2 | 3 if bytes.len() >= 12 => {
+            let len = bytes[8..12]
+                .iter()
+                .rev()
+                .fold(0, |len, &byte| (len << 8) | byte as usize);
+            (len, 12)
+        }
+        version => bail!(invalid_npy(format!("unsupported version {}", version))),
+    };
+    let header = bytes
+        .get(start..start + header_len)
+        .ok_or_else(|| invalid_npy("truncated header"))?;
+    let header = str::from_utf8(header).map_err(|_| invalid_npy("the header is not utf-8"))?;
+    if header_field(header, "fortran_order")?.starts_with("True") {
+        bail!(invalid_npy("Fortran-ordered arrays are not supported"));
+    }
+    let descr = header_field(header, "descr")?
+        .trim_left_matches(|c| c == '\'' || c == '"')
+        .split(|c| c == '\'' || c == '"')
+        .next()
+        .unwrap_or("");
+    let shape = header_field(header, "shape")?
+        .trim_left_matches('(')
+        .split(')')
+        .next()
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|dim| !dim.is_empty())
+        .map(|dim| dim.parse().map_err(|_| invalid_npy(format!("invalid dim `{}`", dim))))
+        .collect::<::std::result::Result<Vec<usize>, _>>()?;
+    Ok((npy_dtype(descr)?, shape, &bytes[start + header_len..]))
+}
+
+// Returns the text of the header following the key `name`.
+fn header_field<'a>(header: &'a str, name: &str) -> Result<&'a str> {
+    let key = format!("'{}':", name);
+    match header.find(&key) {
+        Some(pos) => Ok(header[pos + key.len()..].trim_left()),
+        None => bail!(invalid_npy(format!("missing `{}` in the header", name))),
+    }
+}
+
+// Converts a numpy type string such as `<f4` into a dtype.
+fn npy_dtype(descr: &str) -> Result<DataType> {
+    let unsupported = || invalid_npy(format!("unsupported dtype `{}`", descr));
+    let mut chars = descr.chars();
+    let (order, kind) = match (chars.next(), chars.next()) {
+        (Some(order), Some(kind)) => (order, kind),
+        _ => bail!(unsupported()),
+    };
+    let bytes: u8 = match chars.as_str().parse() {
+        Ok(bytes @ 1) | Ok(bytes @ 2) | Ok(bytes @ 4) | Ok(bytes @ 8) => bytes,
+        _ => bail!(unsupported()),
+    };
+    if order == '>' && bytes > 1 {
+        bail!(invalid_npy("big-endian arrays are not supported"));
+    }
+    let code = match kind {
+        'i' => 0,
+        'u' | 'b' => 1,
+        'f' => 2,
+        _ => bail!(unsupported()),
+    };
+    Ok(DataType::new(code, bytes * 8, 1))
+}
+
+/// The relative and absolute tolerances of a comparison, like in `numpy.allclose`:
+/// `actual` matches `expected` if `|actual - expected| <= atol + rtol * |expected|`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerance {
+    pub rtol: f64,
+    pub atol: f64,
+}
+
+/// The defaults of `numpy.allclose`.
+impl Default for Tolerance {
+    fn default() -> Self {
+        Tolerance {
+            rtol: 1e-5,
+            atol: 1e-8,
+        }
+    }
+}
+
+impl Tolerance {
+    /// Returns whether the values match. NaNs never match.
+    pub fn matches(&self, actual: f64, expected: f64) -> bool {
+        (actual - expected).abs() <= self.atol + self.rtol * expected.abs()
+    }
+}
+
+/// The comparison of an output with its expected values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputDiff {
+    /// The file of the expected values.
+    pub name: String,
+    /// The number of entries of the output.
+    pub len: usize,
+    /// The number of entries not matching within the tolerances.
+    pub mismatches: usize,
+    /// The largest absolute difference, NaN if an entry is NaN.
+    pub max_abs_diff: f64,
+    /// The index, the actual and the expected value of the first mismatching entry.
+    pub first_mismatch: Option<(usize, f64, f64)>,
+}
+
+impl OutputDiff {
+    pub fn is_ok(&self) -> bool {
+        self.mismatches == 0
+    }
+}
+
+impl Display for OutputDiff {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.first_mismatch {
+            None => write!(f, "{}: ok", self.name),
+            Some((index, actual, expected)) => write!(
+                f,
+                "{}: {} of {} entries differ, max abs diff {}, first at {}: {} != {}",
+                self.name, self.mismatches, self.len, self.max_abs_diff, index, actual, expected
+            ),
+        }
+    }
+}
+
+/// Compares an output with its expected values entry by entry, failing if their shapes
+/// or dtypes differ.
+pub fn compare(
+    name: &str,
+    actual: &NDArray,
+    expected: &NDArray,
+    tolerance: &Tolerance,
+) -> Result<OutputDiff> {
+    if actual.shape() != expected.shape() || actual.dtype() != expected.dtype() {
+        bail!(
+            "{}: expected shape {:?} of {}, found {:?} of {}",
+            name,
+            expected.shape(),
+            expected.dtype(),
+            actual.shape(),
+            actual.dtype()
+        );
+    }
+    let (actual, expected) = (actual.host_values()?, expected.host_values()?);
+    let mut diff = OutputDiff {
+        name: name.to_owned(),
+        len: expected.len(),
+        mismatches: 0,
+        max_abs_diff: 0.,
+        first_mismatch: None,
+    };
+    for (index, (&a, &e)) in actual.iter().zip(&expected).enumerate() {
+        let abs_diff = (a - e).abs();
+        if abs_diff.is_nan() || abs_diff > diff.max_abs_diff {
+            diff.max_abs_diff = abs_diff;
+        }
+        if !tolerance.matches(a, e) {
+            diff.mismatches += 1;
+            diff.first_mismatch = diff.first_mismatch.or(Some((index, a, e)));
+        }
+    }
+    Ok(diff)
+}
+
+/// The outcome of a [`GoldenTest`], printed as a diff report.
+///
+/// [`GoldenTest`]:struct.GoldenTest.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct GoldenReport {
+    pub function: String,
+    pub outputs: Vec<OutputDiff>,
+}
+
+impl GoldenReport {
+    pub fn is_ok(&self) -> bool {
+        self.outputs.iter().all(OutputDiff::is_ok)
+    }
+}
+
+impl Display for GoldenReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let failed = self.outputs.iter().filter(|output| !output.is_ok()).count();
+        write!(
+            f,
+            "golden test of `{}`: {} of {} outputs differ",
+            self.function,
+            failed,
+            self.outputs.len()
+        )?;
+        for output in &self.outputs {
+            write!(f, "\n  {}", output)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs a function of a module on golden inputs, see the [module-level documentation].
+///
+/// [module-level documentation]:index.html
+#[derive(Debug, Clone)]
+pub struct GoldenTest {
+    module: Module,
+    function: String,
+    ctx: TVMContext,
+    inputs: Vec<PathBuf>,
+    expected: Vec<PathBuf>,
+    tolerance: Tolerance,
+}
+
+impl GoldenTest {
+    /// Creates a test of the function, running on the cpu with the default tolerances.
+    pub fn new(module: Module, function: &str) -> Self {
+        GoldenTest {
+            module,
+            function: function.to_owned(),
+            ctx: TVMContext::cpu(0),
+            inputs: Vec::new(),
+            expected: Vec::new(),
+            tolerance: Tolerance::default(),
+        }
+    }
+
+    /// Sets the context the inputs and outputs are allocated on.
+    pub fn ctx(&mut self, ctx: TVMContext) -> &mut Self {
+        self.ctx = ctx;
+        self
+    }
+
+    /// Adds an npy file of the next input.
+    pub fn input(&mut self, path: &Path) -> &mut Self {
+        self.inputs.push(path.to_owned());
+        self
+    }
+
+    /// Adds an npy file of the expected values of the next output.
+    pub fn expected(&mut self, path: &Path) -> &mut Self {
+        self.expected.push(path.to_owned());
+        self
+    }
+
+    pub fn tolerance(&mut self, tolerance: Tolerance) -> &mut Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Runs the function and compares its outputs, failing if the files cannot be
+    /// loaded or the function fails.
+    pub fn run(&self) -> Result<GoldenReport> {
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|path| load_npy(path, self.ctx.clone()))
+            .collect::<Result<Vec<_>>>()?;
+        let expected = self
+            .expected
+            .iter()
+            .map(|path| load_npy(path, TVMContext::cpu(0)))
+            .collect::<Result<Vec<_>>>()?;
+        let outputs = expected
+            .iter()
+            .map(|array| empty(&mut array.dims(), self.ctx.clone(), array.dtype()))
+            .collect::<Vec<_>>();
+        let mut call = Builder::from(self.module.get_function(&self.function, false)?);
+        for array in inputs.iter().chain(&outputs) {
+            call.arg(&array.as_dltensor_arg());
+        }
+        call.invoke_catching()?;
+        let outputs = self
+            .expected
+            .iter()
+            .zip(outputs.iter().zip(&expected))
+            .map(|(path, (actual, expected))| {
+                compare(&path.display().to_string(), actual, expected, &self.tolerance)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(GoldenReport {
+            function: self.function.clone(),
+            outputs,
+        })
+    }
+
+    /// Runs the test like [`run`], failing with the report if an output differs, e.g.
+    /// to `unwrap` in a test.
+    ///
+    /// [`run`]:struct.GoldenTest.html#method.run
+    pub fn check(&self) -> Result<GoldenReport> {
+        let report = self.run()?;
+        if !report.is_ok() {
+            bail!("{}", report);
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn npy(descr: &str, shape: &str, data: &[u8]) -> Vec<u8> {
+        let header = format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}\n",
+            descr, shape
+        );
+        let mut bytes = NPY_MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 0, header.len() as u8, 0]);
+        bytes.extend_from_slice(header.as_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn parse() {
+        let bytes = npy("<i2", "(2, 1)", &[1, 0, 2, 0]);
+        let (dtype, shape, data) = parse_npy(&bytes).unwrap();
+        assert_eq!(dtype, DataType::new(0, 16, 1));
+        assert_eq!(shape, vec![2, 1]);
+        assert_eq!(data, &[1, 0, 2, 0]);
+        assert_eq!(parse_npy(&npy("<f8", "()", &[])).unwrap().1, Vec::<usize>::new());
+        assert!(parse_npy(&npy(">f4", "(1,)", &[])).is_err());
+        assert!(parse_npy(&npy("<c8", "(1,)", &[])).is_err());
+        assert!(parse_npy(b"not an npy file").is_err());
+    }
+
+    #[test]
+    fn diff() {
+        let ctx = TVMContext::cpu(0);
+        let mut expected = empty(&mut [3], ctx.clone(), DataType::from("float"));
+        expected.copy_from_buffer(&[1f32, 2., 3.]).unwrap();
+        let mut actual = empty(&mut [3], ctx.clone(), DataType::from("float"));
+        actual.copy_from_buffer(&[1f32, 2.5, 3.]).unwrap();
+        let tolerance = Tolerance::default();
+        let diff = compare("out.npy", &actual, &expected, &tolerance).unwrap();
+        assert_eq!((diff.mismatches, diff.max_abs_diff), (1, 0.5));
+        assert_eq!(diff.first_mismatch, Some((1, 2.5, 2.)));
+        let loose = Tolerance { rtol: 0.5, atol: 0. };
+        assert!(compare("out.npy", &actual, &expected, &loose).unwrap().is_ok());
+        let report = GoldenReport {
+            function: "add".to_owned(),
+            outputs: vec![diff],
+        };
+        assert!(report.to_string().contains("1 of 3 entries differ"));
+        let other = empty(&mut [3, 1], ctx, DataType::from("float"));
+        assert!(compare("out.npy", &other, &expected, &tolerance).is_err());
+    }
+}
@@ -0,0 +1,191 @@
+//! Reads the tuning logs of AutoTVM and the auto-scheduler, e.g. to apply their best
+//! records when compiling with [`compile`].
+//!
+//! A log has one json record per line, with the measured costs of a schedule of a
+//! workload on a target. Both the current `input`/`result` and the compact `i`/`r`
+//! layouts are read.
+//!
+//! # Example
+//!
+//! ```
+//! let log = TuningLog::load(Path::new("resnet18.log")).unwrap();
+//! for record in log.best() {
+//!     println!("{} on {}: {:?}", record.workload, record.target, record.mean_cost());
+//! }
+//! ```
+//!
+//! [`compile`]:../compile/index.html
+
+use std::{collections::HashMap, fs, path::Path};
+
+use serde_json::{self, Value};
+
+use ErrorKind;
+use Result;
+
+// Returns the field of the record by its name or its short name.
+fn field<'a>(record: &'a Value, name: &str, short: &str) -> &'a Value {
+    if record[name].is_null() {
+        &record[short]
+    } else {
+        &record[name]
+    }
+}
+
+/// A measured schedule of a tuning log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TuningRecord {
+    pub target: String,
+    /// The task name and the arguments for AutoTVM, the workload key for the
+    /// auto-scheduler.
+    pub workload: String,
+    /// The measured costs in seconds.
+    pub costs: Vec<f64>,
+    /// `0` if the measurement succeeded, the kind of the error otherwise.
+    pub error_no: i64,
+    /// The record as written in the log.
+    pub line: String,
+}
+
+impl TuningRecord {
+    /// Parses a line of a tuning log.
+    pub fn parse(line: &str) -> Result<TuningRecord> {
+        let record: Value = serde_json::from_str(line)?;
+        let input = field(&record, "input", "i");
+        let result = field(&record, "result", "r");
+        let (target, workload) = if input[0].is_array() {
+            // auto-scheduler: [[workload_key, target, ...], state]
+            (input[0][1].as_str(), input[0][0].as_str().map(str::to_owned))
+        } else {
+            // AutoTVM: [target, task_name, args, kwargs]
+            let workload = input[1]
+                .as_str()
+                .map(|task_name| format!("{} {}", task_name, input[2]));
+            (input[0].as_str(), workload)
+        };
+        let costs = result[0].as_array().and_then(|costs| {
+            costs
+                .iter()
+                .map(Value::as_f64)
+                .collect::<Option<Vec<_>>>()
+        });
+        match (target, workload, costs, result[1].as_i64()) {
+            (Some(target), Some(workload), Some(costs), Some(error_no)) => Ok(TuningRecord {
+                target: target.to_owned(),
+                workload,
+                costs,
+                error_no,
+                line: line.to_owned(),
+            }),
+            _ => bail!("invalid tuning record: {}", line),
+        }
+    }
+
+    /// Returns the mean of the costs, or `None` if the measurement failed.
+    pub fn mean_cost(&self) -> Option<f64> {
+        if self.error_no != 0 || self.costs.is_empty() {
+            return None;
+        }
+        Some(self.costs.iter().sum::<f64>() / self.costs.len() as f64)
+    }
+}
+
+/// The records of a tuning log.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TuningLog {
+    pub records: Vec<TuningRecord>,
+}
+
+impl TuningLog {
+    /// Reads a tuning log, skipping the empty lines.
+    pub fn load(path: &Path) -> Result<TuningLog> {
+        TuningLog::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses the lines of a tuning log, failing with the number of the first invalid one.
+    pub fn parse(log: &str) -> Result<TuningLog> {
+        let records = log
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                TuningRecord::parse(line)
+                    .map_err(|err| ErrorKind::InvalidTuningLog(i + 1, err.to_string()).into())
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(TuningLog { records })
+    }
+
+    /// Returns the successful record with the lowest mean cost of every workload and
+    /// target, in the order of their first records in the log.
+    pub fn best(&self) -> Vec<&TuningRecord> {
+        let mut order = Vec::new();
+        let mut best: HashMap<(&str, &str), &TuningRecord> = HashMap::new();
+        for record in &self.records {
+            let cost = match record.mean_cost() {
+                Some(cost) => cost,
+                None => continue,
+            };
+            let key = (record.target.as_str(), record.workload.as_str());
+            let is_better = match best.get(&key) {
+                Some(current) => current.mean_cost().map_or(true, |current| cost < current),
+                None => {
+                    order.push(key);
+                    true
+                }
+            };
+            if is_better {
+                best.insert(key, record);
+            }
+        }
+        order.iter().map(|key| best[key]).collect()
+    }
+
+    /// Writes the [`best`] records into a log, e.g. to apply them when compiling.
+    ///
+    /// [`best`]:struct.TuningLog.html#method.best
+    pub fn write_best(&self, path: &Path) -> Result<()> {
+        let mut log = String::new();
+        for record in self.best() {
+            log += &record.line;
+            log += "\n";
+        }
+        fs::write(path, log)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &'static str = r#"
+{"input": ["llvm", "dense_nopack.x86", [["TENSOR", [1, 4], "float32"]], {}], "config": {"index": 1}, "result": [[0.5, 0.25], 0, 1.0, 1.0], "version": 0.2}
+{"input": ["llvm", "dense_nopack.x86", [["TENSOR", [1, 4], "float32"]], {}], "config": {"index": 2}, "result": [[0.001], 0, 1.0, 1.0], "version": 0.2}
+{"input": ["llvm", "dense_nopack.x86", [["TENSOR", [1, 4], "float32"]], {}], "config": {"index": 3}, "result": [[1e9], 4, 1.0, 1.0], "version": 0.2}
+{"i": [["[\"conv2d\", 1, 3]", "cuda", [], -1], [[], []]], "r": [[0.5], 0, 1.0, 1.0], "v": "v0.6"}
+"#;
+
+    #[test]
+    fn parse() {
+        let log = TuningLog::parse(LOG).unwrap();
+        assert_eq!(log.records.len(), 4);
+        assert_eq!(log.records[0].mean_cost(), Some(0.375));
+        assert_eq!(log.records[2].mean_cost(), None);
+        assert_eq!(log.records[3].target, "cuda");
+        assert_eq!(log.records[3].workload, r#"["conv2d", 1, 3]"#);
+        match TuningLog::parse("{}\n") {
+            Err(err) => assert!(err.to_string().contains("line 1")),
+            Ok(_) => panic!("expected an invalid record"),
+        }
+    }
+
+    #[test]
+    fn best() {
+        let log = TuningLog::parse(LOG).unwrap();
+        let best = log.best();
+        assert_eq!(best.len(), 2);
+        assert!(best[0].line.contains(r#""index": 2"#));
+        assert_eq!(best[1].target, "cuda");
+    }
+}
@@ -1,19 +1,20 @@
 //! This module implements the required conversions from Rust types to TVM types.
 //!
-//! In TVM frontend only conversions from 32bits numeric types (i32, u32, f32)
-//! and 64bits pointers are supported.
+//! Integers of every width are passed as the `int64_t` of the runtime, so that shapes and
+//! indices need no casts, and floats as `double`. Arrays, modules, functions and other
+//! handles are passed as pointers.
 //!
 //! # Example
 //!
 //! ```
-//! let dtype = TVMType::from("float");
+//! let dtype = DataType::from("float");
 //! println!("dtype is: {}", dtype);
 //! ```
 
 use std::{
-    ffi::{CStr, CString},
     fmt::{self, Display, Formatter},
     ops::{Deref, DerefMut},
+    str::FromStr,
 };
 
 use ts;
@@ -23,138 +24,57 @@ use module::Module;
 use ndarray::NDArray;
 use TVMByteArray;
 use TVMContext;
+use Error;
+use Result;
 use TVMDeviceType;
 
-/// TVM Type codes
-#[repr(u32)]
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-pub enum TypeCode {
-    kDLInt = 0,
-    kDLUInt = 1,
-    kDLFloat = 2,
-    kHandle = 3,
-    kNull = 4,
-    kTVMType = 5,
-    kTVMContext = 6,
-    kArrayHandle = 7,
-    kNodeHandle = 8,
-    kModuleHandle = 9,
-    kFuncHandle = 10,
-    kStr = 11,
-    kBytes = 12,
-}
-
-impl Default for TypeCode {
-    fn default() -> Self {
-        TypeCode::kDLInt
-    }
-}
-
-impl<'a> Into<TypeCode> for i32 {
-    fn into(self) -> TypeCode {
-        match self {
-            0 => TypeCode::kDLInt,
-            1 => TypeCode::kDLUInt,
-            2 => TypeCode::kDLFloat,
-            3 => TypeCode::kHandle,
-            4 => TypeCode::kNull,
-            5 => TypeCode::kTVMType,
-            6 => TypeCode::kTVMContext,
-            7 => TypeCode::kArrayHandle,
-            8 => TypeCode::kNodeHandle,
-            9 => TypeCode::kModuleHandle,
-            10 => TypeCode::kFuncHandle,
-            11 => TypeCode::kStr,
-            12 => TypeCode::kBytes,
-            _ => unreachable!(),
-        }
-    }
-}
-
-impl Display for TypeCode {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                TypeCode::kDLInt => "int",
-                TypeCode::kDLUInt => "uint",
-                TypeCode::kDLFloat => "float",
-                TypeCode::kHandle => "handle",
-                TypeCode::kNull => "null",
-                TypeCode::kTVMType => "TVM type",
-                TypeCode::kTVMContext => "TVM context",
-                TypeCode::kArrayHandle => "Array handle",
-                TypeCode::kNodeHandle => "Node handle",
-                TypeCode::kModuleHandle => "Module handle",
-                TypeCode::kFuncHandle => "Function handle",
-                TypeCode::kStr => "string",
-                TypeCode::kBytes => "bytes",
-            }
-        )
-    }
-}
-
-macro_rules! impl_prim_type {
-    ($type:ty, $variant:ident) => {
-        impl<'a> From<&'a $type> for TypeCode {
-            fn from(_arg: &$type) -> Self {
-                TypeCode::$variant
-            }
-        }
-
-        impl<'a> From<&'a mut $type> for TypeCode {
-            fn from(_arg: &mut $type) -> Self {
-                TypeCode::$variant
-            }
-        }
-    };
-}
+pub use ts::TypeCode;
 
-impl_prim_type!(usize, kDLInt);
-impl_prim_type!(i64, kDLInt);
-impl_prim_type!(i32, kDLInt);
-impl_prim_type!(i16, kDLInt);
-impl_prim_type!(i8, kDLInt);
 impl_prim_type!(TVMDeviceType, kDLInt);
 
-impl_prim_type!(u64, kDLUInt);
-impl_prim_type!(u32, kDLUInt);
-impl_prim_type!(u16, kDLUInt);
-impl_prim_type!(u8, kDLUInt);
-
-impl_prim_type!(f64, kDLFloat);
-impl_prim_type!(f32, kDLFloat);
-
-impl_prim_type!(str, kStr);
-impl_prim_type!(CStr, kStr);
-impl_prim_type!(String, kStr);
-impl_prim_type!(CString, kStr);
-
 impl_prim_type!(TVMContext, kTVMContext);
 
-impl_prim_type!(TVMType, kTVMType);
+impl_prim_type!(DataType, kTVMType);
 
 impl_prim_type!(Function, kFuncHandle);
 
 impl_prim_type!(Module, kModuleHandle);
 
-impl_prim_type!(NDArray, kArrayHandle);
+/// Arrays are passed as the NDArray containers the runtime allocated them in, and views,
+/// e.g. of callback arguments, as plain `DLTensor`s.
+impl<'a> From<&'a NDArray> for TypeCode {
+    fn from(arr: &NDArray) -> Self {
+        if arr.is_view() {
+            TypeCode::kArrayHandle
+        } else {
+            TypeCode::kNDArrayContainer
+        }
+    }
+}
+
+impl<'a> From<&'a mut NDArray> for TypeCode {
+    fn from(arr: &mut NDArray) -> Self {
+        TypeCode::from(&*arr)
+    }
+}
 
-impl_prim_type!([u8], kBytes);
 impl_prim_type!(TVMByteArray, kBytes);
 
 /// See the [module-level documentation](../ty/index.html) for more details.
 ///
 /// Wrapper around underlying TVMType
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub struct TVMType {
+pub struct DataType {
     pub inner: ts::TVMType, // fields are code: u8, bits: u8, lanes: u16
 }
 
-impl TVMType {
+/// Former name of [`DataType`].
+#[deprecated(since = "0.2.0", note = "renamed to `DataType`")]
+pub type TVMType = DataType;
+
+impl DataType {
     pub(crate) fn new(type_code: u8, bits: u8, lanes: u16) -> Self {
-        TVMType {
+        DataType {
             inner: ts::TVMType {
                 code: type_code,
                 bits: bits,
@@ -164,19 +84,83 @@ impl TVMType {
     }
 }
 
-impl<'a> From<&'a str> for TVMType {
+/// Rust types of the entries of NDArrays, whose dtype is checked when copying buffers
+/// into NDArrays.
+pub trait TVMDataType: Copy {
+    /// Returns the dtype of the type, e.g. `float` for `f32`.
+    fn data_type() -> DataType;
+}
+
+macro_rules! impl_tvm_data_type {
+    ($($type:ty => ($code:expr, $bits:expr)),+) => {
+        $(
+            impl TVMDataType for $type {
+                fn data_type() -> DataType {
+                    DataType::new($code, $bits, 1)
+                }
+            }
+        )+
+    };
+}
+
+impl_tvm_data_type!(
+    i8 => (0, 8), i16 => (0, 16), i32 => (0, 32), i64 => (0, 64),
+    u8 => (1, 8), u16 => (1, 16), u32 => (1, 32), u64 => (1, 64),
+    f32 => (2, 32), f64 => (2, 64)
+);
+
+impl<'a> From<&'a str> for DataType {
     fn from(type_str: &'a str) -> Self {
         match type_str {
-            "int" => TVMType::new(0, 32, 1),
-            "uint" => TVMType::new(1, 32, 1),
-            "float" => TVMType::new(2, 32, 1),
-            "handle" => TVMType::new(4, 64, 1),
+            "int" => DataType::new(0, 32, 1),
+            "uint" => DataType::new(1, 32, 1),
+            "float" => DataType::new(2, 32, 1),
+            "handle" => DataType::new(4, 64, 1),
             _ => panic!("Unsupported type {:?}", type_str),
         }
     }
 }
 
-impl Display for TVMType {
+/// Parses the names of [`From<&str>`] and the names with bits and lanes, such as
+/// `float16` or `int8x4`, failing for others instead of panicking.
+///
+/// ## Example
+///
+/// ```
+/// let dtype: DataType = "int8x4".parse().unwrap();
+/// assert_eq!(dtype.inner.lanes, 4);
+/// ```
+///
+/// [`From<&str>`]:struct.DataType.html#impl-From%3C%26%27a%20str%3E
+impl FromStr for DataType {
+    type Err = Error;
+
+    fn from_str(type_str: &str) -> Result<Self> {
+        match type_str {
+            "int" | "uint" | "float" | "handle" => return Ok(DataType::from(type_str)),
+            _ => (),
+        }
+        let invalid = || Error::from(format!("unsupported dtype {:?}", type_str));
+        let (code, rest) = if type_str.starts_with("uint") {
+            (1, &type_str[4..])
+        } else if type_str.starts_with("int") {
+            (0, &type_str[3..])
+        } else if type_str.starts_with("float") {
+            (2, &type_str[5..])
+        } else {
+            return Err(invalid());
+        };
+        let mut parts = rest.splitn(2, 'x');
+        let bits = parts.next().unwrap_or("").parse().map_err(|_| invalid())?;
+        let lanes = match parts.next() {
+            Some(lanes) => lanes.parse().map_err(|_| invalid())?,
+            None => 1,
+        };
+        Ok(DataType::new(code, bits, lanes))
+    }
+}
+
+impl Display for DataType {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self.inner {
             ts::TVMType {
@@ -204,33 +188,33 @@ impl Display for TVMType {
     }
 }
 
-impl From<TVMType> for ts::DLDataType {
-    fn from(dtype: TVMType) -> Self {
+impl From<DataType> for ts::DLDataType {
+    fn from(dtype: DataType) -> Self {
         dtype.inner
     }
 }
 
-impl From<ts::DLDataType> for TVMType {
+impl From<ts::DLDataType> for DataType {
     fn from(dtype: ts::DLDataType) -> Self {
         Self::new(dtype.code, dtype.bits, dtype.lanes)
     }
 }
 
-impl Deref for TVMType {
+impl Deref for DataType {
     type Target = ts::TVMType;
     fn deref(&self) -> &Self::Target {
         &self.inner
     }
 }
 
-impl DerefMut for TVMType {
+impl DerefMut for DataType {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.inner
     }
 }
 
-impl<'a, 'b> From<&'b TVMType> for &'a str {
-    fn from(ty: &TVMType) -> Self {
+impl<'a, 'b> From<&'b DataType> for &'a str {
+    fn from(ty: &DataType) -> Self {
         match **ty {
             ts::TVMType {
                 code: 0,
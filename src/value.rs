@@ -10,27 +10,68 @@
 //! let ret = TVMRetValue::from(&a);
 //! assert_eq!(ret.to_int() as i8, a);
 //! ```
+//!
+//! Integers of every width are promoted to the 64-bit integer slot of a `TVMValue`, with
+//! the `int` type code the runtime reads as `int64_t`, e.g. for shapes and indices. Only
+//! unsigned values above `i64::MAX` get the `uint` type code, which calls reject, and read
+//! back as negative `to_int()`s. Extracting an integer of a given width with
+//! [`FromArgValue`] checks that it fits.
+//!
+//! The [`ArgValue`] and [`RetValue`] enums are the typed form of the values, with one
+//! variant per type code. Values are inspected by matching on them, while the
+//! `TVMValue` and `TypeCode` pairs are what is passed to and returned by the runtime.
+//!
+//! Handles returned by calls are owned by the [`TVMRetValue`] and moved out exactly once
+//! by its consuming `try_into_*` methods, so they are neither freed twice nor leaked.
+//!
+//! Functions returning several values pack them into a runtime Array or a tuple, unpacked
+//! with [`TVMRetValue::into_vec`]. Rust callbacks return several values with
+//! [`TVMRetValue::from_vec`].
+//!
+//! [`FromArgValue`]:trait.FromArgValue.html
+//! [`ArgValue`]:enum.ArgValue.html
+//! [`RetValue`]:enum.RetValue.html
+//! [`TVMRetValue`]:struct.TVMRetValue.html
+//! [`TVMRetValue::into_vec`]:struct.TVMRetValue.html#method.into_vec
+//! [`TVMRetValue::from_vec`]:struct.TVMRetValue.html#method.from_vec
 
 use std::{
     any::Any,
+    convert::TryFrom,
+    f32,
     ffi::{CStr, CString},
     fmt::{self, Debug, Formatter},
     marker::PhantomData,
     mem,
     ops::{Deref, DerefMut},
     os::raw::{c_char, c_void},
+    ptr,
+    sync::Arc,
 };
 
 use ts;
 
+use ffi;
+use function::{self, Builder};
 use ty::TypeCode;
+use DataType;
+use Error;
+use ErrorKind;
 use Function;
 use Module;
 use NDArray;
+use Result;
 use TVMByteArray;
 use TVMContext;
 use TVMDeviceType;
-use TVMType;
+
+const MAKE_ARRAY_FUNCTIONS: [&'static str; 2] = ["runtime.Array", "_Array"];
+const ARRAY_SIZE_FUNCTIONS: [&'static str; 2] = ["runtime.ArraySize", "_ArraySize"];
+const ARRAY_GET_ITEM_FUNCTIONS: [&'static str; 2] = ["runtime.ArrayGetItem", "_ArrayGetItem"];
+pub(crate) const ADT_SIZE_FUNCTIONS: [&'static str; 2] =
+    ["runtime.GetADTSize", "_vmobj.GetADTSize"];
+pub(crate) const ADT_FIELDS_FUNCTIONS: [&'static str; 2] =
+    ["runtime.GetADTFields", "_vmobj.GetADTFields"];
 
 #[derive(Debug, Clone, PartialOrd, Ord, PartialEq, Eq)]
 pub(crate) enum ValueKind {
@@ -49,11 +90,31 @@ pub(crate) enum ValueKind {
 pub struct TVMValue {
     pub(crate) kind: ValueKind,
     pub(crate) inner: ts::TVMValue,
+    // keeps the string of a string value alive as long as the value or its clones.
+    owned: Option<Arc<CString>>,
 }
 
 impl TVMValue {
     pub(crate) fn new(kind: ValueKind, inner: ts::TVMValue) -> Self {
-        TVMValue { kind, inner }
+        TVMValue {
+            kind,
+            inner,
+            owned: None,
+        }
+    }
+
+    // Creates a string value owning the string, which is freed with the last clone of the
+    // value, e.g. once the builder holding the argument is dropped after the call.
+    fn from_cstring(string: CString) -> Self {
+        let string = Arc::new(string);
+        let inner = ts::TVMValue {
+            v_str: string.as_ptr() as *const c_char,
+        };
+        TVMValue {
+            kind: ValueKind::Str,
+            inner,
+            owned: Some(string),
+        }
     }
 
     pub fn to_int(&self) -> i64 {
@@ -84,6 +145,7 @@ macro_rules! impl_prim_val {
 }
 
 impl_prim_val!(usize, ValueKind::Int, v_int64, i64);
+impl_prim_val!(isize, ValueKind::Int, v_int64, i64);
 impl_prim_val!(i64, ValueKind::Int, v_int64, i64);
 impl_prim_val!(i32, ValueKind::Int, v_int64, i64);
 impl_prim_val!(i16, ValueKind::Int, v_int64, i64);
@@ -96,36 +158,24 @@ impl_prim_val!(bool, ValueKind::Int, v_int64, i64);
 impl_prim_val!(f64, ValueKind::Float, v_float64, f64);
 impl_prim_val!(f32, ValueKind::Float, v_float64, f64);
 
+/// Copies the string into a `CString` owned by the value.
+///
+/// Panics if the string contains a NUL byte.
 impl<'a> From<&'a str> for TVMValue {
     fn from(arg: &str) -> TVMValue {
-        let arg = CString::new(arg).unwrap();
-        let inner = ts::TVMValue {
-            v_str: arg.as_ptr() as *const c_char,
-        };
-        mem::forget(arg);
-        Self::new(ValueKind::Str, inner)
+        TVMValue::from_cstring(CString::new(arg).expect("strings passed to TVM have no NUL bytes"))
     }
 }
 
 impl<'a> From<&'a String> for TVMValue {
     fn from(arg: &String) -> TVMValue {
-        let arg = CString::new(arg.as_bytes()).unwrap();
-        let inner = ts::TVMValue {
-            v_str: arg.as_ptr() as *const c_char,
-        };
-        mem::forget(arg);
-        Self::new(ValueKind::Str, inner)
+        TVMValue::from(arg.as_str())
     }
 }
 
 impl<'a> From<&'a CString> for TVMValue {
     fn from(arg: &CString) -> TVMValue {
-        let arg = arg.to_owned();
-        let inner = ts::TVMValue {
-            v_str: arg.as_ptr() as *const c_char,
-        };
-        mem::forget(arg);
-        Self::new(ValueKind::Str, inner)
+        TVMValue::from_cstring(arg.clone())
     }
 }
 
@@ -157,8 +207,8 @@ macro_rules! impl_tvm_val_from_handle {
 
 impl_tvm_val_from_handle!(Module, Function, NDArray);
 
-impl<'a> From<&'a TVMType> for TVMValue {
-    fn from(ty: &TVMType) -> Self {
+impl<'a> From<&'a DataType> for TVMValue {
+    fn from(ty: &DataType) -> Self {
         let inner = ts::TVMValue { v_type: ty.inner };
         Self::new(ValueKind::Type, inner)
     }
@@ -257,6 +307,19 @@ where
     }
 }
 
+/// Passes the argument value as is, e.g. to forward it or to keep its type code.
+impl<'a, 'b> From<&'b TVMArgValue<'a>> for TVMValue {
+    fn from(arg: &TVMArgValue) -> Self {
+        arg.value.clone()
+    }
+}
+
+impl<'a, 'b> From<&'b TVMArgValue<'a>> for TypeCode {
+    fn from(arg: &TVMArgValue) -> Self {
+        arg.type_code
+    }
+}
+
 /// TVMRetValue is an owned TVMArgValue.
 ///
 /// ## Example
@@ -266,12 +329,22 @@ where
 /// let arg = TVMRetValue::from(&ctx);
 /// assert_eq!(arg.to_ctx(), ctx);
 /// ```
+///
+/// A module, function or array handle returned by a call is owned by the return value
+/// and its clones, and freed with the last of them unless it is moved out with
+/// [`try_into_module`], [`try_into_function`] or [`try_into_ndarray`].
+///
+/// [`try_into_module`]:struct.TVMRetValue.html#method.try_into_module
+/// [`try_into_function`]:struct.TVMRetValue.html#method.try_into_function
+/// [`try_into_ndarray`]:struct.TVMRetValue.html#method.try_into_ndarray
 // TODO: WIP for unification of runtime and frontend
 #[derive(Debug)]
 pub struct TVMRetValue {
     pub value: TVMValue,
     box_value: Box<Any>,
     pub type_code: TypeCode,
+    // the handle returned by the runtime, shared by the clones of the value.
+    handle: Option<Arc<RetHandle>>,
 }
 
 impl TVMRetValue {
@@ -280,8 +353,89 @@ impl TVMRetValue {
             value,
             box_value: box (), // starting the unification
             type_code,
+            handle: None,
         }
     }
+
+    // Wraps the value returned by a call, taking the ownership of its handle, if any.
+    pub(crate) fn from_return(inner: ts::TVMValue, type_code: TypeCode) -> Self {
+        let mut ret = TVMRetValue::new(TVMValue::new(ValueKind::Return, inner), type_code);
+        ret.handle = RetHandle::new(&inner, type_code).map(Arc::new);
+        ret
+    }
+
+    // Wraps an object owned by the value and its clones, e.g. to return it from a callback.
+    pub(crate) fn from_object(object: ObjectHandle) -> Self {
+        let inner = ts::TVMValue {
+            v_handle: object.handle,
+        };
+        let value = TVMValue::new(ValueKind::Handle, inner);
+        let mut ret = TVMRetValue::new(value, TypeCode::kNodeHandle);
+        ret.handle = Some(Arc::new(RetHandle::Object(object)));
+        ret
+    }
+
+    // Moves the handle out of the value, with `true` if the caller now owns it and
+    // `false` if it is borrowed, e.g. from a value created with `TVMRetValue::from`.
+    fn take_handle(mut self, type_code: TypeCode) -> Result<(*mut c_void, bool)> {
+        if self.type_code != type_code {
+            bail!(ErrorKind::TypeMismatch(
+                type_code.to_string(),
+                self.type_code.to_string()
+            ));
+        }
+        let raw = unsafe { self.value.inner.v_handle };
+        match self.handle.take().map(Arc::try_unwrap) {
+            Some(Ok(handle)) => {
+                handle.release();
+                Ok((raw, true))
+            }
+            Some(Err(_)) => bail!("the returned handle is shared by clones of the return value"),
+            None => Ok((raw, false)),
+        }
+    }
+
+    /// Moves the returned module out of the value, failing if it is not a module or
+    /// clones of the value share it.
+    pub fn try_into_module(self) -> Result<Module> {
+        let (handle, owned) = self.take_handle(TypeCode::kModuleHandle)?;
+        Ok(Module::new(handle, !owned, None))
+    }
+
+    /// Moves the returned function out of the value, failing if it is not a function or
+    /// clones of the value share it.
+    pub fn try_into_function(self) -> Result<Function> {
+        let (handle, owned) = self.take_handle(TypeCode::kFuncHandle)?;
+        Ok(Function::new(handle, false, !owned))
+    }
+
+    /// Moves the returned array out of the value, failing if it is not an array or
+    /// clones of the value share it. An array not returned by a call is a view.
+    pub fn try_into_ndarray(self) -> Result<NDArray> {
+        let type_code = if is_array(self.type_code) {
+            self.type_code
+        } else {
+            TypeCode::kArrayHandle
+        };
+        let (handle, owned) = self.take_handle(type_code)?;
+        Ok(NDArray::new(handle as ts::TVMArrayHandle, !owned).tracked())
+    }
+
+    /// Like [`try_into_module`] but panics on failure.
+    ///
+    /// [`try_into_module`]:struct.TVMRetValue.html#method.try_into_module
+    pub fn to_module(self) -> Module {
+        self.try_into_module()
+            .unwrap_or_else(|err| panic!("Requires module handle: {}", err))
+    }
+
+    /// Like [`try_into_ndarray`] but panics on failure.
+    ///
+    /// [`try_into_ndarray`]:struct.TVMRetValue.html#method.try_into_ndarray
+    pub fn to_ndarray(self) -> NDArray {
+        self.try_into_ndarray()
+            .unwrap_or_else(|err| panic!("Requires Array handle: {}", err))
+    }
 }
 
 impl Clone for TVMRetValue {
@@ -290,6 +444,89 @@ impl Clone for TVMRetValue {
             value: self.value.clone(),
             box_value: box (),
             type_code: self.type_code,
+            handle: self.handle.clone(),
+        }
+    }
+}
+
+// A handle returned by the runtime, freed on drop unless it is moved out.
+#[derive(Debug)]
+enum RetHandle {
+    Module(ts::TVMModuleHandle),
+    Function(ts::TVMFunctionHandle),
+    NDArray(ts::TVMArrayHandle),
+    // released by the handle itself
+    Object(ObjectHandle),
+}
+
+impl RetHandle {
+    fn new(value: &ts::TVMValue, type_code: TypeCode) -> Option<Self> {
+        let handle = unsafe { value.v_handle };
+        if handle.is_null() {
+            return None;
+        }
+        match type_code {
+            TypeCode::kModuleHandle => Some(RetHandle::Module(handle)),
+            TypeCode::kFuncHandle => Some(RetHandle::Function(handle)),
+            TypeCode::kNodeHandle => Some(RetHandle::Object(ObjectHandle::new(handle))),
+            code if is_array(code) => Some(RetHandle::NDArray(handle as ts::TVMArrayHandle)),
+            _ => None,
+        }
+    }
+
+    // Gives up the handle without freeing it, e.g. once it is moved out of the value.
+    fn release(mut self) {
+        let forget = match self {
+            RetHandle::Object(ref mut object) => {
+                object.handle = ptr::null_mut();
+                false
+            }
+            _ => true,
+        };
+        if forget {
+            mem::forget(self);
+        }
+    }
+}
+
+impl Drop for RetHandle {
+    fn drop(&mut self) {
+        match *self {
+            RetHandle::Module(handle) => mem::drop(Module::new(handle, false, None)),
+            RetHandle::Function(handle) => mem::drop(Function::new(handle, false, false)),
+            RetHandle::NDArray(handle) => mem::drop(NDArray::new(handle, false)),
+            RetHandle::Object(_) => (),
+        }
+    }
+}
+
+// The handle of an object returned by the runtime, e.g. a Relay node or an ADT object,
+// whose reference is released on drop.
+#[derive(Debug)]
+pub(crate) struct ObjectHandle {
+    handle: ts::TVMObjectHandle,
+    _runtime: ffi::RuntimeRef,
+}
+
+impl ObjectHandle {
+    // Takes over the reference on the object held by a returned handle.
+    pub(crate) fn new(handle: ts::TVMObjectHandle) -> Self {
+        ObjectHandle {
+            handle,
+            _runtime: ffi::RuntimeRef::acquire(),
+        }
+    }
+
+    // Returns the object as an argument, borrowing the reference.
+    pub(crate) fn as_arg(&self) -> ArgValue<'static> {
+        ArgValue::Node(self.handle)
+    }
+}
+
+impl Drop for ObjectHandle {
+    fn drop(&mut self) {
+        if !self.handle.is_null() {
+            check_call!(ffi::api().object_free(self.handle));
         }
     }
 }
@@ -306,14 +543,66 @@ where
 
 macro_rules! impl_to_methods {
     ($ty:ty) => {
+        /// Returns the stored 64-bit integer, signed or not, or `0` for NULL.
         pub fn to_int(&self) -> i64 {
-            if self.type_code != TypeCode::kDLInt && self.type_code != TypeCode::kNull {
-                panic!("Requires i64 or NULL, but found {:?}", self.type_code);
+            match self.type_code {
+                TypeCode::kDLInt | TypeCode::kDLUInt | TypeCode::kNull => (),
+                code => panic!("Requires an integer or NULL, but found {:?}", code),
             }
 
             unsafe { self.value.inner.v_int64 }
         }
 
+        /// Returns the stored int-encoded boolean, i.e. `false` for `0` or NULL
+        /// and `true` otherwise.
+        pub fn to_bool(&self) -> bool {
+            self.to_int() != 0
+        }
+
+        /// Returns the stored integer, failing if it is not an integer.
+        pub fn to_i64(&self) -> Result<i64> {
+            checked_int(&self.value, self.type_code, "i64")
+        }
+
+        /// Returns the stored integer, failing if it is not an integer or does not fit.
+        pub fn to_i32(&self) -> Result<i32> {
+            checked_int(&self.value, self.type_code, "i32")
+        }
+
+        /// Returns the stored integer, failing if it is not an integer or is negative.
+        pub fn to_u64(&self) -> Result<u64> {
+            checked_int(&self.value, self.type_code, "u64")
+        }
+
+        /// Returns the stored integer, failing if it is not an integer or does not fit.
+        pub fn to_u32(&self) -> Result<u32> {
+            checked_int(&self.value, self.type_code, "u32")
+        }
+
+        /// Returns the stored integer, failing if it is not an integer or does not fit,
+        /// e.g. for shapes and indices.
+        pub fn to_usize(&self) -> Result<usize> {
+            checked_int(&self.value, self.type_code, "usize")
+        }
+
+        /// Returns the stored float, failing if it is not a float.
+        pub fn to_f64(&self) -> Result<f64> {
+            if self.type_code != TypeCode::kDLFloat {
+                bail!(ErrorKind::TypeMismatch("float".to_owned(), self.type_code.to_string()));
+            }
+            Ok(unsafe { self.value.inner.v_float64 })
+        }
+
+        /// Returns the stored float, failing if it is not a float or a finite value
+        /// overflows `f32`. The precision may be reduced.
+        pub fn to_f32(&self) -> Result<f32> {
+            let v = self.to_f64()?;
+            if v.is_finite() && v.abs() > f32::MAX as f64 {
+                bail!(ErrorKind::OutOfRange(v.to_string(), "f32".to_owned()));
+            }
+            Ok(v as f32)
+        }
+
         pub fn to_float(&self) -> f64 {
             assert_eq!(
                 self.type_code,
@@ -338,17 +627,6 @@ macro_rules! impl_to_methods {
             }
         }
 
-        pub fn to_module(&self) -> Module {
-            assert_eq!(
-                self.type_code,
-                TypeCode::kModuleHandle,
-                "Requires module handle, but found {:?}",
-                self.type_code
-            );
-            let module_handle = unsafe { self.value.inner.v_handle };
-            Module::new(module_handle, false, None)
-        }
-
         pub fn to_string(&self) -> String {
             assert_eq!(
                 self.type_code,
@@ -365,27 +643,15 @@ macro_rules! impl_to_methods {
             ret_str.to_string()
         }
 
-        pub fn to_ndarray(&self) -> NDArray {
-            assert_eq!(
-                self.type_code,
-                TypeCode::kArrayHandle,
-                "Requires Array handle, but found {:?}",
-                self.type_code
-            );
-            let handle = unsafe { self.value.inner.v_handle };
-            let arr_handle = unsafe { mem::transmute::<*mut c_void, ts::TVMArrayHandle>(handle) };
-            NDArray::new(arr_handle, true)
-        }
-
-        pub fn to_type(&self) -> TVMType {
+        pub fn to_type(&self) -> DataType {
             assert_eq!(
                 self.type_code,
                 TypeCode::kTVMType,
-                "Requires TVMType, but found {:?}",
+                "Requires DataType, but found {:?}",
                 self.type_code
             );
             let ty = unsafe { self.value.inner.v_type };
-            TVMType::from(ty)
+            DataType::from(ty)
         }
 
         pub fn to_ctx(&self) -> TVMContext {
@@ -416,10 +682,454 @@ macro_rules! impl_to_methods {
 impl_to_methods!(refnc TVMArgValue<'a>);
 impl_to_methods!(owned TVMRetValue);
 
+impl<'a> TVMArgValue<'a> {
+    pub fn to_module(&self) -> Module {
+        assert_eq!(
+            self.type_code,
+            TypeCode::kModuleHandle,
+            "Requires module handle, but found {:?}",
+            self.type_code
+        );
+        let module_handle = unsafe { self.value.inner.v_handle };
+        // borrowed from the caller, who releases it
+        Module::new(module_handle, true, None)
+    }
+
+    /// Returns a view of the array, passed either as a `DLTensor` or an NDArray container.
+    pub fn to_ndarray(&self) -> NDArray {
+        assert!(
+            is_array(self.type_code),
+            "Requires Array handle, but found {:?}",
+            self.type_code
+        );
+        let handle = unsafe { self.value.inner.v_handle };
+        let arr_handle = unsafe { mem::transmute::<*mut c_void, ts::TVMArrayHandle>(handle) };
+        NDArray::new(arr_handle, true)
+    }
+}
+
+impl<'a> TVMArgValue<'a> {
+    /// Returns a typed view of the value.
+    pub fn as_value(&self) -> ArgValue {
+        view(&self.value, self.type_code)
+    }
+}
+
+impl TVMRetValue {
+    /// Returns a typed view of the value. The handles stay owned by the return value.
+    pub fn as_value(&self) -> ArgValue {
+        view(&self.value, self.type_code)
+    }
+
+    /// Converts the value into an owned [`RetValue`], moving a returned handle out like
+    /// the `try_into_*` methods.
+    ///
+    /// [`RetValue`]:enum.RetValue.html
+    pub fn try_into_value(self) -> Result<RetValue> {
+        match self.type_code {
+            code if is_array(code) => return self.try_into_ndarray().map(RetValue::NDArray),
+            TypeCode::kModuleHandle => return self.try_into_module().map(RetValue::Module),
+            TypeCode::kFuncHandle => return self.try_into_function().map(RetValue::Function),
+            TypeCode::kNodeHandle => {
+                let (handle, _) = self.take_handle(TypeCode::kNodeHandle)?;
+                return Ok(RetValue::Node(handle));
+            }
+            _ => (),
+        }
+        Ok(match self.as_value() {
+            ArgValue::Null => RetValue::Null,
+            ArgValue::Int(v) => RetValue::Int(v),
+            ArgValue::UInt(v) => RetValue::UInt(v),
+            ArgValue::Float(v) => RetValue::Float(v),
+            ArgValue::Str(s) => RetValue::Str(s.to_string_lossy().into_owned()),
+            ArgValue::Bytes(barr) => RetValue::Bytes(barr),
+            ArgValue::Type(ty) => RetValue::Type(ty),
+            ArgValue::Context(ctx) => RetValue::Context(ctx),
+            ArgValue::Node(handle) => RetValue::Node(handle),
+            ArgValue::Handle(handle) => RetValue::Handle(handle),
+            ArgValue::NDArray(_) | ArgValue::Module(_) | ArgValue::Function(_) => unreachable!(),
+        })
+    }
+
+    /// Unpacks a returned runtime Array, or an ADT object such as a tuple, into its
+    /// elements. Any other value is returned as the only element.
+    ///
+    /// The container is released once unpacked, while the elements move out like with
+    /// [`try_into_value`], so the caller owns the objects among them.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let outputs = call_packed!(detect_fn, &image).unwrap().into_vec().unwrap();
+    /// if let [RetValue::NDArray(ref boxes), RetValue::NDArray(ref scores)] = outputs[..] {
+    ///     println!("{:?} {:?}", boxes.shape(), scores.shape());
+    /// }
+    /// ```
+    ///
+    /// [`try_into_value`]:struct.TVMRetValue.html#method.try_into_value
+    pub fn into_vec(self) -> Result<Vec<RetValue>> {
+        if self.type_code != TypeCode::kNodeHandle {
+            return Ok(vec![self.try_into_value()?]);
+        }
+        let containers = [
+            (&ARRAY_SIZE_FUNCTIONS, &ARRAY_GET_ITEM_FUNCTIONS),
+            (&ADT_SIZE_FUNCTIONS, &ADT_FIELDS_FUNCTIONS),
+        ];
+        for &(size_names, item_names) in &containers {
+            let size_fn = match function::get_first_global_func(size_names) {
+                Ok(size_fn) => size_fn,
+                Err(_) => continue,
+            };
+            // the size functions fail for other objects
+            let size = match Builder::from(size_fn).arg(&self.as_value()).invoke_catching() {
+                Ok(size) => size.to_int(),
+                Err(_) => continue,
+            };
+            let item_fn = function::get_first_global_func(item_names)?;
+            return (0..size)
+                .map(|i| {
+                    let mut item = Builder::from(item_fn.clone());
+                    item.arg(&self.as_value()).arg(&i);
+                    item.invoke()?.try_into_value()
+                })
+                .collect();
+        }
+        Ok(vec![self.try_into_value()?])
+    }
+
+    /// Packs the values into a runtime Array, e.g. to return several values from a Rust
+    /// callback. The values must be objects, such as arrays.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// function::register_typed("split", |x: NDArray| {
+    ///     let (head, tail) = split(&x)?;
+    ///     TVMRetValue::from_vec(&[RetValue::NDArray(head), RetValue::NDArray(tail)])
+    /// }).unwrap();
+    /// ```
+    pub fn from_vec(values: &[RetValue]) -> Result<TVMRetValue> {
+        let make_fn = function::get_first_global_func(&MAKE_ARRAY_FUNCTIONS)?;
+        // owned arrays are passed as their containers, which are objects, while views fail
+        let args = values.iter().map(TVMArgValue::from).collect::<Vec<_>>();
+        let mut builder = Builder::from(make_fn);
+        builder.args(&args);
+        let ret = builder.invoke()?;
+        if ret.type_code != TypeCode::kNodeHandle {
+            bail!(ErrorKind::TypeMismatch(
+                TypeCode::kNodeHandle.to_string(),
+                ret.type_code.to_string()
+            ));
+        }
+        Ok(ret)
+    }
+}
+
+/// A typed view of a [`TVMArgValue`] or [`TVMRetValue`], with one variant per type code.
+///
+/// Strings are borrowed from the value, arrays are views, and modules and functions are
+/// not freed on drop. A view can be passed back as an argument, e.g. to forward it.
+///
+/// ## Example
+///
+/// ```
+/// match arg.as_value() {
+///     ArgValue::Int(v) => println!("int {}", v),
+///     ArgValue::Str(s) => println!("string {:?}", s),
+///     other => println!("{:?}", other),
+/// }
+/// ```
+///
+/// [`TVMArgValue`]:struct.TVMArgValue.html
+/// [`TVMRetValue`]:struct.TVMRetValue.html
+#[derive(Debug)]
+pub enum ArgValue<'a> {
+    Null,
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(&'a CStr),
+    Bytes(TVMByteArray),
+    Type(DataType),
+    Context(TVMContext),
+    NDArray(NDArray),
+    Module(Module),
+    Function(Function),
+    Node(*mut c_void),
+    Handle(*mut c_void),
+}
+
+/// An owned return value, see [`TVMRetValue::try_into_value`].
+///
+/// Arrays, modules and functions returned by a call own their handles.
+///
+/// [`TVMRetValue::try_into_value`]:struct.TVMRetValue.html#method.try_into_value
+#[derive(Debug)]
+pub enum RetValue {
+    Null,
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(String),
+    Bytes(TVMByteArray),
+    Type(DataType),
+    Context(TVMContext),
+    NDArray(NDArray),
+    Module(Module),
+    Function(Function),
+    Node(*mut c_void),
+    Handle(*mut c_void),
+}
+
+impl RetValue {
+    /// Returns the type code the value is passed with.
+    pub fn type_code(&self) -> TypeCode {
+        TypeCode::from(self)
+    }
+}
+
+// Fails with `ErrorKind::TypeMismatch` for a value which is not of the expected type.
+pub(crate) fn type_mismatch(expected: TypeCode, found: &RetValue) -> Error {
+    ErrorKind::TypeMismatch(expected.to_string(), found.type_code().to_string()).into()
+}
+
+// Moves the string out of a returned value, failing for other types.
+pub(crate) fn into_string(ret: TVMRetValue) -> Result<String> {
+    match ret.try_into_value()? {
+        RetValue::Str(s) => Ok(s),
+        other => Err(type_mismatch(TypeCode::kStr, &other)),
+    }
+}
+
+// Returns whether the type code is of an array, whose handle is a `DLTensor*` either way
+// since an NDArray container starts with its `DLTensor`.
+fn is_array(type_code: TypeCode) -> bool {
+    type_code == TypeCode::kArrayHandle || type_code == TypeCode::kNDArrayContainer
+}
+
+// Views the value according to its type code.
+fn view(value: &TVMValue, type_code: TypeCode) -> ArgValue {
+    let inner = &value.inner;
+    unsafe {
+        match type_code {
+            TypeCode::kNull => ArgValue::Null,
+            TypeCode::kDLInt => ArgValue::Int(inner.v_int64),
+            TypeCode::kDLUInt => ArgValue::UInt(inner.v_int64 as u64),
+            TypeCode::kDLFloat => ArgValue::Float(inner.v_float64),
+            TypeCode::kStr => ArgValue::Str(CStr::from_ptr(inner.v_str)),
+            TypeCode::kBytes => {
+                ArgValue::Bytes(TVMByteArray::new(*(inner.v_handle as *mut ts::TVMByteArray)))
+            }
+            TypeCode::kTVMType => ArgValue::Type(DataType::from(inner.v_type)),
+            TypeCode::kTVMContext => ArgValue::Context(TVMContext::from(inner.v_ctx)),
+            TypeCode::kArrayHandle | TypeCode::kNDArrayContainer => {
+                ArgValue::NDArray(NDArray::new(inner.v_handle as ts::TVMArrayHandle, true))
+            }
+            TypeCode::kModuleHandle => ArgValue::Module(Module::new(inner.v_handle, true, None)),
+            TypeCode::kFuncHandle => {
+                ArgValue::Function(Function::new(inner.v_handle, false, true))
+            }
+            TypeCode::kNodeHandle => ArgValue::Node(inner.v_handle),
+            TypeCode::kHandle => ArgValue::Handle(inner.v_handle),
+        }
+    }
+}
+
+impl<'a, 'b> From<&'b ArgValue<'a>> for TVMValue {
+    fn from(arg: &ArgValue) -> Self {
+        match *arg {
+            ArgValue::Null => TVMValue::new(ValueKind::Int, ts::TVMValue { v_int64: 0 }),
+            ArgValue::Int(ref v) => TVMValue::from(v),
+            ArgValue::UInt(ref v) => TVMValue::from(v),
+            ArgValue::Float(ref v) => TVMValue::from(v),
+            ArgValue::Str(s) => TVMValue::from_cstring(s.to_owned()),
+            ArgValue::Bytes(ref barr) => TVMValue::from(barr),
+            ArgValue::Type(ref ty) => TVMValue::from(ty),
+            ArgValue::Context(ref ctx) => TVMValue::from(ctx),
+            ArgValue::NDArray(ref nd) => TVMValue::from(nd),
+            ArgValue::Module(ref module) => TVMValue::from(module),
+            ArgValue::Function(ref func) => TVMValue::from(func),
+            ArgValue::Node(handle) | ArgValue::Handle(handle) => {
+                TVMValue::new(ValueKind::Handle, ts::TVMValue { v_handle: handle })
+            }
+        }
+    }
+}
+
+impl<'a, 'b> From<&'b ArgValue<'a>> for TypeCode {
+    fn from(arg: &ArgValue) -> Self {
+        match *arg {
+            ArgValue::Null => TypeCode::kNull,
+            ArgValue::Int(_) => TypeCode::kDLInt,
+            ArgValue::UInt(ref v) => TypeCode::from(v),
+            ArgValue::Float(_) => TypeCode::kDLFloat,
+            ArgValue::Str(_) => TypeCode::kStr,
+            ArgValue::Bytes(_) => TypeCode::kBytes,
+            ArgValue::Type(_) => TypeCode::kTVMType,
+            ArgValue::Context(_) => TypeCode::kTVMContext,
+            ArgValue::NDArray(ref nd) => TypeCode::from(nd),
+            ArgValue::Module(_) => TypeCode::kModuleHandle,
+            ArgValue::Function(_) => TypeCode::kFuncHandle,
+            ArgValue::Node(_) => TypeCode::kNodeHandle,
+            ArgValue::Handle(_) => TypeCode::kHandle,
+        }
+    }
+}
+
+impl<'a> From<&'a RetValue> for TVMValue {
+    fn from(ret: &RetValue) -> Self {
+        match *ret {
+            RetValue::Str(ref s) => TVMValue::from(s),
+            RetValue::Bytes(ref barr) => TVMValue::from(barr),
+            RetValue::NDArray(ref nd) => TVMValue::from(nd),
+            RetValue::Module(ref module) => TVMValue::from(module),
+            RetValue::Function(ref func) => TVMValue::from(func),
+            ref other => TVMValue::from(&scalar_view(other)),
+        }
+    }
+}
+
+impl<'a> From<&'a RetValue> for TypeCode {
+    fn from(ret: &RetValue) -> Self {
+        match *ret {
+            RetValue::Str(_) => TypeCode::kStr,
+            RetValue::Bytes(_) => TypeCode::kBytes,
+            RetValue::NDArray(ref nd) => TypeCode::from(nd),
+            RetValue::Module(_) => TypeCode::kModuleHandle,
+            RetValue::Function(_) => TypeCode::kFuncHandle,
+            ref other => TypeCode::from(&scalar_view(other)),
+        }
+    }
+}
+
+// Returns the view of a value without owned data.
+fn scalar_view(ret: &RetValue) -> ArgValue<'static> {
+    match *ret {
+        RetValue::Null => ArgValue::Null,
+        RetValue::Int(v) => ArgValue::Int(v),
+        RetValue::UInt(v) => ArgValue::UInt(v),
+        RetValue::Float(v) => ArgValue::Float(v),
+        RetValue::Type(ty) => ArgValue::Type(ty),
+        RetValue::Context(ref ctx) => ArgValue::Context(ctx.clone()),
+        RetValue::Node(handle) => ArgValue::Node(handle),
+        RetValue::Handle(handle) => ArgValue::Handle(handle),
+        _ => unreachable!("the value owns data"),
+    }
+}
+
+// Extracts an integer value as `T`, interpreting it as unsigned for `kDLUInt`.
+fn checked_int<T>(value: &TVMValue, type_code: TypeCode, type_name: &str) -> Result<T>
+where
+    T: TryFrom<i64> + TryFrom<u64>,
+{
+    let v = unsafe { value.inner.v_int64 };
+    let converted = match type_code {
+        TypeCode::kDLInt => T::try_from(v).ok(),
+        TypeCode::kDLUInt => T::try_from(v as u64).ok(),
+        code => bail!(ErrorKind::TypeMismatch("int".to_owned(), code.to_string())),
+    };
+    converted.ok_or_else(|| {
+        let v = if type_code == TypeCode::kDLUInt {
+            (v as u64).to_string()
+        } else {
+            v.to_string()
+        };
+        ErrorKind::OutOfRange(v, type_name.to_owned()).into()
+    })
+}
+
+/// Checked conversion from a [`TVMArgValue`], used for extracting the arguments of
+/// functions registered with [`function::register_typed`].
+///
+/// [`function::register_typed`]:../function/fn.register_typed.html
+pub trait FromArgValue: Sized {
+    fn from_arg_value(arg: &TVMArgValue) -> Result<Self>;
+}
+
+fn check_type_code(arg: &TVMArgValue, expected: &[TypeCode]) -> Result<()> {
+    if !expected.contains(&arg.type_code) {
+        bail!(ErrorKind::TypeMismatch(
+            format!("{}", expected[0]),
+            format!("{}", arg.type_code)
+        ));
+    }
+    Ok(())
+}
+
+macro_rules! impl_from_arg_value {
+    (ints $($type:ident),+) => {
+        $(
+            impl_from_arg_value!($type, [kDLInt, kDLUInt], arg => {
+                checked_int(&arg.value, arg.type_code, stringify!($type))?
+            });
+        )+
+    };
+    ($type:ty, [$($code:ident),+], $arg:ident => $conv:expr) => {
+        impl FromArgValue for $type {
+            fn from_arg_value($arg: &TVMArgValue) -> Result<Self> {
+                check_type_code($arg, &[$(TypeCode::$code),+])?;
+                Ok($conv)
+            }
+        }
+    };
+}
+
+impl_from_arg_value!(bool, [kDLInt, kDLUInt], arg => arg.to_bool());
+impl_from_arg_value!(i64, [kDLInt, kDLUInt], arg => arg.to_i64()?);
+impl_from_arg_value!(i32, [kDLInt, kDLUInt], arg => arg.to_i32()?);
+impl_from_arg_value!(usize, [kDLInt, kDLUInt], arg => arg.to_usize()?);
+impl_from_arg_value!(f64, [kDLFloat], arg => arg.to_f64()?);
+impl_from_arg_value!(ints isize, i16, i8, u64, u32, u16, u8);
+impl_from_arg_value!(f32, [kDLFloat], arg => arg.to_f32()?);
+impl_from_arg_value!(String, [kStr], arg => arg.to_string());
+impl_from_arg_value!(NDArray, [kArrayHandle, kNDArrayContainer], arg => arg.to_ndarray());
+impl_from_arg_value!(Module, [kModuleHandle], arg => arg.to_module());
+impl_from_arg_value!(DataType, [kTVMType], arg => arg.to_type());
+impl_from_arg_value!(TVMContext, [kTVMContext], arg => arg.to_ctx());
+impl_from_arg_value!(TVMByteArray, [kBytes], arg => arg.to_bytearray());
+
+/// Conversion of the return value of functions registered with
+/// [`function::register_typed`] into a [`TVMRetValue`].
+///
+/// [`function::register_typed`]:../function/fn.register_typed.html
+pub trait IntoRetValue {
+    fn into_ret_value(self) -> TVMRetValue;
+}
+
+impl IntoRetValue for TVMRetValue {
+    fn into_ret_value(self) -> TVMRetValue {
+        self
+    }
+}
+
+impl IntoRetValue for () {
+    fn into_ret_value(self) -> TVMRetValue {
+        let inner = ts::TVMValue { v_int64: 0 };
+        TVMRetValue::new(TVMValue::new(ValueKind::Int, inner), TypeCode::kNull)
+    }
+}
+
+macro_rules! impl_into_ret_value {
+    ($($type:ty),+) => {
+        $(
+            impl IntoRetValue for $type {
+                fn into_ret_value(self) -> TVMRetValue {
+                    TVMRetValue::from(&self)
+                }
+            }
+        )+
+    };
+}
+
+impl_into_ret_value!(bool, i64, i32, i16, i8, isize, u64, u32, u16, u8, usize);
+impl_into_ret_value!(f64, f32, String, DataType, TVMContext);
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use TVMDataType;
+
     #[test]
     fn numeric() {
         macro_rules! arg_ret_tests {
@@ -443,10 +1153,55 @@ mod tests {
             }};
         }
 
-        arg_ret_tests!(42; ints i8, i16, i32, i64);
+        arg_ret_tests!(42; ints i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
         arg_ret_tests!(42; floats f32, f64);
     }
 
+    #[test]
+    fn owned_strings() {
+        let value = {
+            let transient = "transient".to_owned();
+            TVMArgValue::from(transient.as_str())
+        };
+        assert_eq!(value.type_code, TypeCode::kStr);
+        assert_eq!(value.to_string(), "transient");
+        let string = Arc::downgrade(value.value.owned.as_ref().unwrap());
+        let clone = value.clone();
+        drop(value);
+        assert_eq!(clone.to_string(), "transient");
+        drop(clone);
+        assert!(string.upgrade().is_none());
+    }
+
+    #[test]
+    fn integer_widths() {
+        let arg = TVMArgValue::from(&-5i32);
+        assert_eq!(i8::from_arg_value(&arg).unwrap(), -5);
+        assert_eq!(isize::from_arg_value(&arg).unwrap(), -5);
+        assert!(u16::from_arg_value(&arg).is_err());
+        assert!(u8::from_arg_value(&TVMArgValue::from(&300i64)).is_err());
+        assert_eq!(TVMArgValue::from(&7u32).type_code, TypeCode::kDLInt);
+        assert_eq!(TVMArgValue::from(&7usize).type_code, TypeCode::kDLInt);
+        let arg = TVMArgValue::from(&u64::max_value());
+        assert_eq!(arg.type_code, TypeCode::kDLUInt);
+        assert_eq!(u64::from_arg_value(&arg).unwrap(), u64::max_value());
+        assert!(i64::from_arg_value(&arg).is_err());
+        assert_eq!(7u16.into_ret_value().to_u32().unwrap(), 7);
+    }
+
+    #[test]
+    fn boolean() {
+        let a = TVMArgValue::from(&true);
+        assert_eq!(a.type_code, TypeCode::kDLInt);
+        assert_eq!(a.to_int(), 1);
+        assert!(a.to_bool());
+        assert!(!TVMRetValue::from(&false).to_bool());
+        assert!(TVMRetValue::from(&42).to_bool());
+        assert!(bool::from_arg_value(&a).unwrap());
+        assert!(!().into_ret_value().to_bool());
+        assert!(true.into_ret_value().to_bool());
+    }
+
     #[test]
     fn bytearray() {
         let v = CString::new(b"hello".to_vec()).unwrap();
@@ -476,15 +1231,103 @@ mod tests {
 
     #[test]
     fn ty() {
-        let t = TVMType::from("int");
+        let t = DataType::from("int");
         let tvm = TVMRetValue::from(&t);
         assert_eq!(tvm.to_type(), t);
     }
 
     #[test]
     fn ctx() {
-        let c = TVMContext::from("gpu");
+        let c = TVMContext::gpu(0);
         let tvm = TVMRetValue::from(&c);
         assert_eq!(tvm.to_ctx(), c);
     }
+
+    #[test]
+    fn checked_numeric() {
+        let big = i64::max_value();
+        assert_eq!(TVMArgValue::from(&big).to_i64().unwrap(), big);
+        assert!(TVMArgValue::from(&big).to_i32().is_err());
+        assert!(TVMArgValue::from(&-1).to_usize().is_err());
+        assert!(TVMArgValue::from(&-1).to_u32().is_err());
+        assert_eq!(TVMRetValue::from(&7u32).to_usize().unwrap(), 7);
+        assert_eq!(TVMArgValue::from(&u64::max_value()).to_u64().unwrap(), u64::max_value());
+        assert!(TVMArgValue::from(&u64::max_value()).to_i64().is_err());
+        assert!(TVMArgValue::from(&1.5f64).to_i64().is_err());
+        assert_eq!(TVMArgValue::from(&1.5f64).to_f32().unwrap(), 1.5f32);
+        assert!(TVMArgValue::from(&1e300f64).to_f32().is_err());
+        assert!(TVMArgValue::from(&1).to_f64().is_err());
+    }
+
+    #[test]
+    fn typed_values() {
+        let s = CString::new("abc").unwrap();
+        match TVMArgValue::from(&s).as_value() {
+            ArgValue::Str(v) => assert_eq!(v, s.as_c_str()),
+            other => panic!("expected a string, but found {:?}", other),
+        }
+        match TVMArgValue::from(&u64::max_value()).as_value() {
+            ArgValue::UInt(v) => assert_eq!(v, u64::max_value()),
+            other => panic!("expected an unsigned int, but found {:?}", other),
+        }
+        match TVMRetValue::from(&1.5f64).try_into_value().unwrap() {
+            RetValue::Float(v) => assert_eq!(v, 1.5),
+            other => panic!("expected a float, but found {:?}", other),
+        }
+        let ctx = TVMContext::cpu(0);
+        let arg = TVMArgValue::from(&ArgValue::Context(ctx));
+        assert_eq!(arg.type_code, TypeCode::kTVMContext);
+        assert_eq!(arg.to_ctx(), ctx);
+        let arg = TVMArgValue::from(&ArgValue::Str(&s));
+        assert_eq!(arg.to_string(), "abc");
+    }
+
+    #[test]
+    fn array_type_codes() {
+        let nd = ::empty(&mut [2], TVMContext::cpu(0), i64::data_type());
+        let arg = nd.as_dltensor_arg();
+        assert_eq!(arg.type_code, TypeCode::kArrayHandle);
+        assert_eq!(TVMArgValue::from(&arg).type_code, TypeCode::kArrayHandle);
+        let container = TVMArgValue::from(&nd);
+        assert_eq!(container.type_code, TypeCode::kNDArrayContainer);
+        let view = NDArray::from_arg_value(&container).unwrap();
+        assert_eq!((view.handle(), view.is_view()), (nd.handle(), true));
+        assert_eq!(TVMArgValue::from(&view).type_code, TypeCode::kArrayHandle);
+    }
+
+    #[test]
+    fn ret_handle_ownership() {
+        let nd = ::empty(&mut [2], TVMContext::cpu(0), i64::data_type());
+        let inner = ts::TVMValue {
+            v_handle: nd.handle() as *mut c_void,
+        };
+        mem::forget(nd);
+        let ret = TVMRetValue::from_return(inner, TypeCode::kArrayHandle);
+        let clone = ret.clone();
+        assert!(ret.try_into_ndarray().is_err());
+        let nd = clone.try_into_ndarray().unwrap();
+        assert!(!nd.is_view());
+        assert!(TVMRetValue::from(&nd).try_into_ndarray().unwrap().is_view());
+        assert!(TVMRetValue::from(&1).try_into_module().is_err());
+    }
+
+    #[test]
+    fn from_arg_value() {
+        assert_eq!(i64::from_arg_value(&TVMArgValue::from(&42)).unwrap(), 42);
+        assert_eq!(f64::from_arg_value(&TVMArgValue::from(&1.5f64)).unwrap(), 1.5);
+        assert!(f64::from_arg_value(&TVMArgValue::from(&42)).is_err());
+        assert!(String::from_arg_value(&TVMArgValue::from(&42)).is_err());
+        assert_eq!(().into_ret_value().type_code, TypeCode::kNull);
+    }
+
+    #[test]
+    fn multiple_values() {
+        match TVMRetValue::from(&7).into_vec().unwrap()[..] {
+            [RetValue::Int(7)] => (),
+            ref other => panic!("expected a single int, got {:?}", other),
+        }
+        let arg = TVMArgValue::from(&RetValue::Str("abc".to_owned()));
+        assert_eq!(arg.to_string(), "abc");
+        assert_eq!(TVMArgValue::from(&RetValue::Float(0.5)).type_code, TypeCode::kDLFloat);
+    }
 }
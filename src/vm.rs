@@ -0,0 +1,279 @@
+//! Inspects executables of the Relay virtual machine, e.g. to verify the names and the
+//! parameters of their functions before invoking them.
+//!
+//! An executable is saved by the compiler as its bytecode and a library of the kernels.
+//! The global functions loading and listing it are registered by runtimes built with
+//! the VM, otherwise they fail with `ErrorKind::NullHandle`.
+//!
+//! A [`VirtualMachine`] runs the functions of an executable. Their results are tensors
+//! or ADT objects such as tuples, unpacked recursively into [`VmOutput`]s.
+//!
+//! # Example
+//!
+//! ```
+//! let lib = Module::load(Path::new("vm_lib.so")).unwrap();
+//! let exe = Executable::load(&fs::read("vm_code.ro").unwrap(), &lib).unwrap();
+//! println!("{}", exe.stats().unwrap());
+//! for name in exe.globals().unwrap() {
+//!     println!("{}({:?})", name, exe.function_params(&name).unwrap());
+//! }
+//! let vm = VirtualMachine::new(&exe, &TVMContext::cpu(0)).unwrap();
+//! // (boxes, scores, classes) of an object detection model
+//! let detections = vm.invoke("main", &[&image]).unwrap().into_tensors();
+//! ```
+//!
+//! [`VirtualMachine`]:struct.VirtualMachine.html
+//! [`VmOutput`]:enum.VmOutput.html
+
+use function::{self, Builder};
+use value::{self, ObjectHandle, ADT_FIELDS_FUNCTIONS, ADT_SIZE_FUNCTIONS};
+use ArgValue;
+use ErrorKind;
+use Module;
+use NDArray;
+use Result;
+use RetValue;
+use TVMByteArray;
+use TVMContext;
+use TVMRetValue;
+
+const LOAD_EXECUTABLE_FUNCTIONS: [&'static str; 2] =
+    ["runtime.Load_Executable", "relay._vm.Load_Executable"];
+const NUM_GLOBALS_FUNCTIONS: [&'static str; 2] =
+    ["runtime.GetNumOfGlobals", "relay._vm.GetNumOfGlobals"];
+const GLOBAL_FIELDS_FUNCTIONS: [&'static str; 2] =
+    ["runtime.GetGlobalFields", "relay._vm.GetGlobalFields"];
+const CREATE_VM_FUNCTIONS: [&'static str; 2] =
+    ["runtime._VirtualMachine", "relay._vm._VirtualMachine"];
+const ADT_TAG_FUNCTIONS: [&'static str; 2] = ["runtime.GetADTTag", "_vmobj.GetADTTag"];
+
+// The pooled allocator of the VM.
+const POOLED_ALLOCATOR: i64 = 2;
+
+/// The type key of executable modules.
+pub const EXECUTABLE_TYPE_KEY: &'static str = "VMExecutable";
+
+/// A compiled program of the Relay virtual machine.
+#[derive(Debug, Clone)]
+pub struct Executable {
+    module: Module,
+}
+
+impl Executable {
+    /// Loads an executable from its saved bytecode and the library of its kernels.
+    pub fn load(bytecode: &[u8], lib: &Module) -> Result<Executable> {
+        let load_fn = function::get_first_global_func(&LOAD_EXECUTABLE_FUNCTIONS)?;
+        let bytecode = bytecode.to_vec();
+        let module = call_packed!(load_fn, &TVMByteArray::from(&bytecode), lib)?;
+        Executable::from_module(module.try_into_module()?)
+    }
+
+    /// Wraps an executable module, failing for modules of other types.
+    pub fn from_module(module: Module) -> Result<Executable> {
+        let type_key = module.type_key()?;
+        if type_key != EXECUTABLE_TYPE_KEY {
+            bail!(ErrorKind::TypeMismatch(EXECUTABLE_TYPE_KEY.to_owned(), type_key));
+        }
+        Ok(Executable { module })
+    }
+
+    pub fn module(&self) -> &Module {
+        &self.module
+    }
+
+    /// Returns the statistics of the executable, such as its numbers of constants and
+    /// kernels and the sizes of its functions, as printed by TVM.
+    pub fn stats(&self) -> Result<String> {
+        let stats_fn = self.module.get_function("get_stats", false)?;
+        value::into_string(call_packed!(stats_fn,)?)
+    }
+
+    /// Returns the bytecode of every function in the text format.
+    pub fn bytecode(&self) -> Result<String> {
+        let bytecode_fn = self.module.get_function("get_bytecode", false)?;
+        value::into_string(call_packed!(bytecode_fn,)?)
+    }
+
+    /// Returns the names of the functions, in the order of their indices.
+    pub fn globals(&self) -> Result<Vec<String>> {
+        let num_fn = function::get_first_global_func(&NUM_GLOBALS_FUNCTIONS)?;
+        let num_globals = call_packed!(num_fn, &self.module)?.to_int();
+        let fields_fn = function::get_first_global_func(&GLOBAL_FIELDS_FUNCTIONS)?;
+        (0..num_globals)
+            .map(|i| {
+                let fields_fn = fields_fn.clone();
+                value::into_string(call_packed!(fields_fn, &self.module, &i)?)
+            })
+            .collect()
+    }
+
+    /// Returns the names of the parameters of a function, failing if it does not exist.
+    pub fn function_params(&self, name: &str) -> Result<Vec<String>> {
+        let arity_fn = self.module.get_function("get_function_arity", false)?;
+        let arity = call_packed!(arity_fn, name)?.to_int();
+        if arity < 0 {
+            bail!("function `{}` not found in the executable", name);
+        }
+        let param_fn = self.module.get_function("get_function_param_name", false)?;
+        (0..arity)
+            .map(|i| {
+                let param_fn = param_fn.clone();
+                value::into_string(call_packed!(param_fn, name, &i)?)
+            })
+            .collect()
+    }
+}
+
+/// A virtual machine running the functions of an [`Executable`] on a device.
+///
+/// [`Executable`]:struct.Executable.html
+#[derive(Debug, Clone)]
+pub struct VirtualMachine {
+    module: Module,
+}
+
+impl VirtualMachine {
+    /// Creates a virtual machine for the executable, allocating on `ctx` with the pooled
+    /// allocator.
+    pub fn new(exe: &Executable, ctx: &TVMContext) -> Result<VirtualMachine> {
+        let create_fn = function::get_first_global_func(&CREATE_VM_FUNCTIONS)?;
+        let module = call_packed!(create_fn, &exe.module)?.try_into_module()?;
+        let init_fn = module.get_function("init", false)?;
+        call_packed!(init_fn, &ctx.device_type.0, &ctx.device_id, &POOLED_ALLOCATOR)?;
+        Ok(VirtualMachine { module })
+    }
+
+    /// Runs a function of the executable with the arrays as its arguments and unpacks
+    /// its result.
+    pub fn invoke(&self, func_name: &str, args: &[&NDArray]) -> Result<VmOutput> {
+        let mut set_input = Builder::from(self.module.get_function("set_input", false)?);
+        set_input.arg(func_name);
+        for arg in args {
+            set_input.arg(*arg);
+        }
+        set_input.invoke()?;
+        let invoke_fn = self.module.get_function("invoke", false)?;
+        VmOutput::unpack(call_packed!(invoke_fn, func_name)?)
+    }
+}
+
+/// A result of a [`VirtualMachine`], with tensors as leaves of nested ADT objects.
+///
+/// Tuples are ADT objects with the tag `0`. The ADT objects are released once unpacked,
+/// while their tensors stay alive in the output.
+///
+/// [`VirtualMachine`]:struct.VirtualMachine.html
+#[derive(Debug)]
+pub enum VmOutput {
+    Tensor(NDArray),
+    Adt { tag: i64, fields: Vec<VmOutput> },
+    /// Any other returned value, e.g. an object of another type.
+    Other(RetValue),
+}
+
+impl VmOutput {
+    // Unpacks a returned value, recursing into the fields of ADT objects.
+    fn unpack(ret: TVMRetValue) -> Result<VmOutput> {
+        let handle = match ret.try_into_value()? {
+            RetValue::NDArray(tensor) => return Ok(VmOutput::Tensor(tensor)),
+            RetValue::Node(handle) => handle,
+            other => return Ok(VmOutput::Other(other)),
+        };
+        let node = ArgValue::Node(handle);
+        let size_fn = function::get_first_global_func(&ADT_SIZE_FUNCTIONS)?;
+        let size = match Builder::from(size_fn).arg(&node).invoke_catching() {
+            Ok(size) => size.to_int(),
+            // not an ADT object
+            Err(_) => return Ok(VmOutput::Other(RetValue::Node(handle))),
+        };
+        // the fields hold their own references, so the object is released once unpacked
+        let _adt = ObjectHandle::new(handle);
+        let tag_fn = function::get_first_global_func(&ADT_TAG_FUNCTIONS)?;
+        let tag = call_packed!(tag_fn, &node)?.to_int();
+        let fields = (0..size)
+            .map(|i| {
+                let fields_fn = function::get_first_global_func(&ADT_FIELDS_FUNCTIONS)?;
+                VmOutput::unpack(call_packed!(fields_fn, &node, &i)?)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(VmOutput::Adt { tag, fields })
+    }
+
+    /// Returns the fields of an ADT object, or `None` for other outputs.
+    pub fn fields(&self) -> Option<&[VmOutput]> {
+        match *self {
+            VmOutput::Adt { ref fields, .. } => Some(fields),
+            _ => None,
+        }
+    }
+
+    /// Returns the tensors of the output in depth-first order, e.g. the elements of a
+    /// tuple, skipping other values.
+    pub fn into_tensors(self) -> Vec<NDArray> {
+        let mut tensors = Vec::new();
+        self.collect_tensors(&mut tensors);
+        tensors
+    }
+
+    fn collect_tensors(self, tensors: &mut Vec<NDArray>) {
+        match self {
+            VmOutput::Tensor(tensor) => tensors.push(tensor),
+            VmOutput::Adt { fields, .. } => {
+                for field in fields {
+                    field.collect_tensors(tensors);
+                }
+            }
+            VmOutput::Other(_) => (),
+        }
+    }
+
+    /// Converts the output into `RetValue`s, one per field of an ADT object, and a single
+    /// value otherwise. Nested ADT objects are flattened into their values.
+    pub fn into_values(self) -> Vec<RetValue> {
+        match self {
+            VmOutput::Tensor(tensor) => vec![RetValue::NDArray(tensor)],
+            VmOutput::Other(value) => vec![value],
+            VmOutput::Adt { fields, .. } => {
+                fields.into_iter().flat_map(VmOutput::into_values).collect()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_ndarray;
+    use ty::TypeCode;
+    use value::TVMValue;
+    use DataType;
+
+    fn tensor(v: f32) -> NDArray {
+        let arr = rust_ndarray::arr1(&[v]).into_dyn();
+        NDArray::from_rust_ndarray(&arr, TVMContext::cpu(0), DataType::from("float")).unwrap()
+    }
+
+    #[test]
+    fn tensors() {
+        let output = VmOutput::Adt {
+            tag: 0,
+            fields: vec![
+                VmOutput::Tensor(tensor(1.0)),
+                VmOutput::Adt {
+                    tag: 0,
+                    fields: vec![VmOutput::Tensor(tensor(2.0)), VmOutput::Other(RetValue::Int(3))],
+                },
+            ],
+        };
+        assert_eq!(output.fields().map(<[_]>::len), Some(2));
+        let tensors = output.into_tensors();
+        assert_eq!(tensors.len(), 2);
+        assert_eq!(tensors[1].to_vec::<f32>().unwrap(), vec![2.0]);
+    }
+
+    #[test]
+    fn non_string_return() {
+        let ret = TVMRetValue::new(TVMValue::from(&1i64), TypeCode::kDLInt);
+        assert!(value::into_string(ret).is_err());
+    }
+}
@@ -0,0 +1,288 @@
+//! Ready-to-use models combining the building blocks of the crate, enabled by the `image`
+//! feature.
+//!
+//! An [`ImageClassifier`] loads the [`Artifacts`] of an ImageNet-style classifier, e.g.
+//! the ones of the `resnet` example, pre-processes images with a [`Preprocessor`] matching
+//! the input of the graph, runs them on a [`GraphRuntimePool`] and decodes the `k` best
+//! classes with their labels. It can be shared between threads.
+//!
+//! # Example
+//!
+//! ```
+//! let classifier = ImageClassifier::from_dir(Path::new("resnet"), TVMContext::cpu(0), 4)
+//!     .unwrap();
+//! let img = image::open("cat.png").unwrap();
+//! for prediction in classifier.classify(&img, 5).unwrap() {
+//!     println!("{}", prediction);
+//! }
+//! ```
+//!
+//! [`ImageClassifier`]:struct.ImageClassifier.html
+//! [`Artifacts`]:../graph_runtime/struct.Artifacts.html
+//! [`Preprocessor`]:../preprocess/struct.Preprocessor.html
+//! [`GraphRuntimePool`]:../graph_runtime/struct.GraphRuntimePool.html
+
+use std::{
+    fmt::{self, Display, Formatter},
+    fs,
+    path::Path,
+};
+
+use image::DynamicImage;
+
+use graph::{GraphJson, Params};
+use graph_runtime::GraphRuntimePool;
+use metadata::{Metadata, TensorInfo};
+use ops;
+use preprocess::{Layout, Preprocessor, IMAGENET_MEAN, IMAGENET_STD};
+use Artifacts;
+use Result;
+use TVMContext;
+
+// The files of a model directory, as saved by the `resnet` example.
+const GRAPH_FILE: &'static str = "deploy_graph.json";
+const LIB_FILE: &'static str = "deploy_lib.so";
+const PARAMS_FILE: &'static str = "deploy_param.params";
+const LABEL_FILES: [&'static str; 2] = ["synset.csv", "synset.txt"];
+
+/// A class predicted by an [`ImageClassifier`].
+///
+/// [`ImageClassifier`]:struct.ImageClassifier.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prediction {
+    /// The index of the class in the output of the model.
+    pub class: usize,
+    /// The label of the class, if the classifier has one.
+    pub label: Option<String>,
+    /// The probability of the class, or its raw score if the softmax is disabled.
+    pub score: f64,
+}
+
+impl Display for Prediction {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.label {
+            Some(ref label) => write!(f, "{} ({}): {:.4}", label, self.class, self.score),
+            None => write!(f, "class {}: {:.4}", self.class, self.score),
+        }
+    }
+}
+
+/// Classifies images with a model taking a single RGB image as a `[1, 3, h, w]` or
+/// `[1, h, w, 3]` input and returning the scores of the classes as its first output.
+///
+/// By default the images are resized to `8 / 7` of the input size, center-cropped and
+/// normalized with the ImageNet statistics, and the softmax of the scores is returned.
+#[derive(Debug)]
+pub struct ImageClassifier {
+    pool: GraphRuntimePool,
+    input: String,
+    preprocessor: Preprocessor,
+    labels: Vec<String>,
+    softmax: bool,
+}
+
+impl ImageClassifier {
+    /// Creates `pool_size` executors of the model on the given context, without labels.
+    ///
+    /// The input and its pre-processing are the ones of the [`Metadata`] of the artifacts
+    /// if any. Otherwise the input is the first input of the graph which is not a
+    /// parameter.
+    ///
+    /// [`Metadata`]:../metadata/struct.Metadata.html
+    pub fn new(artifacts: &Artifacts, ctx: TVMContext, pool_size: usize) -> Result<Self> {
+        let metadata = match artifacts.metadata() {
+            Some(metadata) => metadata.clone(),
+            None if artifacts.params.is_empty() => {
+                Metadata::from_graph(&GraphJson::parse(&artifacts.graph)?, &Params::default())
+            }
+            None => Metadata::from_graph(
+                &GraphJson::parse(&artifacts.graph)?,
+                &Params::parse(&artifacts.params)?,
+            ),
+        };
+        let input = match metadata.inputs.first() {
+            Some(input) => input,
+            None => bail!("the graph has no input besides its parameters"),
+        };
+        let preprocessor = match metadata.preprocess {
+            Some(ref preprocess) => preprocess.to_preprocessor(),
+            None => default_preprocessor(input)?,
+        };
+        Ok(ImageClassifier {
+            pool: GraphRuntimePool::new(artifacts, ctx, pool_size)?,
+            input: input.name.clone(),
+            preprocessor,
+            labels: Vec::new(),
+            softmax: true,
+        })
+    }
+
+    /// Loads the model saved in a directory as `deploy_graph.json`, `deploy_lib.so` and
+    /// `deploy_param.params`, with the labels of `synset.csv` or `synset.txt` if present.
+    pub fn from_dir(dir: &Path, ctx: TVMContext, pool_size: usize) -> Result<Self> {
+        let artifacts = Artifacts::from_files(
+            dir.join(GRAPH_FILE),
+            dir.join(LIB_FILE),
+            dir.join(PARAMS_FILE),
+        )?;
+        let mut classifier = ImageClassifier::new(&artifacts, ctx, pool_size)?;
+        if let Some(path) = LABEL_FILES
+            .iter()
+            .map(|file| dir.join(file))
+            .find(|path| path.is_file())
+        {
+            classifier.labels(read_labels(&path)?);
+        }
+        Ok(classifier)
+    }
+
+    /// Sets the labels of the classes, in the order of the output.
+    pub fn labels(&mut self, labels: Vec<String>) -> &mut Self {
+        self.labels = labels;
+        self
+    }
+
+    /// Replaces the default pre-processing, e.g. for a model trained with other statistics.
+    pub fn preprocessor(&mut self, preprocessor: Preprocessor) -> &mut Self {
+        self.preprocessor = preprocessor;
+        self
+    }
+
+    /// Sets whether the softmax is applied to the scores, e.g. disabled for models whose
+    /// output already is a probability. Default is `true`.
+    pub fn softmax(&mut self, softmax: bool) -> &mut Self {
+        self.softmax = softmax;
+        self
+    }
+
+    /// Returns the name of the image input of the graph.
+    pub fn input_name(&self) -> &str {
+        &self.input
+    }
+
+    /// Returns the pool running the model, e.g. to swap it with [`swap_model`].
+    ///
+    /// [`swap_model`]:../graph_runtime/struct.GraphRuntimePool.html#method.swap_model
+    pub fn pool(&self) -> &GraphRuntimePool {
+        &self.pool
+    }
+
+    /// Returns the `k` most likely classes of the image in descending order, blocking
+    /// until an executor of the pool is available.
+    pub fn classify(&self, img: &DynamicImage, k: usize) -> Result<Vec<Prediction>> {
+        let input = self.preprocessor.to_ndarray(img, self.pool.ctx().clone())?;
+        let (name, softmax) = (&self.input, self.softmax);
+        let mut top = self.pool.run(|runtime| {
+            runtime.set_input(name, &input)?;
+            runtime.run()?;
+            let scores = runtime.get_output_view(0)?;
+            if softmax {
+                ops::top_k(&ops::softmax(&scores)?, k)
+            } else {
+                ops::top_k(&scores, k)
+            }
+        })?;
+        if top.len() != 1 {
+            bail!("expected the scores of a single image, found {}", top.len());
+        }
+        Ok(top
+            .remove(0)
+            .into_iter()
+            .map(|(class, score)| Prediction {
+                class,
+                label: self.labels.get(class).cloned(),
+                score,
+            })
+            .collect())
+    }
+}
+
+// Derives the ImageNet pre-processing of an image input from its shape.
+fn default_preprocessor(input: &TensorInfo) -> Result<Preprocessor> {
+    let (layout, height, width) = match input.shape[..] {
+        [1, 3, h, w] => (Layout::NCHW, h, w),
+        [1, h, w, 3] => (Layout::NHWC, h, w),
+        _ => bail!(
+            "expected an RGB image input, found `{}` of shape {:?}",
+            input.name,
+            input.shape
+        ),
+    };
+    let (width, height) = (width as u32, height as u32);
+    let mut preprocessor = Preprocessor::new();
+    preprocessor
+        .resize(width * 8 / 7, height * 8 / 7)
+        .center_crop(width, height)
+        .normalize(IMAGENET_MEAN, IMAGENET_STD)
+        .layout(layout);
+    Ok(preprocessor)
+}
+
+/// Reads the labels of the classes from a file with one label per line.
+///
+/// Lines of the form `<class>,<label>`, as written by the `resnet` example, put the label
+/// at the index of the class and may quote it with `"`.
+pub fn read_labels(path: &Path) -> Result<Vec<String>> {
+    Ok(parse_labels(&fs::read_to_string(path)?))
+}
+
+fn parse_labels(text: &str) -> Vec<String> {
+    let mut labels = Vec::new();
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        let mut fields = line.splitn(2, ',');
+        let class = fields.next().and_then(|class| class.trim().parse::<usize>().ok());
+        match (class, fields.next()) {
+            (Some(class), Some(label)) => {
+                if labels.len() <= class {
+                    labels.resize(class + 1, String::new());
+                }
+                labels[class] = label.trim().trim_matches('"').to_owned();
+            }
+            _ => labels.push(line.trim().to_owned()),
+        }
+    }
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::RgbImage;
+
+    #[test]
+    fn labels() {
+        let csv = "0,\"tench, Tinca tinca\"\n2,goldfish\n";
+        assert_eq!(parse_labels(csv), vec!["tench, Tinca tinca", "", "goldfish"]);
+        assert_eq!(parse_labels("cat\n\ndog\n"), vec!["cat", "dog"]);
+    }
+
+    #[test]
+    fn preprocessing() {
+        let info = |shape: Vec<usize>| TensorInfo {
+            name: "data".to_owned(),
+            shape,
+            dtype: "float32".to_owned(),
+        };
+        let img = DynamicImage::ImageRgb8(RgbImage::new(300, 200));
+        let nchw = default_preprocessor(&info(vec![1, 3, 224, 224])).unwrap();
+        let nd = nchw.to_ndarray(&img, TVMContext::cpu(0)).unwrap();
+        assert_eq!(nd.shape(), &[1, 3, 224, 224]);
+        let nhwc = default_preprocessor(&info(vec![1, 32, 64, 3])).unwrap();
+        let nd = nhwc.to_ndarray(&img, TVMContext::cpu(0)).unwrap();
+        assert_eq!(nd.shape(), &[1, 32, 64, 3]);
+        assert!(default_preprocessor(&info(vec![1, 1000])).is_err());
+    }
+
+    #[test]
+    fn display() {
+        let mut prediction = Prediction {
+            class: 281,
+            label: Some("tabby cat".to_owned()),
+            score: 0.5,
+        };
+        assert_eq!(prediction.to_string(), "tabby cat (281): 0.5000");
+        prediction.label = None;
+        assert_eq!(prediction.to_string(), "class 281: 0.5000");
+    }
+}
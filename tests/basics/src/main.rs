@@ -8,22 +8,21 @@ use tvm::*;
 fn main() {
     println!("start integration test");
     let shape = &mut [2];
-    let mut data = vec![3f32, 4.0];
+    let data = vec![3f32, 4.0];
 
     if cfg!(feature = "cpu") {
         println!("cpu test");
-        let mut arr = empty(shape, TVMContext::cpu(0), TVMType::from("float"));
+        let mut arr = empty(shape, TVMContext::cpu(0), DataType::from("float"));
 
-        arr.copy_from_buffer(data.as_mut_slice());
+        arr.copy_from_buffer(&data).unwrap();
 
-        let mut ret = empty(shape, TVMContext::cpu(0), TVMType::from("float"));
+        let mut ret = empty(shape, TVMContext::cpu(0), DataType::from("float"));
         let path = Path::new("add_cpu.so");
-        let mut fadd = Module::load(&path).unwrap();
+        let fadd = Module::load(&path).unwrap();
         assert!(fadd.enabled("cpu"));
-        fadd.entry_func();
-        function::Builder::from(&mut fadd)
-            .arg(&arr)
-            .arg(&arr)
+        function::Builder::from(&fadd)
+            .arg(&arr.as_dltensor_arg())
+            .arg(&arr.as_dltensor_arg())
             .set_output(&mut ret)
             .invoke()
             .unwrap();
@@ -34,21 +33,20 @@ fn main() {
 
     if cfg!(feature = "gpu") {
         println!("gpu test");
-        let mut arr = empty(shape, TVMContext::gpu(0), TVMType::from("float"));
+        let mut arr = empty(shape, TVMContext::gpu(0), DataType::from("float"));
 
-        arr.copy_from_buffer(data.as_mut_slice());
+        arr.copy_from_buffer(&data).unwrap();
 
-        let mut ret = empty(shape, TVMContext::gpu(0), TVMType::from("float"));
+        let mut ret = empty(shape, TVMContext::gpu(0), DataType::from("float"));
         let path = Path::new("add_gpu.so");
         let ptx = Path::new("add_gpu.ptx");
-        let mut fadd = Module::load(path).unwrap();
+        let fadd = Module::load(path).unwrap();
         let fadd_dep = Module::load(ptx).unwrap();
         assert!(fadd.enabled("gpu"));
-        fadd.import_module(fadd_dep);
-        fadd.entry_func();
-        function::Builder::from(&mut fadd)
-            .arg(&arr)
-            .arg(&arr)
+        fadd.import_module(fadd_dep).unwrap();
+        function::Builder::from(&fadd)
+            .arg(&arr.as_dltensor_arg())
+            .arg(&arr.as_dltensor_arg())
             .set_output(&mut ret)
             .invoke()
             .unwrap();
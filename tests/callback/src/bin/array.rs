@@ -17,7 +17,7 @@ fn main() {
             let mut ret = 0f32;
             let shape = &mut [2];
             for arg in args.iter() {
-                let e = empty(shape, TVMContext::cpu(0), TVMType::from("float"));
+                let e = empty(shape, TVMContext::cpu(0), DataType::from("float"));
                 let arr = arg.to_ndarray().copy_to_ndarray(e).unwrap();
                 let rnd: ArrayD<f32> = ArrayD::try_from(&arr).unwrap();
                 ret += rnd.scalar_sum();
@@ -28,9 +28,9 @@ fn main() {
     }
 
     let shape = &mut [2];
-    let mut data = vec![3f32, 4.0];
-    let mut arr = empty(shape, TVMContext::cpu(0), TVMType::from("float"));
-    arr.copy_from_buffer(data.as_mut_slice());
+    let data = vec![3f32, 4.0];
+    let mut arr = empty(shape, TVMContext::cpu(0), DataType::from("float"));
+    arr.copy_from_buffer(&data).unwrap();
 
     let mut registered = function::Builder::default();
     registered.get_function("sum", true).arg(&arr).arg(&arr);
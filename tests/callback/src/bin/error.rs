@@ -19,7 +19,7 @@ fn main() {
 
     let mut registered = function::Builder::default();
     registered.get_function("error", true);
-    assert!(registered.func.is_some());
+    assert!(registered.function().is_some());
     registered.args(&[10, 20]);
 
     println!("expected error message is:");
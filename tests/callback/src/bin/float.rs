@@ -20,7 +20,7 @@ fn main() {
 
     let mut registered = function::Builder::default();
     registered.get_function("sum", true);
-    assert!(registered.func.is_some());
+    assert!(registered.function().is_some());
     registered.args(&[10f64, 20f64, 30f64]);
     assert_eq!(registered.invoke().unwrap().to_float(), 60f64);
 }
@@ -18,7 +18,7 @@ fn main() {
 
     let mut registered = function::Builder::default();
     registered.get_function("mysum", true);
-    assert!(registered.func.is_some());
+    assert!(registered.function().is_some());
     registered.args(&[10, 20, 30]);
     assert_eq!(registered.invoke().unwrap().to_int(), 60);
 }
@@ -19,7 +19,7 @@ fn main() {
     }
     let mut registered = function::Builder::default();
     registered.get_function("concate_str", true);
-    assert!(registered.func.is_some());
+    assert!(registered.function().is_some());
     let a = "a".to_string();
     let b = "b".to_string();
     let c = "c".to_string();
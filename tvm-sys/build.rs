@@ -1,6 +1,12 @@
 extern crate bindgen;
 
-use std::{env, error::Error, path::PathBuf, process, result::Result};
+use std::{
+    env,
+    error::Error,
+    path::{Path, PathBuf},
+    process,
+    result::Result,
+};
 
 const TVM_RUNTIME: &'static str = "tvm_runtime";
 
@@ -14,14 +20,56 @@ fn main() {
     }
 }
 
+// Links the `libtvm_runtime.a` archive given by `TVM_RUNTIME_STATIC_LIB` or else built in
+// `$TVM_HOME/build`, together with the C++ standard library, which is linked statically
+// as well for musl targets so that the binaries are self-contained.
+fn link_static(tvm_home: &str) -> Result<(), Box<dyn Error>> {
+    println!("cargo:rerun-if-env-changed=TVM_RUNTIME_STATIC_LIB");
+    let archive = match env::var_os("TVM_RUNTIME_STATIC_LIB") {
+        Some(archive) => PathBuf::from(archive),
+        None => Path::new(tvm_home).join("build").join(format!("lib{}.a", TVM_RUNTIME)),
+    };
+    if !archive.is_file() {
+        return Err(format!(
+            "cannot find the static runtime library {}, build it with \
+             `make runtime` and `-DBUILD_STATIC_RUNTIME=ON` or set `TVM_RUNTIME_STATIC_LIB`",
+            archive.display()
+        ).into());
+    }
+    let dir = archive.parent().ok_or("invalid static runtime library path")?;
+    println!("cargo:rustc-link-search=native={}", dir.display());
+    println!("cargo:rustc-link-lib=static={}", TVM_RUNTIME);
+    if env::var("CARGO_CFG_TARGET_ENV")? == "musl" {
+        println!("cargo:rustc-link-lib=static=stdc++");
+    } else {
+        println!("cargo:rustc-link-lib=dylib=stdc++");
+        println!("cargo:rustc-link-lib=dylib=dl");
+        println!("cargo:rustc-link-lib=dylib=pthread");
+    }
+    Ok(())
+}
+
 fn run() -> Result<(), Box<dyn Error>> {
-    println!("cargo:rustc-link-lib=dylib={}", TVM_RUNTIME);
-    let lib = format!("lib{}", TVM_RUNTIME);
-    println!("cargo:rustc-link-search=native={}", lib);
     let tvm_home = env::var("TVM_HOME").expect("TVM_HOME not found!");
+    let dynamic = env::var_os("CARGO_FEATURE_DYNAMIC").is_some();
+    let static_ = env::var_os("CARGO_FEATURE_STATIC").is_some();
+    if dynamic && static_ {
+        return Err("the `dynamic` and `static` features are mutually exclusive".into());
+    }
+    if static_ {
+        link_static(&tvm_home)?;
+    } else if !dynamic {
+        // with the `dynamic` feature the runtime library is opened at run time instead,
+        // e.g. from the native libraries packaged in an Android APK
+        println!("cargo:rustc-link-lib=dylib={}", TVM_RUNTIME);
+        let lib = format!("lib{}", TVM_RUNTIME);
+        println!("cargo:rustc-link-search=native={}", lib);
+    }
     let bindings = bindgen::Builder::default()
         .header(format!("{}/include/tvm/runtime/c_runtime_api.h", tvm_home))
         .clang_arg(format!("-I{}/3rdparty/dlpack/include/", tvm_home))
+        // generate the layouts of the target when cross-compiling, e.g. for Android
+        .clang_arg(format!("--target={}", env::var("TARGET")?))
         .blacklist_type("max_align_t") // https://github.com/rust-lang-nursery/rust-bindgen/issues/550
         .layout_tests(false)
         .derive_partialeq(true)
@@ -1,3 +1,9 @@
+//! Raw bindings to the TVM C runtime API and DLPack, together with the type codes
+//! of packed function arguments and their conversions from Rust primitives.
+//!
+//! This crate links `libtvm_runtime` and has no safe abstractions, which are all in
+//! `tvm-frontend`.
+
 #![allow(
     non_camel_case_types,
     non_snake_case,
@@ -6,4 +12,149 @@
     improper_ctypes
 )]
 
+use std::{
+    ffi::{CStr, CString},
+    fmt::{self, Display, Formatter},
+};
+
 include!("bindgen.rs");
+
+/// TVM Type codes
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TypeCode {
+    kDLInt = 0,
+    kDLUInt = 1,
+    kDLFloat = 2,
+    kHandle = 3,
+    kNull = 4,
+    kTVMType = 5,
+    kTVMContext = 6,
+    /// A `DLTensor*`, as taken by generated kernels. Named `kTVMDLTensorHandle` in newer
+    /// runtimes.
+    kArrayHandle = 7,
+    kNodeHandle = 8,
+    kModuleHandle = 9,
+    kFuncHandle = 10,
+    kStr = 11,
+    kBytes = 12,
+    /// A pointer to an `NDArray::Container`, which starts with its `DLTensor`.
+    kNDArrayContainer = 13,
+}
+
+impl Default for TypeCode {
+    fn default() -> Self {
+        TypeCode::kDLInt
+    }
+}
+
+impl<'a> Into<TypeCode> for i32 {
+    fn into(self) -> TypeCode {
+        match self {
+            0 => TypeCode::kDLInt,
+            1 => TypeCode::kDLUInt,
+            2 => TypeCode::kDLFloat,
+            3 => TypeCode::kHandle,
+            4 => TypeCode::kNull,
+            5 => TypeCode::kTVMType,
+            6 => TypeCode::kTVMContext,
+            7 => TypeCode::kArrayHandle,
+            8 => TypeCode::kNodeHandle,
+            9 => TypeCode::kModuleHandle,
+            10 => TypeCode::kFuncHandle,
+            11 => TypeCode::kStr,
+            12 => TypeCode::kBytes,
+            13 => TypeCode::kNDArrayContainer,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Display for TypeCode {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                TypeCode::kDLInt => "int",
+                TypeCode::kDLUInt => "uint",
+                TypeCode::kDLFloat => "float",
+                TypeCode::kHandle => "handle",
+                TypeCode::kNull => "null",
+                TypeCode::kTVMType => "TVM type",
+                TypeCode::kTVMContext => "TVM context",
+                TypeCode::kArrayHandle => "Array handle",
+                TypeCode::kNodeHandle => "Node handle",
+                TypeCode::kModuleHandle => "Module handle",
+                TypeCode::kFuncHandle => "Function handle",
+                TypeCode::kStr => "string",
+                TypeCode::kBytes => "bytes",
+                TypeCode::kNDArrayContainer => "NDArray container",
+            }
+        )
+    }
+}
+
+/// Implements `From<&T>` and `From<&mut T>` for [`TypeCode`] mapping `T` to the given variant.
+#[macro_export]
+macro_rules! impl_prim_type {
+    ($type:ty, $variant:ident) => {
+        impl<'a> From<&'a $type> for $crate::TypeCode {
+            fn from(_arg: &$type) -> Self {
+                $crate::TypeCode::$variant
+            }
+        }
+
+        impl<'a> From<&'a mut $type> for $crate::TypeCode {
+            fn from(_arg: &mut $type) -> Self {
+                $crate::TypeCode::$variant
+            }
+        }
+    };
+}
+
+// Implements `From<&T>` and `From<&mut T>` for `TypeCode` of an unsigned integer type,
+// mapping the values which fit in `int64_t`, the integer type of the runtime, to `kDLInt`
+// and the larger ones to `kDLUInt`.
+macro_rules! impl_unsigned_type {
+    ($type:ty) => {
+        impl<'a> From<&'a $type> for TypeCode {
+            fn from(arg: &$type) -> Self {
+                if *arg as u64 > i64::max_value() as u64 {
+                    TypeCode::kDLUInt
+                } else {
+                    TypeCode::kDLInt
+                }
+            }
+        }
+
+        impl<'a> From<&'a mut $type> for TypeCode {
+            fn from(arg: &mut $type) -> Self {
+                TypeCode::from(&*arg)
+            }
+        }
+    };
+}
+
+impl_prim_type!(bool, kDLInt);
+impl_prim_type!(isize, kDLInt);
+impl_prim_type!(i64, kDLInt);
+impl_prim_type!(i32, kDLInt);
+impl_prim_type!(i16, kDLInt);
+impl_prim_type!(i8, kDLInt);
+
+impl_unsigned_type!(u64);
+impl_unsigned_type!(usize);
+impl_prim_type!(u32, kDLInt);
+impl_prim_type!(u16, kDLInt);
+impl_prim_type!(u8, kDLInt);
+
+impl_prim_type!(f64, kDLFloat);
+impl_prim_type!(f32, kDLFloat);
+
+impl_prim_type!(str, kStr);
+impl_prim_type!(CStr, kStr);
+impl_prim_type!(String, kStr);
+impl_prim_type!(CString, kStr);
+
+impl_prim_type!([u8], kBytes);